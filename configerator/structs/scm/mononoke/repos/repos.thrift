@@ -141,6 +141,12 @@ struct RawDerivedDataConfig {
   // Defaults to v1
   3: optional RawUnodeVersion raw_unode_version,
   4: optional i64 override_blame_filesize_limit,
+  // Bonsai changeset ids (hex) that blame should look through, e.g. mass
+  // reformatting commits
+  5: optional list<string> blame_ignore_revs,
+  // Version of the mapping from changesets to derived data, per derived
+  // data type. Bumping it makes readers use freshly re-derived data.
+  6: optional map<string, i64> mapping_versions,
 }
 
 union RawUnodeVersion {
@@ -174,6 +180,8 @@ struct RawBlobstoreMultiplexed {
     // The number of components that must successfully `put` a blob before the
     // multiplex as a whole claims that it successfully `put` the blob
     6: optional i64 minimum_successful_writes,
+    // Check blobs before returning them from a get. Off if unset
+    7: optional RawMultiplexedReadVerification read_verification,
 }
 struct RawBlobstoreManifoldWithTtl {
     1: string manifold_bucket,
@@ -188,11 +196,50 @@ struct RawBlobstoreLogging {
 struct RawBlobstorePack {
     1: RawBlobstoreConfig blobstore (rust.box),
 }
+struct RawBlobstoreEncrypted {
+    1: RawBlobstoreConfig blobstore (rust.box),
+    // JSON file mapping key ids to hex encoded 256-bit keys
+    2: string key_file,
+    // Key id used to encrypt new blobs
+    3: string current_key_id,
+}
+struct RawBlobstoreDiskCache {
+    1: RawBlobstoreConfig blobstore (rust.box),
+    // Local directory to keep the cached blobs in
+    2: string path,
+    // Blobs are evicted once the cache is bigger than this
+    3: i64 max_bytes,
+}
+struct RawBlobstoreTiered {
+    // New blobs are written here, and it is read first
+    1: RawBlobstoreConfig hot (rust.box),
+    // Blobs are moved here from the hot tier once they are old
+    2: RawBlobstoreConfig cold (rust.box),
+}
 struct RawBlobstoreS3 {
     1: string bucket,
-    2: string keychain_group,
+    // Deprecated: use credentials = { keychain = { group = ... } } instead
+    2: optional string keychain_group,
     3: string region_name,
     4: string endpoint,
+    5: optional string prefix,
+    // Defaults to environment
+    6: optional RawS3Credentials credentials,
+}
+
+union RawS3Credentials {
+    1: RawS3CredentialsEnvironment environment,
+    2: RawS3CredentialsProfile profile,
+    3: RawS3CredentialsKeychain keychain,
+}
+
+struct RawS3CredentialsEnvironment {}
+struct RawS3CredentialsProfile {
+    1: optional string path,
+    2: optional string profile,
+}
+struct RawS3CredentialsKeychain {
+    1: string group,
 }
 
 // Configuration for a single blobstore. These are intended to be defined in a
@@ -213,6 +260,9 @@ union RawBlobstoreConfig {
     9: RawBlobstoreLogging logging,
     10: RawBlobstorePack pack,
     11: RawBlobstoreS3 s3,
+    12: RawBlobstoreEncrypted encrypted,
+    13: RawBlobstoreDiskCache disk_cache,
+    14: RawBlobstoreTiered tiered,
 }
 
 // A write-mostly blobstore is one that is not read from in normal operation.
@@ -227,6 +277,23 @@ union RawMultiplexedStoreType {
 struct RawMultiplexedStoreNormal {}
 struct RawMultiplexedStoreWriteMostly {}
 
+// A multiplex with read verification checks blobs before returning them from
+// a get. Blobstores that return a bad blob are logged, and if repair is set
+// the key is queued so that the healer overwrites them with a good copy.
+struct RawMultiplexedReadVerification {
+    1: RawMultiplexedReadVerificationMode mode,
+    2: optional bool repair,
+}
+
+union RawMultiplexedReadVerificationMode {
+    // Read from this many normal blobstores and compare their values
+    1: i64 quorum,
+    // Check blobs whose keys name a content hash against that hash
+    2: RawMultiplexedReadVerificationContentHash content_hash,
+}
+
+struct RawMultiplexedReadVerificationContentHash {}
+
 struct RawBlobstoreIdConfig {
     1: i64 blobstore_id,
     2: RawBlobstoreConfig blobstore,
@@ -398,6 +465,7 @@ struct RawInfinitepushParams {
     3: optional bool hydrate_getbundle_response,
     4: optional bool populate_reverse_filler_queue,
     5: optional string commit_scribe_category,
+    6: optional i64 bundle_ttl_secs,
 }
 
 struct RawFilestoreParams {
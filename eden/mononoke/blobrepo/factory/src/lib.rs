@@ -9,8 +9,10 @@ use anyhow::{Context, Error, Result};
 use blame::BlameRoot;
 use blobrepo::BlobRepo;
 use blobrepo_errors::*;
-use blobstore::Blobstore;
-use blobstore_factory::{make_blobstore, make_metadata_sql_factory, MetadataSqlFactory};
+use blobstore::{Blobstore, BlobstoreWithTtl};
+use blobstore_factory::{
+    make_blobstore, make_blobstore_with_ttl, make_metadata_sql_factory, MetadataSqlFactory,
+};
 use bonsai_git_mapping::{BonsaiGitMapping, SqlBonsaiGitMappingConnection};
 use bonsai_globalrev_mapping::{BonsaiGlobalrevMapping, SqlBonsaiGlobalrevMapping};
 use bonsai_hg_mapping::{BonsaiHgMapping, CachingBonsaiHgMapping, SqlBonsaiHgMapping};
@@ -26,13 +28,19 @@ use dbbookmarks::SqlBookmarksBuilder;
 use deleted_files_manifest::RootDeletedManifestId;
 use derived_data::BonsaiDerived;
 use derived_data_filenodes::FilenodesOnlyPublic;
+use diffstat::ChangesetDiffstat;
 use fastlog::RootFastlog;
 use fbinit::FacebookInit;
+use file_classification::RootFileClassification;
 use filenodes::Filenodes;
 use filestore::FilestoreConfig;
 use fsnodes::RootFsnodeId;
-use futures::{compat::Future01CompatExt, future, try_join};
-use git_types::TreeHandle;
+use futures::{
+    compat::Future01CompatExt,
+    future::{self, FutureExt, TryFutureExt},
+    try_join,
+};
+use git_types::{CommitHandle, TreeHandle};
 use maplit::btreeset;
 use memblob::EagerMemblob;
 use mercurial_derived_data::MappedHgChangesetId;
@@ -51,12 +59,17 @@ use scuba_ext::{ScubaSampleBuilder, ScubaSampleBuilderExt};
 use segmented_changelog::{
     DisabledSegmentedChangelog, SegmentedChangelog, SegmentedChangelogBuilder,
 };
+use skeleton_manifest::RootSkeletonManifestId;
 use slog::Logger;
 use sql::{rusqlite::Connection as SqliteConnection, Connection};
 use sql_construct::SqlConstruct;
 use sql_ext::{facebook::MysqlOptions, SqlConnections};
 use std::num::NonZeroUsize;
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Arc,
+    time::Duration,
+};
 use type_map::TypeMap;
 use unodes::RootUnodeManifestId;
 use virtually_sharded_blobstore::VirtuallyShardedBlobstore;
@@ -91,6 +104,7 @@ pub struct BlobrepoBuilder<'a> {
     logger: &'a Logger,
     derived_data_config: DerivedDataConfig,
     segmented_changelog_config: SegmentedChangelogConfig,
+    infinitepush_bundle_ttl: Option<Duration>,
 }
 
 impl<'a> BlobrepoBuilder<'a> {
@@ -121,6 +135,7 @@ impl<'a> BlobrepoBuilder<'a> {
             logger,
             derived_data_config: config.derived_data_config.clone(),
             segmented_changelog_config: config.segmented_changelog_config.clone(),
+            infinitepush_bundle_ttl: config.infinitepush.bundle_ttl,
         }
     }
 
@@ -151,6 +166,7 @@ impl<'a> BlobrepoBuilder<'a> {
             logger,
             derived_data_config,
             segmented_changelog_config,
+            infinitepush_bundle_ttl,
         } = self;
 
         let sql_factory = make_metadata_sql_factory(
@@ -163,6 +179,22 @@ impl<'a> BlobrepoBuilder<'a> {
         )
         .compat();
 
+        // Saved infinitepush bundles are written with a TTL, which the blobstore layers that
+        // make_blobstore adds don't support
+        let ttl_blobstore = match infinitepush_bundle_ttl {
+            Some(_) if !readonly_storage.0 => make_blobstore_with_ttl(
+                fb,
+                storage_config.blobstore.clone(),
+                mysql_options,
+                readonly_storage,
+                &blobstore_options,
+                &logger,
+            )
+            .map_ok(Some)
+            .left_future(),
+            _ => future::ok(None).right_future(),
+        };
+
         let blobstore = make_blobstore(
             fb,
             storage_config.blobstore,
@@ -172,11 +204,13 @@ impl<'a> BlobrepoBuilder<'a> {
             &logger,
         );
 
-        let (sql_factory, blobstore) = future::try_join(sql_factory, blobstore).await?;
+        let (sql_factory, blobstore, ttl_blobstore) =
+            future::try_join3(sql_factory, blobstore, ttl_blobstore).await?;
 
         open_blobrepo_given_datasources(
             fb,
             blobstore,
+            ttl_blobstore,
             sql_factory,
             repoid,
             caching,
@@ -198,6 +232,7 @@ impl<'a> BlobrepoBuilder<'a> {
 pub async fn open_blobrepo_given_datasources(
     fb: FacebookInit,
     blobstore: Arc<dyn Blobstore>,
+    ttl_blobstore: Option<Arc<dyn BlobstoreWithTtl>>,
     sql_factory: MetadataSqlFactory,
     repoid: RepositoryId,
     caching: Caching,
@@ -251,6 +286,7 @@ pub async fn open_blobrepo_given_datasources(
                 fb,
                 &sql_factory,
                 blobstore,
+                ttl_blobstore,
                 redacted_blobs,
                 censored_scuba_params,
                 repoid,
@@ -270,6 +306,7 @@ pub async fn open_blobrepo_given_datasources(
                 fb,
                 &sql_factory,
                 blobstore,
+                ttl_blobstore,
                 redacted_blobs,
                 censored_scuba_params,
                 repoid,
@@ -415,11 +452,17 @@ pub fn init_all_derived_data() -> DerivedDataConfig {
             RootFsnodeId::NAME.to_string(),
             RootDeletedManifestId::NAME.to_string(),
             RootUnodeManifestId::NAME.to_string(),
+            RootSkeletonManifestId::NAME.to_string(),
+            ChangesetDiffstat::NAME.to_string(),
+            RootFileClassification::NAME.to_string(),
             TreeHandle::NAME.to_string(),
+            CommitHandle::NAME.to_string(),
             MappedHgChangesetId::NAME.to_string(),
         },
         unode_version: UnodeVersion::V2,
         override_blame_filesize_limit: None,
+        blame_ignore_revs: BTreeSet::new(),
+        mapping_versions: BTreeMap::new(),
     }
 }
 
@@ -503,6 +546,7 @@ async fn new_development(
     fb: FacebookInit,
     sql_factory: &MetadataSqlFactory,
     blobstore: Arc<dyn Blobstore>,
+    ttl_blobstore: Option<Arc<dyn BlobstoreWithTtl>>,
     redacted_blobs: Option<HashMap<String, RedactedMetadata>>,
     censored_scuba_params: CensoredScubaParams,
     repoid: RepositoryId,
@@ -641,10 +685,16 @@ async fn new_development(
         }
     };
 
+    let mut repo_blobstore_args =
+        RepoBlobstoreArgs::new(blobstore, redacted_blobs, repoid, censored_scuba_builder);
+    if let Some(ttl_blobstore) = ttl_blobstore {
+        repo_blobstore_args = repo_blobstore_args.with_ttl_blobstore(ttl_blobstore);
+    }
+
     Ok(blobrepo_new(
         bookmarks,
         bookmark_update_log,
-        RepoBlobstoreArgs::new(blobstore, redacted_blobs, repoid, censored_scuba_builder),
+        repo_blobstore_args,
         Arc::new(filenodes_builder.build()),
         changesets,
         changeset_fetcher,
@@ -667,6 +717,7 @@ async fn new_production(
     fb: FacebookInit,
     sql_factory: &MetadataSqlFactory,
     blobstore: Arc<dyn Blobstore>,
+    ttl_blobstore: Option<Arc<dyn BlobstoreWithTtl>>,
     redacted_blobs: Option<HashMap<String, RedactedMetadata>>,
     censored_scuba_params: CensoredScubaParams,
     repoid: RepositoryId,
@@ -773,10 +824,16 @@ async fn new_production(
     phases_factory.enable_caching(fb, phases_cache_pool);
     let censored_scuba_builder = get_censored_scuba_builder(fb, censored_scuba_params)?;
 
+    let mut repo_blobstore_args =
+        RepoBlobstoreArgs::new(blobstore, redacted_blobs, repoid, censored_scuba_builder);
+    if let Some(ttl_blobstore) = ttl_blobstore {
+        repo_blobstore_args = repo_blobstore_args.with_ttl_blobstore(ttl_blobstore);
+    }
+
     Ok(blobrepo_new(
         bookmarks,
         bookmark_update_log,
-        RepoBlobstoreArgs::new(blobstore, redacted_blobs, repoid, censored_scuba_builder),
+        repo_blobstore_args,
         Arc::new(filenodes_builder.build()) as Arc<dyn Filenodes>,
         changesets,
         changeset_fetcher,
@@ -6,7 +6,7 @@
  */
 
 use anyhow::Error;
-use blobstore::{Blobstore, BlobstoreGetData};
+use blobstore::{Blobstore, BlobstoreGetData, BlobstoreWithTtl};
 use context::CoreContext;
 use futures::future::BoxFuture;
 use mononoke_types::{BlobstoreBytes, RepositoryId};
@@ -16,6 +16,7 @@ use scuba_ext::ScubaSampleBuilder;
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// RedactedBlobstore should be part of every blobstore since it is a layer
 /// which adds security by preventing users to access sensitive content.
@@ -30,8 +31,14 @@ type RepoBlobstoreStack<T> = RedactedBlobstore<PrefixBlobstore<T>>;
 // so that even if we were to add a blobstore to the RepoBlobstoreStack that actually is a Arc<dyn
 // Blobstore>, then we cannot accidentally forget to unwrap it below (since we wouldn't get a T
 // back).
+//
+// Blobs with a TTL are written through a separate stack over the same storage, as the layers
+// (e.g. caches) that the repo wraps around its storage don't support TTLs.
 #[derive(Clone, Debug)]
-pub struct AbstractRepoBlobstore<T>(RepoBlobstoreStack<T>);
+pub struct AbstractRepoBlobstore<T>(
+    RepoBlobstoreStack<T>,
+    Option<RepoBlobstoreStack<Arc<dyn BlobstoreWithTtl>>>,
+);
 
 impl<T: Blobstore + Clone> AbstractRepoBlobstore<T> {
     pub fn as_parts(&self) -> (T, RedactedBlobstoreConfig) {
@@ -39,6 +46,21 @@ impl<T: Blobstore + Clone> AbstractRepoBlobstore<T> {
 
         (blobstore.into_inner(), redacted_blobstore_config)
     }
+
+    /// Store `value` under `key` so that it expires `ttl` from now, if the repo's storage
+    /// supports TTLs. Otherwise it is stored permanently, as with `put`.
+    pub fn put_with_ttl(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        ttl: Duration,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        match &self.1 {
+            Some(ttl_blobstore) => ttl_blobstore.put_with_ttl(ctx, key, value, ttl),
+            None => self.0.put(ctx, key, value),
+        }
+    }
 }
 
 impl<T> Deref for AbstractRepoBlobstore<T> {
@@ -88,7 +110,20 @@ impl RepoBlobstoreArgs {
         scuba_builder: ScubaSampleBuilder,
     ) -> Self {
         let redacted_blobstore_config = RedactedBlobstoreConfig::new(redacted_blobs, scuba_builder);
-        Self::build(blobstore, repoid, redacted_blobstore_config)
+        Self::build(blobstore, None, repoid, redacted_blobstore_config)
+    }
+
+    /// Write blobs with a TTL to `ttl_blobstore`, which must store its blobs in the same place
+    /// as the repo's blobstore.
+    pub fn with_ttl_blobstore(self, ttl_blobstore: Arc<dyn BlobstoreWithTtl>) -> Self {
+        let Self { blobstore, repoid } = self;
+        let (blobstore, redacted_blobstore_config) = blobstore.as_parts();
+        Self::build(
+            blobstore,
+            Some(ttl_blobstore),
+            repoid,
+            redacted_blobstore_config,
+        )
     }
 
     pub fn new_with_wrapped_inner_blobstore<T, F>(
@@ -100,9 +135,18 @@ impl RepoBlobstoreArgs {
         T: Blobstore + Clone,
         F: FnOnce(Arc<dyn Blobstore>) -> T,
     {
+        let ttl_blobstore = blobstore
+            .1
+            .as_ref()
+            .map(|stack| stack.as_parts().0.into_inner());
         let (blobstore, redacted_blobstore_config) = blobstore.as_parts();
         let new_inner_blobstore = wrapper(blobstore);
-        Self::build(new_inner_blobstore, repoid, redacted_blobstore_config)
+        Self::build(
+            new_inner_blobstore,
+            ttl_blobstore,
+            repoid,
+            redacted_blobstore_config,
+        )
     }
 
     pub fn into_blobrepo_parts(self) -> (RepoBlobstore, RepositoryId) {
@@ -112,13 +156,18 @@ impl RepoBlobstoreArgs {
 
     fn build<T: Blobstore + Clone>(
         blobstore: T,
+        ttl_blobstore: Option<Arc<dyn BlobstoreWithTtl>>,
         repoid: RepositoryId,
         redacted_blobstore_config: RedactedBlobstoreConfig,
     ) -> Self {
+        let ttl_blobstore = ttl_blobstore.map(|ttl_blobstore| {
+            let ttl_blobstore = PrefixBlobstore::new(ttl_blobstore, repoid.prefix());
+            RedactedBlobstore::new(ttl_blobstore, redacted_blobstore_config.clone())
+        });
         let blobstore: Arc<dyn Blobstore> = Arc::new(blobstore);
         let blobstore = PrefixBlobstore::new(blobstore, repoid.prefix());
         let blobstore = RedactedBlobstore::new(blobstore, redacted_blobstore_config);
-        let blobstore = AbstractRepoBlobstore(blobstore);
+        let blobstore = AbstractRepoBlobstore(blobstore, ttl_blobstore);
 
         Self { blobstore, repoid }
     }
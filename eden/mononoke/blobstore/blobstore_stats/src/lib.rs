@@ -10,6 +10,7 @@ use std::time::Duration;
 use anyhow::Error;
 use futures_stats::FutureStats;
 use scuba::{ScubaSampleBuilder, ScubaValue};
+use stats::prelude::*;
 use time_ext::DurationExt;
 
 use blobstore::BlobstoreGetData;
@@ -26,11 +27,66 @@ const SESSION: &str = "session";
 const SIZE: &str = "size";
 const WRITE_ORDER: &str = "write_order";
 
+define_stats_struct! {
+    CacheStatsInner("mononoke.blobstore.cache.{}", cache_name: String),
+    get_hit: timeseries(Rate, Sum),
+    get_miss: timeseries(Rate, Sum),
+    put: timeseries(Rate, Sum),
+    put_bytes: timeseries(Rate, Sum),
+    put_skipped: timeseries(Rate, Sum),
+    evict: timeseries(Rate, Sum),
+    evict_bytes: timeseries(Rate, Sum),
+    corrupt: timeseries(Rate, Sum),
+    error: timeseries(Rate, Sum),
+}
+
+/// Hit, miss and eviction counters for a blobstore cache
+pub struct CacheStats(CacheStatsInner);
+
+impl CacheStats {
+    pub fn new(cache_name: String) -> Self {
+        CacheStats(CacheStatsInner::new(cache_name))
+    }
+
+    pub fn record_get(&self, hit: bool) {
+        if hit {
+            self.0.get_hit.add_value(1);
+        } else {
+            self.0.get_miss.add_value(1);
+        }
+    }
+
+    /// `stored` is false if the value was not cached, e.g. because it is too large
+    pub fn record_put(&self, size: u64, stored: bool) {
+        if stored {
+            self.0.put.add_value(1);
+            self.0.put_bytes.add_value(size as i64);
+        } else {
+            self.0.put_skipped.add_value(1);
+        }
+    }
+
+    pub fn record_evict(&self, size: u64) {
+        self.0.evict.add_value(1);
+        self.0.evict_bytes.add_value(size as i64);
+    }
+
+    /// A cached value failed verification and was dropped
+    pub fn record_corrupt(&self) {
+        self.0.corrupt.add_value(1);
+    }
+
+    pub fn record_error(&self) {
+        self.0.error.add_value(1);
+    }
+}
+
 #[derive(Clone, Copy)]
 pub enum OperationType {
     Get,
     Put,
     ScrubGet,
+    ReadVerification,
 }
 
 impl From<OperationType> for ScubaValue {
@@ -39,6 +95,7 @@ impl From<OperationType> for ScubaValue {
             OperationType::Get => ScubaValue::from("get"),
             OperationType::Put => ScubaValue::from("put"),
             OperationType::ScrubGet => ScubaValue::from("scrub_get"),
+            OperationType::ReadVerification => ScubaValue::from("read_verification"),
         }
     }
 }
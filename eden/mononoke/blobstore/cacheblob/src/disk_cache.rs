@@ -0,0 +1,551 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex, Weak,
+};
+
+use anyhow::{format_err, Error};
+use blobstore::{Blobstore, BlobstoreGetData, CountedBlobstore};
+use blobstore_stats::CacheStats;
+use bytes::Bytes;
+use futures::future::{FutureExt, TryFutureExt};
+use futures_ext::{BoxFuture, FutureExt as _};
+use mononoke_types::hash::Context as HashContext;
+use once_cell::sync::Lazy;
+
+use crate::dummy::DummyLease;
+use crate::in_process_lease::InProcessLease;
+use crate::locking_cache::{CacheBlobstore, CacheOps};
+
+// Start of every cache file, bump the version if the file format changes
+const MAGIC: &[u8] = b"MONONOKE_DISK_CACHE_1";
+const CHECKSUM_LEN: usize = 32;
+// Files are written here then renamed into place, so a crash never leaves a partial entry
+const TMP_DIR: &str = "tmp";
+const CACHE_NAME: &str = "disk";
+
+// Caches open in this process, by canonical path. Each directory has a single index, so that
+// blobstores sharing a directory share its size limit and don't evict each other's files.
+static OPEN_CACHES: Lazy<Mutex<HashMap<PathBuf, Weak<DiskCacheInner>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Clone, Copy, Debug)]
+pub struct DiskCacheOptions {
+    // Upper bound on the total size of the cache files
+    pub max_bytes: u64,
+    // Whether to wait for cache write before returning. Usually false apart from tests.
+    pub lazy_cache_put: bool,
+}
+
+impl DiskCacheOptions {
+    pub fn new_lazy(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            lazy_cache_put: true,
+        }
+    }
+
+    pub fn new_eager(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            lazy_cache_put: false,
+        }
+    }
+}
+
+struct LruEntry {
+    size: u64,
+    last_use: u64,
+}
+
+// In-memory index of the files in the cache, in least recently used order
+#[derive(Default)]
+struct LruIndex {
+    entries: HashMap<String, LruEntry>,
+    by_last_use: BTreeMap<u64, String>,
+    total_bytes: u64,
+    next_use: u64,
+}
+
+impl LruIndex {
+    fn touch(&mut self, name: &str) -> bool {
+        let last_use = self.next_use;
+        match self.entries.get_mut(name) {
+            Some(entry) => {
+                self.by_last_use.remove(&entry.last_use);
+                entry.last_use = last_use;
+                self.by_last_use.insert(last_use, name.to_string());
+                self.next_use += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, name: String, size: u64) {
+        self.remove(&name);
+        let last_use = self.next_use;
+        self.next_use += 1;
+        self.total_bytes += size;
+        self.by_last_use.insert(last_use, name.clone());
+        self.entries.insert(name, LruEntry { size, last_use });
+    }
+
+    fn remove(&mut self, name: &str) -> Option<u64> {
+        let entry = self.entries.remove(name)?;
+        self.by_last_use.remove(&entry.last_use);
+        self.total_bytes -= entry.size;
+        Some(entry.size)
+    }
+
+    // Removes and returns the least recently used entries until the cache fits in `max_bytes`
+    fn evict_to(&mut self, max_bytes: u64) -> Vec<(String, u64)> {
+        let mut evicted = Vec::new();
+        while self.total_bytes > max_bytes {
+            let name = match self.by_last_use.values().next() {
+                Some(name) => name.clone(),
+                None => break,
+            };
+            if let Some(size) = self.remove(&name) {
+                evicted.push((name, size));
+            }
+        }
+        evicted
+    }
+}
+
+struct DiskCacheInner {
+    path: PathBuf,
+    max_bytes: u64,
+    index: Mutex<LruIndex>,
+    tmp_counter: AtomicU64,
+    stats: CacheStats,
+}
+
+impl DiskCacheInner {
+    fn file_name(key: &str) -> String {
+        let mut context = HashContext::new(b"diskcache");
+        context.update(key.as_bytes());
+        context.finish().to_hex().to_string()
+    }
+
+    // Files are spread over 256 directories so that no directory gets too large
+    fn file_path(&self, name: &str) -> PathBuf {
+        self.path.join(&name[..2]).join(name)
+    }
+
+    fn checksum(key: &str, payload: &[u8]) -> Vec<u8> {
+        let mut context = HashContext::new(b"diskcache_value");
+        context.update(key.as_bytes());
+        context.update(payload);
+        context.finish().as_ref().to_vec()
+    }
+
+    fn get(&self, key: &str) -> Result<Option<BlobstoreGetData>, Error> {
+        let name = Self::file_name(key);
+        if !self.index.lock().expect("lock poisoned").touch(&name) {
+            return Ok(None);
+        }
+
+        let path = self.file_path(&name);
+        let mut contents = Vec::new();
+        match File::open(&path) {
+            Ok(mut file) => file.read_to_end(&mut contents)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                self.index.lock().expect("lock poisoned").remove(&name);
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        match Self::decode(key, contents) {
+            Some(value) => Ok(Some(value)),
+            None => {
+                // Treat a corrupt entry as a miss, so the value is fetched and cached again
+                self.stats.record_corrupt();
+                self.remove(&name);
+                Ok(None)
+            }
+        }
+    }
+
+    fn decode(key: &str, contents: Vec<u8>) -> Option<BlobstoreGetData> {
+        let mut contents = Bytes::from(contents);
+        if contents.len() < MAGIC.len() + 4 || &contents[..MAGIC.len()] != MAGIC {
+            return None;
+        }
+        let _ = contents.split_to(MAGIC.len());
+        let key_len = u32::from_le_bytes(contents.split_to(4).as_ref().try_into().ok()?) as usize;
+        if contents.len() < key_len + CHECKSUM_LEN {
+            return None;
+        }
+        // The key is stored to guard against hash collisions in the file name
+        if contents.split_to(key_len).as_ref() != key.as_bytes() {
+            return None;
+        }
+        let checksum = contents.split_to(CHECKSUM_LEN);
+        if checksum.as_ref() != Self::checksum(key, &contents).as_slice() {
+            return None;
+        }
+        BlobstoreGetData::decode(contents).ok()
+    }
+
+    fn put(&self, key: &str, value: BlobstoreGetData) -> Result<(), Error> {
+        let payload = value
+            .encode(None)
+            .map_err(|()| format_err!("Failed to encode value for {}", key))?;
+
+        let mut contents =
+            Vec::with_capacity(MAGIC.len() + 4 + key.len() + CHECKSUM_LEN + payload.len());
+        contents.extend_from_slice(MAGIC);
+        contents.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        contents.extend_from_slice(key.as_bytes());
+        contents.extend_from_slice(&Self::checksum(key, &payload));
+        contents.extend_from_slice(&payload);
+
+        let size = contents.len() as u64;
+        if size > self.max_bytes {
+            self.stats.record_put(size, false);
+            return Ok(());
+        }
+
+        let name = Self::file_name(key);
+        let path = self.file_path(&name);
+        let tmp_path = self.path.join(TMP_DIR).join(format!(
+            "{}.{}",
+            name,
+            self.tmp_counter.fetch_add(1, Ordering::Relaxed)
+        ));
+        {
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(&contents)?;
+            file.sync_all()?;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if let Err(e) = fs::rename(&tmp_path, &path) {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e.into());
+        }
+        self.stats.record_put(size, true);
+
+        let evicted = {
+            let mut index = self.index.lock().expect("lock poisoned");
+            index.insert(name, size);
+            index.evict_to(self.max_bytes)
+        };
+        for (name, size) in evicted {
+            self.stats.record_evict(size);
+            self.remove_file(&name);
+        }
+        Ok(())
+    }
+
+    fn check_present(&self, key: &str) -> bool {
+        self.index
+            .lock()
+            .expect("lock poisoned")
+            .entries
+            .contains_key(&Self::file_name(key))
+    }
+
+    fn remove(&self, name: &str) {
+        self.index.lock().expect("lock poisoned").remove(name);
+        self.remove_file(name);
+    }
+
+    fn remove_file(&self, name: &str) {
+        match fs::remove_file(self.file_path(name)) {
+            Err(ref e) if e.kind() != io::ErrorKind::NotFound => self.stats.record_error(),
+            _ => {}
+        }
+    }
+}
+
+/// A persistent cache of blobs in a local directory, evicting the least recently used blobs
+/// once the total size goes over a limit. Writes are atomic, and values are checksummed so that
+/// corrupted entries are dropped rather than returned.
+///
+/// The directory must not be shared between processes, as each keeps its own index of the
+/// cache contents. Within a process, opening the same directory again returns the cache that
+/// is already open.
+#[derive(Clone)]
+pub struct DiskCacheOps {
+    inner: Arc<DiskCacheInner>,
+}
+
+impl DiskCacheOps {
+    /// Opens the cache in `path`, creating it if needed. Entries left by a previous run are
+    /// kept, with the most recently written treated as the most recently used.
+    ///
+    /// If the cache is already open in this process, that cache is returned, and `max_bytes`
+    /// must match its limit.
+    pub fn open<P: AsRef<Path>>(path: P, max_bytes: u64) -> Result<Self, Error> {
+        fs::create_dir_all(path.as_ref())?;
+        let path = fs::canonicalize(path)?;
+
+        let mut open_caches = OPEN_CACHES.lock().expect("lock poisoned");
+        if let Some(inner) = open_caches.get(&path).and_then(Weak::upgrade) {
+            if inner.max_bytes != max_bytes {
+                return Err(format_err!(
+                    "Disk cache {} is already open with a limit of {} bytes, not {}",
+                    path.display(),
+                    inner.max_bytes,
+                    max_bytes
+                ));
+            }
+            return Ok(Self { inner });
+        }
+
+        // Anything in the temporary directory is from a write that didn't complete
+        let tmp_path = path.join(TMP_DIR);
+        if tmp_path.exists() {
+            fs::remove_dir_all(&tmp_path)?;
+        }
+        fs::create_dir_all(&tmp_path)?;
+
+        let mut existing = Vec::new();
+        for shard in fs::read_dir(&path)? {
+            let shard = shard?;
+            if shard.file_name() == TMP_DIR || !shard.file_type()?.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(shard.path())? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                if let Some(name) = entry.file_name().to_str() {
+                    existing.push((metadata.modified()?, name.to_string(), metadata.len()));
+                }
+            }
+        }
+        existing.sort();
+
+        let mut index = LruIndex::default();
+        for (_, name, size) in existing {
+            index.insert(name, size);
+        }
+        let evicted = index.evict_to(max_bytes);
+
+        let cache = Self {
+            inner: Arc::new(DiskCacheInner {
+                path,
+                max_bytes,
+                index: Mutex::new(index),
+                tmp_counter: AtomicU64::new(0),
+                stats: CacheStats::new(CACHE_NAME.to_string()),
+            }),
+        };
+        for (name, _) in evicted {
+            cache.inner.remove_file(&name);
+        }
+        open_caches.insert(path, Arc::downgrade(&cache.inner));
+        Ok(cache)
+    }
+
+    pub fn size_bytes(&self) -> u64 {
+        self.inner.index.lock().expect("lock poisoned").total_bytes
+    }
+}
+
+pub fn new_disk_cache_blobstore_no_lease<T, P>(
+    blobstore: T,
+    path: P,
+    options: DiskCacheOptions,
+) -> Result<CountedBlobstore<CacheBlobstore<DiskCacheOps, DummyLease, T>>, Error>
+where
+    T: Blobstore + Clone,
+    P: AsRef<Path>,
+{
+    let cache_ops = DiskCacheOps::open(path, options.max_bytes)?;
+    Ok(CountedBlobstore::new(
+        "disk_cache".to_string(),
+        CacheBlobstore::new(cache_ops, DummyLease {}, blobstore, options.lazy_cache_put),
+    ))
+}
+
+pub fn new_disk_cache_blobstore<T, P>(
+    blobstore: T,
+    path: P,
+    options: DiskCacheOptions,
+) -> Result<CountedBlobstore<CacheBlobstore<DiskCacheOps, InProcessLease, T>>, Error>
+where
+    T: Blobstore + Clone,
+    P: AsRef<Path>,
+{
+    let cache_ops = DiskCacheOps::open(path, options.max_bytes)?;
+    Ok(CountedBlobstore::new(
+        "disk_cache".to_string(),
+        CacheBlobstore::new(
+            cache_ops,
+            InProcessLease::new(),
+            blobstore,
+            options.lazy_cache_put,
+        ),
+    ))
+}
+
+impl CacheOps for DiskCacheOps {
+    const CACHE_NAME: &'static str = CACHE_NAME;
+
+    fn get(&self, key: &str) -> BoxFuture<Option<BlobstoreGetData>, ()> {
+        let inner = self.inner.clone();
+        let key = key.to_string();
+        async move {
+            let res = tokio::task::spawn_blocking({
+                let inner = inner.clone();
+                move || inner.get(&key)
+            })
+            .await;
+            match res {
+                Ok(Ok(value)) => {
+                    inner.stats.record_get(value.is_some());
+                    Ok(value)
+                }
+                _ => {
+                    inner.stats.record_error();
+                    Err(())
+                }
+            }
+        }
+        .boxed()
+        .compat()
+        .boxify()
+    }
+
+    fn put(&self, key: &str, value: BlobstoreGetData) -> BoxFuture<(), ()> {
+        let inner = self.inner.clone();
+        let key = key.to_string();
+        async move {
+            let res = tokio::task::spawn_blocking({
+                let inner = inner.clone();
+                move || inner.put(&key, value)
+            })
+            .await;
+            match res {
+                Ok(Ok(())) => Ok(()),
+                _ => {
+                    inner.stats.record_error();
+                    Err(())
+                }
+            }
+        }
+        .boxed()
+        .compat()
+        .boxify()
+    }
+
+    fn check_present(&self, key: &str) -> BoxFuture<bool, ()> {
+        futures_old::future::ok(self.inner.check_present(key)).boxify()
+    }
+}
+
+impl fmt::Debug for DiskCacheOps {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DiskCacheOps")
+            .field("path", &self.inner.path)
+            .field("max_bytes", &self.inner.max_bytes)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blobstore::BlobstoreMetadata;
+    use fbinit::FacebookInit;
+    use futures::compat::Future01CompatExt;
+    use mononoke_types::BlobstoreBytes;
+    use tempdir::TempDir;
+
+    fn value(data: &'static [u8]) -> BlobstoreGetData {
+        BlobstoreGetData::new(
+            BlobstoreMetadata::new(Some(1)),
+            BlobstoreBytes::from_bytes(data),
+        )
+    }
+
+    #[fbinit::compat_test]
+    async fn roundtrip_and_reopen(_fb: FacebookInit) -> Result<(), Error> {
+        let dir = TempDir::new("disk_cache")?;
+        let cache = DiskCacheOps::open(dir.path(), 1024 * 1024)?;
+
+        assert!(!cache.check_present("key").compat().await.unwrap());
+        assert!(cache.get("key").compat().await.unwrap().is_none());
+
+        cache.put("key", value(b"value")).compat().await.unwrap();
+        assert!(cache.check_present("key").compat().await.unwrap());
+        assert_eq!(
+            cache.get("key").compat().await.unwrap(),
+            Some(value(b"value"))
+        );
+
+        // Entries survive a restart
+        let size = cache.size_bytes();
+        drop(cache);
+        let cache = DiskCacheOps::open(dir.path(), 1024 * 1024)?;
+        assert_eq!(cache.size_bytes(), size);
+        assert_eq!(
+            cache.get("key").compat().await.unwrap(),
+            Some(value(b"value"))
+        );
+        Ok(())
+    }
+
+    #[fbinit::compat_test]
+    async fn evicts_least_recently_used(_fb: FacebookInit) -> Result<(), Error> {
+        let dir = TempDir::new("disk_cache")?;
+        let cache = DiskCacheOps::open(dir.path(), 1024 * 1024)?;
+        cache.put("key1", value(b"value1")).compat().await.unwrap();
+        let entry_size = cache.size_bytes();
+
+        // A second open of the same directory shares the cache
+        let shared = DiskCacheOps::open(dir.path(), 1024 * 1024)?;
+        assert!(Arc::ptr_eq(&shared.inner, &cache.inner));
+        assert!(DiskCacheOps::open(dir.path(), 2 * entry_size).is_err());
+        drop((cache, shared));
+
+        // Room for two entries
+        let cache = DiskCacheOps::open(dir.path(), 2 * entry_size)?;
+        cache.put("key2", value(b"value2")).compat().await.unwrap();
+        // Use key1 so that key2 is the least recently used
+        assert!(cache.get("key1").compat().await.unwrap().is_some());
+        cache.put("key3", value(b"value3")).compat().await.unwrap();
+
+        assert!(cache.check_present("key1").compat().await.unwrap());
+        assert!(!cache.check_present("key2").compat().await.unwrap());
+        assert!(cache.check_present("key3").compat().await.unwrap());
+        assert_eq!(cache.size_bytes(), 2 * entry_size);
+        Ok(())
+    }
+
+    #[fbinit::compat_test]
+    async fn drops_corrupt_entries(_fb: FacebookInit) -> Result<(), Error> {
+        let dir = TempDir::new("disk_cache")?;
+        let cache = DiskCacheOps::open(dir.path(), 1024 * 1024)?;
+        cache.put("key", value(b"value")).compat().await.unwrap();
+
+        let path = cache.inner.file_path(&DiskCacheInner::file_name("key"));
+        let mut contents = fs::read(&path)?;
+        let last = contents.len() - 1;
+        contents[last] ^= 0xff;
+        fs::write(&path, contents)?;
+
+        assert!(cache.get("key").compat().await.unwrap().is_none());
+        assert!(!cache.check_present("key").compat().await.unwrap());
+        assert!(!path.exists());
+        Ok(())
+    }
+}
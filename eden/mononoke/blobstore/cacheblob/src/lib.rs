@@ -12,6 +12,11 @@ pub use crate::cachelib_cache::{
     new_cachelib_blobstore, new_cachelib_blobstore_no_lease, CachelibBlobstoreOptions,
 };
 
+mod disk_cache;
+pub use crate::disk_cache::{
+    new_disk_cache_blobstore, new_disk_cache_blobstore_no_lease, DiskCacheOps, DiskCacheOptions,
+};
+
 pub mod dummy;
 
 mod in_process_lease;
@@ -0,0 +1,169 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! On-disk format of an encrypted blob:
+//!
+//! ```text
+//! +---------+------------+--------+-----------+-----------------------+
+//! | version | key id len | key id | nonce     | ciphertext + auth tag |
+//! | 1 byte  | 1 byte     | n      | 24 bytes  | ...                   |
+//! +---------+------------+--------+-----------+-----------------------+
+//! ```
+//!
+//! Everything before the nonce is authenticated as associated data, together with the blobstore
+//! key the value is stored under. Neither the key id nor the location of the value can be swapped
+//! without failing decryption.
+
+use bytes::Bytes;
+use chacha20poly1305::aead::{generic_array::GenericArray, Aead, Payload};
+use rand::RngCore;
+
+use crate::errors::ErrorKind;
+use crate::keys::KeyRing;
+
+const FORMAT_VERSION: u8 = 1;
+const NONCE_LEN: usize = 24;
+
+pub fn encrypt(keys: &KeyRing, blobstore_key: &str, plaintext: &[u8]) -> Result<Bytes, ErrorKind> {
+    let key_id = keys.current_key_id().as_bytes();
+
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let mut envelope = Vec::with_capacity(2 + key_id.len() + NONCE_LEN + plaintext.len() + 16);
+    envelope.push(FORMAT_VERSION);
+    // KeyRing only accepts key ids that fit in a byte
+    envelope.push(key_id.len() as u8);
+    envelope.extend_from_slice(key_id);
+
+    let ciphertext = keys
+        .current_cipher()
+        .encrypt(
+            GenericArray::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: &associated_data(&envelope, blobstore_key),
+            },
+        )
+        .map_err(|_| ErrorKind::EncryptionFailed)?;
+
+    envelope.extend_from_slice(&nonce);
+    envelope.extend_from_slice(&ciphertext);
+    Ok(Bytes::from(envelope))
+}
+
+pub fn decrypt(keys: &KeyRing, blobstore_key: &str, envelope: &[u8]) -> Result<Bytes, ErrorKind> {
+    let (header, key_id, rest) = split_header(envelope)?;
+    if rest.len() < NONCE_LEN {
+        return Err(ErrorKind::InvalidEnvelope("truncated nonce"));
+    }
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let plaintext = keys
+        .cipher(key_id)?
+        .decrypt(
+            GenericArray::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: &associated_data(header, blobstore_key),
+            },
+        )
+        .map_err(|_| ErrorKind::DecryptionFailed(key_id.to_string()))?;
+
+    Ok(Bytes::from(plaintext))
+}
+
+// The header records the length of the key id, so appending the blobstore key is unambiguous
+fn associated_data(header: &[u8], blobstore_key: &str) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(header.len() + blobstore_key.len());
+    aad.extend_from_slice(header);
+    aad.extend_from_slice(blobstore_key.as_bytes());
+    aad
+}
+
+/// Return the id of the key used to encrypt this envelope
+pub fn key_id(envelope: &[u8]) -> Result<&str, ErrorKind> {
+    split_header(envelope).map(|(_, key_id, _)| key_id)
+}
+
+fn split_header(envelope: &[u8]) -> Result<(&[u8], &str, &[u8]), ErrorKind> {
+    match envelope {
+        [FORMAT_VERSION, key_id_len, rest @ ..] => {
+            let key_id_len = *key_id_len as usize;
+            if rest.len() < key_id_len {
+                return Err(ErrorKind::InvalidEnvelope("truncated key id"));
+            }
+            let header_len = 2 + key_id_len;
+            let key_id = std::str::from_utf8(&rest[..key_id_len])
+                .map_err(|_| ErrorKind::InvalidEnvelope("key id is not utf-8"))?;
+            Ok((&envelope[..header_len], key_id, &envelope[header_len..]))
+        }
+        [version, ..] => Err(ErrorKind::UnsupportedVersion(*version)),
+        [] => Err(ErrorKind::InvalidEnvelope("empty")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn keys(current: &str) -> KeyRing {
+        KeyRing::new(
+            current,
+            vec![
+                ("old".to_string(), [1u8; 32]),
+                ("new".to_string(), [2u8; 32]),
+            ],
+        )
+        .expect("valid keys")
+    }
+
+    #[test]
+    fn test_roundtrip() -> Result<(), ErrorKind> {
+        let keys = keys("new");
+        let envelope = encrypt(&keys, "key", b"some plaintext")?;
+        assert_eq!(key_id(&envelope)?, "new");
+        assert!(!envelope.windows(9).any(|w| w == b"plaintext"));
+        assert_eq!(
+            decrypt(&keys, "key", &envelope)?,
+            Bytes::from("some plaintext")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_nonce_is_random() -> Result<(), ErrorKind> {
+        let keys = keys("new");
+        assert_ne!(
+            encrypt(&keys, "key", b"same")?,
+            encrypt(&keys, "key", b"same")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tampering_is_detected() -> Result<(), ErrorKind> {
+        let keys = keys("new");
+        let envelope = encrypt(&keys, "key", b"some plaintext")?;
+
+        let mut flipped = envelope.to_vec();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 1;
+        assert!(decrypt(&keys, "key", &flipped).is_err());
+
+        // Pretend the blob was encrypted with the other key
+        let mut swapped_key_id = envelope.to_vec();
+        swapped_key_id[2..5].copy_from_slice(b"old");
+        assert!(decrypt(&keys, "key", &swapped_key_id).is_err());
+
+        // A value moved to another blobstore key doesn't decrypt there
+        assert!(decrypt(&keys, "other key", &envelope).is_err());
+
+        assert!(decrypt(&keys, "key", &envelope[..10]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ErrorKind {
+    #[error("Unknown encryption key id {0:?}")]
+    UnknownKeyId(String),
+    #[error("Invalid encryption key {0:?}: {1}")]
+    InvalidKey(String, String),
+    #[error("Invalid encrypted envelope: {0}")]
+    InvalidEnvelope(&'static str),
+    #[error("Unsupported encrypted envelope version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Failed to encrypt blob")]
+    EncryptionFailed,
+    #[error("Failed to decrypt blob with key {0:?}, data is corrupt or was tampered with")]
+    DecryptionFailed(String),
+}
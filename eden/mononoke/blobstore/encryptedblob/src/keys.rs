@@ -0,0 +1,121 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Error};
+use chacha20poly1305::aead::{generic_array::GenericArray, NewAead};
+use chacha20poly1305::XChaCha20Poly1305;
+
+use crate::errors::ErrorKind;
+
+/// Length in bytes of the XChaCha20-Poly1305 keys
+pub const KEY_LEN: usize = 32;
+
+/// Key ids are stored in every envelope with a one byte length prefix.
+const MAX_KEY_ID_LEN: usize = u8::MAX as usize;
+
+/// The set of keys an `EncryptedBlob` can decrypt with, and the one it encrypts new blobs with.
+///
+/// Rotating keys is done by adding a new key, making it current, and keeping the old ones for
+/// as long as blobs encrypted with them are around.
+#[derive(Clone)]
+pub struct KeyRing {
+    current_key_id: String,
+    ciphers: Arc<HashMap<String, XChaCha20Poly1305>>,
+}
+
+impl KeyRing {
+    pub fn new(
+        current_key_id: impl Into<String>,
+        keys: impl IntoIterator<Item = (String, [u8; KEY_LEN])>,
+    ) -> Result<Self, Error> {
+        let current_key_id = current_key_id.into();
+
+        let mut ciphers = HashMap::new();
+        for (key_id, key) in keys {
+            if key_id.is_empty() || key_id.len() > MAX_KEY_ID_LEN {
+                return Err(ErrorKind::InvalidKey(
+                    key_id,
+                    format!("key ids must be 1 to {} bytes long", MAX_KEY_ID_LEN),
+                )
+                .into());
+            }
+            let cipher = XChaCha20Poly1305::new(GenericArray::from_slice(&key));
+            ciphers.insert(key_id, cipher);
+        }
+
+        if !ciphers.contains_key(&current_key_id) {
+            return Err(ErrorKind::UnknownKeyId(current_key_id).into());
+        }
+
+        Ok(Self {
+            current_key_id,
+            ciphers: Arc::new(ciphers),
+        })
+    }
+
+    /// Load keys from a JSON file mapping key ids to hex encoded keys, e.g.
+    /// `{"2020-09": "<64 hex digits>", "2020-10": "<64 hex digits>"}`
+    pub fn from_file(
+        path: impl AsRef<Path>,
+        current_key_id: impl Into<String>,
+    ) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let content = fs::read(path)
+            .with_context(|| format!("While reading encryption keys from {:?}", path))?;
+        let hex_keys: BTreeMap<String, String> = serde_json::from_slice(&content)
+            .with_context(|| format!("While parsing encryption keys from {:?}", path))?;
+
+        let keys = hex_keys
+            .into_iter()
+            .map(|(key_id, hex_key)| {
+                let mut key = [0u8; KEY_LEN];
+                hex::decode_to_slice(hex_key.trim(), &mut key).map_err(|e| {
+                    ErrorKind::InvalidKey(
+                        key_id.clone(),
+                        format!("expected {} hex encoded bytes: {}", KEY_LEN, e),
+                    )
+                })?;
+                Ok((key_id, key))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Self::new(current_key_id, keys)
+    }
+
+    pub fn current_key_id(&self) -> &str {
+        &self.current_key_id
+    }
+
+    pub(crate) fn current_cipher(&self) -> &XChaCha20Poly1305 {
+        // Presence of the current key is checked on construction
+        &self.ciphers[&self.current_key_id]
+    }
+
+    pub(crate) fn cipher(&self, key_id: &str) -> Result<&XChaCha20Poly1305, ErrorKind> {
+        self.ciphers
+            .get(key_id)
+            .ok_or_else(|| ErrorKind::UnknownKeyId(key_id.to_string()))
+    }
+}
+
+// Never print the key material
+impl fmt::Debug for KeyRing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut key_ids: Vec<_> = self.ciphers.keys().collect();
+        key_ids.sort();
+        f.debug_struct("KeyRing")
+            .field("current_key_id", &self.current_key_id)
+            .field("key_ids", &key_ids)
+            .finish()
+    }
+}
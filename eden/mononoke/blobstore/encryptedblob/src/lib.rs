@@ -0,0 +1,17 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+mod envelope;
+mod errors;
+mod keys;
+mod store;
+
+pub use errors::ErrorKind;
+pub use keys::{KeyRing, KEY_LEN};
+pub use store::EncryptedBlob;
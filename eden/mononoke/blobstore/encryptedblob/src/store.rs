@@ -0,0 +1,222 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{Context, Error};
+use futures::future::{BoxFuture, FutureExt};
+
+use blobstore::{Blobstore, BlobstoreGetData, BlobstoreWithLink};
+use context::CoreContext;
+use mononoke_types::BlobstoreBytes;
+
+use crate::envelope;
+use crate::keys::KeyRing;
+
+/// A layer over an existing blobstore that encrypts values with XChaCha20-Poly1305 before they
+/// are passed to the inner store, and decrypts them on the way back. Keys are left unchanged.
+///
+/// Each value records the id of the key it was encrypted with, so values written before a key
+/// rotation can still be read as long as the old key stays in the `KeyRing`.
+#[derive(Clone, Debug)]
+pub struct EncryptedBlob<T: Blobstore + Clone> {
+    inner: T,
+    keys: KeyRing,
+}
+
+impl<T: Blobstore + Clone> EncryptedBlob<T> {
+    pub fn new(inner: T, keys: KeyRing) -> Self {
+        Self { inner, keys }
+    }
+
+    /// Return the id of the key the value for `key` is encrypted with, or None if it is missing.
+    /// Useful to find values that still need re-encrypting after a rotation.
+    pub async fn get_key_id(&self, ctx: CoreContext, key: String) -> Result<Option<String>, Error> {
+        let inner_get_data = self.inner.get(ctx, key).await?;
+        inner_get_data
+            .map(|data| Ok(envelope::key_id(data.as_raw_bytes())?.to_string()))
+            .transpose()
+    }
+}
+
+impl<T: Blobstore + Clone> Blobstore for EncryptedBlob<T> {
+    fn get(
+        &self,
+        ctx: CoreContext,
+        key: String,
+    ) -> BoxFuture<'static, Result<Option<BlobstoreGetData>, Error>> {
+        let inner_get_data = self.inner.get(ctx, key.clone());
+        let keys = self.keys.clone();
+
+        async move {
+            let inner_get_data = match inner_get_data.await? {
+                Some(inner_get_data) => inner_get_data,
+                None => return Ok(None),
+            };
+
+            // Metadata such as ctime comes from the inner store untouched
+            let meta = inner_get_data.as_meta().clone();
+            let plaintext = envelope::decrypt(&keys, &key, inner_get_data.as_raw_bytes())
+                .with_context(|| format!("While decrypting {:?}", key))?;
+
+            Ok(Some(BlobstoreGetData::new(
+                meta,
+                BlobstoreBytes::from_bytes(plaintext),
+            )))
+        }
+        .boxed()
+    }
+
+    fn put(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        let envelope = envelope::encrypt(&self.keys, &key, value.as_bytes().as_ref());
+        let inner = self.inner.clone();
+
+        async move {
+            let envelope = envelope.with_context(|| format!("While encrypting {:?}", key))?;
+            inner
+                .put(ctx, key, BlobstoreBytes::from_bytes(envelope))
+                .await
+        }
+        .boxed()
+    }
+
+    fn is_present(&self, ctx: CoreContext, key: String) -> BoxFuture<'static, Result<bool, Error>> {
+        self.inner.is_present(ctx, key)
+    }
+}
+
+// The envelope is bound to the key it is stored under, so a link can't share the inner value and
+// is written as a copy re-encrypted for the new key instead.
+impl<T: Blobstore + Clone> BlobstoreWithLink for EncryptedBlob<T> {
+    fn link(
+        &self,
+        ctx: CoreContext,
+        existing_key: String,
+        link_key: String,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        let this = self.clone();
+
+        async move {
+            let value = this
+                .get(ctx.clone(), existing_key.clone())
+                .await?
+                .with_context(|| format!("Cannot link missing key {:?}", existing_key))?;
+            this.put(ctx, link_key, value.into_bytes()).await
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use fbinit::FacebookInit;
+    use memblob::EagerMemblob;
+    use std::sync::Arc;
+
+    fn keys(current: &str) -> KeyRing {
+        KeyRing::new(
+            current,
+            vec![
+                ("2020-09".to_string(), [7u8; 32]),
+                ("2020-10".to_string(), [9u8; 32]),
+            ],
+        )
+        .expect("valid keys")
+    }
+
+    #[fbinit::compat_test]
+    async fn test_roundtrip(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let inner = Arc::new(EagerMemblob::new());
+        let encrypted = EncryptedBlob::new(inner.clone(), keys("2020-10"));
+
+        let key = "repo0000.content.blake2.0123".to_string();
+        let value = BlobstoreBytes::from_bytes(Bytes::copy_from_slice(b"secret contents"));
+        encrypted
+            .put(ctx.clone(), key.clone(), value.clone())
+            .await?;
+
+        let stored = inner.get(ctx.clone(), key.clone()).await?.unwrap();
+        assert_ne!(stored.as_bytes(), &value);
+
+        let roundtrip = encrypted.get(ctx.clone(), key.clone()).await?.unwrap();
+        assert_eq!(roundtrip.into_bytes(), value);
+        assert!(encrypted.is_present(ctx.clone(), key.clone()).await?);
+        assert!(encrypted
+            .get(ctx.clone(), "missing".to_string())
+            .await?
+            .is_none());
+
+        // Swapping the stored values of two keys is detected
+        let other_key = "repo0000.content.blake2.4567".to_string();
+        encrypted
+            .put(ctx.clone(), other_key.clone(), value.clone())
+            .await?;
+        inner
+            .put(ctx.clone(), other_key.clone(), stored.into_bytes())
+            .await?;
+        assert!(encrypted.get(ctx.clone(), other_key.clone()).await.is_err());
+
+        // Links are re-encrypted for the new key
+        let link_key = "repo0000.content.blake2.89ab".to_string();
+        encrypted
+            .link(ctx.clone(), key.clone(), link_key.clone())
+            .await?;
+        let linked = encrypted.get(ctx.clone(), link_key).await?.unwrap();
+        assert_eq!(linked.into_bytes(), value);
+        Ok(())
+    }
+
+    #[fbinit::compat_test]
+    async fn test_key_rotation(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let inner = Arc::new(EagerMemblob::new());
+        let before = EncryptedBlob::new(inner.clone(), keys("2020-09"));
+        let after = EncryptedBlob::new(inner.clone(), keys("2020-10"));
+
+        let old_key = "old".to_string();
+        let new_key = "new".to_string();
+        let value = BlobstoreBytes::from_bytes(Bytes::copy_from_slice(b"contents"));
+        before
+            .put(ctx.clone(), old_key.clone(), value.clone())
+            .await?;
+        after
+            .put(ctx.clone(), new_key.clone(), value.clone())
+            .await?;
+
+        assert_eq!(
+            after.get_key_id(ctx.clone(), old_key.clone()).await?,
+            Some("2020-09".to_string())
+        );
+        assert_eq!(
+            after.get_key_id(ctx.clone(), new_key.clone()).await?,
+            Some("2020-10".to_string())
+        );
+        assert_eq!(
+            after
+                .get(ctx.clone(), old_key.clone())
+                .await?
+                .unwrap()
+                .into_bytes(),
+            value
+        );
+
+        // Once the old key is dropped, its values can no longer be read
+        let dropped = EncryptedBlob::new(
+            inner,
+            KeyRing::new("2020-10", vec![("2020-10".to_string(), [9u8; 32])])?,
+        );
+        assert!(dropped.get(ctx.clone(), old_key).await.is_err());
+        assert!(dropped.get(ctx, new_key).await?.is_some());
+        Ok(())
+    }
+}
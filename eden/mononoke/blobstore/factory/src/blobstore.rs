@@ -5,11 +5,14 @@
  * GNU General Public License version 2.
  */
 
-use anyhow::{Context, Error};
-use blobstore::{Blobstore, DisabledBlob, ErrorKind};
+use anyhow::{format_err, Context, Error};
+use blobstore::{
+    Blobstore, BlobstoreKeySource, BlobstoreWithTtl, DisabledBlob, ErrorKind, SweepableBlobstore,
+};
 use blobstore_sync_queue::SqlBlobstoreSyncQueue;
-use cacheblob::CachelibBlobstoreOptions;
+use cacheblob::{new_disk_cache_blobstore, CachelibBlobstoreOptions, DiskCacheOptions};
 use chaosblob::{ChaosBlobstore, ChaosOptions};
+use encryptedblob::{EncryptedBlob, KeyRing};
 use fbinit::FacebookInit;
 use fileblob::Fileblob;
 use futures::{
@@ -18,20 +21,25 @@ use futures::{
 };
 use logblob::LogBlob;
 use metaconfig_types::{
-    BlobConfig, BlobstoreId, DatabaseConfig, MultiplexId, MultiplexedStoreType, ScrubAction,
-    ShardableRemoteDatabaseConfig,
+    BlobConfig, BlobstoreId, DatabaseConfig, MultiplexId, MultiplexedStoreType, ReadVerification,
+    ScrubAction, ShardableRemoteDatabaseConfig,
+};
+use multiplexedblob::{
+    LoggingScrubHandler, MultiplexedBlobstore, MultiplexedKeySource, MultiplexedWithTtl,
+    ScrubBlobstore, ScrubHandler, VerifyingBlobstore,
 };
-use multiplexedblob::{LoggingScrubHandler, MultiplexedBlobstore, ScrubBlobstore, ScrubHandler};
 use packblob::{PackBlob, PackOptions};
 use readonlyblob::ReadOnlyBlobstore;
+use s3blob::S3Blob;
 use scuba::ScubaSampleBuilder;
 use slog::Logger;
 use sql_construct::SqlConstructFromDatabaseConfig;
 use sql_ext::facebook::{MysqlConnectionType, MysqlOptions};
-use sqlblob::Sqlblob;
+use sqlblob::{CountedSqlblob, Sqlblob};
 use std::num::{NonZeroU64, NonZeroUsize};
 use std::sync::Arc;
 use throttledblob::{ThrottleOptions, ThrottledBlob};
+use tieredblob::TieredBlob;
 
 use crate::ReadOnlyStorage;
 
@@ -104,84 +112,9 @@ pub fn make_blobstore<'a>(
                 .context(ErrorKind::StateOpen)
                 .map(|store| Arc::new(store) as Arc<dyn Blobstore>)?,
 
-            Mysql { remote } => match remote {
-                ShardableRemoteDatabaseConfig::Unsharded(config) => {
-                    let read_conn_type = mysql_options.read_connection_type();
-                    match mysql_options.connection_type {
-                        MysqlConnectionType::Myrouter(myrouter_port) => {
-                            Sqlblob::with_myrouter_unsharded(
-                                fb,
-                                config.db_address,
-                                myrouter_port,
-                                read_conn_type,
-                                readonly_storage.0,
-                            )
-                            .compat()
-                            .await
-                        }
-                        MysqlConnectionType::Mysql => {
-                            Sqlblob::with_mysql_unsharded(
-                                fb,
-                                config.db_address,
-                                read_conn_type,
-                                readonly_storage.0,
-                            )
-                            .compat()
-                            .await
-                        }
-                        MysqlConnectionType::RawXDB => {
-                            Sqlblob::with_raw_xdb_unsharded(
-                                fb,
-                                config.db_address,
-                                read_conn_type,
-                                readonly_storage.0,
-                            )
-                            .compat()
-                            .await
-                        }
-                    }
-                }
-                ShardableRemoteDatabaseConfig::Sharded(config) => {
-                    let read_conn_type = mysql_options.read_connection_type();
-                    match mysql_options.connection_type {
-                        MysqlConnectionType::Myrouter(myrouter_port) => {
-                            Sqlblob::with_myrouter(
-                                fb,
-                                config.shard_map.clone(),
-                                myrouter_port,
-                                read_conn_type,
-                                config.shard_num,
-                                readonly_storage.0,
-                            )
-                            .compat()
-                            .await
-                        }
-                        MysqlConnectionType::Mysql => {
-                            Sqlblob::with_mysql(
-                                fb,
-                                config.shard_map.clone(),
-                                config.shard_num,
-                                read_conn_type,
-                                readonly_storage.0,
-                            )
-                            .compat()
-                            .await
-                        }
-                        MysqlConnectionType::RawXDB => {
-                            Sqlblob::with_raw_xdb_shardmap(
-                                fb,
-                                config.shard_map.clone(),
-                                read_conn_type,
-                                config.shard_num,
-                                readonly_storage.0,
-                            )
-                            .compat()
-                            .await
-                        }
-                    }
-                }
-            }
-            .map(|store| Arc::new(store) as Arc<dyn Blobstore>)?,
+            Mysql { remote } => make_sqlblob_mysql(fb, remote, mysql_options, readonly_storage)
+                .await
+                .map(|store| Arc::new(store) as Arc<dyn Blobstore>)?,
             Multiplexed {
                 multiplex_id,
                 scuba_table,
@@ -189,6 +122,7 @@ pub fn make_blobstore<'a>(
                 blobstores,
                 minimum_successful_writes,
                 queue_db,
+                read_verification,
             } => {
                 has_components = true;
                 make_blobstore_multiplexed(
@@ -200,6 +134,7 @@ pub fn make_blobstore<'a>(
                     blobstores,
                     minimum_successful_writes,
                     None,
+                    read_verification,
                     mysql_options,
                     readonly_storage,
                     blobstore_options,
@@ -229,6 +164,7 @@ pub fn make_blobstore<'a>(
                         Arc::new(LoggingScrubHandler::new(false)) as Arc<dyn ScrubHandler>,
                         scrub_action,
                     )),
+                    None,
                     mysql_options,
                     readonly_storage,
                     blobstore_options,
@@ -313,25 +249,77 @@ pub fn make_blobstore<'a>(
                 Arc::new(PackBlob::new(store, blobstore_options.pack_options.clone()))
                     as Arc<dyn Blobstore>
             }
+            Encrypted {
+                blobconfig,
+                key_file,
+                current_key_id,
+            } => {
+                let keys = KeyRing::from_file(key_file, current_key_id)?;
+                let store = make_blobstore(
+                    fb,
+                    *blobconfig,
+                    mysql_options,
+                    readonly_storage,
+                    &blobstore_options,
+                    logger,
+                )
+                .await?;
+
+                Arc::new(EncryptedBlob::new(store, keys)) as Arc<dyn Blobstore>
+            }
+            DiskCache {
+                blobconfig,
+                path,
+                max_bytes,
+            } => {
+                let store = make_blobstore(
+                    fb,
+                    *blobconfig,
+                    mysql_options,
+                    readonly_storage,
+                    &blobstore_options,
+                    logger,
+                )
+                .await?;
+
+                new_disk_cache_blobstore(store, path, DiskCacheOptions::new_lazy(max_bytes))
+                    .context(ErrorKind::StateOpen)
+                    .map(|store| Arc::new(store) as Arc<dyn Blobstore>)?
+            }
+            Tiered { hot, cold } => {
+                has_components = true;
+                let (hot, cold) = future::try_join(
+                    make_blobstore(
+                        fb,
+                        *hot,
+                        mysql_options,
+                        readonly_storage,
+                        &blobstore_options,
+                        logger,
+                    ),
+                    make_blobstore(
+                        fb,
+                        *cold,
+                        mysql_options,
+                        readonly_storage,
+                        &blobstore_options,
+                        logger,
+                    ),
+                )
+                .await?;
+
+                Arc::new(TieredBlob::new(hot, cold)) as Arc<dyn Blobstore>
+            }
             S3 {
                 bucket,
-                keychain_group,
+                prefix,
                 region_name,
                 endpoint,
-            } => {
-                #[cfg(fbcode_build)]
-                {
-                    ::s3blob::S3Blob::new(fb, bucket, keychain_group, region_name, endpoint)
-                        .await
-                        .context(ErrorKind::StateOpen)
-                        .map(|store| Arc::new(store) as Arc<dyn Blobstore>)?
-                }
-                #[cfg(not(fbcode_build))]
-                {
-                    let _ = (bucket, keychain_group, region_name, endpoint);
-                    unimplemented!("This is implemented only for fbcode_build")
-                }
-            }
+                credentials,
+            } => S3Blob::new(fb, bucket, prefix, region_name, endpoint, credentials)
+                .await
+                .context(ErrorKind::StateOpen)
+                .map(|store| Arc::new(store) as Arc<dyn Blobstore>)?,
         };
 
         let store = if readonly_storage.0 {
@@ -364,6 +352,318 @@ pub fn make_blobstore<'a>(
     .boxed()
 }
 
+async fn make_sqlblob_mysql(
+    fb: FacebookInit,
+    remote: ShardableRemoteDatabaseConfig,
+    mysql_options: MysqlOptions,
+    readonly_storage: ReadOnlyStorage,
+) -> Result<CountedSqlblob, Error> {
+    match remote {
+        ShardableRemoteDatabaseConfig::Unsharded(config) => {
+            let read_conn_type = mysql_options.read_connection_type();
+            match mysql_options.connection_type {
+                MysqlConnectionType::Myrouter(myrouter_port) => {
+                    Sqlblob::with_myrouter_unsharded(
+                        fb,
+                        config.db_address,
+                        myrouter_port,
+                        read_conn_type,
+                        readonly_storage.0,
+                    )
+                    .compat()
+                    .await
+                }
+                MysqlConnectionType::Mysql => {
+                    Sqlblob::with_mysql_unsharded(
+                        fb,
+                        config.db_address,
+                        read_conn_type,
+                        readonly_storage.0,
+                    )
+                    .compat()
+                    .await
+                }
+                MysqlConnectionType::RawXDB => {
+                    Sqlblob::with_raw_xdb_unsharded(
+                        fb,
+                        config.db_address,
+                        read_conn_type,
+                        readonly_storage.0,
+                    )
+                    .compat()
+                    .await
+                }
+            }
+        }
+        ShardableRemoteDatabaseConfig::Sharded(config) => {
+            let read_conn_type = mysql_options.read_connection_type();
+            match mysql_options.connection_type {
+                MysqlConnectionType::Myrouter(myrouter_port) => {
+                    Sqlblob::with_myrouter(
+                        fb,
+                        config.shard_map.clone(),
+                        myrouter_port,
+                        read_conn_type,
+                        config.shard_num,
+                        readonly_storage.0,
+                    )
+                    .compat()
+                    .await
+                }
+                MysqlConnectionType::Mysql => {
+                    Sqlblob::with_mysql(
+                        fb,
+                        config.shard_map.clone(),
+                        config.shard_num,
+                        read_conn_type,
+                        readonly_storage.0,
+                    )
+                    .compat()
+                    .await
+                }
+                MysqlConnectionType::RawXDB => {
+                    Sqlblob::with_raw_xdb_shardmap(
+                        fb,
+                        config.shard_map.clone(),
+                        read_conn_type,
+                        config.shard_num,
+                        readonly_storage.0,
+                    )
+                    .compat()
+                    .await
+                }
+            }
+        }
+    }
+}
+
+/// Construct a blobstore that can enumerate and unlink keys, for the blobstore configs that
+/// support both. No wrappers are added, so the keys seen are those of the underlying store.
+pub fn make_sweep_blobstore(
+    fb: FacebookInit,
+    blobconfig: BlobConfig,
+    mysql_options: MysqlOptions,
+    readonly_storage: ReadOnlyStorage,
+) -> BoxFuture<'static, Result<Arc<dyn SweepableBlobstore>, Error>> {
+    // NOTE: This needs to return a BoxFuture because it recurses.
+    async move {
+        use BlobConfig::*;
+
+        let store = match blobconfig {
+            Files { path } => Fileblob::open(path.join("blobs"))
+                .context(ErrorKind::StateOpen)
+                .map(|store| Arc::new(store) as Arc<dyn SweepableBlobstore>)?,
+            Sqlite { path } => Sqlblob::with_sqlite_path(path.join("blobs"), readonly_storage.0)
+                .context(ErrorKind::StateOpen)
+                .map(|store| Arc::new(store) as Arc<dyn SweepableBlobstore>)?,
+            Mysql { remote } => make_sqlblob_mysql(fb, remote, mysql_options, readonly_storage)
+                .await
+                .map(|store| Arc::new(store) as Arc<dyn SweepableBlobstore>)?,
+            Pack { blobconfig } => {
+                let store =
+                    make_sweep_blobstore(fb, *blobconfig, mysql_options, readonly_storage).await?;
+                Arc::new(PackBlob::new(store, PackOptions::default()))
+                    as Arc<dyn SweepableBlobstore>
+            }
+            blobconfig => {
+                return Err(format_err!(
+                    "Sweeping is not supported for blobstore {:?}",
+                    blobconfig
+                ));
+            }
+        };
+
+        Ok(store)
+    }
+    .boxed()
+}
+
+/// Construct a blobstore that can store blobs with a TTL and sweep them once they expire, for the
+/// blobstore configs that support it. Blobs with a TTL are written to every component of a
+/// multiplex directly. As with `make_sweep_blobstore`, no wrappers are added.
+pub fn make_blobstore_with_ttl<'a>(
+    fb: FacebookInit,
+    blobconfig: BlobConfig,
+    mysql_options: MysqlOptions,
+    readonly_storage: ReadOnlyStorage,
+    blobstore_options: &'a BlobstoreOptions,
+    logger: &'a Logger,
+) -> BoxFuture<'a, Result<Arc<dyn BlobstoreWithTtl>, Error>> {
+    // NOTE: This needs to return a BoxFuture because it recurses.
+    async move {
+        use BlobConfig::*;
+
+        let store = match blobconfig {
+            Files { path } => Fileblob::open(path.join("blobs"))
+                .context(ErrorKind::StateOpen)
+                .map(|store| Arc::new(store) as Arc<dyn BlobstoreWithTtl>)?,
+            Sqlite { path } => Sqlblob::with_sqlite_path(path.join("blobs"), readonly_storage.0)
+                .context(ErrorKind::StateOpen)
+                .map(|store| Arc::new(store) as Arc<dyn BlobstoreWithTtl>)?,
+            Mysql { remote } => make_sqlblob_mysql(fb, remote, mysql_options, readonly_storage)
+                .await
+                .map(|store| Arc::new(store) as Arc<dyn BlobstoreWithTtl>)?,
+            Multiplexed {
+                multiplex_id,
+                scuba_table,
+                scuba_sample_rate,
+                blobstores,
+                minimum_successful_writes,
+                queue_db,
+                read_verification,
+            } => {
+                let components =
+                    future::try_join_all(blobstores.iter().map(|(blobstore_id, _, config)| {
+                        let blobstore_id = *blobstore_id;
+                        let config = config.clone();
+                        async move {
+                            let store = make_blobstore_with_ttl(
+                                fb,
+                                config,
+                                mysql_options,
+                                readonly_storage,
+                                blobstore_options,
+                                logger,
+                            )
+                            .await?;
+                            Ok::<_, Error>((blobstore_id, store))
+                        }
+                    }))
+                    .await?;
+
+                let store = make_blobstore_multiplexed(
+                    fb,
+                    multiplex_id,
+                    queue_db,
+                    scuba_table,
+                    scuba_sample_rate,
+                    blobstores,
+                    minimum_successful_writes,
+                    None,
+                    read_verification,
+                    mysql_options,
+                    readonly_storage,
+                    blobstore_options,
+                    logger,
+                )
+                .await?;
+
+                Arc::new(MultiplexedWithTtl::new(store, components)) as Arc<dyn BlobstoreWithTtl>
+            }
+            blobconfig => {
+                return Err(format_err!(
+                    "TTLs are not supported for blobstore {:?}",
+                    blobconfig
+                ));
+            }
+        };
+
+        Ok(store)
+    }
+    .boxed()
+}
+
+/// Construct a blobstore that can enumerate its keys, for the blobstore configs that support it.
+/// The components of a multiplex are enumerated individually, so keys that have not yet been
+/// healed to every component are found too. As with `make_sweep_blobstore`, no
+/// wrappers are added.
+pub fn make_blobstore_enumerable<'a>(
+    fb: FacebookInit,
+    blobconfig: BlobConfig,
+    mysql_options: MysqlOptions,
+    readonly_storage: ReadOnlyStorage,
+    blobstore_options: &'a BlobstoreOptions,
+    logger: &'a Logger,
+) -> BoxFuture<'a, Result<Arc<dyn BlobstoreKeySource>, Error>> {
+    // NOTE: This needs to return a BoxFuture because it recurses.
+    async move {
+        use BlobConfig::*;
+
+        let store = match blobconfig {
+            Files { path } => Fileblob::open(path.join("blobs"))
+                .context(ErrorKind::StateOpen)
+                .map(|store| Arc::new(store) as Arc<dyn BlobstoreKeySource>)?,
+            Sqlite { path } => Sqlblob::with_sqlite_path(path.join("blobs"), readonly_storage.0)
+                .context(ErrorKind::StateOpen)
+                .map(|store| Arc::new(store) as Arc<dyn BlobstoreKeySource>)?,
+            Mysql { remote } => make_sqlblob_mysql(fb, remote, mysql_options, readonly_storage)
+                .await
+                .map(|store| Arc::new(store) as Arc<dyn BlobstoreKeySource>)?,
+            Pack { blobconfig } => {
+                let store = make_blobstore_enumerable(
+                    fb,
+                    *blobconfig,
+                    mysql_options,
+                    readonly_storage,
+                    blobstore_options,
+                    logger,
+                )
+                .await?;
+
+                Arc::new(PackBlob::new(store, blobstore_options.pack_options.clone()))
+                    as Arc<dyn BlobstoreKeySource>
+            }
+            Multiplexed {
+                multiplex_id,
+                scuba_table,
+                scuba_sample_rate,
+                blobstores,
+                minimum_successful_writes,
+                queue_db,
+                read_verification,
+            } => {
+                let components =
+                    future::try_join_all(blobstores.iter().map(|(blobstore_id, _, config)| {
+                        let blobstore_id = *blobstore_id;
+                        let config = config.clone();
+                        async move {
+                            let store = make_blobstore_enumerable(
+                                fb,
+                                config,
+                                mysql_options,
+                                readonly_storage,
+                                blobstore_options,
+                                logger,
+                            )
+                            .await?;
+                            Ok::<_, Error>((blobstore_id, store))
+                        }
+                    }))
+                    .await?;
+
+                let store = make_blobstore_multiplexed(
+                    fb,
+                    multiplex_id,
+                    queue_db,
+                    scuba_table,
+                    scuba_sample_rate,
+                    blobstores,
+                    minimum_successful_writes,
+                    None,
+                    read_verification,
+                    mysql_options,
+                    readonly_storage,
+                    blobstore_options,
+                    logger,
+                )
+                .await?;
+
+                Arc::new(MultiplexedKeySource::new(store, components))
+                    as Arc<dyn BlobstoreKeySource>
+            }
+            blobconfig => {
+                return Err(format_err!(
+                    "Enumeration is not supported for blobstore {:?}",
+                    blobconfig
+                ));
+            }
+        };
+
+        Ok(store)
+    }
+    .boxed()
+}
+
 pub fn make_blobstore_multiplexed<'a>(
     fb: FacebookInit,
     multiplex_id: MultiplexId,
@@ -373,6 +673,7 @@ pub fn make_blobstore_multiplexed<'a>(
     inner_config: Vec<(BlobstoreId, MultiplexedStoreType, BlobConfig)>,
     minimum_successful_writes: NonZeroUsize,
     scrub_args: Option<(Arc<dyn ScrubHandler>, ScrubAction)>,
+    read_verification: Option<ReadVerification>,
     mysql_options: MysqlOptions,
     readonly_storage: ReadOnlyStorage,
     blobstore_options: &'a BlobstoreOptions,
@@ -384,6 +685,9 @@ pub fn make_blobstore_multiplexed<'a>(
             Some((_, ScrubAction::Repair)) => ReadOnlyStorage(false),
             _ => readonly_storage,
         };
+        let scuba = scuba_table.map_or(ScubaSampleBuilder::with_discard(), |table| {
+            ScubaSampleBuilder::new(fb, table)
+        });
 
         let mut applied_chaos = false;
 
@@ -442,29 +746,36 @@ pub fn make_blobstore_multiplexed<'a>(
             (normal_components, write_mostly_components)
         };
 
-        let blobstore = match scrub_args {
-            Some((scrub_handler, scrub_action)) => Arc::new(ScrubBlobstore::new(
+        // Scrub reads and compares every blobstore, so it supersedes read verification
+        let blobstore = match (scrub_args, read_verification) {
+            (Some((scrub_handler, scrub_action)), _) => Arc::new(ScrubBlobstore::new(
                 multiplex_id,
                 normal_components,
                 write_mostly_components,
                 minimum_successful_writes,
                 Arc::new(queue),
-                scuba_table.map_or(ScubaSampleBuilder::with_discard(), |table| {
-                    ScubaSampleBuilder::new(fb, table)
-                }),
+                scuba,
                 scuba_sample_rate,
                 scrub_handler,
                 scrub_action,
             )) as Arc<dyn Blobstore>,
-            None => Arc::new(MultiplexedBlobstore::new(
+            (None, Some(read_verification)) => Arc::new(VerifyingBlobstore::new(
                 multiplex_id,
                 normal_components,
                 write_mostly_components,
                 minimum_successful_writes,
                 Arc::new(queue),
-                scuba_table.map_or(ScubaSampleBuilder::with_discard(), |table| {
-                    ScubaSampleBuilder::new(fb, table)
-                }),
+                scuba,
+                scuba_sample_rate,
+                read_verification,
+            )) as Arc<dyn Blobstore>,
+            (None, None) => Arc::new(MultiplexedBlobstore::new(
+                multiplex_id,
+                normal_components,
+                write_mostly_components,
+                minimum_successful_writes,
+                Arc::new(queue),
+                scuba,
                 scuba_sample_rate,
             )) as Arc<dyn Blobstore>,
         };
@@ -15,7 +15,10 @@ pub use chaosblob::ChaosOptions;
 pub use packblob::PackOptions;
 pub use throttledblob::ThrottleOptions;
 
-pub use crate::blobstore::{make_blobstore, make_blobstore_multiplexed, BlobstoreOptions};
+pub use crate::blobstore::{
+    make_blobstore, make_blobstore_enumerable, make_blobstore_multiplexed, make_blobstore_with_ttl,
+    make_sweep_blobstore, BlobstoreOptions,
+};
 pub use crate::sql::{make_metadata_sql_factory, MetadataSqlFactory};
 
 #[derive(Copy, Clone, PartialEq)]
@@ -9,34 +9,47 @@
 
 use std::collections::HashSet;
 use std::convert::TryFrom;
-use std::fs::create_dir_all;
+use std::fs::{create_dir_all, read_dir};
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
-use anyhow::{bail, format_err, Error, Result};
-use futures::future::{self, BoxFuture, FutureExt, TryFutureExt};
-use percent_encoding::{percent_encode, AsciiSet, CONTROLS};
+use anyhow::{bail, Error, Result};
+use futures::future::{BoxFuture, FutureExt, TryFutureExt};
+use percent_encoding::{percent_decode_str, percent_encode, AsciiSet, CONTROLS};
+use serde::{Deserialize, Serialize};
 
 use blobstore::{
-    Blobstore, BlobstoreEnumerationData, BlobstoreGetData, BlobstoreKeyParam, BlobstoreKeySource,
-    BlobstoreMetadata, BlobstoreWithLink,
+    Blobstore, BlobstoreEnumerationData, BlobstoreGetData, BlobstoreGetMetadata, BlobstoreKeyParam,
+    BlobstoreKeySource, BlobstoreKeyToken, BlobstoreMetadata, BlobstoreUnlink, BlobstoreWithLink,
+    BlobstoreWithTtl,
 };
 use context::CoreContext;
 use mononoke_types::BlobstoreBytes;
 use tempfile::NamedTempFile;
 use tokio::{
-    fs::{hard_link, File},
+    fs::{hard_link, metadata, read_to_string, remove_file, File},
     io::{self, AsyncReadExt, AsyncWriteExt},
+    task::spawn_blocking,
 };
 
-use walkdir::WalkDir;
-
 const PREFIX: &str = "blob";
+// Blobs put with a TTL have their expiry time, in seconds since the epoch, in a file next to them
+const EXPIRY_PREFIX: &str = "expiry";
 /// https://url.spec.whatwg.org/#fragment-percent-encode-set
 const FRAGMENT: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b'<').add(b'>').add(b'`');
 /// https://url.spec.whatwg.org/#path-percent-encode-set
 const PATH: &AsciiSet = &FRAGMENT.add(b'#').add(b'?').add(b'{').add(b'}');
 
+// Number of keys returned by each call to enumerate
+pub const ENUMERATE_PAGE_SIZE: usize = 1000;
+
+// Resumes an enumeration after the last key returned
+#[derive(Serialize, Deserialize)]
+struct EnumerationToken {
+    after: String,
+    end_key: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct Fileblob {
     base: PathBuf,
@@ -65,6 +78,70 @@ impl Fileblob {
         let key = percent_encode(key.as_bytes(), PATH);
         self.base.join(format!("{}-{}", PREFIX, key))
     }
+
+    fn expiry_path(&self, key: &String) -> PathBuf {
+        let key = percent_encode(key.as_bytes(), PATH);
+        self.base.join(format!("{}-{}", EXPIRY_PREFIX, key))
+    }
+
+    fn key(file_name: &str) -> Option<String> {
+        Self::key_with_prefix(PREFIX, file_name)
+    }
+
+    fn key_with_prefix(prefix: &str, file_name: &str) -> Option<String> {
+        let key = file_name.strip_prefix(prefix)?.strip_prefix('-')?;
+        percent_decode_str(key)
+            .decode_utf8()
+            .ok()
+            .map(|key| key.into_owned())
+    }
+}
+
+fn now() -> Result<i64> {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+    Ok(i64::try_from(now.as_secs())?)
+}
+
+async fn read_expiry(path: &Path) -> Result<Option<i64>> {
+    match read_to_string(path).await {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+        Ok(expiry) => Ok(Some(expiry.trim().parse()?)),
+    }
+}
+
+async fn is_expired(path: &Path) -> Result<bool> {
+    match read_expiry(path).await? {
+        Some(expiry) => Ok(expiry <= now()?),
+        None => Ok(false),
+    }
+}
+
+async fn exists(path: &Path) -> Result<bool> {
+    match metadata(path).await {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+        Ok(_) => Ok(true),
+    }
+}
+
+async fn remove_if_exists(path: &Path) -> Result<()> {
+    match remove_file(path).await {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+        Ok(()) => Ok(()),
+    }
+}
+
+async fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    // block_in_place on tempfile would be ideal here, but it interacts
+    // badly with tokio_compat
+    let tempfile = NamedTempFile::new()?;
+    let new_file = tempfile.as_file().try_clone()?;
+    let mut tokio_file = File::from_std(new_file);
+    tokio_file.write_all(data).await?;
+    tempfile.persist(path)?;
+    Ok(())
 }
 
 async fn ctime(file: &File) -> Option<i64> {
@@ -81,8 +158,12 @@ impl Blobstore for Fileblob {
         key: String,
     ) -> BoxFuture<'static, Result<Option<BlobstoreGetData>, Error>> {
         let p = self.path(&key);
+        let expiry_path = self.expiry_path(&key);
 
         async move {
+            if is_expired(&expiry_path).await? {
+                return Ok(None);
+            }
             let ret = match File::open(&p).await {
                 Err(ref r) if r.kind() == io::ErrorKind::NotFound => None,
                 Err(e) => return Err(e.into()),
@@ -108,16 +189,12 @@ impl Blobstore for Fileblob {
         value: BlobstoreBytes,
     ) -> BoxFuture<'static, Result<(), Error>> {
         let p = self.path(&key);
+        let expiry_path = self.expiry_path(&key);
 
         async move {
-            // block_in_place on tempfile would be ideal here, but it interacts
-            // badly with tokio_compat
-            let tempfile = NamedTempFile::new()?;
-            let new_file = tempfile.as_file().try_clone()?;
-            let mut tokio_file = File::from_std(new_file);
-            tokio_file.write_all(value.as_bytes().as_ref()).await?;
-            tempfile.persist(&p)?;
-            Ok(())
+            write_file(&p, value.as_bytes().as_ref()).await?;
+            // A plain put makes the blob permanent
+            remove_if_exists(&expiry_path).await
         }
         .boxed()
     }
@@ -128,8 +205,12 @@ impl Blobstore for Fileblob {
         key: String,
     ) -> BoxFuture<'static, Result<bool, Error>> {
         let p = self.path(&key);
+        let expiry_path = self.expiry_path(&key);
 
         async move {
+            if is_expired(&expiry_path).await? {
+                return Ok(false);
+            }
             let ret = match File::open(&p).await {
                 Err(ref e) if e.kind() == io::ErrorKind::NotFound => false,
                 Err(e) => return Err(e.into()),
@@ -157,32 +238,164 @@ impl BlobstoreWithLink for Fileblob {
     }
 }
 
+impl BlobstoreGetMetadata for Fileblob {
+    fn get_metadata(
+        &self,
+        _ctx: CoreContext,
+        key: String,
+    ) -> BoxFuture<'static, Result<Option<BlobstoreMetadata>, Error>> {
+        let p = self.path(&key);
+        let expiry_path = self.expiry_path(&key);
+
+        async move {
+            if is_expired(&expiry_path).await? {
+                return Ok(None);
+            }
+            match File::open(&p).await {
+                Err(ref r) if r.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+                Ok(f) => Ok(Some(BlobstoreMetadata::new(ctime(&f).await))),
+            }
+        }
+        .boxed()
+    }
+}
+
+impl BlobstoreUnlink for Fileblob {
+    // Removing one hardlink leaves the data reachable via any other links to it
+    fn unlink(&self, _ctx: CoreContext, key: String) -> BoxFuture<'static, Result<(), Error>> {
+        let p = self.path(&key);
+        let expiry_path = self.expiry_path(&key);
+
+        async move {
+            remove_if_exists(&p).await?;
+            remove_if_exists(&expiry_path).await
+        }
+        .boxed()
+    }
+}
+
+impl BlobstoreWithTtl for Fileblob {
+    fn put_with_ttl(
+        &self,
+        _ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        ttl: Duration,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        let p = self.path(&key);
+        let expiry_path = self.expiry_path(&key);
+
+        async move {
+            let existing_expiry = read_expiry(&expiry_path).await?;
+            if existing_expiry.is_none() && exists(&p).await? {
+                // Already stored permanently
+                return write_file(&p, value.as_bytes().as_ref()).await;
+            }
+
+            let expiry = now()? + i64::try_from(ttl.as_secs())?;
+            let expiry = existing_expiry.map_or(expiry, |existing| existing.max(expiry));
+            // Write the expiry first, so that the blob is never visible without it
+            write_file(&expiry_path, expiry.to_string().as_bytes()).await?;
+            write_file(&p, value.as_bytes().as_ref()).await
+        }
+        .boxed()
+    }
+
+    fn sweep_expired(
+        &self,
+        _ctx: CoreContext,
+        limit: u64,
+    ) -> BoxFuture<'static, Result<u64, Error>> {
+        let this = self.clone();
+
+        async move {
+            let base = this.base.clone();
+            let keys = spawn_blocking(move || -> Result<Vec<String>> {
+                Ok(read_dir(&base)?
+                    .filter_map(|entry| entry.ok())
+                    .filter_map(|entry| {
+                        entry
+                            .file_name()
+                            .to_str()
+                            .and_then(|name| Self::key_with_prefix(EXPIRY_PREFIX, name))
+                    })
+                    .collect())
+            })
+            .await??;
+
+            let now = now()?;
+            let mut swept = 0;
+            for key in keys {
+                if swept >= limit {
+                    break;
+                }
+                let expiry_path = this.expiry_path(&key);
+                match read_expiry(&expiry_path).await? {
+                    Some(expiry) if expiry <= now => {
+                        remove_if_exists(&this.path(&key)).await?;
+                        remove_if_exists(&expiry_path).await?;
+                        swept += 1;
+                    }
+                    _ => {}
+                }
+            }
+            Ok(swept)
+        }
+        .boxed()
+    }
+}
+
 impl BlobstoreKeySource for Fileblob {
+    // begin_key is inclusive and end_key is exclusive. An empty end_key means no upper bound.
     fn enumerate(
         &self,
         range: BlobstoreKeyParam,
     ) -> BoxFuture<'static, Result<BlobstoreEnumerationData, Error>> {
-        match range {
-            BlobstoreKeyParam::Start(range) => {
-                let mut enum_data = BlobstoreEnumerationData {
-                    keys: HashSet::new(),
-                    next_token: None,
+        let base = self.base.clone();
+
+        async move {
+            let (begin, exclude_begin, end_key) = match range {
+                BlobstoreKeyParam::Start(range) => (range.begin_key, false, range.end_key),
+                BlobstoreKeyParam::Continuation(BlobstoreKeyToken::StringToken(token)) => {
+                    let token: EnumerationToken = serde_json::from_str(&token)?;
+                    (token.after, true, token.end_key)
+                }
+            };
+
+            let mut keys = spawn_blocking({
+                let end_key = end_key.clone();
+                move || -> Result<Vec<String>> {
+                    Ok(read_dir(&base)?
+                        .filter_map(|entry| entry.ok())
+                        .filter_map(|entry| entry.file_name().to_str().and_then(Self::key))
+                        .filter(|key| {
+                            (key > &begin || (!exclude_begin && key == &begin))
+                                && (end_key.is_empty() || key < &end_key)
+                        })
+                        .collect())
+                }
+            })
+            .await??;
+            keys.sort();
+            keys.truncate(ENUMERATE_PAGE_SIZE);
+
+            let next_token = if keys.len() == ENUMERATE_PAGE_SIZE {
+                let token = EnumerationToken {
+                    after: keys[keys.len() - 1].clone(),
+                    end_key,
                 };
-                WalkDir::new(&self.base)
-                    .into_iter()
-                    .filter_map(|v| v.ok())
-                    .for_each(|entry| {
-                        let entry = entry.path().to_str();
-                        if let Some(data) = entry {
-                            let key = data.to_string();
-                            if key < range.end_key && key > range.begin_key {
-                                enum_data.keys.insert(key);
-                            }
-                        }
-                    });
-                future::ok(enum_data)
-            }
-            _ => future::err(format_err!("Fileblob does not support token, only ranges")),
+                Some(BlobstoreKeyParam::Continuation(
+                    BlobstoreKeyToken::StringToken(serde_json::to_string(&token)?),
+                ))
+            } else {
+                None
+            };
+
+            Ok(BlobstoreEnumerationData {
+                keys: keys.into_iter().collect(),
+                next_token,
+            })
         }
         .boxed()
     }
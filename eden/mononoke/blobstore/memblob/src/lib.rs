@@ -10,9 +10,9 @@ use std::fmt;
 use std::sync::{Arc, Mutex};
 
 use anyhow::{format_err, Error};
-use futures::future::{self, lazy, BoxFuture, FutureExt};
+use futures::future::{self, lazy, BoxFuture, FutureExt, TryFutureExt};
 
-use blobstore::{Blobstore, BlobstoreGetData, BlobstoreWithLink};
+use blobstore::{Blobstore, BlobstoreGetData, BlobstoreUnlink, BlobstoreWithLink};
 use context::CoreContext;
 use mononoke_types::BlobstoreBytes;
 
@@ -50,7 +50,11 @@ impl MemState {
     }
 
     fn unlink(&mut self, key: &str) -> Option<()> {
-        self.links.remove(key).map(|_| ())
+        let id = self.links.remove(key)?;
+        if !self.links.values().any(|other| *other == id) {
+            self.data.remove(&id);
+        }
+        Some(())
     }
 }
 
@@ -135,6 +139,12 @@ impl BlobstoreWithLink for EagerMemblob {
     }
 }
 
+impl BlobstoreUnlink for EagerMemblob {
+    fn unlink(&self, _ctx: CoreContext, key: String) -> BoxFuture<'static, Result<(), Error>> {
+        EagerMemblob::unlink(self, key).map_ok(|_| ()).boxed()
+    }
+}
+
 impl Blobstore for LazyMemblob {
     fn put(
         &self,
@@ -185,6 +195,12 @@ impl BlobstoreWithLink for LazyMemblob {
     }
 }
 
+impl BlobstoreUnlink for LazyMemblob {
+    fn unlink(&self, _ctx: CoreContext, key: String) -> BoxFuture<'static, Result<(), Error>> {
+        LazyMemblob::unlink(self, key).map_ok(|_| ()).boxed()
+    }
+}
+
 impl fmt::Debug for EagerMemblob {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("EagerMemblob")
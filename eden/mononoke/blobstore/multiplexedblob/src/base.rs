@@ -17,8 +17,8 @@ use futures::{
 };
 use futures_stats::TimedFutureExt;
 use itertools::{Either, Itertools};
-use metaconfig_types::{BlobstoreId, MultiplexId};
-use mononoke_types::BlobstoreBytes;
+use metaconfig_types::{BlobstoreId, MultiplexId, ReadVerificationMode};
+use mononoke_types::{verify_blob_id, BlobstoreBytes};
 use scuba::ScubaSampleBuilder;
 use std::{
     borrow::Borrow,
@@ -44,6 +44,7 @@ const REQUEST_TIMEOUT: Duration = Duration::from_secs(600);
 type BlobstoresWithEntry = Vec<HashSet<BlobstoreId>>;
 type BlobstoresReturnedNone = HashSet<BlobstoreId>;
 type BlobstoresReturnedError = HashMap<BlobstoreId, Error>;
+type BlobstoresReturnedBadValue = HashSet<BlobstoreId>;
 
 #[derive(Error, Debug, Clone)]
 pub enum ErrorKind {
@@ -61,6 +62,24 @@ pub enum ErrorKind {
     SomeMissingItem(Arc<BlobstoresReturnedNone>, Option<BlobstoreGetData>),
     #[error("Multiple failures on put: {0:?}")]
     MultiplePutFailures(Arc<BlobstoresReturnedError>),
+    #[error(
+        "All blobstores that returned this item returned a value that failed verification: {0:?}"
+    )]
+    AllValuesBad(Arc<BlobstoresReturnedBadValue>),
+}
+
+/// The result of a `verified_get`. `good` are the blobstores that returned `value`, and `bad`
+/// those that returned a different value, or one that does not match its key's content hash.
+pub struct VerifiedGet {
+    pub value: Option<BlobstoreGetData>,
+    pub good: HashSet<BlobstoreId>,
+    pub bad: HashSet<BlobstoreId>,
+}
+
+fn content_hash(value: &BlobstoreGetData) -> u64 {
+    let mut content_hash = XxHash::with_seed(0);
+    content_hash.write(value.as_raw_bytes());
+    content_hash.finish()
 }
 
 /// This handler is called on each successful put to underlying blobstore,
@@ -186,11 +205,8 @@ impl MultiplexedBlobstoreBase {
                     missing.insert(blobstore_id);
                 }
                 Some(value) => {
-                    let mut content_hash = XxHash::with_seed(0);
-                    content_hash.write(value.as_raw_bytes());
-                    let content_hash = content_hash.finish();
                     all_values
-                        .entry(content_hash)
+                        .entry(content_hash(&value))
                         .or_insert_with(HashSet::new)
                         .insert(blobstore_id);
                     last_get_data = Some(value);
@@ -224,6 +240,126 @@ impl MultiplexedBlobstoreBase {
     }
 }
 
+impl MultiplexedBlobstoreBase {
+    /// Get a blob, checking it before it is returned. With `ReadVerificationMode::Quorum`, reads
+    /// continue until that many blobstores agree on the value or every blobstore has answered;
+    /// with `ReadVerificationMode::ContentHash`, values that don't match the content hash in
+    /// their key are skipped. Write-mostly blobstores are only read once the normal blobstores
+    /// are exhausted, as in a normal `get`.
+    pub async fn verified_get(
+        &self,
+        ctx: &CoreContext,
+        key: &String,
+        mode: ReadVerificationMode,
+    ) -> Result<VerifiedGet, ErrorKind> {
+        let mut scuba = self.scuba.clone();
+        scuba.sampled(self.scuba_sample_rate);
+        let blobstores_count = self.blobstores.len() + self.write_mostly_blobstores.len();
+        let needed = match mode {
+            ReadVerificationMode::Quorum(quorum) => quorum.get(),
+            ReadVerificationMode::ContentHash => 1,
+        };
+
+        let main_requests: FuturesUnordered<_> = multiplexed_get(
+            ctx,
+            self.blobstores.as_ref(),
+            key,
+            OperationType::Get,
+            scuba.clone(),
+        )
+        .collect();
+        let write_mostly_requests: FuturesUnordered<_> = multiplexed_get(
+            ctx,
+            self.write_mostly_blobstores.as_ref(),
+            key,
+            OperationType::Get,
+            scuba,
+        )
+        .collect();
+        let mut requests = main_requests.chain(write_mostly_requests);
+
+        let mut errors = HashMap::new();
+        let mut bad = HashSet::new();
+        let mut answered = 0;
+        // Values grouped by content, with the blobstores that returned each of them
+        let mut all_values: HashMap<u64, (HashSet<BlobstoreId>, BlobstoreGetData)> = HashMap::new();
+
+        while let Some((blobstore_id, result)) = requests.next().await {
+            match result {
+                Ok(Some(value)) => {
+                    if mode == ReadVerificationMode::ContentHash
+                        && verify_blob_id(key, value.as_raw_bytes()) == Some(false)
+                    {
+                        bad.insert(blobstore_id);
+                        continue;
+                    }
+                    answered += 1;
+                    all_values
+                        .entry(content_hash(&value))
+                        .or_insert_with(|| (HashSet::new(), value))
+                        .0
+                        .insert(blobstore_id);
+                    // Keep reading on disagreement, so that the other blobstores can settle it
+                    if answered >= needed && all_values.len() == 1 {
+                        break;
+                    }
+                }
+                Ok(None) => {}
+                Err(error) => {
+                    errors.insert(blobstore_id, error);
+                }
+            }
+        }
+
+        if all_values.is_empty() {
+            return if !bad.is_empty() {
+                Err(ErrorKind::AllValuesBad(Arc::new(bad)))
+            } else if errors.is_empty() {
+                Ok(VerifiedGet {
+                    value: None,
+                    good: HashSet::new(),
+                    bad,
+                })
+            } else if errors.len() == blobstores_count {
+                Err(ErrorKind::AllFailed(Arc::new(errors)))
+            } else {
+                Err(write_mostly_error(&self.blobstores, errors))
+            };
+        }
+
+        // Prefer a value that matches its key's content hash. Otherwise, go with the majority,
+        // and give up if there isn't one.
+        let mut groups: Vec<_> = all_values
+            .into_iter()
+            .map(|(_, (stores, value))| {
+                let verified = verify_blob_id(key, value.as_raw_bytes()) == Some(true);
+                (verified, stores, value)
+            })
+            .collect();
+        groups.sort_by_key(|(verified, stores, _)| (*verified, stores.len()));
+        if let [.., (false, runner_up, _), (false, best, _)] = groups.as_slice() {
+            if runner_up.len() == best.len() {
+                let answered = groups.into_iter().map(|(_, stores, _)| stores).collect();
+                return Err(ErrorKind::ValueMismatch(
+                    Arc::new(answered),
+                    Arc::new(HashSet::new()),
+                ));
+            }
+        }
+
+        let (_, good, mut value) = groups.pop().expect("at least one value was returned");
+        for (_, stores, _) in groups {
+            bad.extend(stores);
+        }
+        value.remove_ctime();
+        Ok(VerifiedGet {
+            value: Some(value),
+            good,
+            bad,
+        })
+    }
+}
+
 fn remap_timeout_result<O>(
     timeout_or_result: Result<Result<O, Error>, tokio::time::Elapsed>,
 ) -> Result<O, Error> {
@@ -0,0 +1,129 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{format_err, Error};
+use blobstore::{
+    Blobstore, BlobstoreEnumerationData, BlobstoreGetData, BlobstoreKeyParam, BlobstoreKeySource,
+    BlobstoreKeyToken,
+};
+use context::CoreContext;
+use futures::future::{self, BoxFuture, FutureExt};
+use metaconfig_types::BlobstoreId;
+use mononoke_types::BlobstoreBytes;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Adds key enumeration to a multiplexed blobstore. Every component is enumerated and the union
+/// of their keys is returned, so keys that are still missing from some components (e.g. as they
+/// are waiting to be healed) are found too. A key can be returned by more than one call.
+#[derive(Clone, Debug)]
+pub struct MultiplexedKeySource<T> {
+    blobstore: T,
+    components: Arc<[(BlobstoreId, Arc<dyn BlobstoreKeySource>)]>,
+}
+
+impl<T: Blobstore> MultiplexedKeySource<T> {
+    /// `components` must be the key sources for the component stores of `blobstore`
+    pub fn new(blobstore: T, components: Vec<(BlobstoreId, Arc<dyn BlobstoreKeySource>)>) -> Self {
+        Self {
+            blobstore,
+            components: components.into(),
+        }
+    }
+
+    pub fn as_inner(&self) -> &T {
+        &self.blobstore
+    }
+}
+
+impl<T: Blobstore> Blobstore for MultiplexedKeySource<T> {
+    fn get(
+        &self,
+        ctx: CoreContext,
+        key: String,
+    ) -> BoxFuture<'static, Result<Option<BlobstoreGetData>, Error>> {
+        self.blobstore.get(ctx, key)
+    }
+
+    fn put(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        self.blobstore.put(ctx, key, value)
+    }
+
+    fn is_present(&self, ctx: CoreContext, key: String) -> BoxFuture<'static, Result<bool, Error>> {
+        self.blobstore.is_present(ctx, key)
+    }
+}
+
+impl<T: Blobstore> BlobstoreKeySource for MultiplexedKeySource<T> {
+    fn enumerate(
+        &self,
+        range: BlobstoreKeyParam,
+    ) -> BoxFuture<'static, Result<BlobstoreEnumerationData, Error>> {
+        let components = self.components.clone();
+        async move {
+            // The token holds the position of each component, in component order. Components
+            // that have been fully enumerated have no position.
+            let positions: Vec<Option<BlobstoreKeyParam>> = match range {
+                range @ BlobstoreKeyParam::Start(_) => vec![Some(range); components.len()],
+                BlobstoreKeyParam::Continuation(BlobstoreKeyToken::StringToken(token)) => {
+                    serde_json::from_str(&token)?
+                }
+            };
+            if positions.len() != components.len() {
+                return Err(format_err!(
+                    "Enumeration token is for {} blobstores, but there are {}",
+                    positions.len(),
+                    components.len()
+                ));
+            }
+
+            let pages = future::try_join_all(components.iter().zip(positions).map(
+                |((blobstore_id, blobstore), position)| {
+                    let blobstore_id = *blobstore_id;
+                    let page = position.map(|position| blobstore.enumerate(position));
+                    async move {
+                        match page {
+                            Some(page) => page.await.map(Some).map_err(|e| {
+                                e.context(format!("While enumerating blobstore {}", blobstore_id))
+                            }),
+                            None => Ok(None),
+                        }
+                    }
+                },
+            ))
+            .await?;
+
+            let mut keys = HashSet::new();
+            let mut next_positions = Vec::with_capacity(pages.len());
+            for page in pages {
+                match page {
+                    Some(page) => {
+                        keys.extend(page.keys);
+                        next_positions.push(page.next_token);
+                    }
+                    None => next_positions.push(None),
+                }
+            }
+
+            let next_token = if next_positions.iter().any(Option::is_some) {
+                Some(BlobstoreKeyParam::Continuation(
+                    BlobstoreKeyToken::StringToken(serde_json::to_string(&next_positions)?),
+                ))
+            } else {
+                None
+            };
+
+            Ok(BlobstoreEnumerationData { keys, next_token })
+        }
+        .boxed()
+    }
+}
@@ -8,11 +8,17 @@
 #![deny(warnings)]
 
 pub mod base;
+pub mod key_source;
 pub mod queue;
 pub mod scrub;
+pub mod ttl;
+pub mod verify;
 
+pub use crate::key_source::MultiplexedKeySource;
 pub use crate::queue::MultiplexedBlobstore;
 pub use crate::scrub::{LoggingScrubHandler, ScrubBlobstore, ScrubHandler};
+pub use crate::ttl::MultiplexedWithTtl;
+pub use crate::verify::VerifyingBlobstore;
 
 #[cfg(test)]
 mod test;
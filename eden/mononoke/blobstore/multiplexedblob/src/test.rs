@@ -6,18 +6,24 @@
  */
 
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     fmt,
     future::Future,
     pin::Pin,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use crate::base::{MultiplexedBlobstoreBase, MultiplexedBlobstorePutHandler};
+use crate::key_source::MultiplexedKeySource;
 use crate::queue::MultiplexedBlobstore;
 use crate::scrub::{LoggingScrubHandler, ScrubBlobstore, ScrubHandler};
+use crate::ttl::MultiplexedWithTtl;
+use crate::verify::VerifyingBlobstore;
 use anyhow::{bail, Error};
-use blobstore::{Blobstore, BlobstoreGetData};
+use blobstore::{
+    Blobstore, BlobstoreGetData, BlobstoreKeyParam, BlobstoreKeySource, BlobstoreWithTtl,
+};
 use blobstore_sync_queue::{
     BlobstoreSyncQueue, BlobstoreSyncQueueEntry, OperationKey, SqlBlobstoreSyncQueue,
 };
@@ -25,6 +31,7 @@ use bytes::Bytes;
 use cloned::cloned;
 use context::{CoreContext, SessionClass};
 use fbinit::FacebookInit;
+use fileblob::Fileblob;
 use futures::{
     channel::oneshot,
     future::{BoxFuture, FutureExt, TryFutureExt},
@@ -32,12 +39,15 @@ use futures::{
 };
 use lock_ext::LockExt;
 use memblob::LazyMemblob;
-use metaconfig_types::{BlobstoreId, MultiplexId, ScrubAction};
-use mononoke_types::{BlobstoreBytes, DateTime};
+use metaconfig_types::{
+    BlobstoreId, MultiplexId, ReadVerification, ReadVerificationMode, ScrubAction,
+};
+use mononoke_types::{BlobstoreBytes, BlobstoreValue, DateTime, FileContents, MononokeId};
 use nonzero_ext::nonzero;
 use readonlyblob::ReadOnlyBlobstore;
 use scuba::ScubaSampleBuilder;
 use sql_construct::SqlConstruct;
+use tempdir::TempDir;
 
 pub struct Tickable<T> {
     pub storage: Arc<Mutex<HashMap<String, T>>>,
@@ -1355,3 +1365,213 @@ async fn no_handlers(fb: FacebookInit) {
         clear();
     }
 }
+
+#[fbinit::compat_test]
+async fn enumerate_union(fb: FacebookInit) {
+    let ctx = CoreContext::test_mock(fb);
+    let dir0 = TempDir::new("multiplexed_enumerate0").unwrap();
+    let dir1 = TempDir::new("multiplexed_enumerate1").unwrap();
+    let bs0 = Arc::new(Fileblob::create(dir0.path()).unwrap());
+    let bs1 = Arc::new(Fileblob::create(dir1.path()).unwrap());
+    let v = BlobstoreBytes::from_bytes("v");
+
+    // "both" is in every component, the others haven't been healed yet
+    for (bs, key) in &[
+        (&bs0, "both"),
+        (&bs1, "both"),
+        (&bs0, "only0"),
+        (&bs1, "only1"),
+    ] {
+        bs.put(ctx.clone(), key.to_string(), v.clone())
+            .await
+            .unwrap();
+    }
+
+    let bs = MultiplexedKeySource::new(
+        bs0.clone(),
+        vec![
+            (
+                BlobstoreId::new(0),
+                bs0.clone() as Arc<dyn BlobstoreKeySource>,
+            ),
+            (
+                BlobstoreId::new(1),
+                bs1.clone() as Arc<dyn BlobstoreKeySource>,
+            ),
+        ],
+    );
+    let entries = bs.enumerate(BlobstoreKeyParam::from(..)).await.unwrap();
+    let expected: HashSet<_> = vec!["both", "only0", "only1"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    assert_eq!(entries.keys, expected);
+    assert_eq!(entries.next_token, None);
+}
+
+#[fbinit::compat_test]
+async fn ttl_writes_every_component(fb: FacebookInit) {
+    let ctx = CoreContext::test_mock(fb);
+    let dir0 = TempDir::new("multiplexed_ttl0").unwrap();
+    let dir1 = TempDir::new("multiplexed_ttl1").unwrap();
+    let bs0 = Arc::new(Fileblob::create(dir0.path()).unwrap());
+    let bs1 = Arc::new(Fileblob::create(dir1.path()).unwrap());
+
+    let bs = MultiplexedWithTtl::new(
+        bs0.clone(),
+        vec![
+            (
+                BlobstoreId::new(0),
+                bs0.clone() as Arc<dyn BlobstoreWithTtl>,
+            ),
+            (
+                BlobstoreId::new(1),
+                bs1.clone() as Arc<dyn BlobstoreWithTtl>,
+            ),
+        ],
+    );
+    for (key, ttl) in &[("expiring", 3600), ("expired", 0)] {
+        bs.put_with_ttl(
+            ctx.clone(),
+            key.to_string(),
+            BlobstoreBytes::from_bytes("v"),
+            Duration::from_secs(*ttl),
+        )
+        .await
+        .unwrap();
+    }
+    for component in &[&bs0, &bs1] {
+        assert!(component
+            .is_present(ctx.clone(), "expiring".to_string())
+            .await
+            .unwrap());
+        assert!(!component
+            .is_present(ctx.clone(), "expired".to_string())
+            .await
+            .unwrap());
+    }
+
+    // The expired key is swept from both components
+    assert_eq!(bs.sweep_expired(ctx.clone(), 100).await.unwrap(), 2);
+    assert_eq!(bs.sweep_expired(ctx.clone(), 100).await.unwrap(), 0);
+}
+
+#[fbinit::test]
+async fn verified_quorum(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let queue = Arc::new(SqlBlobstoreSyncQueue::with_sqlite_in_memory().unwrap());
+    let bid0 = BlobstoreId::new(0);
+    let bs0 = Arc::new(LazyMemblob::new());
+    let bid1 = BlobstoreId::new(1);
+    let bs1 = Arc::new(LazyMemblob::new());
+    let bid2 = BlobstoreId::new(2);
+    let bs2 = Arc::new(LazyMemblob::new());
+
+    let bs = VerifyingBlobstore::new(
+        MultiplexId::new(1),
+        vec![
+            (bid0, bs0.clone()),
+            (bid1, bs1.clone()),
+            (bid2, bs2.clone()),
+        ],
+        vec![],
+        nonzero!(1usize),
+        queue.clone(),
+        ScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+        ReadVerification {
+            mode: ReadVerificationMode::Quorum(nonzero!(3usize)),
+            action: ScrubAction::Repair,
+        },
+    );
+
+    // The majority wins, and the key is queued from the stores that agree on it
+    let k = "k".to_string();
+    bs0.put(ctx.clone(), k.clone(), make_value("good")).await?;
+    bs1.put(ctx.clone(), k.clone(), make_value("good")).await?;
+    bs2.put(ctx.clone(), k.clone(), make_value("bad")).await?;
+    let value = bs.get(ctx.clone(), k.clone()).await?;
+    assert_eq!(value.map(|v| v.into_bytes()), Some(make_value("good")));
+    let queued: HashSet<_> = queue
+        .get(&ctx, &k)
+        .await?
+        .into_iter()
+        .map(|entry| entry.blobstore_id)
+        .collect();
+    assert_eq!(queued, vec![bid0, bid1].into_iter().collect());
+
+    // Without a majority, nothing is returned
+    let k = "tie".to_string();
+    bs0.put(ctx.clone(), k.clone(), make_value("one")).await?;
+    bs1.put(ctx.clone(), k.clone(), make_value("two")).await?;
+    assert!(bs.get(ctx.clone(), k.clone()).await.is_err());
+    assert!(queue.get(&ctx, &k).await?.is_empty());
+
+    // Stores that agree need no repair
+    let k = "same".to_string();
+    for store in &[&bs0, &bs1, &bs2] {
+        store.put(ctx.clone(), k.clone(), make_value("v")).await?;
+    }
+    let value = bs.get(ctx.clone(), k.clone()).await?;
+    assert_eq!(value.map(|v| v.into_bytes()), Some(make_value("v")));
+    assert!(queue.get(&ctx, &k).await?.is_empty());
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn verified_content_hash(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let queue = Arc::new(SqlBlobstoreSyncQueue::with_sqlite_in_memory().unwrap());
+    let bid0 = BlobstoreId::new(0);
+    let bs0 = Arc::new(LazyMemblob::new());
+    let bid1 = BlobstoreId::new(1);
+    let bs1 = Arc::new(LazyMemblob::new());
+
+    let bs = VerifyingBlobstore::new(
+        MultiplexId::new(1),
+        vec![(bid0, bs0.clone()), (bid1, bs1.clone())],
+        vec![],
+        nonzero!(1usize),
+        queue.clone(),
+        ScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+        ReadVerification {
+            mode: ReadVerificationMode::ContentHash,
+            action: ScrubAction::ReportOnly,
+        },
+    );
+
+    let blob = FileContents::new_bytes("content").into_blob();
+    let k = blob.id().blobstore_key();
+    let good = BlobstoreBytes::from_bytes(blob.data().clone());
+    let corrupt = FileContents::new_bytes("corrupt")
+        .into_blob()
+        .data()
+        .clone();
+    bs0.put(
+        ctx.clone(),
+        k.clone(),
+        BlobstoreBytes::from_bytes(corrupt.clone()),
+    )
+    .await?;
+    bs1.put(ctx.clone(), k.clone(), good.clone()).await?;
+
+    // The corrupt copy is never returned, and ReportOnly doesn't queue a repair
+    let value = bs.get(ctx.clone(), k.clone()).await?;
+    assert_eq!(value.map(|v| v.into_bytes()), Some(good));
+    assert!(queue.get(&ctx, &k).await?.is_empty());
+
+    // If every copy is corrupt, the get fails
+    bs1.put(ctx.clone(), k.clone(), BlobstoreBytes::from_bytes(corrupt))
+        .await?;
+    assert!(bs.get(ctx.clone(), k.clone()).await.is_err());
+
+    // Keys without a content hash are returned unchecked
+    let k = "unhashed".to_string();
+    bs0.put(ctx.clone(), k.clone(), make_value("v")).await?;
+    let value = bs.get(ctx.clone(), k.clone()).await?;
+    assert_eq!(value.map(|v| v.into_bytes()), Some(make_value("v")));
+
+    Ok(())
+}
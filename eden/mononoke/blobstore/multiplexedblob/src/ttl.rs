@@ -0,0 +1,105 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use blobstore::{Blobstore, BlobstoreGetData, BlobstoreWithTtl};
+use context::CoreContext;
+use futures::future::{self, BoxFuture, FutureExt};
+use metaconfig_types::BlobstoreId;
+use mononoke_types::BlobstoreBytes;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Adds TTL support to a multiplexed blobstore. Blobs with a TTL are written to every component
+/// directly, and every write must succeed: they bypass the sync queue, as healing them would store
+/// them permanently. For the same reason, scrubbing may make a blob that was only written to some
+/// of the components permanent.
+#[derive(Clone, Debug)]
+pub struct MultiplexedWithTtl<T> {
+    blobstore: T,
+    components: Arc<[(BlobstoreId, Arc<dyn BlobstoreWithTtl>)]>,
+}
+
+impl<T: Blobstore> MultiplexedWithTtl<T> {
+    /// `components` must be the component stores of `blobstore`
+    pub fn new(blobstore: T, components: Vec<(BlobstoreId, Arc<dyn BlobstoreWithTtl>)>) -> Self {
+        Self {
+            blobstore,
+            components: components.into(),
+        }
+    }
+
+    pub fn as_inner(&self) -> &T {
+        &self.blobstore
+    }
+}
+
+impl<T: Blobstore> Blobstore for MultiplexedWithTtl<T> {
+    fn get(
+        &self,
+        ctx: CoreContext,
+        key: String,
+    ) -> BoxFuture<'static, Result<Option<BlobstoreGetData>, Error>> {
+        self.blobstore.get(ctx, key)
+    }
+
+    fn put(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        self.blobstore.put(ctx, key, value)
+    }
+
+    fn is_present(&self, ctx: CoreContext, key: String) -> BoxFuture<'static, Result<bool, Error>> {
+        self.blobstore.is_present(ctx, key)
+    }
+}
+
+impl<T: Blobstore> BlobstoreWithTtl for MultiplexedWithTtl<T> {
+    fn put_with_ttl(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        ttl: Duration,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        let puts = future::try_join_all(self.components.iter().map(|(blobstore_id, blobstore)| {
+            let blobstore_id = *blobstore_id;
+            let put = blobstore.put_with_ttl(ctx.clone(), key.clone(), value.clone(), ttl);
+            async move {
+                put.await
+                    .map_err(|e| e.context(format!("While writing to blobstore {}", blobstore_id)))
+            }
+        }));
+        async move {
+            puts.await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    // Every component is swept, so a key counts once for each component it was removed from
+    fn sweep_expired(
+        &self,
+        ctx: CoreContext,
+        limit: u64,
+    ) -> BoxFuture<'static, Result<u64, Error>> {
+        let sweeps =
+            future::try_join_all(self.components.iter().map(|(blobstore_id, blobstore)| {
+                let blobstore_id = *blobstore_id;
+                let sweep = blobstore.sweep_expired(ctx.clone(), limit);
+                async move {
+                    sweep.await.map_err(|e| {
+                        e.context(format!("While sweeping blobstore {}", blobstore_id))
+                    })
+                }
+            }));
+        async move { Ok(sweeps.await?.into_iter().sum()) }.boxed()
+    }
+}
@@ -0,0 +1,189 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::{
+    base::{ErrorKind, MultiplexedBlobstoreBase, VerifiedGet},
+    queue::MultiplexedBlobstore,
+};
+
+use anyhow::Error;
+use blobstore::{Blobstore, BlobstoreGetData};
+use blobstore_stats::OperationType;
+use blobstore_sync_queue::{BlobstoreSyncQueue, BlobstoreSyncQueueEntry, OperationKey};
+use cloned::cloned;
+use context::CoreContext;
+use futures::future::{BoxFuture, FutureExt};
+use metaconfig_types::{BlobstoreId, MultiplexId, ReadVerification, ScrubAction};
+use mononoke_types::{BlobstoreBytes, DateTime};
+use scuba::ScubaSampleBuilder;
+use slog::warn;
+use std::fmt;
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::sync::Arc;
+
+/// A multiplexed blobstore that checks blobs before returning them from `get`, as configured by
+/// `ReadVerification`. Blobstores that return a bad blob are logged to scuba, and with
+/// `ScrubAction::Repair` the key is queued for the healer, which overwrites them with the
+/// verified copy.
+#[derive(Clone)]
+pub struct VerifyingBlobstore {
+    inner: MultiplexedBlobstore,
+    multiplex_id: MultiplexId,
+    read_verification: ReadVerification,
+    scuba: ScubaSampleBuilder,
+    queue: Arc<dyn BlobstoreSyncQueue>,
+}
+
+impl VerifyingBlobstore {
+    pub fn new(
+        multiplex_id: MultiplexId,
+        blobstores: Vec<(BlobstoreId, Arc<dyn Blobstore>)>,
+        write_mostly_blobstores: Vec<(BlobstoreId, Arc<dyn Blobstore>)>,
+        minimum_successful_writes: NonZeroUsize,
+        queue: Arc<dyn BlobstoreSyncQueue>,
+        mut scuba: ScubaSampleBuilder,
+        scuba_sample_rate: NonZeroU64,
+        read_verification: ReadVerification,
+    ) -> Self {
+        let inner = MultiplexedBlobstore::new(
+            multiplex_id,
+            blobstores,
+            write_mostly_blobstores,
+            minimum_successful_writes,
+            queue.clone(),
+            scuba.clone(),
+            scuba_sample_rate,
+        );
+        scuba.add_common_server_data();
+        Self {
+            inner,
+            multiplex_id,
+            read_verification,
+            scuba,
+            queue,
+        }
+    }
+}
+
+impl fmt::Debug for VerifyingBlobstore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VerifyingBlobstore")
+            .field("inner", &self.inner)
+            .field("read_verification", &self.read_verification)
+            .finish()
+    }
+}
+
+// Workaround for Blobstore returning a static lifetime future
+async fn blobstore_get(
+    inner_blobstore: &MultiplexedBlobstoreBase,
+    ctx: &CoreContext,
+    key: String,
+    queue: &dyn BlobstoreSyncQueue,
+    multiplex_id: MultiplexId,
+    read_verification: ReadVerification,
+    mut scuba: ScubaSampleBuilder,
+) -> Result<Option<BlobstoreGetData>, Error> {
+    let VerifiedGet { value, good, bad } = match inner_blobstore
+        .verified_get(ctx, &key, read_verification.mode)
+        .await
+    {
+        Ok(verified) => verified,
+        Err(error @ ErrorKind::SomeFailedOthersNone(_)) => {
+            // As in MultiplexedBlobstore, this is a true None only if there's no pending write
+            let entries = queue.get(ctx, &key).await?;
+            if entries.is_empty() {
+                return Ok(None);
+            } else {
+                return Err(error.into());
+            }
+        }
+        Err(error) => return Err(error.into()),
+    };
+
+    if bad.is_empty() {
+        return Ok(value);
+    }
+
+    let is_repaired = read_verification.action == ScrubAction::Repair;
+    for blobstore_id in bad.iter() {
+        warn!(
+            ctx.logger(),
+            "read verification: blobstore_id {:?} returned a bad value for {}", blobstore_id, key
+        );
+        scuba
+            .add("key", key.clone())
+            .add("blobstore_id", *blobstore_id)
+            .add("operation", OperationType::ReadVerification)
+            .add("session", ctx.metadata().session_id().to_string())
+            .add("is_repaired", is_repaired)
+            .log();
+    }
+
+    if is_repaired {
+        // The healer copies the blob from the blobstores named in the queue to all the others
+        let operation_key = OperationKey::gen();
+        let entries = good
+            .into_iter()
+            .map(|blobstore_id| {
+                BlobstoreSyncQueueEntry::new(
+                    key.clone(),
+                    blobstore_id,
+                    multiplex_id,
+                    DateTime::now(),
+                    operation_key.clone(),
+                )
+            })
+            .collect();
+        queue.add_many(ctx, entries).await?;
+    }
+
+    Ok(value)
+}
+
+impl Blobstore for VerifyingBlobstore {
+    fn get(
+        &self,
+        ctx: CoreContext,
+        key: String,
+    ) -> BoxFuture<'static, Result<Option<BlobstoreGetData>, Error>> {
+        cloned!(
+            self.queue,
+            self.multiplex_id,
+            self.read_verification,
+            self.scuba
+        );
+        let inner_blobstore = self.inner.blobstore.clone();
+
+        async move {
+            blobstore_get(
+                inner_blobstore.as_ref(),
+                &ctx,
+                key,
+                queue.as_ref(),
+                multiplex_id,
+                read_verification,
+                scuba,
+            )
+            .await
+        }
+        .boxed()
+    }
+
+    fn put(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        self.inner.put(ctx, key, value)
+    }
+
+    fn is_present(&self, ctx: CoreContext, key: String) -> BoxFuture<'static, Result<bool, Error>> {
+        self.inner.is_present(ctx, key)
+    }
+}
@@ -5,10 +5,21 @@
  * GNU General Public License version 2.
  */
 
+// Zstandard blob compressed with a trained dictionary. dict_key is the
+// dictionary's blobstore key without the repo prefix, and is resolved against
+// the repo prefix of the key being loaded, so that each repo has its own
+// dictionaries. Dictionaries are versioned and never overwritten, so that
+// blobs written with an older dictionary still decode.
+struct ZstdWithDictValue {
+    1: string dict_key,
+    2: binary zstd,
+}
+
 // Independent single data value.
 union SingleValue {
     1: binary Raw,
     2: binary Zstd,
+    3: ZstdWithDictValue ZstdWithDict,
 }
 
 // Represents dictionary encoded Zstandard blob. dict_key must point to a
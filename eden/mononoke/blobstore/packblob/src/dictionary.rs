@@ -0,0 +1,114 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use bytes::Bytes;
+use mononoke_types::repo::REPO_PREFIX_REGEX;
+use std::io::{Cursor, Read, Write};
+
+// Trained dictionaries are stored per repo under `<repo prefix>zstd_dictionary.v<version>`
+const DICTIONARY_KEY_PREFIX: &str = "zstd_dictionary.v";
+
+/// The blobstore key, without repo prefix, that version `version` of a repo's zstd dictionary is
+/// stored under.
+pub fn dictionary_key(version: u32) -> String {
+    format!("{}{}", DICTIONARY_KEY_PREFIX, version)
+}
+
+// Split a key into its repo prefix (empty if none) and the rest of the key
+pub(crate) fn split_repo_prefix(key: &str) -> (&str, &str) {
+    match REPO_PREFIX_REGEX.find(key) {
+        Some(m) => key.split_at(m.end()),
+        None => ("", key),
+    }
+}
+
+// Dictionaries themselves must never be compressed with a dictionary
+pub(crate) fn is_dictionary_key(key: &str) -> bool {
+    split_repo_prefix(key).1.starts_with(DICTIONARY_KEY_PREFIX)
+}
+
+/// Train a zstd dictionary of at most `max_size` bytes from sample blobs, e.g. those dumped by
+/// the walker's corpus subcommand.
+pub fn train_dictionary(samples: &[Bytes], max_size: usize) -> Result<Bytes, Error> {
+    let dict = zstd::dict::from_samples(samples, max_size)?;
+    Ok(Bytes::from(dict))
+}
+
+pub(crate) fn compress_with_dictionary(
+    value: &[u8],
+    zstd_level: i32,
+    dict: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let mut encoder = zstd::stream::Encoder::with_dictionary(Vec::new(), zstd_level, dict)?;
+    encoder.write_all(value)?;
+    Ok(encoder.finish()?)
+}
+
+pub(crate) fn decompress_with_dictionary(value: &[u8], dict: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decoder = zstd::stream::Decoder::with_dictionary(Cursor::new(value), dict)?;
+    let mut decoded = Vec::new();
+    decoder.read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+
+    // Small, similar blobs, like filenode envelopes
+    fn make_samples(count: usize) -> Vec<Bytes> {
+        let mut rng = XorShiftRng::seed_from_u64(0); // reproducable Rng
+        (0..count)
+            .map(|i| {
+                Bytes::from(format!(
+                    "{{\"node\": \"{:032x}\", \"p1\": \"{:032x}\", \"path\": \"fbcode/eden/mononoke/file{}.rs\", \"flags\": \"\"}}",
+                    rng.gen::<u128>(),
+                    rng.gen::<u128>(),
+                    i
+                ))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dictionary_key_test() {
+        assert_eq!(dictionary_key(3), "zstd_dictionary.v3");
+        assert!(is_dictionary_key("repo0000.zstd_dictionary.v3"));
+        assert!(is_dictionary_key("zstd_dictionary.v3"));
+        assert!(!is_dictionary_key(
+            "repo0000.content.blake2.zstd_dictionary.v3"
+        ));
+        assert_eq!(
+            split_repo_prefix("repo0123.content"),
+            ("repo0123.", "content")
+        );
+        assert_eq!(split_repo_prefix("content"), ("", "content"));
+    }
+
+    #[test]
+    fn train_roundtrip_test() -> Result<(), Error> {
+        let samples = make_samples(1000);
+        let dict = train_dictionary(&samples, 4096)?;
+        assert!(!dict.is_empty() && dict.len() <= 4096);
+
+        let value = make_samples(1001).pop().unwrap();
+        let compressed = compress_with_dictionary(&value, 0, &dict)?;
+        let plain = zstd::encode_all(Cursor::new(value.clone()), 0)?;
+        assert!(compressed.len() < plain.len());
+
+        let decompressed = decompress_with_dictionary(&compressed, &dict)?;
+        assert_eq!(Bytes::from(decompressed), value);
+
+        // Decoding needs the same dictionary
+        let other_dict = train_dictionary(&samples[..500], 1024)?;
+        assert!(decompress_with_dictionary(&compressed, &other_dict).is_err());
+        Ok(())
+    }
+}
@@ -7,8 +7,10 @@
 
 #![deny(warnings)]
 
+mod dictionary;
 mod envelope;
 mod pack;
 mod store;
 
+pub use dictionary::{dictionary_key, train_dictionary};
 pub use store::{PackBlob, PackOptions};
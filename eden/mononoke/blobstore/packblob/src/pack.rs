@@ -5,6 +5,7 @@
  * GNU General Public License version 2.
  */
 
+use crate::dictionary::decompress_with_dictionary;
 use crate::store;
 
 use anyhow::{format_err, Error};
@@ -12,17 +13,53 @@ use ascii::AsciiString;
 use blobstore::{BlobstoreGetData, BlobstoreMetadata};
 use bytes::Bytes;
 use mononoke_types::{hash::Context as HashContext, repo::REPO_PREFIX_REGEX, BlobstoreBytes};
-use packblob_thrift::{PackedEntry, PackedFormat, PackedValue, SingleValue, ZstdFromDictValue};
-use std::{collections::HashMap, io::Cursor};
+use packblob_thrift::{
+    PackedEntry, PackedFormat, PackedValue, SingleValue, StorageFormat, ZstdFromDictValue,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::Cursor,
+};
+
+// The trained dictionaries, by key without repo prefix, that are needed to decode `storage`
+pub fn trained_dictionary_keys(storage: &StorageFormat) -> BTreeSet<String> {
+    let single_values: Vec<&SingleValue> = match storage {
+        StorageFormat::Single(single) => vec![single],
+        StorageFormat::Packed(packed) => packed
+            .entries
+            .iter()
+            .filter_map(|entry| match &entry.data {
+                PackedValue::Single(single) => Some(single),
+                _ => None,
+            })
+            .collect(),
+        StorageFormat::UnknownField(_) => vec![],
+    };
+    single_values
+        .into_iter()
+        .filter_map(|single| match single {
+            SingleValue::ZstdWithDict(v) => Some(v.dict_key.clone()),
+            _ => None,
+        })
+        .collect()
+}
 
 pub fn decode_independent(
     meta: BlobstoreMetadata,
     v: SingleValue,
+    trained_dicts: &HashMap<String, Bytes>,
 ) -> Result<BlobstoreGetData, Error> {
     match v {
         SingleValue::Raw(v) => Ok(BlobstoreGetData::new(meta, BlobstoreBytes::from_bytes(v))),
         SingleValue::Zstd(v) => Ok(zstd::decode_all(Cursor::new(v))
             .map(|v| BlobstoreGetData::new(meta, BlobstoreBytes::from_bytes(v)))?),
+        SingleValue::ZstdWithDict(v) => match trained_dicts.get(&v.dict_key) {
+            Some(dict) => {
+                let v = decompress_with_dictionary(&v.zstd, dict)?;
+                Ok(BlobstoreGetData::new(meta, BlobstoreBytes::from_bytes(v)))
+            }
+            None => Err(format_err!("Trained dictionary {} not loaded", v.dict_key)),
+        },
         SingleValue::UnknownField(e) => Err(format_err!("SingleValue::UnknownField {:?}", e)),
     }
 }
@@ -51,6 +88,7 @@ pub fn decode_pack(
     pack_meta: BlobstoreMetadata,
     packed: PackedFormat,
     key: String,
+    trained_dicts: &HashMap<String, Bytes>,
 ) -> Result<BlobstoreGetData, Error> {
     // Strip repo prefix, if any
     let key = match REPO_PREFIX_REGEX.find(&key) {
@@ -63,7 +101,9 @@ pub fn decode_pack(
     for entry in packed.entries {
         let current_key = entry.key;
         let value = match entry.data {
-            PackedValue::Single(v) => Some(decode_independent(pack_meta.clone(), v)?),
+            PackedValue::Single(v) => {
+                Some(decode_independent(pack_meta.clone(), v, trained_dicts)?)
+            }
             v => {
                 remaining_entries.push(PackedEntry {
                     key: current_key.clone(),
@@ -159,7 +199,11 @@ mod tests {
         assert!(bytes.len() < bytes_in.len());
 
         // Test the decoder
-        let decoded = decode_independent(BlobstoreMetadata::new(None), SingleValue::Zstd(bytes))?;
+        let decoded = decode_independent(
+            BlobstoreMetadata::new(None),
+            SingleValue::Zstd(bytes),
+            &HashMap::new(),
+        )?;
         assert_eq!(decoded.as_bytes().as_bytes(), &Bytes::from(bytes_in));
 
         Ok(())
@@ -247,7 +291,12 @@ mod tests {
 
         // Test reads roundtrip back to the raw form
         for i in 0..20 {
-            let value = decode_pack(BlobstoreMetadata::new(None), packed.clone(), i.to_string())?;
+            let value = decode_pack(
+                BlobstoreMetadata::new(None),
+                packed.clone(),
+                i.to_string(),
+                &HashMap::new(),
+            )?;
             assert_eq!(value.as_bytes().as_bytes().to_vec(), raw_data[i]);
         }
 
@@ -283,11 +332,17 @@ mod tests {
             BlobstoreMetadata::new(None),
             packed.clone(),
             "1".to_string(),
+            &HashMap::new(),
         )?;
         assert_eq!(value1.as_bytes().len(), 1024);
 
         // See if we get error for unknown key
-        let missing = decode_pack(BlobstoreMetadata::new(None), packed, "missing".to_string());
+        let missing = decode_pack(
+            BlobstoreMetadata::new(None),
+            packed,
+            "missing".to_string(),
+            &HashMap::new(),
+        );
         assert!(missing.is_err());
 
         Ok(())
@@ -5,31 +5,62 @@
  * GNU General Public License version 2.
  */
 
+use crate::dictionary::{
+    compress_with_dictionary, dictionary_key, is_dictionary_key, split_repo_prefix,
+};
 use crate::envelope::PackEnvelope;
 use crate::pack;
 
 use anyhow::{format_err, Context, Error};
-use blobstore::{Blobstore, BlobstoreGetData, BlobstoreWithLink};
+use blobstore::{
+    Blobstore, BlobstoreEnumerationData, BlobstoreGetData, BlobstoreGetMetadata, BlobstoreKeyParam,
+    BlobstoreKeySource, BlobstoreMetadata, BlobstoreUnlink, BlobstoreWithLink,
+};
 use bytes::Bytes;
 use context::CoreContext;
 use futures::{
-    future::{BoxFuture, FutureExt},
+    future::{BoxFuture, FutureExt, TryFutureExt},
     stream::{FuturesUnordered, TryStreamExt},
 };
 use mononoke_types::BlobstoreBytes;
-use packblob_thrift::{PackedEntry, SingleValue, StorageEnvelope, StorageFormat};
-use std::{convert::TryInto, io::Cursor};
+use packblob_thrift::{
+    PackedEntry, SingleValue, StorageEnvelope, StorageFormat, ZstdWithDictValue,
+};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    io::Cursor,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+// How long to remember that a repo has no dictionary to compress puts with. Once one is trained,
+// puts start using it within this time.
+const MISSING_DICTIONARY_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+enum CachedDictionary {
+    Found(Bytes),
+    // Not found at the recorded time
+    Missing(Instant),
+}
 
 #[derive(Clone, Debug, Default)]
 pub struct PackOptions {
     // If Some, this is used as zstd compression level on put.
     // Some(0) means use zstd default level.
     put_compress_level: Option<i32>,
+    // If Some, and put_compress_level is Some, puts are compressed with this version of the
+    // key's repo's trained dictionary. Repos without that version compress without a dictionary.
+    put_dictionary_version: Option<u32>,
 }
 
 impl PackOptions {
-    pub fn new(put_compress_level: Option<i32>) -> Self {
-        Self { put_compress_level }
+    pub fn new(put_compress_level: Option<i32>, put_dictionary_version: Option<u32>) -> Self {
+        Self {
+            put_compress_level,
+            put_dictionary_version,
+        }
     }
 }
 
@@ -38,11 +69,103 @@ impl PackOptions {
 pub struct PackBlob<T: Blobstore + Clone> {
     inner: T,
     options: PackOptions,
+    // Trained dictionaries by full key. Versions are never overwritten, so can be cached forever.
+    // Misses are cached for missing_dictionary_ttl, so we don't look for them on every put.
+    dictionaries: Arc<RwLock<HashMap<String, CachedDictionary>>>,
+    missing_dictionary_ttl: Duration,
 }
 
 impl<T: Blobstore + Clone> PackBlob<T> {
     pub fn new(inner: T, options: PackOptions) -> Self {
-        Self { inner, options }
+        Self {
+            inner,
+            options,
+            dictionaries: Arc::new(RwLock::new(HashMap::new())),
+            missing_dictionary_ttl: MISSING_DICTIONARY_TTL,
+        }
+    }
+
+    // Load a trained dictionary. Recent misses are cached too, unless `retry_missing` is set.
+    async fn load_dictionary(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        retry_missing: bool,
+    ) -> Result<Option<Bytes>, Error> {
+        let cached = self
+            .dictionaries
+            .read()
+            .expect("lock poisoned")
+            .get(&key)
+            .cloned();
+        match cached {
+            Some(CachedDictionary::Found(dict)) => return Ok(Some(dict)),
+            Some(CachedDictionary::Missing(at))
+                if !retry_missing && at.elapsed() < self.missing_dictionary_ttl =>
+            {
+                return Ok(None);
+            }
+            _ => {}
+        }
+
+        let dict = self
+            .get(ctx, key.clone())
+            .await
+            .with_context(|| format!("While loading dictionary {:?}", key))?
+            .map(|dict| dict.into_bytes().into_bytes());
+        let cached = match &dict {
+            Some(dict) => CachedDictionary::Found(dict.clone()),
+            None => CachedDictionary::Missing(Instant::now()),
+        };
+        self.dictionaries
+            .write()
+            .expect("lock poisoned")
+            .insert(key, cached);
+        Ok(dict)
+    }
+
+    // Load all the trained dictionaries needed to decode `storage`, which was loaded for `key`
+    async fn load_dictionaries_for(
+        &self,
+        ctx: CoreContext,
+        key: &str,
+        storage: &StorageFormat,
+    ) -> Result<HashMap<String, Bytes>, Error> {
+        let (repo_prefix, _) = split_repo_prefix(key);
+        let mut dicts = HashMap::new();
+        for dict_key in pack::trained_dictionary_keys(storage) {
+            let full_key = format!("{}{}", repo_prefix, dict_key);
+            let dict = self
+                .load_dictionary(ctx.clone(), full_key, true)
+                .await?
+                .ok_or_else(|| format_err!("Dictionary {} not found for key {}", dict_key, key))?;
+            dicts.insert(dict_key, dict);
+        }
+        Ok(dicts)
+    }
+
+    // Compress with the configured trained dictionary, if there is one for this key's repo
+    async fn compress(
+        &self,
+        ctx: CoreContext,
+        key: &str,
+        value: Bytes,
+        zstd_level: i32,
+    ) -> Result<SingleValue, Error> {
+        let (repo_prefix, _) = split_repo_prefix(key);
+        let version = match self.options.put_dictionary_version {
+            Some(version) if !repo_prefix.is_empty() && !is_dictionary_key(key) => version,
+            _ => return compress_if_worthwhile(value, zstd_level),
+        };
+
+        let dict_key = dictionary_key(version);
+        let full_key = format!("{}{}", repo_prefix, dict_key);
+        match self.load_dictionary(ctx, full_key, false).await? {
+            Some(dict) => {
+                compress_with_dictionary_if_worthwhile(value, zstd_level, dict_key, &dict)
+            }
+            None => compress_if_worthwhile(value, zstd_level),
+        }
     }
 }
 
@@ -57,6 +180,24 @@ fn compress_if_worthwhile(value: Bytes, zstd_level: i32) -> Result<SingleValue,
     }
 }
 
+// As compress_if_worthwhile, but recording the dictionary used in the envelope
+fn compress_with_dictionary_if_worthwhile(
+    value: Bytes,
+    zstd_level: i32,
+    dict_key: String,
+    dict: &[u8],
+) -> Result<SingleValue, Error> {
+    let compressed = compress_with_dictionary(&value, zstd_level, dict)?;
+    if compressed.len() < value.len() {
+        Ok(SingleValue::ZstdWithDict(ZstdWithDictValue {
+            dict_key,
+            zstd: compressed,
+        }))
+    } else {
+        Ok(SingleValue::Raw(value.to_vec()))
+    }
+}
+
 // differentiate keys just in case packblob is run in an existing unpacked store
 pub const ENVELOPE_SUFFIX: &str = ".pack";
 
@@ -69,8 +210,9 @@ impl<T: Blobstore + Clone> Blobstore for PackBlob<T> {
         let inner_get_data = {
             let mut inner_key = key.clone();
             inner_key.push_str(ENVELOPE_SUFFIX);
-            self.inner.get(ctx, inner_key)
+            self.inner.get(ctx.clone(), inner_key)
         };
+        let this = self.clone();
         async move {
             let inner_get_data = match inner_get_data
                 .await
@@ -82,12 +224,19 @@ impl<T: Blobstore + Clone> Blobstore for PackBlob<T> {
 
             let meta = inner_get_data.as_meta().clone();
             let envelope: PackEnvelope = inner_get_data.into_bytes().try_into()?;
+            let trained_dicts = this
+                .load_dictionaries_for(ctx, &key, &envelope.0.storage)
+                .await?;
 
             let get_data = match envelope.0.storage {
-                StorageFormat::Single(single) => pack::decode_independent(meta, single)
-                    .with_context(|| format!("While decoding independent {:?}", key))?,
-                StorageFormat::Packed(packed) => pack::decode_pack(meta, packed, key.clone())
-                    .with_context(|| format!("While decoding pack for {:?}", key))?,
+                StorageFormat::Single(single) => {
+                    pack::decode_independent(meta, single, &trained_dicts)
+                        .with_context(|| format!("While decoding independent {:?}", key))?
+                }
+                StorageFormat::Packed(packed) => {
+                    pack::decode_pack(meta, packed, key.clone(), &trained_dicts)
+                        .with_context(|| format!("While decoding pack for {:?}", key))?
+                }
                 StorageFormat::UnknownField(e) => {
                     return Err(format_err!("StorageFormat::UnknownField {:?}", e));
                 }
@@ -101,28 +250,26 @@ impl<T: Blobstore + Clone> Blobstore for PackBlob<T> {
     fn put(
         &self,
         ctx: CoreContext,
-        mut key: String,
+        key: String,
         value: BlobstoreBytes,
     ) -> BoxFuture<'static, Result<(), Error>> {
-        key.push_str(ENVELOPE_SUFFIX);
-
         let value = value.into_bytes();
-
-        let single = if let Some(zstd_level) = self.options.put_compress_level {
-            compress_if_worthwhile(value, zstd_level)
-        } else {
-            Ok(SingleValue::Raw(value.to_vec()))
-        };
-
-        let inner = self.inner.clone();
+        let this = self.clone();
 
         async move {
+            let single = match this.options.put_compress_level {
+                Some(zstd_level) => this.compress(ctx.clone(), &key, value, zstd_level).await?,
+                None => SingleValue::Raw(value.to_vec()),
+            };
+
             // Wrap in thrift encoding
             let envelope: PackEnvelope = PackEnvelope(StorageEnvelope {
-                storage: StorageFormat::Single(single?),
+                storage: StorageFormat::Single(single),
             });
             // pass through the put after wrapping
-            inner.put(ctx, key, envelope.into()).await
+            let mut inner_key = key;
+            inner_key.push_str(ENVELOPE_SUFFIX);
+            this.inner.put(ctx, inner_key, envelope.into()).await
         }
         .boxed()
     }
@@ -137,6 +284,47 @@ impl<T: Blobstore + Clone> Blobstore for PackBlob<T> {
     }
 }
 
+impl<T: BlobstoreGetMetadata + Clone> BlobstoreGetMetadata for PackBlob<T> {
+    // As with get, the metadata is that of this key's envelope
+    fn get_metadata(
+        &self,
+        ctx: CoreContext,
+        mut key: String,
+    ) -> BoxFuture<'static, Result<Option<BlobstoreMetadata>, Error>> {
+        key.push_str(ENVELOPE_SUFFIX);
+        self.inner.get_metadata(ctx, key)
+    }
+}
+
+impl<T: BlobstoreUnlink + Clone> BlobstoreUnlink for PackBlob<T> {
+    // Unlinks only this key's envelope. Packs stay alive while other keys are linked to them.
+    fn unlink(&self, ctx: CoreContext, mut key: String) -> BoxFuture<'static, Result<(), Error>> {
+        key.push_str(ENVELOPE_SUFFIX);
+        self.inner.unlink(ctx, key)
+    }
+}
+
+impl<T: BlobstoreKeySource + Clone> BlobstoreKeySource for PackBlob<T> {
+    // The range is applied to the inner keys, which include the envelope suffix. Keys without
+    // the suffix are packs rather than application keys, so are skipped.
+    fn enumerate(
+        &self,
+        range: BlobstoreKeyParam,
+    ) -> BoxFuture<'static, Result<BlobstoreEnumerationData, Error>> {
+        self.inner
+            .enumerate(range)
+            .map_ok(|data| BlobstoreEnumerationData {
+                keys: data
+                    .keys
+                    .iter()
+                    .filter_map(|key| key.strip_suffix(ENVELOPE_SUFFIX).map(String::from))
+                    .collect(),
+                next_token: data.next_token,
+            })
+            .boxed()
+    }
+}
+
 impl<T: Blobstore + BlobstoreWithLink + Clone> PackBlob<T> {
     // Put packed content, returning the pack's key if successful.
     // `prefix` is in the control of the packer, e.g. if packing only
@@ -183,13 +371,16 @@ impl<T: Blobstore + BlobstoreWithLink + Clone> PackBlob<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dictionary::train_dictionary;
     use bytes::Bytes;
     use fbinit::FacebookInit;
+    use fileblob::Fileblob;
     use memblob::EagerMemblob;
     use packblob_thrift::{PackedEntry, PackedValue, SingleValue};
     use rand::{RngCore, SeedableRng};
     use rand_xorshift::XorShiftRng;
-    use std::sync::Arc;
+    use std::{collections::HashSet, sync::Arc};
+    use tempdir::TempDir;
 
     #[fbinit::compat_test]
     async fn simple_roundtrip_test(fb: FacebookInit) -> Result<(), Error> {
@@ -207,7 +398,7 @@ mod tests {
     async fn compressible_roundtrip_test(fb: FacebookInit) -> Result<(), Error> {
         let ctx = CoreContext::test_mock(fb);
         let innerblob = Arc::new(EagerMemblob::new());
-        let packblob = PackBlob::new(innerblob.clone(), PackOptions::new(Some(0)));
+        let packblob = PackBlob::new(innerblob.clone(), PackOptions::new(Some(0), None));
 
         let bytes_in = Bytes::from(vec![7u8; 65535]);
         let value = BlobstoreBytes::from_bytes(bytes_in.clone());
@@ -226,7 +417,7 @@ mod tests {
     async fn incompressible_roundtrip_test(fb: FacebookInit) -> Result<(), Error> {
         let ctx = CoreContext::test_mock(fb);
         let innerblob = Arc::new(EagerMemblob::new());
-        let packblob = PackBlob::new(innerblob.clone(), PackOptions::new(Some(0)));
+        let packblob = PackBlob::new(innerblob.clone(), PackOptions::new(Some(0), None));
 
         let mut rng = XorShiftRng::seed_from_u64(0); // reproducable Rng
         let mut bytes_in = vec![7u8; 65535];
@@ -317,7 +508,9 @@ mod tests {
             .await?;
 
         // Check the inner key is present (as we haven't unlinked it yet)
-        let is_present = inner_blobstore.is_present(ctx.clone(), inner_key).await?;
+        let is_present = inner_blobstore
+            .is_present(ctx.clone(), inner_key.clone())
+            .await?;
         assert!(is_present);
 
         // Get, should remove the thrift envelope as it is loaded
@@ -330,6 +523,182 @@ mod tests {
         // Make sure the thrift wrapper is not still there
         assert_eq!(input_values[1], fetched_value.unwrap().into_bytes());
 
+        // Unlink the pack's own key and one of the entries, the rest should still load
+        BlobstoreUnlink::unlink(&inner_blobstore, ctx.clone(), inner_key).await?;
+        packblob
+            .unlink(ctx.clone(), input_entries[0].key.clone())
+            .await?;
+        let is_present = packblob
+            .is_present(ctx.clone(), input_entries[0].key.clone())
+            .await?;
+        assert!(!is_present);
+        let fetched_value = packblob
+            .get(ctx.clone(), input_entries[2].key.clone())
+            .await?;
+        assert_eq!(input_values[2], fetched_value.unwrap().into_bytes());
+
+        Ok(())
+    }
+
+    #[fbinit::compat_test]
+    async fn enumerate_test(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let dir = TempDir::new("packblob_enumerate")?;
+        let inner_blobstore = Fileblob::create(dir.path())?;
+        let packblob = PackBlob::new(inner_blobstore.clone(), PackOptions::default());
+
+        packblob
+            .put(
+                ctx.clone(),
+                "repo0000.single".to_string(),
+                BlobstoreBytes::from_bytes(Bytes::from_static(b"single")),
+            )
+            .await?;
+        let entries = vec![PackedEntry {
+            key: "repo0000.packed_entry".to_string(),
+            data: PackedValue::Single(SingleValue::Raw(b"packed".to_vec())),
+        }];
+        let pack_key = packblob
+            .put_packed(ctx.clone(), entries, "repo0000.packed.".to_string())
+            .await?;
+
+        // The pack itself is only visible in the inner store
+        let inner_keys = inner_blobstore
+            .enumerate(BlobstoreKeyParam::from(..))
+            .await?
+            .keys;
+        assert!(inner_keys.contains(&pack_key));
+
+        let keys = packblob.enumerate(BlobstoreKeyParam::from(..)).await?.keys;
+        let expected: HashSet<_> = vec![
+            "repo0000.single".to_string(),
+            "repo0000.packed_entry".to_string(),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(keys, expected);
+
+        Ok(())
+    }
+
+    #[fbinit::compat_test]
+    async fn trained_dictionary_test(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let innerblob = Arc::new(EagerMemblob::new());
+        let old_packblob = PackBlob::new(innerblob.clone(), PackOptions::new(Some(0), None));
+        let mut packblob = PackBlob::new(innerblob.clone(), PackOptions::new(Some(0), Some(1)));
+
+        // Small, similar blobs, that compress poorly on their own
+        let mut rng = XorShiftRng::seed_from_u64(0); // reproducable Rng
+        let samples: Vec<_> = (0..1001)
+            .map(|i| {
+                Bytes::from(format!(
+                    "{{\"node\": \"{:032x}\", \"path\": \"dir/file{}.rs\", \"flags\": \"\"}}",
+                    rng.next_u64(),
+                    i
+                ))
+            })
+            .collect();
+
+        // Without the repo's dictionary, puts fall back to compressing without one
+        let value = BlobstoreBytes::from_bytes(samples[1000].clone());
+        let old_key = "repo0000.old".to_string();
+        let old_inner_key = roundtrip(
+            ctx.clone(),
+            innerblob.clone(),
+            &packblob,
+            old_key.clone(),
+            value.clone(),
+        )
+        .await?;
+
+        let dict = train_dictionary(&samples[..1000], 4096)?;
+        old_packblob
+            .put(
+                ctx.clone(),
+                format!("repo0000.{}", dictionary_key(1)),
+                BlobstoreBytes::from_bytes(dict),
+            )
+            .await?;
+
+        // The miss is cached for a while, so the dictionary isn't used yet
+        let cached_inner_key = roundtrip(
+            ctx.clone(),
+            innerblob.clone(),
+            &packblob,
+            "repo0000.cached".to_string(),
+            value.clone(),
+        )
+        .await?;
+        let cached_envelope: PackEnvelope = innerblob
+            .get(ctx.clone(), cached_inner_key)
+            .await?
+            .unwrap()
+            .try_into()?;
+        match cached_envelope.0.storage {
+            StorageFormat::Single(SingleValue::Zstd(_)) => {}
+            _ => panic!("Expected a blob compressed without a dictionary"),
+        }
+
+        // Once the cached miss expires, the same PackBlob picks up the dictionary
+        packblob.missing_dictionary_ttl = Duration::from_secs(0);
+        let inner_key = roundtrip(
+            ctx.clone(),
+            innerblob.clone(),
+            &packblob,
+            "repo0000.new".to_string(),
+            value.clone(),
+        )
+        .await?;
+
+        let envelope: PackEnvelope = innerblob
+            .get(ctx.clone(), inner_key)
+            .await?
+            .unwrap()
+            .try_into()?;
+        match envelope.0.storage {
+            StorageFormat::Single(SingleValue::ZstdWithDict(v)) => {
+                assert_eq!(v.dict_key, dictionary_key(1))
+            }
+            _ => panic!("Expected a blob compressed with the dictionary"),
+        }
+        let old_envelope: PackEnvelope = innerblob
+            .get(ctx.clone(), old_inner_key)
+            .await?
+            .unwrap()
+            .try_into()?;
+        match old_envelope.0.storage {
+            StorageFormat::Single(SingleValue::Zstd(_)) => {}
+            _ => panic!("Expected a blob compressed without a dictionary"),
+        }
+
+        // Both decode, with or without the dictionary options
+        for packblob in &[old_packblob, packblob.clone()] {
+            for key in &["repo0000.old", "repo0000.new"] {
+                let fetched = packblob.get(ctx.clone(), key.to_string()).await?.unwrap();
+                assert_eq!(value, fetched.into_bytes());
+            }
+        }
+
+        // Other repos don't have this dictionary
+        let inner_key = roundtrip(
+            ctx.clone(),
+            innerblob.clone(),
+            &packblob,
+            "repo0001.new".to_string(),
+            value,
+        )
+        .await?;
+        let envelope: PackEnvelope = innerblob
+            .get(ctx.clone(), inner_key)
+            .await?
+            .unwrap()
+            .try_into()?;
+        match envelope.0.storage {
+            StorageFormat::Single(SingleValue::Zstd(_)) => {}
+            _ => panic!("Expected a blob compressed without a dictionary"),
+        }
+
         Ok(())
     }
 }
@@ -9,12 +9,16 @@
 
 use anyhow::Error;
 use inlinable_string::InlinableString;
+use std::time::Duration;
 
-use futures::future::BoxFuture;
+use futures::future::{BoxFuture, FutureExt, TryFutureExt};
 
 use context::CoreContext;
 
-use blobstore::{Blobstore, BlobstoreGetData};
+use blobstore::{
+    Blobstore, BlobstoreEnumerationData, BlobstoreGetData, BlobstoreKeyParam, BlobstoreKeyRange,
+    BlobstoreKeySource, BlobstoreWithTtl,
+};
 use mononoke_types::BlobstoreBytes;
 
 /// A layer over an existing blobstore that prepends a fixed string to each get and put.
@@ -45,6 +49,19 @@ impl<T: Blobstore + Clone> PrefixBlobstore<T> {
     pub fn prepend(&self, key: String) -> String {
         [&self.prefix, key.as_str()].concat()
     }
+
+    // The smallest key that is greater than every key with the prefix, or the empty (unbounded)
+    // key if there is no such key.
+    fn prefix_end(&self) -> String {
+        let mut end = self.prefix.to_string();
+        while let Some(c) = end.pop() {
+            if let Some(next) = std::char::from_u32(c as u32 + 1) {
+                end.push(next);
+                return end;
+            }
+        }
+        end
+    }
 }
 
 impl<T: Blobstore + Clone> Blobstore for PrefixBlobstore<T> {
@@ -73,6 +90,62 @@ impl<T: Blobstore + Clone> Blobstore for PrefixBlobstore<T> {
     }
 }
 
+impl<T: BlobstoreKeySource + Clone> BlobstoreKeySource for PrefixBlobstore<T> {
+    fn enumerate(
+        &self,
+        range: BlobstoreKeyParam,
+    ) -> BoxFuture<'static, Result<BlobstoreEnumerationData, Error>> {
+        let range = match range {
+            BlobstoreKeyParam::Start(range) => BlobstoreKeyParam::Start(BlobstoreKeyRange {
+                begin_key: self.prepend(range.begin_key),
+                end_key: if range.end_key.is_empty() {
+                    self.prefix_end()
+                } else {
+                    self.prepend(range.end_key)
+                },
+            }),
+            // Tokens come from the inner blobstore, so pass them back unchanged
+            token @ BlobstoreKeyParam::Continuation(_) => token,
+        };
+        let prefix = self.prefix.clone();
+        self.blobstore
+            .enumerate(range)
+            .map_ok(move |data| BlobstoreEnumerationData {
+                keys: data
+                    .keys
+                    .iter()
+                    .filter_map(|key| key.strip_prefix(&*prefix).map(String::from))
+                    .collect(),
+                next_token: data.next_token,
+            })
+            .boxed()
+    }
+}
+
+impl<T: BlobstoreWithTtl + Clone> BlobstoreWithTtl for PrefixBlobstore<T> {
+    #[inline]
+    fn put_with_ttl(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        ttl: Duration,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        self.blobstore
+            .put_with_ttl(ctx, self.prepend(key), value, ttl)
+    }
+
+    // Expired keys are of no use under any prefix, so this sweeps the whole inner blobstore
+    #[inline]
+    fn sweep_expired(
+        &self,
+        ctx: CoreContext,
+        limit: u64,
+    ) -> BoxFuture<'static, Result<u64, Error>> {
+        self.blobstore.sweep_expired(ctx, limit)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -80,7 +153,10 @@ mod test {
     use bytes::Bytes;
     use fbinit::FacebookInit;
 
+    use fileblob::Fileblob;
     use memblob::EagerMemblob;
+    use std::collections::HashSet;
+    use tempdir::TempDir;
 
     #[fbinit::compat_test]
     async fn test_prefix(fb: FacebookInit) {
@@ -132,4 +208,80 @@ mod test {
                 .expect("is_present should succeed")
         );
     }
+
+    #[fbinit::compat_test]
+    async fn test_prefix_enumerate(fb: FacebookInit) {
+        let ctx = CoreContext::test_mock(fb);
+        let dir = TempDir::new("prefixblob_enumerate").unwrap();
+        let base = Fileblob::create(dir.path()).unwrap();
+        let prefixed = PrefixBlobstore::new(base.clone(), "prefix123-");
+
+        for key in &["foo", "bar"] {
+            prefixed
+                .put(
+                    ctx.clone(),
+                    key.to_string(),
+                    BlobstoreBytes::from_bytes("test"),
+                )
+                .await
+                .expect("put should succeed");
+        }
+        base.put(
+            ctx.clone(),
+            "other-baz".to_string(),
+            BlobstoreBytes::from_bytes("test"),
+        )
+        .await
+        .expect("put should succeed");
+
+        // Only the keys with the prefix are enumerated, without the prefix.
+        let entries = prefixed
+            .enumerate(BlobstoreKeyParam::from(..))
+            .await
+            .expect("enumerate should succeed");
+        let expected: HashSet<_> = vec!["foo".to_string(), "bar".to_string()]
+            .into_iter()
+            .collect();
+        assert_eq!(entries.keys, expected);
+
+        let entries = prefixed
+            .enumerate(BlobstoreKeyParam::from("c".to_string()..))
+            .await
+            .expect("enumerate should succeed");
+        let expected: HashSet<_> = vec!["foo".to_string()].into_iter().collect();
+        assert_eq!(entries.keys, expected);
+    }
+
+    #[fbinit::compat_test]
+    async fn test_prefix_ttl(fb: FacebookInit) {
+        let ctx = CoreContext::test_mock(fb);
+        let dir = TempDir::new("prefixblob_ttl").unwrap();
+        let base = Fileblob::create(dir.path()).unwrap();
+        let prefixed = PrefixBlobstore::new(base.clone(), "prefix123-");
+
+        prefixed
+            .put_with_ttl(
+                ctx.clone(),
+                "expired".to_string(),
+                BlobstoreBytes::from_bytes("test"),
+                Duration::from_secs(0),
+            )
+            .await
+            .expect("put_with_ttl should succeed");
+
+        // The key is stored with the prefix, and swept like any other expired key.
+        let entries = base
+            .enumerate(BlobstoreKeyParam::from(..))
+            .await
+            .expect("enumerate should succeed");
+        let expected: HashSet<_> = vec!["prefix123-expired".to_string()].into_iter().collect();
+        assert_eq!(entries.keys, expected);
+        assert_eq!(
+            prefixed
+                .sweep_expired(ctx.clone(), 100)
+                .await
+                .expect("sweep_expired should succeed"),
+            1
+        );
+    }
 }
@@ -8,13 +8,14 @@
 #![deny(warnings)]
 
 use anyhow::Error;
-use blobstore::{Blobstore, BlobstoreGetData};
+use blobstore::{Blobstore, BlobstoreGetData, BlobstoreWithTtl};
 use context::CoreContext;
 use futures::future::{BoxFuture, FutureExt};
 use mononoke_types::{BlobstoreBytes, Timestamp};
 use scuba_ext::ScubaSampleBuilder;
 use slog::debug;
 use std::collections::HashMap;
+use std::time::Duration;
 mod errors;
 pub use crate::errors::ErrorKind;
 use std::{
@@ -232,6 +233,52 @@ where
     }
 }
 
+impl<T: BlobstoreWithTtl + Clone> BlobstoreWithTtl for RedactedBlobstoreInner<T> {
+    fn put_with_ttl(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        ttl: Duration,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        let put = self
+            .access_blobstore(&ctx, &key, config::PUT_OPERATION)
+            .map(move |blobstore| blobstore.put_with_ttl(ctx, key, value, ttl));
+        async move { put?.await }.boxed()
+    }
+
+    fn sweep_expired(
+        &self,
+        ctx: CoreContext,
+        limit: u64,
+    ) -> BoxFuture<'static, Result<u64, Error>> {
+        self.blobstore.sweep_expired(ctx, limit)
+    }
+}
+
+impl<B> BlobstoreWithTtl for RedactedBlobstore<B>
+where
+    B: BlobstoreWithTtl + Clone,
+{
+    fn put_with_ttl(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        ttl: Duration,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        self.inner.put_with_ttl(ctx, key, value, ttl)
+    }
+
+    fn sweep_expired(
+        &self,
+        ctx: CoreContext,
+        limit: u64,
+    ) -> BoxFuture<'static, Result<u64, Error>> {
+        self.inner.sweep_expired(ctx, limit)
+    }
+}
+
 pub fn has_redaction_root_cause(e: &Error) -> bool {
     match e.root_cause().downcast_ref::<ErrorKind>() {
         Some(ErrorKind::Censored(_, _)) => true,
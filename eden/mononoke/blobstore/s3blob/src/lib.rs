@@ -0,0 +1,389 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{format_err, Error, Result};
+use chrono::DateTime;
+use fbinit::FacebookInit;
+use futures::{
+    future::{BoxFuture, FutureExt},
+    stream::TryStreamExt,
+};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use rusoto_core::{request::HttpClient, ByteStream, Region, RusotoError};
+use rusoto_credential::{EnvironmentProvider, ProfileProvider, ProvideAwsCredentials};
+use rusoto_s3::{
+    CopyObjectRequest, GetObjectError, GetObjectRequest, HeadObjectError, HeadObjectRequest,
+    ListObjectsV2Request, PutObjectRequest, S3Client, S3,
+};
+use serde::{Deserialize, Serialize};
+
+use blobstore::{
+    Blobstore, BlobstoreEnumerationData, BlobstoreGetData, BlobstoreKeyParam, BlobstoreKeyRange,
+    BlobstoreKeySource, BlobstoreKeyToken, BlobstoreMetadata, BlobstoreWithLink,
+};
+use context::CoreContext;
+use metaconfig_types::S3Credentials;
+use mononoke_types::BlobstoreBytes;
+
+/// Maximum number of keys returned by a single `enumerate` call.
+const MAX_KEYS_PER_PAGE: i64 = 1000;
+
+/// Characters that must be escaped in the `x-amz-copy-source` header. Slashes are kept as is, as
+/// they separate the bucket from the key.
+const COPY_SOURCE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Blobstore backed by an S3 compatible object store (AWS S3, MinIO, Ceph RGW...).
+///
+/// Requests are sent to `endpoint` using path-style addressing (`endpoint/bucket/key`), so
+/// stores without virtual-host bucket DNS (such as a local MinIO) work out of the box.
+#[derive(Clone)]
+pub struct S3Blob {
+    client: Arc<S3Client>,
+    bucket: String,
+    prefix: String,
+    region: Region,
+}
+
+impl fmt::Debug for S3Blob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("S3Blob")
+            .field("bucket", &self.bucket)
+            .field("prefix", &self.prefix)
+            .field("region", &self.region)
+            .finish()
+    }
+}
+
+fn make_client<P>(provider: P, region: Region) -> Result<S3Client>
+where
+    P: ProvideAwsCredentials + Send + Sync + 'static,
+{
+    let dispatcher = HttpClient::new()?;
+    Ok(S3Client::new_with(dispatcher, provider, region))
+}
+
+impl S3Blob {
+    pub async fn new(
+        fb: FacebookInit,
+        bucket: String,
+        prefix: String,
+        region_name: String,
+        endpoint: String,
+        credentials: S3Credentials,
+    ) -> Result<Self> {
+        let region = Region::Custom {
+            name: region_name,
+            endpoint,
+        };
+
+        let client = match credentials {
+            S3Credentials::Environment => {
+                make_client(EnvironmentProvider::default(), region.clone())?
+            }
+            S3Credentials::Profile { path, profile } => {
+                let mut provider = match path {
+                    Some(path) => ProfileProvider::with_default_configuration(path),
+                    None => ProfileProvider::new()?,
+                };
+                if let Some(profile) = profile {
+                    provider.set_profile(profile);
+                }
+                make_client(provider, region.clone())?
+            }
+            S3Credentials::Keychain { group } => {
+                let _ = fb;
+                return Err(format_err!(
+                    "Keychain credentials (group {}) are not supported by s3blob",
+                    group
+                ));
+            }
+        };
+
+        Ok(Self {
+            client: Arc::new(client),
+            bucket,
+            prefix,
+            region,
+        })
+    }
+
+    fn object_key(&self, key: &str) -> String {
+        [self.prefix.as_str(), key].concat()
+    }
+
+    fn blobstore_key<'a>(&self, object_key: &'a str) -> Option<&'a str> {
+        if object_key.starts_with(&self.prefix) {
+            Some(&object_key[self.prefix.len()..])
+        } else {
+            None
+        }
+    }
+
+    fn copy_source(&self, key: &str) -> String {
+        let source = format!("{}/{}", self.bucket, self.object_key(key));
+        utf8_percent_encode(&source, COPY_SOURCE).to_string()
+    }
+
+    /// List one page of keys, starting at `start_after` (exclusive) or resuming from
+    /// `continuation_token`, and stopping at `end_key` (exclusive).
+    async fn list_page(
+        &self,
+        start_after: Option<String>,
+        continuation_token: Option<String>,
+        end_key: String,
+    ) -> Result<BlobstoreEnumerationData> {
+        let request = ListObjectsV2Request {
+            bucket: self.bucket.clone(),
+            prefix: Some(self.prefix.clone()),
+            start_after: start_after.map(|key| self.object_key(&key)),
+            continuation_token,
+            max_keys: Some(MAX_KEYS_PER_PAGE),
+            ..Default::default()
+        };
+        let output = self.client.list_objects_v2(request).await?;
+
+        let mut keys = HashSet::new();
+        let mut reached_end = false;
+        for object in output.contents.unwrap_or_default() {
+            let key = match object.key.as_ref().and_then(|k| self.blobstore_key(k)) {
+                Some(key) => key.to_string(),
+                None => continue,
+            };
+            if !end_key.is_empty() && key >= end_key {
+                // Listing is ordered, so nothing past this point is in range
+                reached_end = true;
+                break;
+            }
+            keys.insert(key);
+        }
+
+        let next_token = match output.next_continuation_token {
+            Some(token) if !reached_end && output.is_truncated == Some(true) => {
+                let token = S3ContinuationToken { token, end_key };
+                Some(BlobstoreKeyParam::Continuation(
+                    BlobstoreKeyToken::StringToken(serde_json::to_string(&token)?),
+                ))
+            }
+            _ => None,
+        };
+
+        Ok(BlobstoreEnumerationData { keys, next_token })
+    }
+
+    async fn enumerate_range(&self, range: BlobstoreKeyRange) -> Result<BlobstoreEnumerationData> {
+        let BlobstoreKeyRange { begin_key, end_key } = range;
+        if begin_key.is_empty() {
+            return self.list_page(None, None, end_key).await;
+        }
+
+        // S3 treats StartAfter as exclusive, but ranges include their first key.
+        let include_begin =
+            self.exists(begin_key.clone()).await? && (end_key.is_empty() || begin_key < end_key);
+        let mut data = self
+            .list_page(Some(begin_key.clone()), None, end_key)
+            .await?;
+        if include_begin {
+            data.keys.insert(begin_key);
+        }
+        Ok(data)
+    }
+
+    async fn exists(&self, key: String) -> Result<bool> {
+        let request = HeadObjectRequest {
+            bucket: self.bucket.clone(),
+            key: self.object_key(&key),
+            ..Default::default()
+        };
+        match self.client.head_object(request).await {
+            Ok(_) => Ok(true),
+            Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => Ok(false),
+            // HEAD responses have no body, so a missing object usually surfaces as a bare 404
+            Err(RusotoError::Unknown(ref response)) if response.status.as_u16() == 404 => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Continuation state for a paginated listing. S3 continuation tokens only remember where
+/// the listing got to, so the end of the requested range travels alongside them.
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+struct S3ContinuationToken {
+    token: String,
+    end_key: String,
+}
+
+fn parse_last_modified(last_modified: Option<String>) -> Option<i64> {
+    let last_modified = last_modified?;
+    DateTime::parse_from_rfc2822(&last_modified)
+        .ok()
+        .map(|dt| dt.timestamp())
+}
+
+async fn read_body(body: Option<ByteStream>) -> Result<Vec<u8>> {
+    match body {
+        Some(body) => {
+            let bytes = body
+                .try_fold(Vec::new(), |mut acc, chunk| async move {
+                    acc.extend_from_slice(&chunk);
+                    Ok(acc)
+                })
+                .await?;
+            Ok(bytes)
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+impl Blobstore for S3Blob {
+    fn get(
+        &self,
+        _ctx: CoreContext,
+        key: String,
+    ) -> BoxFuture<'static, Result<Option<BlobstoreGetData>, Error>> {
+        let this = self.clone();
+
+        async move {
+            let request = GetObjectRequest {
+                bucket: this.bucket.clone(),
+                key: this.object_key(&key),
+                ..Default::default()
+            };
+            let output = match this.client.get_object(request).await {
+                Ok(output) => output,
+                Err(RusotoError::Service(GetObjectError::NoSuchKey(_))) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+
+            let ctime = parse_last_modified(output.last_modified);
+            let bytes = read_body(output.body).await?;
+
+            Ok(Some(BlobstoreGetData::new(
+                BlobstoreMetadata::new(ctime),
+                BlobstoreBytes::from_bytes(bytes),
+            )))
+        }
+        .boxed()
+    }
+
+    fn put(
+        &self,
+        _ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        let this = self.clone();
+
+        async move {
+            let bytes = value.into_bytes();
+            let request = PutObjectRequest {
+                bucket: this.bucket.clone(),
+                key: this.object_key(&key),
+                content_length: Some(bytes.len() as i64),
+                body: Some(ByteStream::from(bytes.to_vec())),
+                ..Default::default()
+            };
+            this.client.put_object(request).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn is_present(
+        &self,
+        _ctx: CoreContext,
+        key: String,
+    ) -> BoxFuture<'static, Result<bool, Error>> {
+        let this = self.clone();
+        async move { this.exists(key).await }.boxed()
+    }
+}
+
+impl BlobstoreWithLink for S3Blob {
+    // S3 has no hardlinks, so this is a server-side copy. As blobstore values are immutable
+    // the result is indistinguishable from a link.
+    fn link(
+        &self,
+        _ctx: CoreContext,
+        existing_key: String,
+        link_key: String,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        let this = self.clone();
+
+        async move {
+            let request = CopyObjectRequest {
+                bucket: this.bucket.clone(),
+                key: this.object_key(&link_key),
+                copy_source: this.copy_source(&existing_key),
+                ..Default::default()
+            };
+            this.client.copy_object(request).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+impl BlobstoreKeySource for S3Blob {
+    fn enumerate(
+        &self,
+        range: BlobstoreKeyParam,
+    ) -> BoxFuture<'static, Result<BlobstoreEnumerationData, Error>> {
+        let this = self.clone();
+
+        async move {
+            match range {
+                BlobstoreKeyParam::Start(range) => this.enumerate_range(range).await,
+                BlobstoreKeyParam::Continuation(BlobstoreKeyToken::StringToken(token)) => {
+                    let S3ContinuationToken { token, end_key } = serde_json::from_str(&token)
+                        .map_err(|e| format_err!("Invalid S3 continuation token: {}", e))?;
+                    this.list_page(None, Some(token), end_key).await
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_last_modified() {
+        assert_eq!(
+            parse_last_modified(Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string())),
+            Some(1445412480)
+        );
+        assert_eq!(parse_last_modified(Some("yesterday".to_string())), None);
+        assert_eq!(parse_last_modified(None), None);
+    }
+
+    #[test]
+    fn test_continuation_token_roundtrip() -> Result<()> {
+        let token = S3ContinuationToken {
+            token: "1ueGcxLPRx1Tr/XYExHnhbYLgveDs2J/wm36Hy4vbOwM=".to_string(),
+            end_key: "repo0000.\x7f".to_string(),
+        };
+        let serialized = serde_json::to_string(&token)?;
+        assert_eq!(
+            serde_json::from_str::<S3ContinuationToken>(&serialized)?,
+            token
+        );
+        Ok(())
+    }
+}
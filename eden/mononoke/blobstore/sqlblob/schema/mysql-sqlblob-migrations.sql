@@ -0,0 +1,17 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+-- Changes to apply to each shard of an existing MySQL sqlblob, in order.
+-- New sqlite databases get these from sqlite-sqlblob.sql, and existing ones
+-- are migrated when opened.
+
+-- Lets keys be put with a TTL, and swept once they expire
+ALTER TABLE `data` ADD COLUMN `expiry_time` BIGINT DEFAULT NULL;
+ALTER TABLE `data` ADD INDEX `data_expiry_time` (`expiry_time`);
+
+-- Lets unlink find out whether any key still refers to a chunk
+ALTER TABLE `data` ADD INDEX `data_chunk_id` (`chunk_id`);
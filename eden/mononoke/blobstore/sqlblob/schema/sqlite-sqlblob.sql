@@ -11,9 +11,13 @@ CREATE TABLE `data` (
   `chunk_id` VARCHAR(255) NOT NULL,
   `chunk_count` INT UNSIGNED NOT NULL,
   `chunking_method` INT UNSIGNED NOT NULL,
+  `expiry_time` BIGINT DEFAULT NULL,
   PRIMARY KEY (`id`)
 );
 
+CREATE INDEX `data_expiry_time` ON `data` (`expiry_time`);
+CREATE INDEX `data_chunk_id` ON `data` (`chunk_id`);
+
 CREATE TABLE `chunk` (
   `id` VARCHAR(255) NOT NULL,
   `creation_time` TIMESTAMP DEFAULT CURRENT NOT NULL,
@@ -24,7 +24,9 @@ use crate::myadmin_delay_dummy as myadmin_delay;
 use crate::store::{ChunkSqlStore, ChunkingMethod, DataSqlStore};
 use anyhow::{format_err, Error, Result};
 use blobstore::{
-    Blobstore, BlobstoreGetData, BlobstoreMetadata, BlobstoreWithLink, CountedBlobstore,
+    Blobstore, BlobstoreEnumerationData, BlobstoreGetData, BlobstoreGetMetadata, BlobstoreKeyParam,
+    BlobstoreKeySource, BlobstoreKeyToken, BlobstoreMetadata, BlobstoreUnlink, BlobstoreWithLink,
+    BlobstoreWithTtl, CountedBlobstore,
 };
 use bytes::BytesMut;
 use cloned::cloned;
@@ -38,6 +40,7 @@ use futures_ext::{try_boxfuture, BoxFuture as BoxFuture01, FutureExt as _};
 use futures_old::future::join_all;
 use futures_old::prelude::*;
 use mononoke_types::{hash::Context as HashContext, BlobstoreBytes};
+use serde::{Deserialize, Serialize};
 use sql::{rusqlite::Connection as SqliteConnection, Connection};
 use sql_ext::{
     facebook::{
@@ -46,22 +49,31 @@ use sql_ext::{
     },
     open_sqlite_in_memory, open_sqlite_path, SqlConnections,
 };
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 use std::fmt;
 use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 // Leaving some space for metadata
 const MAX_KEY_SIZE: usize = 200;
 // MySQL wants multiple chunks, each around 1 MiB, as a tradeoff between query latency and replication lag
 const CHUNK_SIZE: usize = 1024 * 1024;
+// Number of keys returned by each call to enumerate
+const ENUMERATE_PAGE_SIZE: u64 = 10000;
 const SQLITE_SHARD_NUM: NonZeroUsize = unsafe { NonZeroUsize::new_unchecked(2) };
 
 const COUNTED_ID: &str = "sqlblob";
 pub type CountedSqlblob = CountedBlobstore<Sqlblob>;
 
+// Resumes an enumeration after the last key returned
+#[derive(Serialize, Deserialize)]
+struct EnumerationToken {
+    after: String,
+    end_key: String,
+}
+
 pub struct Sqlblob {
     data_store: Arc<DataSqlStore>,
     chunk_store: Arc<ChunkSqlStore>,
@@ -277,6 +289,10 @@ impl Sqlblob {
             // When opening an sqlite database we might already have the proper tables in it, so ignore
             // errors from table creation
             let _ = con.execute_batch(Self::CREATION_QUERY);
+            // Bring databases created by older versions up to date
+            for migration in Self::SQLITE_MIGRATIONS {
+                let _ = con.execute_batch(migration);
+            }
             Ok(con)
         })
     }
@@ -316,6 +332,14 @@ impl Sqlblob {
 
     const CREATION_QUERY: &'static str = include_str!("../schema/sqlite-sqlblob.sql");
 
+    // Each migration must be safe to run against a database that already has it applied
+    const SQLITE_MIGRATIONS: &'static [&'static str] = &[
+        // Fails harmlessly if the column already exists
+        "ALTER TABLE `data` ADD COLUMN `expiry_time` BIGINT DEFAULT NULL",
+        "CREATE INDEX IF NOT EXISTS `data_expiry_time` ON `data` (`expiry_time`)",
+        "CREATE INDEX IF NOT EXISTS `data_chunk_id` ON `data` (`chunk_id`)",
+    ];
+
     fn counted(self, label: String) -> CountedBlobstore<Self> {
         CountedBlobstore::new(format!("{}.{}", COUNTED_ID, label), self)
     }
@@ -324,48 +348,27 @@ impl Sqlblob {
     pub(crate) fn get_data_store(&self) -> &DataSqlStore {
         &self.data_store
     }
-}
 
-impl fmt::Debug for Sqlblob {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Sqlblob").finish()
+    #[cfg(test)]
+    pub(crate) fn get_chunk_store(&self) -> &ChunkSqlStore {
+        &self.chunk_store
     }
 }
 
-impl Blobstore for Sqlblob {
-    fn get(
-        &self,
-        _ctx: CoreContext,
-        key: String,
-    ) -> BoxFuture<'static, Result<Option<BlobstoreGetData>, Error>> {
-        cloned!(self.data_store, self.chunk_store);
-
-        async move {
-            let chunked = data_store.get(&key).await?;
-            if let Some(chunked) = chunked {
-                let fetch_chunks: FuturesOrdered<_> = (0..chunked.count)
-                    .map(|chunk_num| {
-                        chunk_store.get(&chunked.id, chunk_num, chunked.chunking_method)
-                    })
-                    .collect();
-                let blob: BytesMut = fetch_chunks.try_concat().await?;
-                let meta = BlobstoreMetadata::new(Some(chunked.ctime));
-                Ok(Some(BlobstoreGetData::new(
-                    meta,
-                    BlobstoreBytes::from_bytes(blob.freeze()),
-                )))
-            } else {
-                Ok(None)
-            }
-        }
-        .boxed()
-    }
+fn now() -> Result<i64> {
+    let now = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(offset) => offset.as_secs().try_into(),
+        Err(negative) => negative.duration().as_secs().try_into().map(|v: i64| -v),
+    }?;
+    Ok(now)
+}
 
-    fn put(
+impl Sqlblob {
+    fn put_impl(
         &self,
-        _ctx: CoreContext,
         key: String,
         value: BlobstoreBytes,
+        ttl: Option<Duration>,
     ) -> BoxFuture<'static, Result<(), Error>> {
         if key.as_bytes().len() > MAX_KEY_SIZE {
             return future::err(format_err!(
@@ -397,12 +400,11 @@ impl Blobstore for Sqlblob {
                     )
                     .await?;
             }
-            let ctime = {
-                match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-                    Ok(offset) => offset.as_secs().try_into(),
-                    Err(negative) => negative.duration().as_secs().try_into().map(|v: i64| -v),
-                }
-            }?;
+            let ctime = now()?;
+            let expiry_time = match ttl {
+                Some(ttl) => Some(ctime + i64::try_from(ttl.as_secs())?),
+                None => None,
+            };
             data_store
                 .put(
                     &key,
@@ -410,11 +412,58 @@ impl Blobstore for Sqlblob {
                     chunk_key.as_str(),
                     chunk_count,
                     chunking_method,
+                    expiry_time,
                 )
                 .await
         }
         .boxed()
     }
+}
+
+impl fmt::Debug for Sqlblob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sqlblob").finish()
+    }
+}
+
+impl Blobstore for Sqlblob {
+    fn get(
+        &self,
+        _ctx: CoreContext,
+        key: String,
+    ) -> BoxFuture<'static, Result<Option<BlobstoreGetData>, Error>> {
+        cloned!(self.data_store, self.chunk_store);
+
+        async move {
+            let now = now()?;
+            let chunked = data_store.get(&key).await?;
+            if let Some(chunked) = chunked.filter(|chunked| !chunked.is_expired(now)) {
+                let fetch_chunks: FuturesOrdered<_> = (0..chunked.count)
+                    .map(|chunk_num| {
+                        chunk_store.get(&chunked.id, chunk_num, chunked.chunking_method)
+                    })
+                    .collect();
+                let blob: BytesMut = fetch_chunks.try_concat().await?;
+                let meta = BlobstoreMetadata::new(Some(chunked.ctime));
+                Ok(Some(BlobstoreGetData::new(
+                    meta,
+                    BlobstoreBytes::from_bytes(blob.freeze()),
+                )))
+            } else {
+                Ok(None)
+            }
+        }
+        .boxed()
+    }
+
+    fn put(
+        &self,
+        _ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        self.put_impl(key, value, None)
+    }
 
     fn is_present(
         &self,
@@ -422,7 +471,7 @@ impl Blobstore for Sqlblob {
         key: String,
     ) -> BoxFuture<'static, Result<bool, Error>> {
         cloned!(self.data_store);
-        async move { data_store.is_present(&key).await }.boxed()
+        async move { data_store.is_present(&key, now()?).await }.boxed()
     }
 }
 
@@ -435,9 +484,14 @@ impl BlobstoreWithLink for Sqlblob {
     ) -> BoxFuture<'static, Result<(), Error>> {
         cloned!(self.data_store);
         async move {
-            let existing_data = data_store.get(&existing_key).await?.ok_or_else(|| {
-                format_err!("Key {} does not exist in the blobstore", existing_key)
-            })?;
+            let now = now()?;
+            let existing_data = data_store
+                .get(&existing_key)
+                .await?
+                .filter(|existing_data| !existing_data.is_expired(now))
+                .ok_or_else(|| {
+                    format_err!("Key {} does not exist in the blobstore", existing_key)
+                })?;
             data_store
                 .put(
                     &link_key,
@@ -445,9 +499,131 @@ impl BlobstoreWithLink for Sqlblob {
                     &existing_data.id,
                     existing_data.count,
                     existing_data.chunking_method,
+                    // The link is a new permanent key for the content
+                    None,
                 )
                 .await
         }
         .boxed()
     }
 }
+
+impl BlobstoreGetMetadata for Sqlblob {
+    fn get_metadata(
+        &self,
+        _ctx: CoreContext,
+        key: String,
+    ) -> BoxFuture<'static, Result<Option<BlobstoreMetadata>, Error>> {
+        cloned!(self.data_store);
+        async move {
+            let now = now()?;
+            Ok(data_store
+                .get(&key)
+                .await?
+                .filter(|chunked| !chunked.is_expired(now))
+                .map(|chunked| BlobstoreMetadata::new(Some(chunked.ctime))))
+        }
+        .boxed()
+    }
+}
+
+impl BlobstoreUnlink for Sqlblob {
+    // Chunks are shared between all keys with the same content (including links), so they are
+    // only removed once no key refers to them. A put of the same content that races with the
+    // unlink can still lose its chunks, so as with any unlink, callers must only unlink keys that
+    // nothing is expected to write to (e.g. keys that gc found to be unreachable and old).
+    fn unlink(&self, _ctx: CoreContext, key: String) -> BoxFuture<'static, Result<(), Error>> {
+        cloned!(self.data_store, self.chunk_store);
+        async move {
+            if let Some(chunked) = data_store.unlink(&key).await? {
+                delete_unreferenced_chunks(
+                    &data_store,
+                    &chunk_store,
+                    &chunked.id,
+                    chunked.count,
+                    chunked.chunking_method,
+                )
+                .await?;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+async fn delete_unreferenced_chunks(
+    data_store: &DataSqlStore,
+    chunk_store: &ChunkSqlStore,
+    chunk_id: &str,
+    chunk_count: u32,
+    chunking_method: ChunkingMethod,
+) -> Result<(), Error> {
+    if !data_store.is_chunk_referenced(chunk_id).await? {
+        chunk_store
+            .delete(chunk_id, chunk_count, chunking_method)
+            .await?;
+    }
+    Ok(())
+}
+
+impl BlobstoreWithTtl for Sqlblob {
+    fn put_with_ttl(
+        &self,
+        _ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        ttl: Duration,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        self.put_impl(key, value, Some(ttl))
+    }
+
+    // Only the data rows are removed, and their chunks are left in place: chunks are shared by
+    // content, so a concurrent put of the same value may be about to refer to them again.
+    fn sweep_expired(
+        &self,
+        _ctx: CoreContext,
+        limit: u64,
+    ) -> BoxFuture<'static, Result<u64, Error>> {
+        cloned!(self.data_store);
+        async move { data_store.delete_expired(now()?, limit).await }.boxed()
+    }
+}
+
+impl BlobstoreKeySource for Sqlblob {
+    fn enumerate(
+        &self,
+        range: BlobstoreKeyParam,
+    ) -> BoxFuture<'static, Result<BlobstoreEnumerationData, Error>> {
+        cloned!(self.data_store);
+        async move {
+            let (begin, exclude_begin, end_key) = match range {
+                BlobstoreKeyParam::Start(range) => (range.begin_key, false, range.end_key),
+                BlobstoreKeyParam::Continuation(BlobstoreKeyToken::StringToken(token)) => {
+                    let token: EnumerationToken = serde_json::from_str(&token)?;
+                    (token.after, true, token.end_key)
+                }
+            };
+
+            let keys = data_store
+                .enumerate(&begin, exclude_begin, &end_key, ENUMERATE_PAGE_SIZE)
+                .await?;
+            let next_token = if keys.len() as u64 == ENUMERATE_PAGE_SIZE {
+                let token = EnumerationToken {
+                    after: keys[keys.len() - 1].clone(),
+                    end_key,
+                };
+                Some(BlobstoreKeyParam::Continuation(
+                    BlobstoreKeyToken::StringToken(serde_json::to_string(&token)?),
+                ))
+            } else {
+                None
+            };
+
+            Ok(BlobstoreEnumerationData {
+                keys: keys.into_iter().collect(),
+                next_token,
+            })
+        }
+        .boxed()
+    }
+}
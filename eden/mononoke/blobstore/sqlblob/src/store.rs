@@ -11,7 +11,7 @@ use std::sync::Arc;
 
 use anyhow::{format_err, Error};
 use bytes::BytesMut;
-use futures::compat::Future01CompatExt;
+use futures::{compat::Future01CompatExt, future};
 use sql::{queries, Connection};
 use twox_hash::XxHash32;
 
@@ -65,38 +65,170 @@ mod types {
 pub use self::types::ChunkingMethod;
 
 queries! {
+    // A plain put makes the key permanent, even if it was put with an expiry before. Putting an
+    // existing key again replaces its chunks, so that a re-put repairs a corrupt value.
     write InsertData(values: (id: &str, ctime: i64, chunk_id: &str, chunk_count: u32, chunking_method: ChunkingMethod)) {
-        insert_or_ignore,
-        "{insert_or_ignore} INTO data (
+        none,
+        mysql("INSERT INTO data (
             id
             , creation_time
             , chunk_id
             , chunk_count
             , chunking_method
-        ) VALUES {values}"
+        ) VALUES {values}
+        ON DUPLICATE KEY UPDATE
+            chunk_id = VALUES(chunk_id),
+            chunk_count = VALUES(chunk_count),
+            chunking_method = VALUES(chunking_method),
+            expiry_time = NULL")
+        sqlite("INSERT INTO data (
+            id
+            , creation_time
+            , chunk_id
+            , chunk_count
+            , chunking_method
+        ) VALUES {values}
+        ON CONFLICT(id) DO UPDATE SET
+            chunk_id = excluded.chunk_id,
+            chunk_count = excluded.chunk_count,
+            chunking_method = excluded.chunking_method,
+            expiry_time = NULL")
+    }
+
+    // Never adds an expiry to a permanent key, and never brings an existing expiry forward. As
+    // with a plain put, the chunks are replaced.
+    write InsertDataWithExpiry(values: (id: &str, ctime: i64, chunk_id: &str, chunk_count: u32, chunking_method: ChunkingMethod, expiry_time: i64)) {
+        none,
+        mysql("INSERT INTO data (
+            id
+            , creation_time
+            , chunk_id
+            , chunk_count
+            , chunking_method
+            , expiry_time
+        ) VALUES {values}
+        ON DUPLICATE KEY UPDATE
+            chunk_id = VALUES(chunk_id),
+            chunk_count = VALUES(chunk_count),
+            chunking_method = VALUES(chunking_method),
+            expiry_time = IF(expiry_time IS NULL, NULL, GREATEST(expiry_time, VALUES(expiry_time)))")
+        sqlite("INSERT INTO data (
+            id
+            , creation_time
+            , chunk_id
+            , chunk_count
+            , chunking_method
+            , expiry_time
+        ) VALUES {values}
+        ON CONFLICT(id) DO UPDATE SET
+            chunk_id = excluded.chunk_id,
+            chunk_count = excluded.chunk_count,
+            chunking_method = excluded.chunking_method,
+            expiry_time = CASE
+                WHEN data.expiry_time IS NULL THEN NULL
+                ELSE MAX(data.expiry_time, excluded.expiry_time)
+            END")
     }
 
+    // Chunks are content addressed, so rewriting an existing chunk only changes it if it was
+    // corrupt
     write InsertChunk(values: (id: &str, chunk_num: u32, value: &[u8])) {
-        insert_or_ignore,
-        "{insert_or_ignore} INTO chunk (
+        none,
+        mysql("INSERT INTO chunk (
+            id
+            , chunk_num
+            , value
+        ) VALUES {values}
+        ON DUPLICATE KEY UPDATE value = VALUES(value)")
+        sqlite("INSERT INTO chunk (
             id
             , chunk_num
             , value
-        ) VALUES {values}"
+        ) VALUES {values}
+        ON CONFLICT(id, chunk_num) DO UPDATE SET value = excluded.value")
+    }
+
+    write DeleteData(id: &str) {
+        none,
+        "DELETE FROM data
+         WHERE id = {id}"
+    }
+
+    // Only deletes keys that are still expired, in case they were put again since being selected
+    write DeleteExpiredData(now: i64, >list ids: String) {
+        none,
+        "DELETE FROM data
+         WHERE id IN {ids}
+           AND expiry_time <= {now}"
+    }
+
+    write DeleteChunk(id: &str, chunk_num: u32) {
+        none,
+        "DELETE FROM chunk
+         WHERE id = {id}
+           AND chunk_num = {chunk_num}"
     }
 
-    read SelectData(id: &str) -> (i64, Vec<u8>, u32, ChunkingMethod) {
-        "SELECT creation_time, chunk_id, chunk_count, chunking_method
+    read SelectData(id: &str) -> (i64, Vec<u8>, u32, ChunkingMethod, Option<i64>) {
+        "SELECT creation_time, chunk_id, chunk_count, chunking_method, expiry_time
          FROM data
          WHERE id = {id}"
     }
 
-    read SelectIsDataPresent(id: &str) -> (i32) {
+    read SelectIsChunkReferenced(chunk_id: &str) -> (i64) {
         "SELECT 1
+         FROM data
+         WHERE chunk_id = {chunk_id}
+         LIMIT 1"
+    }
+
+    read SelectIsDataPresent(id: &str) -> (Option<i64>) {
+        "SELECT expiry_time
          FROM data
          WHERE id = {id}"
     }
 
+    read SelectExpiredKeys(now: i64, limit: u64) -> (String) {
+        "SELECT id
+         FROM data
+         WHERE expiry_time <= {now}
+         LIMIT {limit}"
+    }
+
+    read SelectKeysInRange(begin: &str, end: &str, limit: u64) -> (String) {
+        "SELECT id
+         FROM data
+         WHERE id >= {begin}
+           AND id < {end}
+         ORDER BY id ASC
+         LIMIT {limit}"
+    }
+
+    read SelectKeysInRangeAfter(after: &str, end: &str, limit: u64) -> (String) {
+        "SELECT id
+         FROM data
+         WHERE id > {after}
+           AND id < {end}
+         ORDER BY id ASC
+         LIMIT {limit}"
+    }
+
+    read SelectKeysFrom(begin: &str, limit: u64) -> (String) {
+        "SELECT id
+         FROM data
+         WHERE id >= {begin}
+         ORDER BY id ASC
+         LIMIT {limit}"
+    }
+
+    read SelectKeysAfter(after: &str, limit: u64) -> (String) {
+        "SELECT id
+         FROM data
+         WHERE id > {after}
+         ORDER BY id ASC
+         LIMIT {limit}"
+    }
+
     read SelectChunk(id: &str, chunk_num: u32) -> (Vec<u8>) {
         "SELECT value
          FROM chunk
@@ -110,6 +242,17 @@ pub struct Chunked {
     pub count: u32,
     pub ctime: i64,
     pub chunking_method: ChunkingMethod,
+    pub expiry_time: Option<i64>,
+}
+
+impl Chunked {
+    pub fn is_expired(&self, now: i64) -> bool {
+        is_expired(self.expiry_time, now)
+    }
+}
+
+fn is_expired(expiry_time: Option<i64>, now: i64) -> bool {
+    expiry_time.map_or(false, |expiry_time| expiry_time <= now)
 }
 
 #[derive(Clone)]
@@ -154,15 +297,15 @@ impl DataSqlStore {
             }
         };
 
-        Ok(rows
-            .into_iter()
-            .next()
-            .map(|(ctime, chunk_id, chunk_count, chunking_method)| Chunked {
+        Ok(rows.into_iter().next().map(
+            |(ctime, chunk_id, chunk_count, chunking_method, expiry_time)| Chunked {
                 id: String::from_utf8_lossy(&chunk_id).to_string(),
                 count: chunk_count,
                 ctime,
                 chunking_method,
-            }))
+                expiry_time,
+            },
+        ))
     }
 
     pub(crate) async fn put(
@@ -172,21 +315,41 @@ impl DataSqlStore {
         chunk_id: &str,
         chunk_count: u32,
         chunking_method: ChunkingMethod,
+        expiry_time: Option<i64>,
     ) -> Result<(), Error> {
         let shard_id = self.shard(key);
 
         self.delay.delay(shard_id).await;
 
-        InsertData::query(
-            &self.write_connection[shard_id],
-            &[(&key, &ctime, &chunk_id, &chunk_count, &chunking_method)],
-        )
-        .compat()
-        .await?;
+        match expiry_time {
+            None => {
+                InsertData::query(
+                    &self.write_connection[shard_id],
+                    &[(&key, &ctime, &chunk_id, &chunk_count, &chunking_method)],
+                )
+                .compat()
+                .await?;
+            }
+            Some(expiry_time) => {
+                InsertDataWithExpiry::query(
+                    &self.write_connection[shard_id],
+                    &[(
+                        &key,
+                        &ctime,
+                        &chunk_id,
+                        &chunk_count,
+                        &chunking_method,
+                        &expiry_time,
+                    )],
+                )
+                .compat()
+                .await?;
+            }
+        }
         Ok(())
     }
 
-    pub(crate) async fn is_present(&self, key: &str) -> Result<bool, Error> {
+    pub(crate) async fn is_present(&self, key: &str, now: i64) -> Result<bool, Error> {
         let shard_id = self.shard(key);
 
         let rows = {
@@ -201,7 +364,123 @@ impl DataSqlStore {
                 rows
             }
         };
-        Ok(!rows.is_empty())
+        Ok(rows
+            .into_iter()
+            .next()
+            .map_or(false, |(expiry_time,)| !is_expired(expiry_time, now)))
+    }
+
+    /// Deletes up to `limit` keys from each shard that expired at or before `now`, returning how
+    /// many were deleted.
+    pub(crate) async fn delete_expired(&self, now: i64, limit: u64) -> Result<u64, Error> {
+        let deleted = future::try_join_all(self.write_connection.iter().enumerate().map(
+            |(shard_id, connection)| async move {
+                let keys: Vec<String> =
+                    SelectExpiredKeys::query(&self.read_master_connection[shard_id], &now, &limit)
+                        .compat()
+                        .await?
+                        .into_iter()
+                        .map(|(key,)| key)
+                        .collect();
+                if keys.is_empty() {
+                    return Ok::<_, Error>(0);
+                }
+
+                self.delay.delay(shard_id).await;
+                let result = DeleteExpiredData::query(connection, &now, &keys[..])
+                    .compat()
+                    .await?;
+                Ok(result.affected_rows())
+            },
+        ))
+        .await?;
+        Ok(deleted.into_iter().sum())
+    }
+
+    /// Deletes the data row for `key`, returning the chunks it referred to, if it existed
+    pub(crate) async fn unlink(&self, key: &str) -> Result<Option<Chunked>, Error> {
+        let shard_id = self.shard(key);
+
+        let chunked = SelectData::query(&self.read_master_connection[shard_id], &key)
+            .compat()
+            .await?
+            .into_iter()
+            .next()
+            .map(
+                |(ctime, chunk_id, chunk_count, chunking_method, expiry_time)| Chunked {
+                    id: String::from_utf8_lossy(&chunk_id).to_string(),
+                    count: chunk_count,
+                    ctime,
+                    chunking_method,
+                    expiry_time,
+                },
+            );
+
+        self.delay.delay(shard_id).await;
+
+        DeleteData::query(&self.write_connection[shard_id], &key)
+            .compat()
+            .await?;
+        Ok(chunked)
+    }
+
+    /// Returns true if any key still refers to the chunks with id `chunk_id`
+    pub(crate) async fn is_chunk_referenced(&self, chunk_id: &str) -> Result<bool, Error> {
+        // Keys are sharded by hash, so every shard has to be checked
+        let referenced = future::try_join_all(self.read_master_connection.iter().map(
+            |connection| async move {
+                let rows = SelectIsChunkReferenced::query(connection, &chunk_id)
+                    .compat()
+                    .await?;
+                Ok::<_, Error>(!rows.is_empty())
+            },
+        ))
+        .await?;
+        Ok(referenced.into_iter().any(|referenced| referenced))
+    }
+
+    /// Returns the first `limit` keys, in order, that are after `begin` (inclusive unless
+    /// `exclude_begin` is set) and before `end`. An empty `end` means there is no upper bound.
+    pub(crate) async fn enumerate(
+        &self,
+        begin: &str,
+        exclude_begin: bool,
+        end: &str,
+        limit: u64,
+    ) -> Result<Vec<String>, Error> {
+        // Keys are sharded by hash, so every shard has to be queried for the range
+        let shard_keys =
+            future::try_join_all(self.read_connection.iter().map(|connection| async move {
+                let rows = match (exclude_begin, end.is_empty()) {
+                    (false, false) => {
+                        SelectKeysInRange::query(connection, &begin, &end, &limit)
+                            .compat()
+                            .await?
+                    }
+                    (true, false) => {
+                        SelectKeysInRangeAfter::query(connection, &begin, &end, &limit)
+                            .compat()
+                            .await?
+                    }
+                    (false, true) => {
+                        SelectKeysFrom::query(connection, &begin, &limit)
+                            .compat()
+                            .await?
+                    }
+                    (true, true) => {
+                        SelectKeysAfter::query(connection, &begin, &limit)
+                            .compat()
+                            .await?
+                    }
+                };
+                Ok::<_, Error>(rows)
+            }))
+            .await?;
+
+        let mut keys: Vec<String> = shard_keys.into_iter().flatten().map(|(key,)| key).collect();
+        keys.sort();
+        keys.truncate(limit as usize);
+        Ok(keys)
     }
 
     fn shard(&self, key: &str) -> usize {
@@ -282,6 +561,25 @@ impl ChunkSqlStore {
         Ok(())
     }
 
+    pub(crate) async fn delete(
+        &self,
+        key: &str,
+        chunk_count: u32,
+        chunking_method: ChunkingMethod,
+    ) -> Result<(), Error> {
+        future::try_join_all((0..chunk_count).map(|chunk_num| async move {
+            let shard_id = self.shard(key, chunk_num, chunking_method);
+
+            self.delay.delay(shard_id).await;
+            DeleteChunk::query(&self.write_connection[shard_id], &key, &chunk_num)
+                .compat()
+                .await?;
+            Ok::<_, Error>(())
+        }))
+        .await?;
+        Ok(())
+    }
+
     fn shard(&self, key: &str, chunk_id: u32, _chunking_method: ChunkingMethod) -> usize {
         let mut hasher = XxHash32::with_seed(0);
         hasher.write(key.as_bytes());
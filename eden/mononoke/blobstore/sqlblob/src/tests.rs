@@ -9,6 +9,7 @@ use super::*;
 use bytes::Bytes;
 use fbinit::FacebookInit;
 use rand::{distributions::Alphanumeric, thread_rng, Rng, RngCore};
+use std::collections::HashSet;
 
 #[fbinit::compat_test]
 async fn read_write(fb: FacebookInit) {
@@ -132,6 +133,43 @@ async fn dedup(fb: FacebookInit) {
     );
 }
 
+#[fbinit::compat_test]
+async fn put_repairs_corruption(fb: FacebookInit) {
+    let ctx = CoreContext::test_mock(fb);
+    let key = "repair_test".to_string();
+    let bs = Arc::new(Sqlblob::with_sqlite_in_memory().unwrap());
+    let blobstore_bytes = BlobstoreBytes::from_bytes(Bytes::from_static(b"value"));
+    bs.put(ctx.clone(), key.clone(), blobstore_bytes.clone())
+        .await
+        .unwrap();
+    let data_store = bs.as_inner().get_data_store();
+    let chunked = data_store.get(&key).await.unwrap().unwrap();
+
+    // A corrupt chunk is overwritten by putting the value again
+    bs.as_inner()
+        .get_chunk_store()
+        .put(&chunked.id, 0, chunked.chunking_method, b"garbage")
+        .await
+        .unwrap();
+    bs.put(ctx.clone(), key.clone(), blobstore_bytes.clone())
+        .await
+        .unwrap();
+    let bytes_out = bs.get(ctx.clone(), key.clone()).await.unwrap().unwrap();
+    assert_eq!(bytes_out.as_raw_bytes(), &Bytes::from_static(b"value"));
+
+    // So is a data row that points at the wrong chunks
+    data_store
+        .put(&key, 0, "missing", 1, chunked.chunking_method, None)
+        .await
+        .unwrap();
+    assert!(bs.get(ctx.clone(), key.clone()).await.is_err());
+    bs.put(ctx.clone(), key.clone(), blobstore_bytes.clone())
+        .await
+        .unwrap();
+    let bytes_out = bs.get(ctx.clone(), key.clone()).await.unwrap().unwrap();
+    assert_eq!(bytes_out.as_raw_bytes(), &Bytes::from_static(b"value"));
+}
+
 #[fbinit::compat_test]
 async fn link(fb: FacebookInit) {
     let ctx = CoreContext::test_mock(fb);
@@ -194,3 +232,197 @@ async fn link(fb: FacebookInit) {
         "Chunking method differs"
     );
 }
+
+#[fbinit::compat_test]
+async fn unlink(fb: FacebookInit) {
+    let ctx = CoreContext::test_mock(fb);
+    // Generate unique keys.
+    let suffix: String = thread_rng().sample_iter(&Alphanumeric).take(10).collect();
+    let key1 = format!("manifoldblob_test_{}", suffix);
+    let suffix: String = thread_rng().sample_iter(&Alphanumeric).take(10).collect();
+    let key2 = format!("manifoldblob_test_{}", suffix);
+
+    let bs = Arc::new(Sqlblob::with_sqlite_in_memory().unwrap());
+
+    let mut bytes_in = [0u8; 64];
+    thread_rng().fill_bytes(&mut bytes_in);
+
+    let blobstore_bytes = BlobstoreBytes::from_bytes(Bytes::copy_from_slice(&bytes_in));
+
+    // Write a fresh blob and link it to a different key
+    bs.put(ctx.clone(), key1.clone(), blobstore_bytes.clone())
+        .await
+        .unwrap();
+    bs.link(ctx.clone(), key1.clone(), key2.clone())
+        .await
+        .unwrap();
+
+    // Unlink the original key
+    bs.unlink(ctx.clone(), key1.clone()).await.unwrap();
+    assert!(
+        !bs.is_present(ctx.clone(), key1.clone()).await.unwrap(),
+        "Blob should be gone"
+    );
+
+    // The link still reads the shared chunks
+    let bytes_out = bs.get(ctx.clone(), key2.clone()).await.unwrap();
+    assert_eq!(&bytes_in.to_vec(), bytes_out.unwrap().as_raw_bytes());
+
+    // Unlinking the last key referring to the chunks removes them too
+    let chunked = bs
+        .as_inner()
+        .get_data_store()
+        .get(&key2)
+        .await
+        .unwrap()
+        .unwrap();
+    bs.unlink(ctx.clone(), key2.clone()).await.unwrap();
+    assert!(
+        bs.as_inner()
+            .get_chunk_store()
+            .get(&chunked.id, 0, chunked.chunking_method)
+            .await
+            .is_err(),
+        "Chunks should be gone"
+    );
+
+    // Unlinking a missing key is not an error
+    bs.unlink(ctx.clone(), key1.clone()).await.unwrap();
+}
+
+#[fbinit::compat_test]
+async fn enumerate(fb: FacebookInit) {
+    let ctx = CoreContext::test_mock(fb);
+    let bs = Arc::new(Sqlblob::with_sqlite_in_memory().unwrap());
+
+    let blobstore_bytes = BlobstoreBytes::from_bytes(Bytes::from_static(b"content"));
+    for key in &["a1", "a2", "a3", "b1"] {
+        bs.put(ctx.clone(), key.to_string(), blobstore_bytes.clone())
+            .await
+            .unwrap();
+    }
+
+    let to_set = |keys: &[&str]| keys.iter().map(|k| k.to_string()).collect::<HashSet<_>>();
+
+    // Everything
+    let entries = bs.enumerate(BlobstoreKeyParam::from(..)).await.unwrap();
+    assert_eq!(entries.keys, to_set(&["a1", "a2", "a3", "b1"]));
+    assert_eq!(entries.next_token, None);
+
+    // Begin is inclusive, end is exclusive
+    let entries = bs
+        .enumerate(BlobstoreKeyParam::from("a2".to_string().."b1".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(entries.keys, to_set(&["a2", "a3"]));
+
+    // Pages are in key order across shards, and resume after the last key
+    let data_store = bs.as_inner().get_data_store();
+    let page = data_store.enumerate("", false, "", 2).await.unwrap();
+    assert_eq!(page, vec!["a1".to_string(), "a2".to_string()]);
+    let page = data_store.enumerate("a2", true, "", 2).await.unwrap();
+    assert_eq!(page, vec!["a3".to_string(), "b1".to_string()]);
+    let page = data_store.enumerate("a2", true, "b", 2).await.unwrap();
+    assert_eq!(page, vec!["a3".to_string()]);
+}
+
+#[fbinit::compat_test]
+async fn ttl(fb: FacebookInit) {
+    let ctx = CoreContext::test_mock(fb);
+    let bs = Arc::new(Sqlblob::with_sqlite_in_memory().unwrap());
+    let value = BlobstoreBytes::from_bytes("value");
+    let hour = Duration::from_secs(60 * 60);
+
+    // A zero TTL has expired as soon as it is written
+    bs.put_with_ttl(
+        ctx.clone(),
+        "expired".to_string(),
+        value.clone(),
+        Duration::from_secs(0),
+    )
+    .await
+    .unwrap();
+    bs.put_with_ttl(ctx.clone(), "expiring".to_string(), value.clone(), hour)
+        .await
+        .unwrap();
+    assert!(bs
+        .get(ctx.clone(), "expired".to_string())
+        .await
+        .unwrap()
+        .is_none());
+    assert!(!bs
+        .is_present(ctx.clone(), "expired".to_string())
+        .await
+        .unwrap());
+    assert!(bs
+        .is_present(ctx.clone(), "expiring".to_string())
+        .await
+        .unwrap());
+
+    // A TTL never applies to a permanent key, and a plain put makes a key permanent
+    bs.put(ctx.clone(), "permanent".to_string(), value.clone())
+        .await
+        .unwrap();
+    bs.put_with_ttl(
+        ctx.clone(),
+        "permanent".to_string(),
+        value.clone(),
+        Duration::from_secs(0),
+    )
+    .await
+    .unwrap();
+    bs.put_with_ttl(
+        ctx.clone(),
+        "revived".to_string(),
+        value.clone(),
+        Duration::from_secs(0),
+    )
+    .await
+    .unwrap();
+    bs.put(ctx.clone(), "revived".to_string(), value.clone())
+        .await
+        .unwrap();
+    for key in &["permanent", "revived"] {
+        assert!(bs.is_present(ctx.clone(), key.to_string()).await.unwrap());
+    }
+
+    // An expired key whose chunks nothing else refers to
+    bs.put_with_ttl(
+        ctx.clone(),
+        "expired_unique".to_string(),
+        BlobstoreBytes::from_bytes("unique value"),
+        Duration::from_secs(0),
+    )
+    .await
+    .unwrap();
+    let unique_chunked = bs
+        .as_inner()
+        .get_data_store()
+        .get("expired_unique")
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Only the expired keys are swept
+    assert_eq!(bs.sweep_expired(ctx.clone(), 100).await.unwrap(), 2);
+    assert_eq!(bs.sweep_expired(ctx.clone(), 100).await.unwrap(), 0);
+    let keys = bs.enumerate((..).into()).await.unwrap().keys;
+    let expected: HashSet<_> = vec!["expiring", "permanent", "revived"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    assert_eq!(keys, expected);
+
+    // Sweeping never removes chunks, as a concurrent put of the same content may rely on them
+    for key in &expected {
+        assert!(bs.get(ctx.clone(), key.clone()).await.unwrap().is_some());
+    }
+    assert!(
+        bs.as_inner()
+            .get_chunk_store()
+            .get(&unique_chunked.id, 0, unique_chunked.chunking_method)
+            .await
+            .is_ok(),
+        "Chunks should be kept"
+    );
+}
@@ -7,6 +7,7 @@
 
 use std::ops::Deref;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Error;
 use futures::future::{BoxFuture, FutureExt};
@@ -14,7 +15,11 @@ use stats::prelude::*;
 
 use context::CoreContext;
 
-use crate::{Blobstore, BlobstoreBytes, BlobstoreGetData, BlobstoreWithLink};
+use crate::{
+    Blobstore, BlobstoreBytes, BlobstoreEnumerationData, BlobstoreGetData, BlobstoreGetMetadata,
+    BlobstoreKeyParam, BlobstoreKeySource, BlobstoreMetadata, BlobstoreUnlink, BlobstoreWithLink,
+    BlobstoreWithTtl,
+};
 
 define_stats_struct! {
     CountedBlobstoreStats("mononoke.blobstore.{}", prefix: String),
@@ -30,6 +35,18 @@ define_stats_struct! {
     link: timeseries(Rate, Sum),
     link_ok: timeseries(Rate, Sum),
     link_err: timeseries(Rate, Sum),
+    unlink: timeseries(Rate, Sum),
+    unlink_ok: timeseries(Rate, Sum),
+    unlink_err: timeseries(Rate, Sum),
+    enumerate: timeseries(Rate, Sum),
+    enumerate_ok: timeseries(Rate, Sum),
+    enumerate_err: timeseries(Rate, Sum),
+    put_with_ttl: timeseries(Rate, Sum),
+    put_with_ttl_ok: timeseries(Rate, Sum),
+    put_with_ttl_err: timeseries(Rate, Sum),
+    sweep_expired: timeseries(Rate, Sum),
+    sweep_expired_ok: timeseries(Rate, Sum),
+    sweep_expired_err: timeseries(Rate, Sum),
 }
 
 #[derive(Clone, Debug)]
@@ -133,6 +150,95 @@ impl<T: BlobstoreWithLink> BlobstoreWithLink for CountedBlobstore<T> {
     }
 }
 
+impl<T: BlobstoreGetMetadata> BlobstoreGetMetadata for CountedBlobstore<T> {
+    fn get_metadata(
+        &self,
+        ctx: CoreContext,
+        key: String,
+    ) -> BoxFuture<'static, Result<Option<BlobstoreMetadata>, Error>> {
+        self.blobstore.get_metadata(ctx, key)
+    }
+}
+
+impl<T: BlobstoreUnlink> BlobstoreUnlink for CountedBlobstore<T> {
+    fn unlink(&self, ctx: CoreContext, key: String) -> BoxFuture<'static, Result<(), Error>> {
+        let stats = self.stats.clone();
+        stats.unlink.add_value(1);
+        let res = self.blobstore.unlink(ctx, key);
+        async move {
+            let res = res.await;
+            match res {
+                Ok(()) => stats.unlink_ok.add_value(1),
+                Err(_) => stats.unlink_err.add_value(1),
+            }
+            res
+        }
+        .boxed()
+    }
+}
+
+impl<T: BlobstoreKeySource> BlobstoreKeySource for CountedBlobstore<T> {
+    fn enumerate(
+        &self,
+        range: BlobstoreKeyParam,
+    ) -> BoxFuture<'static, Result<BlobstoreEnumerationData, Error>> {
+        let stats = self.stats.clone();
+        stats.enumerate.add_value(1);
+        let res = self.blobstore.enumerate(range);
+        async move {
+            let res = res.await;
+            match res {
+                Ok(_) => stats.enumerate_ok.add_value(1),
+                Err(_) => stats.enumerate_err.add_value(1),
+            }
+            res
+        }
+        .boxed()
+    }
+}
+
+impl<T: BlobstoreWithTtl> BlobstoreWithTtl for CountedBlobstore<T> {
+    fn put_with_ttl(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        ttl: Duration,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        let stats = self.stats.clone();
+        stats.put_with_ttl.add_value(1);
+        let res = self.blobstore.put_with_ttl(ctx, key, value, ttl);
+        async move {
+            let res = res.await;
+            match res {
+                Ok(()) => stats.put_with_ttl_ok.add_value(1),
+                Err(_) => stats.put_with_ttl_err.add_value(1),
+            }
+            res
+        }
+        .boxed()
+    }
+
+    fn sweep_expired(
+        &self,
+        ctx: CoreContext,
+        limit: u64,
+    ) -> BoxFuture<'static, Result<u64, Error>> {
+        let stats = self.stats.clone();
+        stats.sweep_expired.add_value(1);
+        let res = self.blobstore.sweep_expired(ctx, limit);
+        async move {
+            let res = res.await;
+            match res {
+                Ok(_) => stats.sweep_expired_ok.add_value(1),
+                Err(_) => stats.sweep_expired_err.add_value(1),
+            }
+            res
+        }
+        .boxed()
+    }
+}
+
 impl<T: Blobstore> Deref for CountedBlobstore<T> {
     type Target = T;
 
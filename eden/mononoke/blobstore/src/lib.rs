@@ -14,6 +14,7 @@ use serde_derive::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::fmt;
 use std::ops::{Range, RangeFrom, RangeFull, RangeTo};
+use std::time::Duration;
 
 use abomonation_derive::Abomonation;
 use anyhow::Error;
@@ -318,8 +319,43 @@ pub trait BlobstoreWithLink: Blobstore {
     ) -> BoxFuture<'static, Result<(), Error>>;
 }
 
+/// Mixin trait for blobstores that support the `unlink()` operation
+#[auto_impl(Arc, Box)]
+pub trait BlobstoreUnlink: Blobstore {
+    /// Remove `key` from the blobstore. Unlinking a key that is not present is not an error.
+    /// Blobstores that share storage between keys (e.g. via `link()`) must only release the
+    /// storage once no key refers to it anymore.
+    fn unlink(&self, ctx: CoreContext, key: String) -> BoxFuture<'static, Result<(), Error>>;
+}
+
+/// Mixin trait for blobstores that can store blobs that expire, e.g. for scratch data that should
+/// age out on its own. Expired blobs are no longer returned by `get` or `is_present`, and are
+/// removed by `sweep_expired`.
+///
+/// A plain `put` always stores the key permanently, even if it was stored with a TTL before, and
+/// `put_with_ttl` never makes a permanently stored key expire. Content shared between scratch
+/// and permanent data is therefore never lost.
+#[auto_impl(Arc, Box)]
+pub trait BlobstoreWithTtl: Blobstore {
+    /// Store `value` under `key` so that it expires `ttl` from now. If the key is already stored
+    /// with a later expiry, that expiry is kept.
+    fn put_with_ttl(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        ttl: Duration,
+    ) -> BoxFuture<'static, Result<(), Error>>;
+
+    /// Remove up to `limit` expired keys, and return how many were removed. Call repeatedly until
+    /// it returns 0 to remove everything that has expired.
+    fn sweep_expired(&self, ctx: CoreContext, limit: u64)
+        -> BoxFuture<'static, Result<u64, Error>>;
+}
+
 /// BlobstoreKeySource Interface
 /// Abstract for use with populate_healer
+#[auto_impl(Arc, Box)]
 pub trait BlobstoreKeySource: Blobstore {
     fn enumerate(
         &self,
@@ -327,6 +363,23 @@ pub trait BlobstoreKeySource: Blobstore {
     ) -> BoxFuture<'static, Result<BlobstoreEnumerationData, Error>>;
 }
 
+/// Mixin trait for blobstores that can read the metadata of a key without fetching its value
+#[auto_impl(Arc, Box)]
+pub trait BlobstoreGetMetadata: Blobstore {
+    /// Return the metadata of `key`, or None if it is not present
+    fn get_metadata(
+        &self,
+        ctx: CoreContext,
+        key: String,
+    ) -> BoxFuture<'static, Result<Option<BlobstoreMetadata>, Error>>;
+}
+
+/// A blobstore whose keys can be both enumerated and unlinked, for tools that remove or move
+/// blobs in bulk (e.g. the walker's gc or tiered blobstore migration)
+pub trait SweepableBlobstore: BlobstoreKeySource + BlobstoreUnlink + BlobstoreGetMetadata {}
+
+impl<T: BlobstoreKeySource + BlobstoreUnlink + BlobstoreGetMetadata> SweepableBlobstore for T {}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct BlobstoreKeyRange {
     // Should match manifold inclusiveness rules, please check and document.
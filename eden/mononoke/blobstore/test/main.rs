@@ -10,14 +10,19 @@
 #![deny(warnings)]
 #![feature(never_type)]
 
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Error;
 use bytes::Bytes;
 use fbinit::FacebookInit;
 use tempdir::TempDir;
 
-use blobstore::{Blobstore, BlobstoreWithLink};
+use blobstore::{
+    Blobstore, BlobstoreGetMetadata, BlobstoreKeyParam, BlobstoreKeySource, BlobstoreUnlink,
+    BlobstoreWithLink, BlobstoreWithTtl,
+};
 use context::CoreContext;
 use fileblob::Fileblob;
 use memblob::{EagerMemblob, LazyMemblob};
@@ -76,6 +81,164 @@ async fn missing<B: Blobstore>(fb: FacebookInit, blobstore: B) -> Result<(), Err
     Ok(())
 }
 
+async fn unlink<B: BlobstoreUnlink + BlobstoreWithLink>(
+    fb: FacebookInit,
+    blobstore: B,
+) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+
+    let key = "unlinkkey".to_string();
+    let linkkey = "unlinklinkkey".to_string();
+    let value = BlobstoreBytes::from_bytes(Bytes::copy_from_slice(b"appleveldata"));
+
+    blobstore
+        .put(ctx.clone(), key.clone(), value.clone())
+        .await?;
+    blobstore
+        .link(ctx.clone(), key.clone(), linkkey.clone())
+        .await?;
+
+    blobstore.unlink(ctx.clone(), key.clone()).await?;
+    assert!(!blobstore.is_present(ctx.clone(), key.clone()).await?);
+
+    // Other links to the same data are unaffected
+    let linkvalue = blobstore.get(ctx.clone(), linkkey.clone()).await?.unwrap();
+    assert_eq!(value, linkvalue.into_bytes());
+
+    // Unlinking a missing key is not an error
+    blobstore.unlink(ctx.clone(), key).await?;
+
+    blobstore.unlink(ctx.clone(), linkkey.clone()).await?;
+    assert!(!blobstore.is_present(ctx, linkkey).await?);
+
+    Ok(())
+}
+
+async fn enumerate<B: BlobstoreKeySource>(fb: FacebookInit, blobstore: B) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let value = BlobstoreBytes::from_bytes(Bytes::copy_from_slice(b"appleveldata"));
+
+    // Includes characters that stores might need to escape
+    let keys = vec!["repo0000.a", "repo0000.b/c", "repo0000.d e", "repo0001.a"];
+    for key in &keys {
+        blobstore
+            .put(ctx.clone(), key.to_string(), value.clone())
+            .await?;
+    }
+
+    let all = blobstore.enumerate(BlobstoreKeyParam::from(..)).await?;
+    assert_eq!(
+        all.keys,
+        keys.iter().map(|k| k.to_string()).collect::<HashSet<_>>()
+    );
+
+    let repo0 = blobstore
+        .enumerate(BlobstoreKeyParam::from(
+            "repo0000.".to_string().."repo0001.".to_string(),
+        ))
+        .await?;
+    assert_eq!(
+        repo0.keys,
+        keys[..3]
+            .iter()
+            .map(|k| k.to_string())
+            .collect::<HashSet<_>>()
+    );
+
+    Ok(())
+}
+
+async fn ttl<B: BlobstoreWithTtl + BlobstoreKeySource>(
+    fb: FacebookInit,
+    blobstore: B,
+) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let value = BlobstoreBytes::from_bytes(Bytes::copy_from_slice(b"appleveldata"));
+    let expired = Duration::from_secs(0);
+    let hour = Duration::from_secs(60 * 60);
+
+    blobstore
+        .put_with_ttl(ctx.clone(), "expired".to_string(), value.clone(), expired)
+        .await?;
+    blobstore
+        .put_with_ttl(ctx.clone(), "expiring".to_string(), value.clone(), hour)
+        .await?;
+    assert!(blobstore
+        .get(ctx.clone(), "expired".to_string())
+        .await?
+        .is_none());
+    assert!(
+        !blobstore
+            .is_present(ctx.clone(), "expired".to_string())
+            .await?
+    );
+    assert_eq!(
+        blobstore
+            .get(ctx.clone(), "expiring".to_string())
+            .await?
+            .map(|v| v.into_bytes()),
+        Some(value.clone())
+    );
+
+    // A TTL never applies to a permanent key, and a plain put makes a key permanent
+    blobstore
+        .put(ctx.clone(), "permanent".to_string(), value.clone())
+        .await?;
+    blobstore
+        .put_with_ttl(ctx.clone(), "permanent".to_string(), value.clone(), expired)
+        .await?;
+    blobstore
+        .put_with_ttl(ctx.clone(), "revived".to_string(), value.clone(), expired)
+        .await?;
+    blobstore
+        .put(ctx.clone(), "revived".to_string(), value.clone())
+        .await?;
+    for key in &["permanent", "revived"] {
+        assert!(blobstore.is_present(ctx.clone(), key.to_string()).await?);
+    }
+
+    assert_eq!(blobstore.sweep_expired(ctx.clone(), 100).await?, 1);
+    assert_eq!(blobstore.sweep_expired(ctx.clone(), 100).await?, 0);
+    let all = blobstore.enumerate(BlobstoreKeyParam::from(..)).await?;
+    assert_eq!(
+        all.keys,
+        vec!["expiring", "permanent", "revived"]
+            .into_iter()
+            .map(String::from)
+            .collect::<HashSet<_>>()
+    );
+
+    Ok(())
+}
+
+async fn get_metadata<B: BlobstoreGetMetadata>(
+    fb: FacebookInit,
+    blobstore: B,
+) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let value = BlobstoreBytes::from_bytes(Bytes::copy_from_slice(b"appleveldata"));
+
+    assert!(blobstore
+        .get_metadata(ctx.clone(), "metakey".to_string())
+        .await?
+        .is_none());
+
+    blobstore
+        .put(ctx.clone(), "metakey".to_string(), value)
+        .await?;
+    let fetched = blobstore
+        .get(ctx.clone(), "metakey".to_string())
+        .await?
+        .unwrap();
+    let meta = blobstore
+        .get_metadata(ctx.clone(), "metakey".to_string())
+        .await?
+        .unwrap();
+    assert_eq!(meta.ctime(), fetched.as_meta().ctime());
+
+    Ok(())
+}
+
 macro_rules! blobstore_test_impl {
     ($mod_name: ident => {
         state: $state: expr,
@@ -101,6 +264,13 @@ macro_rules! blobstore_test_impl {
                 missing(fb, factory(state)?).await
             }
 
+            #[fbinit::compat_test]
+            async fn test_unlink(fb: FacebookInit) -> Result<(), Error> {
+                let state = $state;
+                let factory = $new_cb;
+                unlink(fb, factory(state)?).await
+            }
+
             #[fbinit::compat_test]
             async fn test_boxable(_fb: FacebookInit) -> Result<(), Error> {
                 let state = $state;
@@ -149,6 +319,56 @@ blobstore_test_impl! {
     }
 }
 
+#[fbinit::compat_test]
+async fn test_fileblob_enumerate(fb: FacebookInit) -> Result<(), Error> {
+    let dir = TempDir::new("fileblob_enumerate_test")?;
+    enumerate(fb, Fileblob::open(dir.path())?).await
+}
+
+#[fbinit::compat_test]
+async fn test_fileblob_enumerate_pages(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let dir = TempDir::new("fileblob_enumerate_pages_test")?;
+    let blobstore = Fileblob::open(dir.path())?;
+    let value = BlobstoreBytes::from_bytes(Bytes::copy_from_slice(b"appleveldata"));
+    let keys: HashSet<_> = (0..=fileblob::ENUMERATE_PAGE_SIZE)
+        .map(|i| format!("repo0000.{:05}", i))
+        .collect();
+    for key in &keys {
+        blobstore
+            .put(ctx.clone(), key.clone(), value.clone())
+            .await?;
+    }
+
+    let first = blobstore.enumerate(BlobstoreKeyParam::from(..)).await?;
+    assert_eq!(first.keys.len(), fileblob::ENUMERATE_PAGE_SIZE);
+    let next_token = first.next_token.expect("more keys to enumerate");
+    let second = blobstore.enumerate(next_token).await?;
+    assert_eq!(second.keys.len(), 1);
+    assert!(second.next_token.is_none());
+    assert_eq!(
+        first
+            .keys
+            .into_iter()
+            .chain(second.keys)
+            .collect::<HashSet<_>>(),
+        keys
+    );
+    Ok(())
+}
+
+#[fbinit::compat_test]
+async fn test_fileblob_ttl(fb: FacebookInit) -> Result<(), Error> {
+    let dir = TempDir::new("fileblob_ttl_test")?;
+    ttl(fb, Fileblob::open(dir.path())?).await
+}
+
+#[fbinit::compat_test]
+async fn test_fileblob_get_metadata(fb: FacebookInit) -> Result<(), Error> {
+    let dir = TempDir::new("fileblob_get_metadata_test")?;
+    get_metadata(fb, Fileblob::open(dir.path())?).await
+}
+
 #[cfg(fbcode_build)]
 fn create_cache(fb: FacebookInit) -> Result<(), Error> {
     let config = cachelib::LruCacheConfig::new(128 * 1024 * 1024);
@@ -0,0 +1,109 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use blobstore::{Blobstore, BlobstoreGetData};
+use context::CoreContext;
+use futures::future::{self, BoxFuture, FutureExt};
+use mononoke_types::BlobstoreBytes;
+use slog::warn;
+use std::fmt;
+use std::sync::Arc;
+
+pub trait TierCheckHandler: Send + Sync {
+    /// Called when a key that was read is present in both the hot and the cold tier.
+    fn on_both_tiers(&self, ctx: &CoreContext, key: &str, hot: &BlobstoreGetData);
+}
+
+pub struct LoggingTierCheckHandler {
+    quiet: bool,
+}
+
+impl LoggingTierCheckHandler {
+    pub fn new(quiet: bool) -> Self {
+        Self { quiet }
+    }
+}
+
+impl TierCheckHandler for LoggingTierCheckHandler {
+    fn on_both_tiers(&self, ctx: &CoreContext, key: &str, _hot: &BlobstoreGetData) {
+        if !self.quiet {
+            warn!(ctx.logger(), "tier_check: {} is in both tiers", key);
+        }
+    }
+}
+
+/// A `TieredBlob` that reads from both tiers on every get, so that keys that are present in
+/// more than one tier are reported to the handler. Used by the walker to verify that
+/// migrations leave every blob in exactly one tier.
+#[derive(Clone)]
+pub struct TierCheckBlobstore<H, C> {
+    hot: H,
+    cold: C,
+    handler: Arc<dyn TierCheckHandler>,
+}
+
+impl<H, C> fmt::Debug for TierCheckBlobstore<H, C>
+where
+    H: fmt::Debug,
+    C: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TierCheckBlobstore")
+            .field("hot", &self.hot)
+            .field("cold", &self.cold)
+            .finish()
+    }
+}
+
+impl<H: Blobstore + Clone, C: Blobstore + Clone> TierCheckBlobstore<H, C> {
+    pub fn new(hot: H, cold: C, handler: Arc<dyn TierCheckHandler>) -> Self {
+        Self { hot, cold, handler }
+    }
+}
+
+impl<H: Blobstore + Clone, C: Blobstore + Clone> Blobstore for TierCheckBlobstore<H, C> {
+    fn get(
+        &self,
+        ctx: CoreContext,
+        key: String,
+    ) -> BoxFuture<'static, Result<Option<BlobstoreGetData>, Error>> {
+        let hot = self.hot.get(ctx.clone(), key.clone());
+        let cold = self.cold.get(ctx.clone(), key.clone());
+        let handler = self.handler.clone();
+        async move {
+            match future::try_join(hot, cold).await? {
+                (Some(hot), Some(_)) => {
+                    handler.on_both_tiers(&ctx, &key, &hot);
+                    Ok(Some(hot))
+                }
+                (Some(hot), None) => Ok(Some(hot)),
+                (None, cold) => Ok(cold),
+            }
+        }
+        .boxed()
+    }
+
+    fn put(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        self.hot.put(ctx, key, value)
+    }
+
+    fn is_present(&self, ctx: CoreContext, key: String) -> BoxFuture<'static, Result<bool, Error>> {
+        let hot = self.hot.is_present(ctx.clone(), key.clone());
+        let cold = self.cold.is_present(ctx, key);
+        async move {
+            let (hot, cold) = future::try_join(hot, cold).await?;
+            Ok(hot || cold)
+        }
+        .boxed()
+    }
+}
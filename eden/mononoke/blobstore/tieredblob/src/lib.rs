@@ -0,0 +1,19 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+mod check;
+mod migrate;
+mod store;
+
+pub use crate::check::{LoggingTierCheckHandler, TierCheckBlobstore, TierCheckHandler};
+pub use crate::migrate::{migrate_to_cold, MigrationStats};
+pub use crate::store::TieredBlob;
+
+#[cfg(test)]
+mod test;
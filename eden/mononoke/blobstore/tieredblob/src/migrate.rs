@@ -0,0 +1,105 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use blobstore::{Blobstore, BlobstoreKeyParam, BlobstoreKeySource, BlobstoreUnlink};
+use context::CoreContext;
+use futures::{
+    future,
+    stream::{self, StreamExt, TryStreamExt},
+};
+use slog::{debug, info};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MigrationStats {
+    /// Keys enumerated from the hot tier
+    pub enumerated: u64,
+    /// Keys that were old enough to move to the cold tier
+    pub migrated: u64,
+    /// Total size of the blobs moved to the cold tier
+    pub migrated_bytes: u64,
+}
+
+// Returns the size of the blob if it was old enough to be moved
+async fn migrate_key<H, C>(
+    ctx: &CoreContext,
+    hot: &H,
+    cold: &C,
+    key: String,
+    cutoff: i64,
+    dry_run: bool,
+) -> Result<Option<u64>, Error>
+where
+    H: BlobstoreKeySource + BlobstoreUnlink,
+    C: Blobstore,
+{
+    let value = match hot.get(ctx.clone(), key.clone()).await? {
+        Some(value) => value,
+        // Already moved by a concurrent migration
+        None => return Ok(None),
+    };
+    match value.as_meta().ctime() {
+        Some(ctime) if ctime < cutoff => {}
+        // Blobs without a ctime stay in the hot tier, as we can't tell their age
+        _ => return Ok(None),
+    }
+
+    let size = value.as_bytes().len() as u64;
+    if dry_run {
+        info!(ctx.logger(), "Would migrate {}", key);
+    } else {
+        // The blob must be in the cold tier before it leaves the hot tier, so that it is
+        // always readable from one of them
+        cold.put(ctx.clone(), key.clone(), value.into_bytes())
+            .await?;
+        hot.unlink(ctx.clone(), key.clone()).await?;
+        debug!(ctx.logger(), "Migrated {}", key);
+    }
+    Ok(Some(size))
+}
+
+/// Moves the blobs in `range` of the hot tier that were created before `cutoff` (in seconds
+/// since the epoch) to the cold tier. Blobs are immutable, so a blob that is written again while
+/// it is being moved still has the same contents in the cold tier. With `dry_run`, the blobs
+/// that would be moved are logged and counted, but left in place.
+pub async fn migrate_to_cold<H, C>(
+    ctx: &CoreContext,
+    hot: &H,
+    cold: &C,
+    range: BlobstoreKeyParam,
+    cutoff: i64,
+    concurrency: usize,
+    dry_run: bool,
+) -> Result<MigrationStats, Error>
+where
+    H: BlobstoreKeySource + BlobstoreUnlink,
+    C: Blobstore,
+{
+    let mut stats = MigrationStats::default();
+    let mut range = range;
+    loop {
+        let entries = hot.enumerate(range).await?;
+        stats.enumerated += entries.keys.len() as u64;
+
+        stream::iter(entries.keys)
+            .map(|key| migrate_key(ctx, hot, cold, key, cutoff, dry_run))
+            .buffer_unordered(concurrency)
+            .try_for_each(|size| {
+                if let Some(size) = size {
+                    stats.migrated += 1;
+                    stats.migrated_bytes += size;
+                }
+                future::ok(())
+            })
+            .await?;
+
+        match entries.next_token {
+            Some(next_token) => range = next_token,
+            None => return Ok(stats),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use blobstore::{Blobstore, BlobstoreGetData};
+use context::CoreContext;
+use futures::future::{BoxFuture, FutureExt};
+use mononoke_types::BlobstoreBytes;
+
+/// A blobstore split into a fast hot tier and a cheap cold tier. New blobs are written to the
+/// hot tier, and reads fall back to the cold tier for blobs that are not in the hot tier.
+/// Blobs that are already in the cold tier are written there instead, so that a key is never
+/// stored in both tiers.
+/// Blobs are moved from the hot tier to the cold tier by `migrate_to_cold`.
+#[derive(Clone, Debug)]
+pub struct TieredBlob<H, C> {
+    hot: H,
+    cold: C,
+}
+
+impl<H: Blobstore + Clone, C: Blobstore + Clone> TieredBlob<H, C> {
+    pub fn new(hot: H, cold: C) -> Self {
+        Self { hot, cold }
+    }
+
+    pub fn as_hot(&self) -> &H {
+        &self.hot
+    }
+
+    pub fn as_cold(&self) -> &C {
+        &self.cold
+    }
+}
+
+impl<H: Blobstore + Clone, C: Blobstore + Clone> Blobstore for TieredBlob<H, C> {
+    fn get(
+        &self,
+        ctx: CoreContext,
+        key: String,
+    ) -> BoxFuture<'static, Result<Option<BlobstoreGetData>, Error>> {
+        let hot = self.hot.get(ctx.clone(), key.clone());
+        let cold = self.cold.clone();
+        async move {
+            match hot.await? {
+                Some(value) => Ok(Some(value)),
+                None => cold.get(ctx, key).await,
+            }
+        }
+        .boxed()
+    }
+
+    fn put(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        let hot = self.hot.clone();
+        let cold = self.cold.clone();
+        async move {
+            if cold.is_present(ctx.clone(), key.clone()).await? {
+                cold.put(ctx, key, value).await
+            } else {
+                hot.put(ctx, key, value).await
+            }
+        }
+        .boxed()
+    }
+
+    fn is_present(&self, ctx: CoreContext, key: String) -> BoxFuture<'static, Result<bool, Error>> {
+        let hot = self.hot.is_present(ctx.clone(), key.clone());
+        let cold = self.cold.clone();
+        async move {
+            if hot.await? {
+                Ok(true)
+            } else {
+                cold.is_present(ctx, key).await
+            }
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,196 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::{migrate_to_cold, MigrationStats, TierCheckBlobstore, TierCheckHandler, TieredBlob};
+
+use blobstore::{Blobstore, BlobstoreGetData};
+use bytes::Bytes;
+use context::CoreContext;
+use fbinit::FacebookInit;
+use fileblob::Fileblob;
+use mononoke_types::BlobstoreBytes;
+use std::sync::{Arc, Mutex};
+use tempdir::TempDir;
+
+fn make_tiers() -> (TempDir, Fileblob, Fileblob) {
+    let dir = TempDir::new("tieredblob").unwrap();
+    let hot = Fileblob::create(dir.path().join("hot")).unwrap();
+    let cold = Fileblob::create(dir.path().join("cold")).unwrap();
+    (dir, hot, cold)
+}
+
+async fn get_bytes<B: Blobstore>(ctx: &CoreContext, blobstore: &B, key: &str) -> Option<Bytes> {
+    blobstore
+        .get(ctx.clone(), key.to_string())
+        .await
+        .unwrap()
+        .map(|value| value.into_raw_bytes())
+}
+
+#[fbinit::compat_test]
+async fn read_hot_then_cold(fb: FacebookInit) {
+    let ctx = CoreContext::test_mock(fb);
+    let (_dir, hot, cold) = make_tiers();
+    let tiered = TieredBlob::new(hot.clone(), cold.clone());
+
+    tiered
+        .put(
+            ctx.clone(),
+            "new".to_string(),
+            BlobstoreBytes::from_bytes("new value"),
+        )
+        .await
+        .unwrap();
+    cold.put(
+        ctx.clone(),
+        "old".to_string(),
+        BlobstoreBytes::from_bytes("old value"),
+    )
+    .await
+    .unwrap();
+
+    // New keys are only written to the hot tier
+    assert_eq!(
+        get_bytes(&ctx, &hot, "new").await,
+        Some(Bytes::from("new value"))
+    );
+    assert_eq!(get_bytes(&ctx, &cold, "new").await, None);
+
+    assert_eq!(
+        get_bytes(&ctx, &tiered, "new").await,
+        Some(Bytes::from("new value"))
+    );
+    assert_eq!(
+        get_bytes(&ctx, &tiered, "old").await,
+        Some(Bytes::from("old value"))
+    );
+    assert_eq!(get_bytes(&ctx, &tiered, "missing").await, None);
+
+    // Keys that are already cold are not duplicated in the hot tier
+    tiered
+        .put(
+            ctx.clone(),
+            "old".to_string(),
+            BlobstoreBytes::from_bytes("old value"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(get_bytes(&ctx, &hot, "old").await, None);
+
+    assert!(tiered
+        .is_present(ctx.clone(), "old".to_string())
+        .await
+        .unwrap());
+    assert!(!tiered
+        .is_present(ctx.clone(), "missing".to_string())
+        .await
+        .unwrap());
+}
+
+#[fbinit::compat_test]
+async fn migrate(fb: FacebookInit) {
+    let ctx = CoreContext::test_mock(fb);
+    let (_dir, hot, cold) = make_tiers();
+    let tiered = TieredBlob::new(hot.clone(), cold.clone());
+
+    for key in &["a", "b", "c"] {
+        tiered
+            .put(
+                ctx.clone(),
+                key.to_string(),
+                BlobstoreBytes::from_bytes(format!("value {}", key)),
+            )
+            .await
+            .unwrap();
+    }
+
+    // Nothing is older than the epoch
+    let stats = migrate_to_cold(&ctx, &hot, &cold, (..).into(), 0, 10, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        stats,
+        MigrationStats {
+            enumerated: 3,
+            migrated: 0,
+            migrated_bytes: 0,
+        }
+    );
+
+    // A dry run counts, but leaves everything in place
+    let stats = migrate_to_cold(&ctx, &hot, &cold, (..).into(), i64::MAX, 10, true)
+        .await
+        .unwrap();
+    assert_eq!(stats.migrated, 3);
+    assert_eq!(get_bytes(&ctx, &cold, "a").await, None);
+
+    let stats = migrate_to_cold(&ctx, &hot, &cold, (..).into(), i64::MAX, 10, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        stats,
+        MigrationStats {
+            enumerated: 3,
+            migrated: 3,
+            migrated_bytes: 21,
+        }
+    );
+
+    for key in &["a", "b", "c"] {
+        assert_eq!(get_bytes(&ctx, &hot, key).await, None);
+        assert_eq!(
+            get_bytes(&ctx, &cold, key).await,
+            Some(Bytes::from(format!("value {}", key)))
+        );
+        assert_eq!(
+            get_bytes(&ctx, &tiered, key).await,
+            Some(Bytes::from(format!("value {}", key)))
+        );
+    }
+}
+
+#[derive(Default)]
+struct RecordingTierCheckHandler {
+    keys: Mutex<Vec<String>>,
+}
+
+impl TierCheckHandler for RecordingTierCheckHandler {
+    fn on_both_tiers(&self, _ctx: &CoreContext, key: &str, _hot: &BlobstoreGetData) {
+        self.keys.lock().unwrap().push(key.to_string());
+    }
+}
+
+#[fbinit::compat_test]
+async fn tier_check(fb: FacebookInit) {
+    let ctx = CoreContext::test_mock(fb);
+    let (_dir, hot, cold) = make_tiers();
+    let handler = Arc::new(RecordingTierCheckHandler::default());
+    let check = TierCheckBlobstore::new(hot.clone(), cold.clone(), handler.clone());
+
+    let value = BlobstoreBytes::from_bytes("value");
+    hot.put(ctx.clone(), "hot".to_string(), value.clone())
+        .await
+        .unwrap();
+    cold.put(ctx.clone(), "cold".to_string(), value.clone())
+        .await
+        .unwrap();
+    hot.put(ctx.clone(), "both".to_string(), value.clone())
+        .await
+        .unwrap();
+    cold.put(ctx.clone(), "both".to_string(), value.clone())
+        .await
+        .unwrap();
+
+    for key in &["hot", "cold", "both"] {
+        assert_eq!(
+            get_bytes(&ctx, &check, key).await,
+            Some(Bytes::from("value"))
+        );
+    }
+    assert_eq!(get_bytes(&ctx, &check, "missing").await, None);
+    assert_eq!(*handler.keys.lock().unwrap(), vec!["both".to_string()]);
+}
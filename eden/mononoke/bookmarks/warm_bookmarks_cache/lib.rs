@@ -21,6 +21,8 @@ use cloned::cloned;
 use context::CoreContext;
 use deleted_files_manifest::RootDeletedManifestId;
 use derived_data::BonsaiDerived;
+use diffstat::ChangesetDiffstat;
+use file_classification::RootFileClassification;
 use fsnodes::RootFsnodeId;
 use futures::{
     channel::oneshot,
@@ -29,11 +31,13 @@ use futures::{
     stream::{self, FuturesUnordered, StreamExt, TryStreamExt},
 };
 use futures_ext::BoxFuture as OldBoxFuture;
+use git_types::CommitHandle;
 use itertools::Itertools;
 use lock_ext::RwLockExt;
 use mercurial_derived_data::MappedHgChangesetId;
 use mononoke_types::{ChangesetId, Timestamp};
 use mutable_counters::MutableCounters;
+use skeleton_manifest::RootSkeletonManifestId;
 use slog::{debug, info, warn};
 use stats::prelude::*;
 use tunables::tunables;
@@ -126,6 +130,26 @@ impl<'a> WarmBookmarksCacheBuilder<'a> {
                     &self.ctx,
                 ));
         }
+        if types.contains(CommitHandle::NAME) {
+            self.warmers
+                .push(create_derived_data_warmer::<CommitHandle>(&self.ctx));
+        }
+        if types.contains(RootSkeletonManifestId::NAME) {
+            self.warmers
+                .push(create_derived_data_warmer::<RootSkeletonManifestId>(
+                    &self.ctx,
+                ));
+        }
+        if types.contains(ChangesetDiffstat::NAME) {
+            self.warmers
+                .push(create_derived_data_warmer::<ChangesetDiffstat>(&self.ctx));
+        }
+        if types.contains(RootFileClassification::NAME) {
+            self.warmers
+                .push(create_derived_data_warmer::<RootFileClassification>(
+                    &self.ctx,
+                ));
+        }
 
         Ok(())
     }
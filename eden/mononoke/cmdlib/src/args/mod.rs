@@ -9,7 +9,7 @@ mod cache;
 #[cfg(fbcode_build)]
 mod facebook;
 
-pub use self::cache::{add_cachelib_args, init_cachelib};
+pub use self::cache::{add_cachelib_args, init_cachelib, parse_caching};
 
 use std::collections::{HashMap, HashSet};
 use std::io;
@@ -54,8 +54,6 @@ use crate::helpers::{
 };
 use crate::log;
 
-use self::cache::parse_caching;
-
 const CONFIG_PATH: &str = "mononoke-config-path";
 const REPO_ID: &str = "repo-id";
 const REPO_NAME: &str = "repo-name";
@@ -80,6 +78,7 @@ const WRITE_QPS_ARG: &str = "blobstore-write-qps";
 const READ_CHAOS_ARG: &str = "blobstore-read-chaos-rate";
 const WRITE_CHAOS_ARG: &str = "blobstore-write-chaos-rate";
 const WRITE_ZSTD_ARG: &str = "blobstore-write-zstd-level";
+const WRITE_ZSTD_DICTIONARY_ARG: &str = "blobstore-write-zstd-dictionary-version";
 const MANIFOLD_API_KEY_ARG: &str = "manifold-api-key";
 const CACHELIB_ATTEMPT_ZSTD_ARG: &str = "blobstore-cachelib-attempt-zstd";
 const TEST_INSTANCE_ARG: &str = "test-instance";
@@ -753,6 +752,14 @@ pub fn add_blobstore_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
             .required(false)
             .help("Set the zstd compression level to be used on writes via the packed blobstore (if configured).  Default is None."),
     )
+    .arg(
+        Arg::with_name(WRITE_ZSTD_DICTIONARY_ARG)
+            .long(WRITE_ZSTD_DICTIONARY_ARG)
+            .takes_value(true)
+            .required(false)
+            .requires(WRITE_ZSTD_ARG)
+            .help("Compress writes via the packed blobstore with this version of each repo's trained zstd dictionary, for repos that have it.  Default is None."),
+    )
     .arg(
         Arg::with_name(MANIFOLD_API_KEY_ARG)
             .long(MANIFOLD_API_KEY_ARG)
@@ -1071,6 +1078,12 @@ pub fn parse_blobstore_options<'a>(matches: &ArgMatches<'a>) -> BlobstoreOptions
             .expect("Provided Zstd compression level is not i32")
     });
 
+    let write_zstd_dictionary_version: Option<u32> =
+        matches.value_of(WRITE_ZSTD_DICTIONARY_ARG).map(|v| {
+            v.parse()
+                .expect("Provided Zstd dictionary version is not u32")
+        });
+
     let attempt_zstd: Option<bool> = matches.value_of(CACHELIB_ATTEMPT_ZSTD_ARG).map(|v| {
         v.parse()
             .expect("Provided blobstore-cachelib-attempt-zstd is not bool")
@@ -1080,7 +1093,7 @@ pub fn parse_blobstore_options<'a>(matches: &ArgMatches<'a>) -> BlobstoreOptions
         ChaosOptions::new(read_chaos, write_chaos),
         ThrottleOptions::new(read_qps, write_qps),
         manifold_api_key,
-        PackOptions::new(write_zstd_level),
+        PackOptions::new(write_zstd_level, write_zstd_dictionary_version),
         CachelibBlobstoreOptions::new_lazy(attempt_zstd),
     )
 }
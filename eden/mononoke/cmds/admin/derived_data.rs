@@ -15,7 +15,7 @@ use clap::{App, Arg, ArgMatches, SubCommand};
 use cmdlib::{args, helpers::csid_resolve};
 use context::CoreContext;
 use derived_data::BonsaiDerived;
-use derived_data_utils::{derived_data_utils, POSSIBLE_DERIVED_TYPES};
+use derived_data_utils::{derived_data_status, derived_data_utils, POSSIBLE_DERIVED_TYPES};
 use fbinit::FacebookInit;
 use fsnodes::RootFsnodeId;
 use futures::{
@@ -38,9 +38,11 @@ use crate::error::SubcommandError;
 pub const DERIVED_DATA: &str = "derived-data";
 const SUBCOMMAND_EXISTS: &str = "exists";
 const SUBCOMMAND_VERIFY_MANIFESTS: &str = "verify-manifests";
+const SUBCOMMAND_STATUS: &str = "status";
 
 const ARG_HASH_OR_BOOKMARK: &str = "hash-or-bookmark";
 const ARG_TYPE: &str = "type";
+const ARG_LIMIT: &str = "limit";
 
 const MANIFEST_DERIVED_DATA_TYPES: &'static [&'static str] = &[
     RootFsnodeId::NAME,
@@ -87,6 +89,31 @@ pub fn build_subcommand<'a, 'b>() -> App<'a, 'b> {
                         .required(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name(SUBCOMMAND_STATUS)
+                .about("report how far derived data is from being derived for a commit")
+                .arg(
+                    Arg::with_name(ARG_TYPE)
+                        .help("types of derived data, defaults to the ones enabled for the repo")
+                        .long(ARG_TYPE)
+                        .takes_value(true)
+                        .multiple(true)
+                        .possible_values(POSSIBLE_DERIVED_TYPES),
+                )
+                .arg(
+                    Arg::with_name(ARG_LIMIT)
+                        .help("maximum number of underived ancestors to look for")
+                        .long(ARG_LIMIT)
+                        .takes_value(true)
+                        .default_value("100000"),
+                )
+                .arg(
+                    Arg::with_name(ARG_HASH_OR_BOOKMARK)
+                        .help("(hg|bonsai) commit hash or bookmark")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
 }
 
 pub fn subcommand_derived_data(
@@ -139,6 +166,31 @@ pub fn subcommand_derived_data(
             }
             .boxed()
         }
+        (SUBCOMMAND_STATUS, Some(arg_matches)) => {
+            let hash_or_bookmark = arg_matches
+                .value_of(ARG_HASH_OR_BOOKMARK)
+                .map(|m| m.to_string())
+                .unwrap();
+
+            let derived_data_types: Option<Vec<String>> = arg_matches
+                .values_of(ARG_TYPE)
+                .map(|matches| matches.map(|ty| ty.to_string()).collect());
+            let limit = args::get_u64_opt(arg_matches, ARG_LIMIT);
+
+            async move {
+                let repo = repo.compat().await?;
+                let derived_data_types = derived_data_types.unwrap_or_else(|| {
+                    repo.get_derived_data_config()
+                        .derived_data_types
+                        .iter()
+                        .cloned()
+                        .collect()
+                });
+                print_derived_data_status(ctx, repo, derived_data_types, hash_or_bookmark, limit)
+                    .await
+            }
+            .boxed()
+        }
         _ => async move { Err(SubcommandError::InvalidArgs) }.boxed(),
     }
 }
@@ -174,6 +226,40 @@ async fn check_derived_data_exists(
     Ok(())
 }
 
+async fn print_derived_data_status(
+    ctx: CoreContext,
+    repo: BlobRepo,
+    derived_data_types: Vec<String>,
+    hash_or_bookmark: String,
+    limit: Option<u64>,
+) -> Result<(), SubcommandError> {
+    let cs_id = csid_resolve(ctx.clone(), repo.clone(), hash_or_bookmark)
+        .compat()
+        .await?;
+    let statuses = derived_data_status(&ctx, &repo, cs_id, &derived_data_types, limit).await?;
+
+    for status in statuses {
+        let latest = match status.latest_derived_ancestor {
+            Some(cs_id) => cs_id.to_string(),
+            None if status.limit_reached => "unknown".to_string(),
+            None => "none".to_string(),
+        };
+        let at_least = if status.limit_reached { ">=" } else { "" };
+        println!(
+            "{}{}: latest derived ancestor {}, underived {}{}, estimated derivations {}{}",
+            status.name,
+            if status.enabled { "" } else { " (disabled)" },
+            latest,
+            at_least,
+            status.underived_count,
+            at_least,
+            status.estimated_derivations,
+        );
+    }
+
+    Ok(())
+}
+
 async fn verify_manifests(
     ctx: CoreContext,
     repo: BlobRepo,
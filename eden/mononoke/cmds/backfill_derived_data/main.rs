@@ -21,14 +21,15 @@ use clap::{Arg, ArgMatches, SubCommand};
 use cloned::cloned;
 use cmdlib::{args, helpers};
 use context::CoreContext;
-use derived_data::BonsaiDerived;
+use derived_data::{mapping_version, BonsaiDerived};
 use derived_data_utils::{
-    derived_data_utils, derived_data_utils_unsafe, DerivedUtils, POSSIBLE_DERIVED_TYPES,
+    derived_data_utils, derived_data_utils_unsafe, derived_data_with_dependencies,
+    mapping_migration, DerivedUtils, MappingDifference, POSSIBLE_DERIVED_TYPES,
 };
 use fbinit::FacebookInit;
 use fsnodes::RootFsnodeId;
 use futures::{
-    compat::Future01CompatExt,
+    compat::{Future01CompatExt, Stream01CompatExt},
     future::{self, try_join},
     stream::{self, StreamExt, TryStreamExt},
 };
@@ -38,15 +39,25 @@ use futures_stats::Timed;
 use futures_stats::TimedFutureExt;
 use metaconfig_types::DerivedDataConfig;
 use mononoke_types::{ChangesetId, DateTime};
-use slog::{info, Logger};
+use mutable_counters::SqlMutableCounters;
+use revset::RangeNodeStream;
+use slog::{info, warn, Logger};
 use stats::prelude::*;
-use std::{collections::HashMap, fs, path::Path, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
 use time_ext::DurationExt;
 
 mod warmup;
 
 mod dry_run;
 
+mod parallel;
+
 define_stats! {
     prefix = "mononoke.derived_data";
     oldest_underived_secs: dynamic_singleton_counter("{}.oldest_underived_secs", (reponame: String)),
@@ -63,13 +74,22 @@ const ARG_REGENERATE: &str = "regenerate";
 const ARG_PREFETCHED_COMMITS_PATH: &str = "prefetched-commits-path";
 const ARG_CHANGESET: &str = "changeset";
 const ARG_USE_SHARED_LEASES: &str = "use-shared-leases";
+const ARG_INVALID_FROM: &str = "invalid-from";
+const ARG_INVALID_TO: &str = "invalid-to";
+const ARG_FAIL_ON_DIFFERENCE: &str = "fail-on-difference";
+const ARG_PARALLEL: &str = "parallel";
+const ARG_CONCURRENCY: &str = "concurrency";
+const ARG_BATCH_SIZE: &str = "batch-size";
 
 const SUBCOMMAND_BACKFILL: &str = "backfill";
 const SUBCOMMAND_TAIL: &str = "tail";
 const SUBCOMMAND_PREFETCH_COMMITS: &str = "prefetch-commits";
 const SUBCOMMAND_SINGLE: &str = "single";
+const SUBCOMMAND_REDERIVE: &str = "rederive";
 
 const CHUNK_SIZE: usize = 4096;
+const DEFAULT_CONCURRENCY: usize = 10;
+const DEFAULT_BATCH_SIZE: usize = 128;
 
 /// Derived data types that are permitted to access redacted files. This list
 /// should be limited to those data types that need access to the content of
@@ -150,6 +170,34 @@ fn main(fb: FacebookInit) -> Result<(), Error> {
                         .help(
                             "Derives all data but writes it to memory. Note - requires --readonly",
                         ),
+                )
+                .arg(
+                    Arg::with_name(ARG_PARALLEL)
+                        .long(ARG_PARALLEL)
+                        .takes_value(false)
+                        .required(false)
+                        .conflicts_with(ARG_DRY_RUN)
+                        .help(
+                            "derive independent stacks of changesets concurrently, together with \
+                             the derived data types this type depends on, and save progress in \
+                             mutable counters so that an interrupted backfill resumes from there",
+                        ),
+                )
+                .arg(
+                    Arg::with_name(ARG_CONCURRENCY)
+                        .long(ARG_CONCURRENCY)
+                        .takes_value(true)
+                        .required(false)
+                        .requires(ARG_PARALLEL)
+                        .help("number of stacks that are derived concurrently"),
+                )
+                .arg(
+                    Arg::with_name(ARG_BATCH_SIZE)
+                        .long(ARG_BATCH_SIZE)
+                        .takes_value(true)
+                        .required(false)
+                        .requires(ARG_PARALLEL)
+                        .help("number of changesets of a stack whose mappings are written at once"),
                 ),
         )
         .subcommand(
@@ -214,6 +262,44 @@ fn main(fb: FacebookInit) -> Result<(), Error> {
                         .possible_values(POSSIBLE_DERIVED_TYPES)
                         .help("derived data type for which backfill will be run"),
                 ),
+        )
+        .subcommand(
+            SubCommand::with_name(SUBCOMMAND_REDERIVE)
+                .about(
+                    "re-derive data for a range of changesets and their descendants into the \
+                     next version of the mapping, and compare it with the current version",
+                )
+                .arg(
+                    Arg::with_name(ARG_DERIVED_DATA_TYPE)
+                        .required(true)
+                        .index(1)
+                        .possible_values(POSSIBLE_DERIVED_TYPES)
+                        .help("derived data type which will be re-derived"),
+                )
+                .arg(
+                    Arg::with_name(ARG_INVALID_FROM)
+                        .long(ARG_INVALID_FROM)
+                        .takes_value(true)
+                        .required(true)
+                        .help("oldest invalid changeset by {hg|bonsai} hash or bookmark"),
+                )
+                .arg(
+                    Arg::with_name(ARG_INVALID_TO)
+                        .long(ARG_INVALID_TO)
+                        .takes_value(true)
+                        .required(true)
+                        .help("newest invalid changeset by {hg|bonsai} hash or bookmark"),
+                )
+                .arg(
+                    Arg::with_name(ARG_FAIL_ON_DIFFERENCE)
+                        .long(ARG_FAIL_ON_DIFFERENCE)
+                        .takes_value(false)
+                        .required(false)
+                        .help(
+                            "exit with an error if any changeset has different derived data in \
+                             the new version of the mapping",
+                        ),
+                )
         );
     let matches = app.get_matches();
     args::init_cachelib(fb, &matches, None);
@@ -271,11 +357,13 @@ async fn run_subcmd<'a>(
             // Backfill is used when when a derived data type is not enabled yet, and so
             // any attempt to call BonsaiDerived::derive() fails. However calling
             // BonsaiDerived::derive() might be useful, and so the lines below explicitly
-            // enable `derived_data_type` to allow calling BonsaiDerived::derive() if necessary.
+            // enable `derived_data_type` and the types it depends on to allow calling
+            // BonsaiDerived::derive() if necessary.
+            let derived_data_types = derived_data_with_dependencies(&derived_data_type)?;
             let mut repo = repo.dangerous_override(|mut derived_data_config: DerivedDataConfig| {
                 derived_data_config
                     .derived_data_types
-                    .insert(derived_data_type.clone());
+                    .extend(derived_data_types.iter().map(|ty| ty.to_string()));
                 derived_data_config
             });
             info!(
@@ -286,6 +374,64 @@ async fn run_subcmd<'a>(
             let mut changesets = parse_serialized_commits(prefetched_commits_path)?;
             changesets.sort_by_key(|cs_entry| cs_entry.gen);
 
+            if sub_m.is_present(ARG_PARALLEL) {
+                let concurrency = sub_m
+                    .value_of(ARG_CONCURRENCY)
+                    .map(|concurrency| concurrency.parse::<usize>())
+                    .transpose()?
+                    .unwrap_or(DEFAULT_CONCURRENCY);
+                let batch_size = sub_m
+                    .value_of(ARG_BATCH_SIZE)
+                    .map(|batch_size| batch_size.parse::<usize>())
+                    .transpose()?
+                    .unwrap_or(DEFAULT_BATCH_SIZE);
+
+                let mutable_counters = args::open_sql::<SqlMutableCounters>(fb, &matches)
+                    .compat()
+                    .await?;
+                let checkpoint = parallel::Checkpoint::new(
+                    Arc::new(mutable_counters),
+                    repo.get_repoid(),
+                    &derived_data_type,
+                    &changesets,
+                );
+                let skip = match checkpoint.load(&ctx).await? {
+                    Some(resume_from) if resume_from > skip => {
+                        info!(ctx.logger(), "resuming from changeset {}", resume_from);
+                        resume_from
+                    }
+                    _ => skip,
+                };
+
+                let iter = changesets.into_iter().skip(skip);
+                let changesets: Vec<_> = match maybe_limit {
+                    Some(limit) => iter.take(limit).collect(),
+                    None => iter.collect(),
+                };
+
+                let derived_utils = derived_data_types
+                    .into_iter()
+                    .map(|ty| derived_data_utils_unsafe(repo.clone(), ty))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if regenerate {
+                    let csids: Vec<_> = changesets.iter().map(|entry| entry.cs_id).collect();
+                    if let Some(derived_utils) = derived_utils.last() {
+                        derived_utils.regenerate(&csids);
+                    }
+                }
+
+                return parallel::backfill_in_parallel(
+                    &ctx,
+                    &repo,
+                    &derived_utils,
+                    changesets,
+                    concurrency,
+                    batch_size,
+                    Some((&checkpoint, skip)),
+                )
+                .await;
+            }
+
             let mut cleaner = None;
 
             if sub_m.is_present(ARG_DRY_RUN) {
@@ -407,6 +553,45 @@ async fn run_subcmd<'a>(
                 .await?;
             subcommand_single(&ctx, &repo, csid, types).await
         }
+        (SUBCOMMAND_REDERIVE, Some(sub_m)) => {
+            let derived_data_type = sub_m
+                .value_of(ARG_DERIVED_DATA_TYPE)
+                .ok_or_else(|| format_err!("missing required argument: {}", ARG_DERIVED_DATA_TYPE))?
+                .to_string();
+            let invalid_from = sub_m
+                .value_of(ARG_INVALID_FROM)
+                .ok_or_else(|| format_err!("missing required argument: {}", ARG_INVALID_FROM))?
+                .to_string();
+            let invalid_to = sub_m
+                .value_of(ARG_INVALID_TO)
+                .ok_or_else(|| format_err!("missing required argument: {}", ARG_INVALID_TO))?
+                .to_string();
+
+            let repo = open_repo_maybe_unredacted(fb, &logger, &matches, &derived_data_type)
+                .compat()
+                .await?;
+            let (invalid_from, invalid_to) = try_join(
+                helpers::csid_resolve(ctx.clone(), repo.clone(), invalid_from).compat(),
+                helpers::csid_resolve(ctx.clone(), repo.clone(), invalid_to).compat(),
+            )
+            .await?;
+
+            let new_version = mapping_version(&repo, &derived_data_type) + 1;
+            let differences =
+                subcommand_rederive(&ctx, &repo, &derived_data_type, invalid_from, invalid_to)
+                    .await?;
+            // Differences are expected when re-deriving fixes invalid data,
+            // and have already been logged
+            if differences.is_empty() || !sub_m.is_present(ARG_FAIL_ON_DIFFERENCE) {
+                Ok(())
+            } else {
+                Err(format_err!(
+                    "{} changesets have different derived data in mapping version {}",
+                    differences.len(),
+                    new_version
+                ))
+            }
+        }
         (name, _) => Err(format_err!("unhandled subcommand: {}", name)),
     }
 }
@@ -642,14 +827,154 @@ async fn subcommand_single(
         .await
 }
 
+/// Re-derive data for changesets that are descendants of `invalid_from` and
+/// ancestors of `invalid_to` into the next version of the mapping, together
+/// with their descendants that are ancestors of a publishing bookmark, and
+/// compare the result with the version of the mapping that is currently in
+/// use.
+///
+/// Mapping entries of the parents of re-derived changesets are copied from
+/// the current version, so that re-derivation stops there. Readers that are
+/// switched over to the new version fall back to the current version for
+/// every changeset that was not re-derived, so any descendants of the
+/// invalid range that are not reachable from a bookmark keep their old data.
+async fn subcommand_rederive(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    derived_data_type: &str,
+    invalid_from: ChangesetId,
+    invalid_to: ChangesetId,
+) -> Result<Vec<MappingDifference>, Error> {
+    let current_version = mapping_version(repo, derived_data_type);
+    let new_version = current_version + 1;
+    let migration = mapping_migration(repo, derived_data_type, current_version, new_version)?;
+    let repo = repo.dangerous_override(|_| Arc::new(DummyLease {}) as Arc<dyn LeaseOps>);
+    let changeset_fetcher = repo.get_changeset_fetcher();
+
+    let range = |head| {
+        RangeNodeStream::new(ctx.clone(), changeset_fetcher.clone(), invalid_from, head)
+            .compat()
+            .try_collect::<Vec<_>>()
+    };
+    let invalid = range(invalid_to).await?;
+    if invalid.is_empty() {
+        return Err(format_err!(
+            "{} is not an ancestor of {}",
+            invalid_from,
+            invalid_to
+        ));
+    }
+
+    // Everything that descends from an invalid changeset was derived from
+    // invalid data too. All of them are descendants of `invalid_from`, so
+    // the ones that matter are those between it and the bookmarks.
+    let heads = repo
+        .bookmarks()
+        .list(
+            ctx.clone(),
+            Freshness::MostRecent,
+            &BookmarkPrefix::empty(),
+            BookmarkKind::ALL_PUBLISHING,
+            &BookmarkPagination::FromStart,
+            std::u64::MAX,
+        )
+        .map_ok(|(_name, csid)| csid)
+        .try_collect::<Vec<_>>()
+        .await?;
+    let mut to_rederive: HashSet<_> = invalid.into_iter().collect();
+    let mut rederive_heads = vec![invalid_to];
+    for head in heads {
+        let descendants = range(head).await?;
+        if !descendants.is_empty() {
+            to_rederive.extend(descendants);
+            rederive_heads.push(head);
+        }
+    }
+
+    let parents: Vec<Vec<ChangesetId>> = stream::iter(to_rederive.clone())
+        .map(|csid| changeset_fetcher.get_parents(ctx.clone(), csid).compat())
+        .buffer_unordered(100)
+        .try_collect()
+        .await?;
+    let mut valid: Vec<_> = parents
+        .into_iter()
+        .flatten()
+        .filter(|csid| !to_rederive.contains(csid))
+        .collect();
+    valid.sort();
+    valid.dedup();
+
+    let mut copied_count = 0;
+    for chunk in valid.chunks(CHUNK_SIZE) {
+        copied_count += migration.copy(ctx, chunk.to_vec()).await?;
+    }
+    info!(
+        ctx.logger(),
+        "copied {}/{} mapping entries from version {} to version {}",
+        copied_count,
+        valid.len(),
+        current_version,
+        new_version
+    );
+
+    // Deriving the heads derives everything between them and the copied
+    // parents, in topological order
+    let (stats, res) = async {
+        for csid in &rederive_heads {
+            migration.rederive(ctx, &repo, *csid).await?;
+        }
+        Result::<_, Error>::Ok(())
+    }
+    .timed()
+    .await;
+    res?;
+    info!(
+        ctx.logger(),
+        "re-derived {} changesets into version {} in {:?}",
+        to_rederive.len(),
+        new_version,
+        stats.completion_time
+    );
+
+    let mut to_rederive: Vec<_> = to_rederive.into_iter().collect();
+    to_rederive.sort();
+    let mut differences = vec![];
+    for chunk in to_rederive.chunks(CHUNK_SIZE) {
+        differences.extend(migration.verify(ctx, chunk.to_vec()).await?);
+    }
+    for difference in &differences {
+        warn!(
+            ctx.logger(),
+            "{} differs: {:?} in version {}, {:?} in version {}",
+            difference.csid,
+            difference.old,
+            current_version,
+            difference.new,
+            new_version
+        );
+    }
+    info!(
+        ctx.logger(),
+        "{}/{} changesets have different derived data. Set the mapping version of {} to {} \
+         in the repo config to switch readers over",
+        differences.len(),
+        to_rederive.len(),
+        migration.name(),
+        new_version
+    );
+    Ok(differences)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use blobrepo_hg::BlobRepoHg;
     use blobstore::{Blobstore, BlobstoreBytes, BlobstoreGetData};
+    use derived_data::{exact_versioned_mapping_blobstore, BonsaiDerivedMapping};
     use fixtures::linear;
     use futures::future::{BoxFuture, FutureExt};
     use mercurial_types::HgChangesetId;
+    use skeleton_manifest::{RootSkeletonManifestId, RootSkeletonManifestMapping};
     use std::{
         str::FromStr,
         sync::atomic::{AtomicUsize, Ordering},
@@ -705,6 +1030,91 @@ mod tests {
         Ok(())
     }
 
+    #[fbinit::compat_test]
+    async fn test_rederive(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = linear::getrepo(fb).await;
+        let master = resolve_cs_id(&ctx, &repo, "master").await?;
+        RootSkeletonManifestId::derive(ctx.clone(), repo.clone(), master)
+            .compat()
+            .await?;
+
+        let fetcher = repo.get_changeset_fetcher();
+        let parent = fetcher.get_parents(ctx.clone(), master).compat().await?[0];
+        let grandparent = fetcher.get_parents(ctx.clone(), parent).compat().await?[0];
+        let great_grandparent = fetcher
+            .get_parents(ctx.clone(), grandparent)
+            .compat()
+            .await?[0];
+
+        let differences =
+            subcommand_rederive(&ctx, &repo, RootSkeletonManifestId::NAME, parent, parent).await?;
+        assert_eq!(differences, vec![]);
+
+        // The invalid changeset and its descendant on master were derived
+        // into the new version, and the parent of the range was copied
+        let new_mapping = RootSkeletonManifestMapping::new(exact_versioned_mapping_blobstore(
+            repo.get_blobstore().boxed(),
+            1,
+        ));
+        let in_new_version = new_mapping
+            .get(
+                ctx.clone(),
+                vec![master, parent, grandparent, great_grandparent],
+            )
+            .compat()
+            .await?;
+        let mut in_new_version: Vec<_> = in_new_version.into_iter().map(|(csid, _)| csid).collect();
+        in_new_version.sort();
+        let mut expected = vec![master, parent, grandparent];
+        expected.sort();
+        assert_eq!(in_new_version, expected);
+
+        // Readers that are switched over fall back to the old version for
+        // the other changesets
+        let repo = repo.dangerous_override(|mut derived_data_config: DerivedDataConfig| {
+            derived_data_config
+                .mapping_versions
+                .insert(RootSkeletonManifestId::NAME.to_string(), 1);
+            derived_data_config
+        });
+        for csid in &[master, parent, grandparent, great_grandparent] {
+            assert!(RootSkeletonManifestId::is_derived(&ctx, &repo, csid).await?);
+        }
+
+        // Another migration goes to the next version, and still falls back
+        // through all the versions before it
+        let differences =
+            subcommand_rederive(&ctx, &repo, RootSkeletonManifestId::NAME, master, master).await?;
+        assert_eq!(differences, vec![]);
+        let repo = repo.dangerous_override(|mut derived_data_config: DerivedDataConfig| {
+            derived_data_config
+                .mapping_versions
+                .insert(RootSkeletonManifestId::NAME.to_string(), 2);
+            derived_data_config
+        });
+        for csid in &[master, parent, grandparent, great_grandparent] {
+            assert!(RootSkeletonManifestId::is_derived(&ctx, &repo, csid).await?);
+        }
+
+        // Types that other types are derived from can't be migrated on their
+        // own
+        assert!(
+            subcommand_rederive(&ctx, &repo, RootUnodeManifestId::NAME, master, master)
+                .await
+                .is_err()
+        );
+
+        // Data that isn't stored in the mapping can't be migrated
+        assert!(
+            subcommand_rederive(&ctx, &repo, BlameRoot::NAME, master, master)
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+
     #[fbinit::test]
     fn test_backfill_data_latest(fb: FacebookInit) -> Result<(), Error> {
         let mut runtime = Runtime::new()?;
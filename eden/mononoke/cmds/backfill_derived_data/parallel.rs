@@ -0,0 +1,464 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use blobrepo::BlobRepo;
+use changesets::ChangesetEntry;
+use context::CoreContext;
+use derived_data_utils::DerivedUtils;
+use futures::{
+    compat::Future01CompatExt,
+    stream::{FuturesUnordered, StreamExt},
+};
+use mononoke_types::{hash::Context as HashContext, ChangesetId, RepositoryId};
+use mutable_counters::MutableCounters;
+use slog::info;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use crate::warmup;
+
+/// A linear chain of changesets, in which every changeset but the first one
+/// is the only child of the previous changeset. Changesets of a stack have to
+/// be derived one after another, but different stacks can be derived
+/// concurrently once the stacks that they depend on are derived.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Stack {
+    /// Indices of the changesets of this stack, in topological order
+    changesets: Vec<usize>,
+    /// Number of stacks that contain parents of the first changeset
+    parent_count: usize,
+    /// Stacks whose first changeset is a child of the last changeset of this stack
+    children: Vec<usize>,
+}
+
+/// Splits changesets into stacks. Changesets must be sorted topologically,
+/// and parents that are not in `changesets` are assumed to be derived
+/// already.
+pub(crate) fn split_into_stacks(changesets: &[ChangesetEntry]) -> Vec<Stack> {
+    let index: HashMap<ChangesetId, usize> = changesets
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| (entry.cs_id, i))
+        .collect();
+
+    let mut children_count = vec![0; changesets.len()];
+    for entry in changesets {
+        for parent in &entry.parents {
+            if let Some(parent) = index.get(parent) {
+                children_count[*parent] += 1;
+            }
+        }
+    }
+
+    let mut stacks: Vec<Stack> = Vec::new();
+    let mut stack_of = Vec::with_capacity(changesets.len());
+    for (i, entry) in changesets.iter().enumerate() {
+        let parents: Vec<usize> = entry
+            .parents
+            .iter()
+            .filter_map(|parent| index.get(parent).copied())
+            .collect();
+        match parents.as_slice() {
+            [parent] if children_count[*parent] == 1 => {
+                let stack = stack_of[*parent];
+                stacks[stack].changesets.push(i);
+                stack_of.push(stack);
+            }
+            _ => {
+                let stack = stacks.len();
+                let mut parent_stacks: Vec<usize> =
+                    parents.iter().map(|parent| stack_of[*parent]).collect();
+                parent_stacks.sort();
+                parent_stacks.dedup();
+                for parent_stack in &parent_stacks {
+                    stacks[*parent_stack].children.push(stack);
+                }
+                stacks.push(Stack {
+                    changesets: vec![i],
+                    parent_count: parent_stacks.len(),
+                    children: Vec::new(),
+                });
+                stack_of.push(stack);
+            }
+        }
+    }
+    stacks
+}
+
+/// Progress of a parallel backfill, stored in mutable counters as the
+/// number of changesets from the start of the list of changesets to
+/// backfill that are all derived. The counter is specific to the list, so
+/// a backfill of a different list starts from the beginning.
+pub(crate) struct Checkpoint {
+    mutable_counters: Arc<dyn MutableCounters>,
+    repo_id: RepositoryId,
+    name: String,
+}
+
+impl Checkpoint {
+    pub(crate) fn new(
+        mutable_counters: Arc<dyn MutableCounters>,
+        repo_id: RepositoryId,
+        derived_data_type: &str,
+        changesets: &[ChangesetEntry],
+    ) -> Self {
+        let mut context = HashContext::new(b"backfill_derived_data_checkpoint");
+        for entry in changesets {
+            context.update(entry.cs_id);
+        }
+        // Counter names are limited to 128 characters, and a prefix of the
+        // hash is plenty to tell lists apart
+        let list_hash = context.finish().to_hex();
+        Self {
+            mutable_counters,
+            repo_id,
+            name: format!(
+                "backfill_derived_data.{}.{}",
+                derived_data_type,
+                &list_hash[..16]
+            ),
+        }
+    }
+
+    pub(crate) async fn load(&self, ctx: &CoreContext) -> Result<Option<usize>, Error> {
+        let value = self
+            .mutable_counters
+            .get_counter(ctx.clone(), self.repo_id, &self.name)
+            .compat()
+            .await?;
+        Ok(value.map(|value| value as usize))
+    }
+
+    async fn save(&self, ctx: &CoreContext, value: usize) -> Result<(), Error> {
+        self.mutable_counters
+            .set_counter(ctx.clone(), self.repo_id, &self.name, value as i64, None)
+            .compat()
+            .await?;
+        Ok(())
+    }
+}
+
+/// Backfills derived data for changesets, deriving independent stacks of
+/// them concurrently.
+///
+/// `derived_utils` are derived in order for every batch of changesets, so
+/// dependencies of a derived data type must come before it. If a checkpoint
+/// is given, it is updated to `skip` plus the number of changesets from the
+/// start of `changesets` that are all derived.
+pub(crate) async fn backfill_in_parallel(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    derived_utils: &[Arc<dyn DerivedUtils>],
+    changesets: Vec<ChangesetEntry>,
+    concurrency: usize,
+    batch_size: usize,
+    checkpoint: Option<(&Checkpoint, usize)>,
+) -> Result<(), Error> {
+    let stacks = split_into_stacks(&changesets);
+    info!(
+        ctx.logger(),
+        "deriving {} changesets in {} stacks",
+        changesets.len(),
+        stacks.len()
+    );
+
+    let mut parents_left: Vec<usize> = stacks.iter().map(|stack| stack.parent_count).collect();
+    let mut ready: VecDeque<usize> = (0..stacks.len())
+        .filter(|stack| parents_left[*stack] == 0)
+        .collect();
+    let mut derived = vec![false; changesets.len()];
+    let mut derived_prefix = 0;
+    let mut running = FuturesUnordered::new();
+
+    loop {
+        while running.len() < concurrency {
+            match ready.pop_front() {
+                Some(stack) => {
+                    let (batch, end) = stack_batch(&changesets, &stacks[stack], 0, batch_size);
+                    running.push(derive_stack_batch(
+                        ctx,
+                        repo,
+                        derived_utils,
+                        batch,
+                        stack,
+                        0,
+                        end,
+                    ));
+                }
+                None => break,
+            }
+        }
+
+        let (stack, start, end) = match running.next().await {
+            Some(res) => res?,
+            None => break,
+        };
+
+        for i in &stacks[stack].changesets[start..end] {
+            derived[*i] = true;
+        }
+        let prev_derived_prefix = derived_prefix;
+        while derived_prefix < derived.len() && derived[derived_prefix] {
+            derived_prefix += 1;
+        }
+        if derived_prefix != prev_derived_prefix {
+            info!(
+                ctx.logger(),
+                "{}/{} changesets derived",
+                derived_prefix,
+                changesets.len()
+            );
+            if let Some((checkpoint, skip)) = checkpoint {
+                checkpoint.save(ctx, skip + derived_prefix).await?;
+            }
+        }
+
+        if end < stacks[stack].changesets.len() {
+            let start = end;
+            let (batch, end) = stack_batch(&changesets, &stacks[stack], start, batch_size);
+            running.push(derive_stack_batch(
+                ctx,
+                repo,
+                derived_utils,
+                batch,
+                stack,
+                start,
+                end,
+            ));
+        } else {
+            for child in &stacks[stack].children {
+                parents_left[*child] -= 1;
+                if parents_left[*child] == 0 {
+                    ready.push_back(*child);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the changesets of the batch of a stack that starts at `start`,
+/// and the end of the batch.
+fn stack_batch(
+    changesets: &[ChangesetEntry],
+    stack: &Stack,
+    start: usize,
+    batch_size: usize,
+) -> (Vec<ChangesetId>, usize) {
+    let end = std::cmp::min(start + batch_size, stack.changesets.len());
+    let batch = stack.changesets[start..end]
+        .iter()
+        .map(|i| changesets[*i].cs_id)
+        .collect();
+    (batch, end)
+}
+
+/// Derives a batch of changesets of a stack, returning the stack and the
+/// bounds of the batch.
+async fn derive_stack_batch(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    derived_utils: &[Arc<dyn DerivedUtils>],
+    batch: Vec<ChangesetId>,
+    stack: usize,
+    start: usize,
+    end: usize,
+) -> Result<(usize, usize, usize), Error> {
+    for derived_utils in derived_utils {
+        let pending = derived_utils
+            .pending(ctx.clone(), repo.clone(), batch.clone())
+            .compat()
+            .await?;
+        if pending.is_empty() {
+            continue;
+        }
+        warmup::warmup(ctx, repo, &derived_utils.name().to_string(), &pending).await?;
+        derived_utils
+            .backfill_batch_dangerous(ctx.clone(), repo.clone(), pending)
+            .compat()
+            .await?;
+    }
+
+    Ok((stack, start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blame::BlameRoot;
+    use derived_data::BonsaiDerived;
+    use derived_data_utils::derived_data_utils;
+    use fbinit::FacebookInit;
+    use mononoke_types_mocks::changesetid::{
+        FIVES_CSID, FOURS_CSID, ONES_CSID, SIXES_CSID, THREES_CSID, TWOS_CSID,
+    };
+    use mutable_counters::SqlMutableCounters;
+    use sql_construct::SqlConstruct;
+    use tests_utils::CreateCommitContext;
+    use unodes::RootUnodeManifestId;
+
+    fn entry(cs_id: ChangesetId, parents: Vec<ChangesetId>, gen: u64) -> ChangesetEntry {
+        ChangesetEntry {
+            repo_id: RepositoryId::new(0),
+            cs_id,
+            parents,
+            gen,
+        }
+    }
+
+    #[test]
+    fn test_split_into_stacks() {
+        // 1 - 2 - 3 - 6
+        //  \         /
+        //   4 ----- 5
+        let changesets = vec![
+            entry(ONES_CSID, vec![], 1),
+            entry(TWOS_CSID, vec![ONES_CSID], 2),
+            entry(FOURS_CSID, vec![ONES_CSID], 2),
+            entry(THREES_CSID, vec![TWOS_CSID], 3),
+            entry(FIVES_CSID, vec![FOURS_CSID], 3),
+            entry(SIXES_CSID, vec![THREES_CSID, FIVES_CSID], 4),
+        ];
+
+        assert_eq!(
+            split_into_stacks(&changesets),
+            vec![
+                Stack {
+                    changesets: vec![0],
+                    parent_count: 0,
+                    children: vec![1, 2],
+                },
+                Stack {
+                    changesets: vec![1, 3],
+                    parent_count: 1,
+                    children: vec![3],
+                },
+                Stack {
+                    changesets: vec![2, 4],
+                    parent_count: 1,
+                    children: vec![3],
+                },
+                Stack {
+                    changesets: vec![5],
+                    parent_count: 2,
+                    children: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_into_stacks_parents_outside() {
+        // Parents outside of the changesets are derived already, so 2 and 3
+        // don't depend on anything
+        let changesets = vec![
+            entry(TWOS_CSID, vec![ONES_CSID], 2),
+            entry(THREES_CSID, vec![ONES_CSID], 2),
+            entry(FOURS_CSID, vec![TWOS_CSID], 3),
+        ];
+
+        assert_eq!(
+            split_into_stacks(&changesets),
+            vec![
+                Stack {
+                    changesets: vec![0, 2],
+                    parent_count: 0,
+                    children: vec![],
+                },
+                Stack {
+                    changesets: vec![1],
+                    parent_count: 0,
+                    children: vec![],
+                },
+            ]
+        );
+    }
+
+    #[fbinit::compat_test]
+    async fn test_backfill_in_parallel(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = blobrepo_factory::new_memblob_empty(None)?;
+        let root = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("file", "root")
+            .commit()
+            .await?;
+        let left = CreateCommitContext::new(&ctx, &repo, vec![root])
+            .add_file("file", "left")
+            .commit()
+            .await?;
+        let right = CreateCommitContext::new(&ctx, &repo, vec![root])
+            .add_file("other", "right")
+            .commit()
+            .await?;
+        let merge = CreateCommitContext::new(&ctx, &repo, vec![left, right])
+            .commit()
+            .await?;
+        let csids = vec![root, left, right, merge];
+
+        let changeset_fetcher = repo.get_changeset_fetcher();
+        let mut changesets = vec![];
+        for cs_id in &csids {
+            let parents = changeset_fetcher
+                .get_parents(ctx.clone(), *cs_id)
+                .compat()
+                .await?;
+            let gen = changeset_fetcher
+                .get_generation_number(ctx.clone(), *cs_id)
+                .compat()
+                .await?;
+            changesets.push(ChangesetEntry {
+                repo_id: repo.get_repoid(),
+                cs_id: *cs_id,
+                parents,
+                gen: gen.value(),
+            });
+        }
+
+        // Blame depends on unodes, so they are derived first
+        let derived_utils = vec![
+            derived_data_utils(repo.clone(), RootUnodeManifestId::NAME)?,
+            derived_data_utils(repo.clone(), BlameRoot::NAME)?,
+        ];
+        let mutable_counters = Arc::new(SqlMutableCounters::with_sqlite_in_memory()?);
+        let checkpoint = Checkpoint::new(
+            mutable_counters.clone(),
+            repo.get_repoid(),
+            BlameRoot::NAME,
+            &changesets,
+        );
+        let other_checkpoint = Checkpoint::new(
+            mutable_counters,
+            repo.get_repoid(),
+            BlameRoot::NAME,
+            &changesets[1..],
+        );
+        backfill_in_parallel(
+            &ctx,
+            &repo,
+            &derived_utils,
+            changesets,
+            2,
+            1,
+            Some((&checkpoint, 0)),
+        )
+        .await?;
+
+        for cs_id in &csids {
+            assert!(RootUnodeManifestId::is_derived(&ctx, &repo, cs_id).await?);
+            assert!(BlameRoot::is_derived(&ctx, &repo, cs_id).await?);
+        }
+        assert_eq!(checkpoint.load(&ctx).await?, Some(csids.len()));
+        // Progress through one list of changesets doesn't apply to another
+        assert_eq!(other_checkpoint.load(&ctx).await?, None);
+
+        Ok(())
+    }
+}
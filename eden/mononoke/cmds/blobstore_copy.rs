@@ -0,0 +1,443 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use anyhow::{bail, format_err, Context, Error};
+use blobstore::{Blobstore, BlobstoreKeyParam, BlobstoreKeySource};
+use blobstore_factory::{make_blobstore, make_blobstore_enumerable, ReadOnlyStorage};
+use clap::{Arg, ArgMatches};
+use cmdlib::args;
+use context::CoreContext;
+use fbinit::FacebookInit;
+use futures::{
+    future,
+    stream::{self, StreamExt, TryStreamExt},
+};
+use metaconfig_types::BlobConfig;
+use slog::{info, Logger};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+const NAME: &str = "blobstore_copy";
+
+const ARG_SOURCE_STORAGE_CONFIG_NAME: &str = "source-storage-config-name";
+const ARG_DEST_STORAGE_CONFIG_NAME: &str = "dest-storage-config-name";
+const ARG_START_KEY: &str = "start-key";
+const ARG_END_KEY: &str = "end-key";
+const ARG_RESUME_TOKEN_FILE: &str = "resume-token-file";
+const ARG_SCHEDULED_MAX: &str = "scheduled-max";
+const ARG_SKIP_EXISTING: &str = "skip-existing";
+const ARG_VERIFY: &str = "verify";
+
+const DEFAULT_SCHEDULED_MAX: usize = 100;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct CopyStats {
+    enumerated: u64,
+    copied: u64,
+    copied_bytes: u64,
+    skipped: u64,
+    // Keys that were deleted from the source after they were enumerated
+    missing: u64,
+}
+
+impl CopyStats {
+    fn add(&mut self, other: CopyStats) {
+        self.enumerated += other.enumerated;
+        self.copied += other.copied;
+        self.copied_bytes += other.copied_bytes;
+        self.skipped += other.skipped;
+        self.missing += other.missing;
+    }
+}
+
+struct CopyConfig {
+    scheduled_max: usize,
+    skip_existing: bool,
+    verify: bool,
+    resume_token_file: Option<PathBuf>,
+}
+
+fn load_blobconfig(
+    fb: FacebookInit,
+    matches: &ArgMatches<'_>,
+    name_arg: &str,
+) -> Result<BlobConfig, Error> {
+    let name = matches
+        .value_of(name_arg)
+        .ok_or_else(|| format_err!("No {} given", name_arg))?;
+    let storage_config = args::load_storage_configs(fb, matches)
+        .context("Could not read storage configs")?
+        .storage
+        .remove(name)
+        .ok_or_else(|| format_err!("Storage config {} not found", name))?;
+    Ok(storage_config.blobstore)
+}
+
+// The token file holds the position to continue from as JSON, or null once the copy is complete
+fn read_resume_token(path: &Path) -> Result<Option<Option<BlobstoreKeyParam>>, Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let token = fs::read_to_string(path)
+        .with_context(|| format!("While reading resume token from {}", path.display()))?;
+    Ok(Some(serde_json::from_str(&token)?))
+}
+
+fn write_resume_token(path: &Path, token: Option<&BlobstoreKeyParam>) -> Result<(), Error> {
+    // Write then rename, so that an interrupted copy never leaves a truncated token behind
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, serde_json::to_string(&token)?)?;
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("While writing resume token to {}", path.display()))?;
+    Ok(())
+}
+
+async fn copy_key(
+    ctx: &CoreContext,
+    source: &dyn BlobstoreKeySource,
+    dest: &dyn Blobstore,
+    key: String,
+    config: &CopyConfig,
+) -> Result<CopyStats, Error> {
+    let mut stats = CopyStats::default();
+    if config.skip_existing && dest.is_present(ctx.clone(), key.clone()).await? {
+        stats.skipped = 1;
+        return Ok(stats);
+    }
+
+    let value = match source.get(ctx.clone(), key.clone()).await? {
+        Some(value) => value.into_bytes(),
+        None => {
+            stats.missing = 1;
+            return Ok(stats);
+        }
+    };
+    let size = value.len() as u64;
+    dest.put(ctx.clone(), key.clone(), value.clone()).await?;
+
+    if config.verify {
+        let copied = dest.get(ctx.clone(), key.clone()).await?;
+        if copied.as_ref().map(|copied| copied.as_bytes()) != Some(&value) {
+            bail!("Verification failed for {}, the copy does not match", key);
+        }
+    }
+
+    stats.copied = 1;
+    stats.copied_bytes = size;
+    Ok(stats)
+}
+
+async fn copy(
+    ctx: &CoreContext,
+    logger: &Logger,
+    source: Arc<dyn BlobstoreKeySource>,
+    dest: Arc<dyn Blobstore>,
+    range: BlobstoreKeyParam,
+    config: CopyConfig,
+) -> Result<CopyStats, Error> {
+    let mut range = match &config.resume_token_file {
+        Some(path) => match read_resume_token(path)? {
+            Some(Some(token)) => {
+                info!(logger, "Resuming from {}", path.display());
+                token
+            }
+            Some(None) => {
+                info!(
+                    logger,
+                    "Copy already complete according to {}",
+                    path.display()
+                );
+                return Ok(CopyStats::default());
+            }
+            None => range,
+        },
+        None => range,
+    };
+
+    let start = Instant::now();
+    let mut total = CopyStats::default();
+    loop {
+        let entries = source.enumerate(range).await?;
+        let mut page = CopyStats::default();
+        page.enumerated = entries.keys.len() as u64;
+
+        let source = &*source;
+        let dest = &*dest;
+        let config = &config;
+        page.add(
+            stream::iter(entries.keys)
+                .map(|key| copy_key(ctx, source, dest, key, config))
+                .buffer_unordered(config.scheduled_max)
+                .try_fold(CopyStats::default(), |mut acc, stats| {
+                    acc.add(stats);
+                    future::ok(acc)
+                })
+                .await?,
+        );
+        total.add(page);
+
+        // Only record the position once the whole page is copied, so a restart copies any keys
+        // that were in flight again
+        if let Some(path) = &config.resume_token_file {
+            write_resume_token(path, entries.next_token.as_ref())?;
+        }
+
+        let elapsed = start.elapsed().as_secs_f64();
+        info!(
+            logger,
+            "Copied {} of {} keys ({} bytes, {} skipped, {} missing), {:.0} keys/s",
+            total.copied,
+            total.enumerated,
+            total.copied_bytes,
+            total.skipped,
+            total.missing,
+            total.enumerated as f64 / elapsed.max(1.0),
+        );
+
+        match entries.next_token {
+            Some(next_token) => range = next_token,
+            None => return Ok(total),
+        }
+    }
+}
+
+#[fbinit::main]
+fn main(fb: FacebookInit) -> Result<(), Error> {
+    let matches = args::MononokeApp::new(NAME)
+        .with_advanced_args_hidden()
+        .with_all_repos()
+        .build()
+        .about("Copy every blob from one storage config's blobstore to another's")
+        .arg(
+            Arg::with_name(ARG_SOURCE_STORAGE_CONFIG_NAME)
+                .long(ARG_SOURCE_STORAGE_CONFIG_NAME)
+                .takes_value(true)
+                .required(true)
+                .help("the name of the storage config to copy from"),
+        )
+        .arg(
+            Arg::with_name(ARG_DEST_STORAGE_CONFIG_NAME)
+                .long(ARG_DEST_STORAGE_CONFIG_NAME)
+                .takes_value(true)
+                .required(true)
+                .help("the name of the storage config to copy to"),
+        )
+        .arg(
+            Arg::with_name(ARG_START_KEY)
+                .long(ARG_START_KEY)
+                .takes_value(true)
+                .required(false)
+                .help("Only copy keys from this key onwards, inclusive"),
+        )
+        .arg(
+            Arg::with_name(ARG_END_KEY)
+                .long(ARG_END_KEY)
+                .takes_value(true)
+                .required(false)
+                .help("Only copy keys before this key, exclusive"),
+        )
+        .arg(
+            Arg::with_name(ARG_RESUME_TOKEN_FILE)
+                .long(ARG_RESUME_TOKEN_FILE)
+                .takes_value(true)
+                .required(false)
+                .help("File to save the enumeration position in after each page of keys, and to resume from if it exists"),
+        )
+        .arg(
+            Arg::with_name(ARG_SCHEDULED_MAX)
+                .long(ARG_SCHEDULED_MAX)
+                .takes_value(true)
+                .required(false)
+                .help("Maximum number of keys to copy at once. Default 100."),
+        )
+        .arg(
+            Arg::with_name(ARG_SKIP_EXISTING)
+                .long(ARG_SKIP_EXISTING)
+                .takes_value(false)
+                .required(false)
+                .help("Don't copy keys that are already present in the destination"),
+        )
+        .arg(
+            Arg::with_name(ARG_VERIFY)
+                .long(ARG_VERIFY)
+                .takes_value(false)
+                .required(false)
+                .help("Read back each key after copying it, and fail if it doesn't match the source"),
+        )
+        .get_matches();
+
+    let (_, logger, mut runtime) =
+        args::init_mononoke(fb, &matches, None).context("failed to initialise mononoke")?;
+
+    let source_config = load_blobconfig(fb, &matches, ARG_SOURCE_STORAGE_CONFIG_NAME)?;
+    let dest_config = load_blobconfig(fb, &matches, ARG_DEST_STORAGE_CONFIG_NAME)?;
+    let readonly_storage = args::parse_readonly_storage(&matches);
+    if readonly_storage.0 {
+        bail!("Can't copy with --readonly-storage");
+    }
+
+    let range = BlobstoreKeyParam::from(
+        matches.value_of(ARG_START_KEY).unwrap_or("").to_string()
+            ..matches.value_of(ARG_END_KEY).unwrap_or("").to_string(),
+    );
+    let config = CopyConfig {
+        scheduled_max: args::get_usize_opt(&matches, ARG_SCHEDULED_MAX)
+            .unwrap_or(DEFAULT_SCHEDULED_MAX),
+        skip_existing: matches.is_present(ARG_SKIP_EXISTING),
+        verify: matches.is_present(ARG_VERIFY),
+        resume_token_file: matches.value_of(ARG_RESUME_TOKEN_FILE).map(PathBuf::from),
+    };
+
+    let mysql_options = args::parse_mysql_options(&matches);
+    let blobstore_options = args::parse_blobstore_options(&matches);
+    let ctx = CoreContext::new_bulk_with_logger(fb, logger.clone());
+
+    let blobstore_copy = async move {
+        // Only read from the source
+        let source = make_blobstore_enumerable(
+            fb,
+            source_config,
+            mysql_options,
+            ReadOnlyStorage(true),
+            &blobstore_options,
+            &logger,
+        );
+        let dest = make_blobstore(
+            fb,
+            dest_config,
+            mysql_options,
+            readonly_storage,
+            &blobstore_options,
+            &logger,
+        );
+        let (source, dest) = future::try_join(source, dest).await?;
+
+        let stats = copy(&ctx, &logger, source, dest, range, config).await?;
+        info!(logger, "Copy complete: {:?}", stats);
+        Ok(())
+    };
+
+    runtime.block_on_std(blobstore_copy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use fileblob::Fileblob;
+    use memblob::EagerMemblob;
+    use mononoke_types::BlobstoreBytes;
+    use slog::{o, Discard};
+    use tempdir::TempDir;
+
+    fn config(skip_existing: bool, resume_token_file: Option<PathBuf>) -> CopyConfig {
+        CopyConfig {
+            scheduled_max: DEFAULT_SCHEDULED_MAX,
+            skip_existing,
+            verify: true,
+            resume_token_file,
+        }
+    }
+
+    fn bytes(value: &'static [u8]) -> BlobstoreBytes {
+        BlobstoreBytes::from_bytes(Bytes::from_static(value))
+    }
+
+    async fn source_with_keys(ctx: &CoreContext, dir: &TempDir) -> Result<Fileblob, Error> {
+        let source = Fileblob::create(dir.path())?;
+        for key in &["a", "b", "c"] {
+            source
+                .put(ctx.clone(), key.to_string(), bytes(b"source"))
+                .await?;
+        }
+        Ok(source)
+    }
+
+    #[fbinit::compat_test]
+    async fn test_copy_skip_existing(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let logger = Logger::root(Discard, o!());
+        let dir = TempDir::new("blobstore_copy_skip_existing")?;
+        let source = source_with_keys(&ctx, &dir).await?;
+        let dest = EagerMemblob::new();
+        dest.put(ctx.clone(), "b".to_string(), bytes(b"dest"))
+            .await?;
+
+        let stats = copy(
+            &ctx,
+            &logger,
+            Arc::new(source),
+            Arc::new(dest.clone()),
+            BlobstoreKeyParam::from(..),
+            config(true, None),
+        )
+        .await?;
+        assert_eq!(stats.enumerated, 3);
+        assert_eq!(stats.copied, 2);
+        assert_eq!(stats.skipped, 1);
+
+        let expected: [(&str, &'static [u8]); 3] =
+            [("a", b"source"), ("b", b"dest"), ("c", b"source")];
+        for (key, value) in &expected {
+            let copied = dest.get(ctx.clone(), key.to_string()).await?.unwrap();
+            assert_eq!(copied.into_bytes(), bytes(value), "key {}", key);
+        }
+
+        Ok(())
+    }
+
+    #[fbinit::compat_test]
+    async fn test_copy_resume(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let logger = Logger::root(Discard, o!());
+        let dir = TempDir::new("blobstore_copy_resume")?;
+        let source = Arc::new(source_with_keys(&ctx, &dir).await?);
+        let dest = EagerMemblob::new();
+
+        // Pretend an earlier run stopped after copying "a"
+        let token_dir = TempDir::new("blobstore_copy_resume_token")?;
+        let token_file = token_dir.path().join("token");
+        write_resume_token(
+            &token_file,
+            Some(&BlobstoreKeyParam::from("b".to_string()..)),
+        )?;
+
+        let stats = copy(
+            &ctx,
+            &logger,
+            source.clone(),
+            Arc::new(dest.clone()),
+            BlobstoreKeyParam::from(..),
+            config(false, Some(token_file.clone())),
+        )
+        .await?;
+        assert_eq!(stats.enumerated, 2);
+        assert_eq!(stats.copied, 2);
+        assert!(dest.get(ctx.clone(), "a".to_string()).await?.is_none());
+        assert!(dest.get(ctx.clone(), "c".to_string()).await?.is_some());
+        assert_eq!(read_resume_token(&token_file)?, Some(None));
+
+        // Once the token records completion, running again copies nothing
+        let stats = copy(
+            &ctx,
+            &logger,
+            source,
+            Arc::new(dest.clone()),
+            BlobstoreKeyParam::from(..),
+            config(false, Some(token_file)),
+        )
+        .await?;
+        assert_eq!(stats.enumerated, 0);
+        assert_eq!(stats.copied, 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use anyhow::{bail, Context, Error};
+use blobstore_factory::make_blobstore_with_ttl;
+use clap::Arg;
+use cmdlib::args;
+use context::CoreContext;
+use fbinit::FacebookInit;
+use slog::info;
+
+const NAME: &str = "blobstore_sweep_expired";
+
+const ARG_STORAGE_CONFIG_NAME: &str = "storage-config-name";
+const ARG_BATCH_SIZE: &str = "batch-size";
+
+const DEFAULT_BATCH_SIZE: u64 = 1000;
+
+#[fbinit::main]
+fn main(fb: FacebookInit) -> Result<(), Error> {
+    let matches = args::MononokeApp::new(NAME)
+        .with_advanced_args_hidden()
+        .with_all_repos()
+        .build()
+        .about("Remove blobs that were put with a TTL and have expired")
+        .arg(
+            Arg::with_name(ARG_STORAGE_CONFIG_NAME)
+                .long(ARG_STORAGE_CONFIG_NAME)
+                .takes_value(true)
+                .required(true)
+                .help("the name of the storage config to sweep"),
+        )
+        .arg(
+            Arg::with_name(ARG_BATCH_SIZE)
+                .long(ARG_BATCH_SIZE)
+                .takes_value(true)
+                .required(false)
+                .help("Maximum number of blobs to remove at once. Default 1000."),
+        )
+        .get_matches();
+
+    let (_, logger, mut runtime) =
+        args::init_mononoke(fb, &matches, None).context("failed to initialise mononoke")?;
+
+    let storage_config_name = matches
+        .value_of(ARG_STORAGE_CONFIG_NAME)
+        .context("No storage config name")?;
+    let storage_config = args::load_storage_configs(fb, &matches)
+        .context("Could not read storage configs")?
+        .storage
+        .remove(storage_config_name)
+        .context("Requested storage config not found")?;
+    let batch_size = args::get_u64_opt(&matches, ARG_BATCH_SIZE).unwrap_or(DEFAULT_BATCH_SIZE);
+
+    let readonly_storage = args::parse_readonly_storage(&matches);
+    if readonly_storage.0 {
+        bail!("Can't sweep with --readonly-storage");
+    }
+    let mysql_options = args::parse_mysql_options(&matches);
+    let blobstore_options = args::parse_blobstore_options(&matches);
+    let ctx = CoreContext::new_bulk_with_logger(fb, logger.clone());
+
+    let sweep = async move {
+        let blobstore = make_blobstore_with_ttl(
+            fb,
+            storage_config.blobstore,
+            mysql_options,
+            readonly_storage,
+            &blobstore_options,
+            &logger,
+        )
+        .await?;
+
+        let mut total = 0;
+        loop {
+            let swept = blobstore.sweep_expired(ctx.clone(), batch_size).await?;
+            if swept == 0 {
+                break;
+            }
+            total += swept;
+            info!(logger, "Removed {} expired blobs", total);
+        }
+        info!(logger, "Sweep complete, removed {} expired blobs", total);
+        Ok(())
+    };
+
+    runtime.block_on_std(sweep)
+}
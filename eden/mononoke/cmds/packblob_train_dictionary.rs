@@ -0,0 +1,135 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use anyhow::{bail, Context, Error};
+use blobstore::Blobstore;
+use bytes::Bytes;
+use clap::Arg;
+use cmdlib::args;
+use context::CoreContext;
+use fbinit::FacebookInit;
+use futures::compat::Future01CompatExt;
+use mononoke_types::BlobstoreBytes;
+use packblob::{dictionary_key, train_dictionary};
+use slog::info;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const NAME: &str = "packblob_train_dictionary";
+
+const ARG_SAMPLE_DIR: &str = "sample-dir";
+const ARG_VERSION: &str = "version";
+const ARG_MAX_SIZE: &str = "max-size";
+const ARG_MAX_SAMPLES: &str = "max-samples";
+
+// zstd's own default dictionary size
+const DEFAULT_MAX_SIZE: usize = 112640;
+const DEFAULT_MAX_SAMPLES: usize = 100000;
+
+// Every file under `dir` is a sample blob, as in the walker's corpus output
+fn read_samples(dir: &Path, max_samples: usize, samples: &mut Vec<Bytes>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir).with_context(|| format!("While reading {}", dir.display()))? {
+        if samples.len() >= max_samples {
+            break;
+        }
+        let path = entry?.path();
+        if path.is_dir() {
+            read_samples(&path, max_samples, samples)?;
+        } else {
+            samples.push(Bytes::from(fs::read(&path)?));
+        }
+    }
+    Ok(())
+}
+
+#[fbinit::main]
+fn main(fb: FacebookInit) -> Result<(), Error> {
+    let matches = args::MononokeApp::new(NAME)
+        .with_advanced_args_hidden()
+        .build()
+        .about("Train a zstd dictionary for a repo from sample blobs, and store it for packblob to compress with")
+        .arg(
+            Arg::with_name(ARG_SAMPLE_DIR)
+                .long(ARG_SAMPLE_DIR)
+                .takes_value(true)
+                .required(true)
+                .help("Directory of sample blobs to train on, e.g. a node type directory from the walker's corpus output"),
+        )
+        .arg(
+            Arg::with_name(ARG_VERSION)
+                .long(ARG_VERSION)
+                .takes_value(true)
+                .required(true)
+                .help("Version to store the dictionary as. Existing versions are never overwritten"),
+        )
+        .arg(
+            Arg::with_name(ARG_MAX_SIZE)
+                .long(ARG_MAX_SIZE)
+                .takes_value(true)
+                .required(false)
+                .help("Maximum size of the dictionary in bytes. Default 112640."),
+        )
+        .arg(
+            Arg::with_name(ARG_MAX_SAMPLES)
+                .long(ARG_MAX_SAMPLES)
+                .takes_value(true)
+                .required(false)
+                .help("Maximum number of sample blobs to train on. Default 100000."),
+        )
+        .get_matches();
+
+    let (_, logger, mut runtime) =
+        args::init_mononoke(fb, &matches, None).context("failed to initialise mononoke")?;
+
+    let sample_dir = matches
+        .value_of(ARG_SAMPLE_DIR)
+        .map(PathBuf::from)
+        .context("No sample directory")?;
+    let version = matches
+        .value_of(ARG_VERSION)
+        .context("No version")?
+        .parse::<u32>()
+        .context("Version is not u32")?;
+    let max_size = args::get_usize_opt(&matches, ARG_MAX_SIZE).unwrap_or(DEFAULT_MAX_SIZE);
+    let max_samples = args::get_usize_opt(&matches, ARG_MAX_SAMPLES).unwrap_or(DEFAULT_MAX_SAMPLES);
+
+    if args::parse_readonly_storage(&matches).0 {
+        bail!("Can't store a dictionary with --readonly-storage");
+    }
+    let ctx = CoreContext::new_bulk_with_logger(fb, logger.clone());
+    let repo = args::open_repo(fb, &logger, &matches);
+
+    let train = async move {
+        let repo = repo.compat().await?;
+        let blobstore = repo.get_blobstore();
+        let key = dictionary_key(version);
+        // Blobs compressed with a dictionary record only its key, so they must never change
+        if blobstore.is_present(ctx.clone(), key.clone()).await? {
+            bail!("Dictionary version {} already exists", version);
+        }
+
+        let mut samples = Vec::new();
+        read_samples(&sample_dir, max_samples, &mut samples)?;
+        info!(
+            logger,
+            "Training dictionary on {} samples, {} bytes",
+            samples.len(),
+            samples.iter().map(|sample| sample.len()).sum::<usize>(),
+        );
+        let dict = train_dictionary(&samples, max_size)?;
+
+        info!(logger, "Storing {} byte dictionary as {}", dict.len(), key);
+        blobstore
+            .put(ctx, key, BlobstoreBytes::from_bytes(dict))
+            .await?;
+        Ok(())
+    };
+
+    runtime.block_on_std(train)
+}
@@ -31,6 +31,7 @@ use metaconfig_types::{
 use mononoke_types::{BlobstoreBytes, DateTime, RepositoryId};
 use sql_construct::facebook::FbSqlConstruct;
 use sql_ext::facebook::{MysqlConnectionType, ReadConnectionType};
+use sqlblob::Sqlblob;
 
 /// Save manifold continuation token each once per `PRESERVE_STATE_RATIO` entries
 const PRESERVE_STATE_RATIO: usize = 10_000;
@@ -383,6 +384,11 @@ fn make_key_source(
             let res = Arc::new(Fileblob::create(path)?);
             Ok(res)
         }
+        BlobConfig::Sqlite { path } => {
+            // Only enumerating keys, so the store can be opened readonly
+            let res = Arc::new(Sqlblob::with_sqlite_path(path.join("blobs"), true)?);
+            Ok(res)
+        }
         _ => Err(format_err!("Unsupported Blobstore type")),
     }
 }
@@ -0,0 +1,132 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use anyhow::{bail, Context, Error};
+use blobstore_factory::{make_blobstore, make_sweep_blobstore};
+use clap::Arg;
+use cmdlib::args;
+use context::CoreContext;
+use fbinit::FacebookInit;
+use metaconfig_types::BlobConfig;
+use slog::info;
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime};
+use tieredblob::migrate_to_cold;
+
+const NAME: &str = "tiered_blobstore_migrate";
+
+const ARG_STORAGE_CONFIG_NAME: &str = "storage-config-name";
+const ARG_MAX_AGE_DAYS: &str = "max-age-days";
+const ARG_SCHEDULED_MAX: &str = "scheduled-max";
+const ARG_DRY_RUN: &str = "dry-run";
+
+const DEFAULT_SCHEDULED_MAX: usize = 100;
+
+#[fbinit::main]
+fn main(fb: FacebookInit) -> Result<(), Error> {
+    let matches = args::MononokeApp::new(NAME)
+        .with_advanced_args_hidden()
+        .with_all_repos()
+        .build()
+        .about("Move blobs older than the maximum age from the hot to the cold tier of a tiered blobstore")
+        .arg(
+            Arg::with_name(ARG_STORAGE_CONFIG_NAME)
+                .long(ARG_STORAGE_CONFIG_NAME)
+                .takes_value(true)
+                .required(true)
+                .help("the name of the tiered storage config to migrate"),
+        )
+        .arg(
+            Arg::with_name(ARG_MAX_AGE_DAYS)
+                .long(ARG_MAX_AGE_DAYS)
+                .takes_value(true)
+                .required(true)
+                .help("Blobs created more than this many days ago are moved to the cold tier"),
+        )
+        .arg(
+            Arg::with_name(ARG_SCHEDULED_MAX)
+                .long(ARG_SCHEDULED_MAX)
+                .takes_value(true)
+                .required(false)
+                .help("Maximum number of blobs to move at once. Default 100."),
+        )
+        .arg(
+            Arg::with_name(ARG_DRY_RUN)
+                .long(ARG_DRY_RUN)
+                .takes_value(false)
+                .required(false)
+                .help("Log the blobs that would be moved, without moving them"),
+        )
+        .get_matches();
+
+    let (_, logger, mut runtime) =
+        args::init_mononoke(fb, &matches, None).context("failed to initialise mononoke")?;
+
+    let storage_config_name = matches
+        .value_of(ARG_STORAGE_CONFIG_NAME)
+        .context("No storage config name")?;
+    let storage_config = args::load_storage_configs(fb, &matches)
+        .context("Could not read storage configs")?
+        .storage
+        .remove(storage_config_name)
+        .context("Requested storage config not found")?;
+    let (hot, cold) = match storage_config.blobstore {
+        BlobConfig::Tiered { hot, cold } => (*hot, *cold),
+        _ => bail!("Storage config {} is not tiered", storage_config_name),
+    };
+
+    let max_age_days = args::get_u64_opt(&matches, ARG_MAX_AGE_DAYS).context("No max age")?;
+    let max_age = Duration::from_secs(max_age_days * 24 * 60 * 60);
+    let scheduled_max =
+        args::get_usize_opt(&matches, ARG_SCHEDULED_MAX).unwrap_or(DEFAULT_SCHEDULED_MAX);
+    let dry_run = matches.is_present(ARG_DRY_RUN);
+
+    let readonly_storage = args::parse_readonly_storage(&matches);
+    if readonly_storage.0 && !dry_run {
+        bail!("Can't migrate with --readonly-storage, pass --dry-run");
+    }
+    let mysql_options = args::parse_mysql_options(&matches);
+    let blobstore_options = args::parse_blobstore_options(&matches);
+    let ctx = CoreContext::new_bulk_with_logger(fb, logger.clone());
+
+    let migrate = async move {
+        let hot = make_sweep_blobstore(fb, hot, mysql_options, readonly_storage).await?;
+        let cold = make_blobstore(
+            fb,
+            cold,
+            mysql_options,
+            readonly_storage,
+            &blobstore_options,
+            &logger,
+        )
+        .await?;
+
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+        let cutoff = i64::try_from(now.checked_sub(max_age).unwrap_or_default().as_secs())?;
+        info!(logger, "Migrating blobs created before {}", cutoff);
+
+        let stats = migrate_to_cold(
+            &ctx,
+            &hot,
+            &cold,
+            (..).into(),
+            cutoff,
+            scheduled_max,
+            dry_run,
+        )
+        .await?;
+        info!(
+            logger,
+            "Migration complete, dry run {}: {:?}", dry_run, stats
+        );
+        Ok(())
+    };
+
+    runtime.block_on_std(migrate)
+}
@@ -11,7 +11,7 @@ use blobstore::{Blobstore, BlobstoreBytes, Loadable};
 use bytes::Bytes;
 use cloned::cloned;
 use context::CoreContext;
-use derived_data::{BonsaiDerived, BonsaiDerivedMapping};
+use derived_data::{mapping_blobstore, BonsaiDerived, BonsaiDerivedMapping};
 use filestore::{self, FetchKey};
 use futures::{
     compat::Future01CompatExt,
@@ -30,15 +30,16 @@ use unodes::{find_unode_renames, RootUnodeManifestId};
 
 pub const BLAME_FILESIZE_LIMIT: u64 = 10 * 1024 * 1024;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct BlameRoot(ChangesetId);
 
 impl BonsaiDerived for BlameRoot {
     const NAME: &'static str = "blame";
+    const DEPENDENCIES: &'static [&'static str] = &[RootUnodeManifestId::NAME];
     type Mapping = BlameRootMapping;
 
     fn mapping(_ctx: &CoreContext, repo: &BlobRepo) -> Self::Mapping {
-        BlameRootMapping::new(repo.blobstore().boxed())
+        BlameRootMapping::new(mapping_blobstore(repo, Self::NAME))
     }
 
     fn derive_from_parents(
@@ -0,0 +1,153 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use blobrepo::BlobRepo;
+use blobstore::Loadable;
+use bytes::Bytes;
+use context::CoreContext;
+use derived_data::BonsaiDerived;
+use futures::{
+    compat::Future01CompatExt,
+    future::{BoxFuture, FutureExt},
+};
+use manifest::ManifestOps;
+use mononoke_types::{
+    blame::{Blame, BlameId, BlameMaybeRejected},
+    ChangesetId, MPath,
+};
+use std::collections::{HashMap, HashSet};
+use unodes::{find_unode_renames, RootUnodeManifestId};
+
+use crate::{derived::fetch_file_full_content, fetch_blame, BlameError};
+
+/// Blames of `(csid, path)` computed as if `csid` was ignored.
+type Resolved = HashMap<(ChangesetId, MPath), Blame>;
+
+/// Fetch content and blame for a file with specified file path, looking
+/// through the changesets in `ignore` to the previous changes of each line.
+///
+/// Stored blame can't depend on the set of ignored changesets, so the blame
+/// of each file version changed by an ignored changeset is recomputed from
+/// the (recursively resolved) blames of its parents.
+pub async fn fetch_blame_with_ignored(
+    ctx: CoreContext,
+    repo: BlobRepo,
+    csid: ChangesetId,
+    path: MPath,
+    ignore: &HashSet<ChangesetId>,
+) -> Result<(Bytes, Blame), BlameError> {
+    let (content, blame) = fetch_blame(ctx.clone(), repo.clone(), csid, path)
+        .compat()
+        .await?;
+    if ignore.is_empty() {
+        return Ok((content, blame));
+    }
+    let mut resolved = Resolved::new();
+    let blame = resolve_ignored(&ctx, &repo, blame, ignore, &mut resolved).await?;
+    Ok((content, blame))
+}
+
+/// Replace all lines in `blame` attributed to ignored changesets.
+fn resolve_ignored<'a>(
+    ctx: &'a CoreContext,
+    repo: &'a BlobRepo,
+    blame: Blame,
+    ignore: &'a HashSet<ChangesetId>,
+    resolved: &'a mut Resolved,
+) -> BoxFuture<'a, Result<Blame, BlameError>> {
+    async move {
+        let ignored: Vec<_> = blame
+            .ranges()
+            .iter()
+            .filter(|range| ignore.contains(&range.csid))
+            .map(|range| (range.csid, range.path.clone()))
+            .collect();
+        if ignored.is_empty() {
+            return Ok(blame);
+        }
+        for (csid, path) in ignored {
+            if resolved.contains_key(&(csid, path.clone())) {
+                continue;
+            }
+            let replacement =
+                blame_ignoring_changeset(ctx, repo, csid, &path, ignore, resolved).await?;
+            resolved.insert((csid, path), replacement);
+        }
+        Ok(blame.substitute(resolved)?)
+    }
+    .boxed()
+}
+
+/// Compute the blame of `path` at `csid` as if `csid` was not there, with
+/// all ignored changesets in its history resolved too.
+async fn blame_ignoring_changeset(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    csid: ChangesetId,
+    path: &MPath,
+    ignore: &HashSet<ChangesetId>,
+    resolved: &mut Resolved,
+) -> Result<Blame, BlameError> {
+    let blobstore = repo.get_blobstore();
+    let root = RootUnodeManifestId::derive(ctx.clone(), repo.clone(), csid)
+        .compat()
+        .await?;
+    let file_unode_id = root
+        .manifest_unode_id()
+        .clone()
+        .find_entry(ctx.clone(), blobstore.clone(), Some(path.clone()))
+        .compat()
+        .await?
+        .ok_or_else(|| BlameError::NoSuchPath(path.clone()))?
+        .into_leaf()
+        .ok_or_else(|| BlameError::IsDirectory(path.clone()))?;
+    let file_unode = file_unode_id
+        .load(ctx.clone(), &blobstore)
+        .await
+        .map_err(Error::from)?;
+    let bonsai = csid
+        .load(ctx.clone(), &blobstore)
+        .await
+        .map_err(Error::from)?;
+    let renames = find_unode_renames(ctx.clone(), repo.clone(), &bonsai)
+        .compat()
+        .await?;
+
+    let content = fetch_file_full_content(ctx, repo, file_unode_id).await??;
+
+    let mut parents = Vec::new();
+    for parent_id in file_unode
+        .parents()
+        .iter()
+        .cloned()
+        .chain(renames.get(path).cloned())
+    {
+        // Parents without blame are skipped, the same way derivation does.
+        let parent_content = match fetch_file_full_content(ctx, repo, parent_id).await? {
+            Ok(parent_content) => parent_content,
+            Err(_) => continue,
+        };
+        let parent_blame = match BlameId::from(parent_id)
+            .load(ctx.clone(), &blobstore)
+            .await
+            .map_err(Error::from)?
+        {
+            BlameMaybeRejected::Blame(parent_blame) => parent_blame,
+            BlameMaybeRejected::Rejected(_) => continue,
+        };
+        let parent_blame = resolve_ignored(ctx, repo, parent_blame, ignore, resolved).await?;
+        parents.push((parent_content, parent_blame));
+    }
+
+    Ok(Blame::from_parents_ignoring(
+        csid,
+        content,
+        path.clone(),
+        parents,
+    )?)
+}
@@ -5,14 +5,14 @@
  * GNU General Public License version 2.
  */
 
-use crate::{fetch_blame, BlameError};
+use crate::{fetch_blame, fetch_blame_with_ignored, BlameError};
 use anyhow::{anyhow, Error};
 use blobrepo_override::DangerousOverride;
 use bytes::Bytes;
 use context::CoreContext;
 use fbinit::FacebookInit;
 use futures::compat::Future01CompatExt;
-use maplit::{btreemap, hashmap};
+use maplit::{btreemap, hashmap, hashset};
 use metaconfig_types::DerivedDataConfig;
 use mononoke_types::{Blame, ChangesetId, MPath};
 use std::collections::HashMap;
@@ -285,6 +285,59 @@ async fn test_blame_file_size_limit_rejected(fb: FacebookInit) -> Result<(), Err
     Ok(())
 }
 
+#[fbinit::compat_test]
+async fn test_blame_ignored(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let repo = blobrepo_factory::new_memblob_empty(None)?;
+    let c0 = CreateCommitContext::new_root(&ctx, &repo)
+        .add_file("file", "one\ntwo\nthree\n")
+        .commit()
+        .await?;
+    // mass reformatting that should not be blamed
+    let c1 = CreateCommitContext::new(&ctx, &repo, vec![c0])
+        .add_file("file", "ONE\ntwo\nTHREE\n")
+        .commit()
+        .await?;
+    let c2 = CreateCommitContext::new(&ctx, &repo, vec![c1])
+        .add_file("file", "ONE\nsix\nTHREE\nfour\n")
+        .commit()
+        .await?;
+
+    let names = hashmap! {
+        c0 => "c0",
+        c1 => "c1",
+        c2 => "c2",
+    };
+
+    let (content, blame) = fetch_blame_with_ignored(
+        ctx.clone(),
+        repo.clone(),
+        c2,
+        MPath::new("file")?,
+        &hashset! {},
+    )
+    .await?;
+    assert_eq!(
+        annotate(content, blame, &names)?,
+        "c1: ONE\nc2: six\nc1: THREE\nc2: four\n"
+    );
+
+    let (content, blame) = fetch_blame_with_ignored(
+        ctx.clone(),
+        repo.clone(),
+        c2,
+        MPath::new("file")?,
+        &hashset! {c1},
+    )
+    .await?;
+    assert_eq!(
+        annotate(content, blame, &names)?,
+        "c0: ONE\nc2: six\nc0: THREE\nc2: four\n"
+    );
+
+    Ok(())
+}
+
 fn annotate(
     content: Bytes,
     blame: Blame,
@@ -11,7 +11,7 @@ use std::{collections::HashMap, iter::FromIterator, sync::Arc};
 use blobrepo::BlobRepo;
 use blobstore::Blobstore;
 use context::CoreContext;
-use derived_data::{BonsaiDerived, BonsaiDerivedMapping};
+use derived_data::{mapping_blobstore, BonsaiDerived, BonsaiDerivedMapping};
 use fbthrift::compact_protocol;
 use futures::future::TryFutureExt;
 use futures_ext::{BoxFuture, FutureExt};
@@ -25,7 +25,7 @@ impl BonsaiDerived for ChangesetInfo {
     type Mapping = ChangesetInfoMapping;
 
     fn mapping(_ctx: &CoreContext, repo: &BlobRepo) -> Self::Mapping {
-        ChangesetInfoMapping::new(repo.blobstore().boxed())
+        ChangesetInfoMapping::new(mapping_blobstore(repo, Self::NAME))
     }
 
     fn derive_from_parents(
@@ -11,7 +11,7 @@ use blobrepo::BlobRepo;
 use blobstore::{Blobstore, BlobstoreGetData};
 use bytes::Bytes;
 use context::CoreContext;
-use derived_data::{BonsaiDerived, BonsaiDerivedMapping};
+use derived_data::{mapping_blobstore, BonsaiDerived, BonsaiDerivedMapping};
 use futures::future::{FutureExt as NewFutureExt, TryFutureExt};
 use futures_ext::{BoxFuture, FutureExt, StreamExt};
 use futures_old::{
@@ -19,12 +19,13 @@ use futures_old::{
     Future, Stream,
 };
 use mononoke_types::{BlobstoreBytes, BonsaiChangeset, ChangesetId, DeletedManifestId};
-use repo_blobstore::RepoBlobstore;
 use std::{
     collections::HashMap,
     convert::{TryFrom, TryInto},
     iter::FromIterator,
+    sync::Arc,
 };
+use unodes::RootUnodeManifestId;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct RootDeletedManifestId(DeletedManifestId);
@@ -57,10 +58,11 @@ impl From<RootDeletedManifestId> for BlobstoreBytes {
 
 impl BonsaiDerived for RootDeletedManifestId {
     const NAME: &'static str = "deleted_manifest";
+    const DEPENDENCIES: &'static [&'static str] = &[RootUnodeManifestId::NAME];
     type Mapping = RootDeletedManifestMapping;
 
     fn mapping(_ctx: &CoreContext, repo: &BlobRepo) -> Self::Mapping {
-        RootDeletedManifestMapping::new(repo.blobstore().clone())
+        RootDeletedManifestMapping::new(mapping_blobstore(repo, Self::NAME))
     }
 
     fn derive_from_parents(
@@ -92,11 +94,11 @@ impl BonsaiDerived for RootDeletedManifestId {
 
 #[derive(Clone)]
 pub struct RootDeletedManifestMapping {
-    blobstore: RepoBlobstore,
+    blobstore: Arc<dyn Blobstore>,
 }
 
 impl RootDeletedManifestMapping {
-    pub fn new(blobstore: RepoBlobstore) -> Self {
+    pub fn new(blobstore: Arc<dyn Blobstore>) -> Self {
         Self { blobstore }
     }
 
@@ -0,0 +1,387 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{format_err, Error};
+use bytes::Bytes;
+use std::{collections::HashMap, iter::FromIterator, sync::Arc};
+
+use blobrepo::BlobRepo;
+use blobstore::Blobstore;
+use context::CoreContext;
+use derived_data::{mapping_blobstore, BonsaiDerived, BonsaiDerivedMapping};
+use fbthrift::compact_protocol;
+use filestore::FetchKey;
+use fsnodes::RootFsnodeId;
+use futures::{
+    compat::{Future01CompatExt, Stream01CompatExt},
+    future::{FutureExt as NewFutureExt, TryFutureExt},
+    stream::{self, StreamExt as NewStreamExt, TryStreamExt},
+    try_join,
+};
+use futures_ext::{BoxFuture, FutureExt};
+use futures_old::{stream::FuturesUnordered, Future, Stream};
+use manifest::{Diff, Entry, ManifestOps};
+use mononoke_types::{fsnode::FsnodeFile, BlobstoreBytes, BonsaiChangeset, ChangesetId, MPath};
+use repo_blobstore::RepoBlobstore;
+use xdiff::{diff_hunks, Hunk};
+
+use crate::{ChangesetDiffstat, FileDiffstat};
+
+/// Number of small files whose contents are fetched and diffed concurrently. Small files are read
+/// in full by the peek that checks whether they are binary.
+const CONCURRENT_FILE_DIFFS: usize = 100;
+
+/// Number of larger files whose contents are fetched and diffed concurrently. Each of them can
+/// be up to `DIFFSTAT_FILESIZE_LIMIT` on either side, so this bounds the memory used.
+const CONCURRENT_LARGE_FILE_DIFFS: usize = 10;
+
+/// Files larger than this are not diffed, and are reported as binary.
+pub const DIFFSTAT_FILESIZE_LIMIT: u64 = 10 * 1024 * 1024;
+
+/// As in git, a file is binary if its first few kilobytes contain a NUL byte.
+const BINARY_PEEK_SIZE: usize = 8000;
+
+impl BonsaiDerived for ChangesetDiffstat {
+    const NAME: &'static str = "diffstat";
+    const DEPENDENCIES: &'static [&'static str] = &[RootFsnodeId::NAME];
+    type Mapping = ChangesetDiffstatMapping;
+
+    fn mapping(_ctx: &CoreContext, repo: &BlobRepo) -> Self::Mapping {
+        ChangesetDiffstatMapping::new(mapping_blobstore(repo, Self::NAME))
+    }
+
+    fn derive_from_parents(
+        ctx: CoreContext,
+        repo: BlobRepo,
+        bonsai: BonsaiChangeset,
+        _parents: Vec<Self>,
+    ) -> BoxFuture<Self, Error> {
+        async move { derive_diffstat(&ctx, &repo, bonsai, DIFFSTAT_FILESIZE_LIMIT).await }
+            .boxed()
+            .compat()
+            .boxify()
+    }
+}
+
+async fn derive_diffstat(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    bonsai: BonsaiChangeset,
+    filesize_limit: u64,
+) -> Result<ChangesetDiffstat, Error> {
+    let csid = bonsai.get_changeset_id();
+    let blobstore = repo.get_blobstore();
+    let root = RootFsnodeId::derive(ctx.clone(), repo.clone(), csid)
+        .compat()
+        .await?;
+    let first_parent = bonsai.parents().next();
+
+    // Changed files as (path, content in the first parent, content in this changeset)
+    let changes: Vec<(MPath, Option<FsnodeFile>, Option<FsnodeFile>)> = match first_parent {
+        Some(parent) => {
+            let parent_root = RootFsnodeId::derive(ctx.clone(), repo.clone(), parent)
+                .compat()
+                .await?;
+            parent_root
+                .fsnode_id()
+                .diff(ctx.clone(), blobstore.clone(), *root.fsnode_id())
+                .compat()
+                .try_filter_map(|diff| async move {
+                    let change = match diff {
+                        Diff::Added(Some(path), Entry::Leaf(new)) => Some((path, None, Some(new))),
+                        Diff::Removed(Some(path), Entry::Leaf(old)) => {
+                            Some((path, Some(old), None))
+                        }
+                        Diff::Changed(Some(path), Entry::Leaf(old), Entry::Leaf(new)) => {
+                            Some((path, Some(old), Some(new)))
+                        }
+                        _ => None,
+                    };
+                    Ok(change)
+                })
+                .try_collect()
+                .await?
+        }
+        None => {
+            root.fsnode_id()
+                .list_leaf_entries(ctx.clone(), blobstore.clone())
+                .compat()
+                .map_ok(|(path, new)| (path, None, Some(new)))
+                .try_collect()
+                .await?
+        }
+    };
+
+    let is_small = |file: &Option<FsnodeFile>| {
+        file.as_ref()
+            .map_or(true, |file| file.size() <= BINARY_PEEK_SIZE as u64)
+    };
+    let (small, large): (Vec<_>, Vec<_>) = changes
+        .into_iter()
+        .partition(|(_, old, new)| is_small(old) && is_small(new));
+    let small = stream::iter(small)
+        .map(|(path, old, new)| file_diffstat(ctx, &blobstore, filesize_limit, path, old, new))
+        .buffer_unordered(CONCURRENT_FILE_DIFFS);
+    let large = stream::iter(large)
+        .map(|(path, old, new)| file_diffstat(ctx, &blobstore, filesize_limit, path, old, new))
+        .buffer_unordered(CONCURRENT_LARGE_FILE_DIFFS);
+    let files = stream::select(small, large).try_collect().await?;
+
+    Ok(ChangesetDiffstat::new(csid, files))
+}
+
+async fn file_diffstat(
+    ctx: &CoreContext,
+    blobstore: &RepoBlobstore,
+    filesize_limit: u64,
+    path: MPath,
+    old: Option<FsnodeFile>,
+    new: Option<FsnodeFile>,
+) -> Result<FileDiffstat, Error> {
+    let contents = match (&old, &new) {
+        (Some(old), Some(new)) if old.content_id() == new.content_id() => {
+            // Only the file type changed
+            (Some(Bytes::new()), Some(Bytes::new()))
+        }
+        _ => try_join!(
+            fetch_text_content(ctx, blobstore, filesize_limit, old.as_ref()),
+            fetch_text_content(ctx, blobstore, filesize_limit, new.as_ref()),
+        )?,
+    };
+
+    let (old_content, new_content) = match contents {
+        (Some(old_content), Some(new_content)) => (old_content, new_content),
+        _ => {
+            return Ok(FileDiffstat {
+                path,
+                lines_added: 0,
+                lines_removed: 0,
+                is_binary: true,
+            });
+        }
+    };
+
+    let (lines_added, lines_removed) = diff_hunks(old_content, new_content).into_iter().fold(
+        (0, 0),
+        |(added, removed), Hunk { add, remove }| {
+            (added + add.len() as u64, removed + remove.len() as u64)
+        },
+    );
+    Ok(FileDiffstat {
+        path,
+        lines_added,
+        lines_removed,
+        is_binary: false,
+    })
+}
+
+/// Fetch the content of a file to diff, or None if it is binary or too large to diff. Only the
+/// start of the file is fetched to tell whether it is binary.
+async fn fetch_text_content(
+    ctx: &CoreContext,
+    blobstore: &RepoBlobstore,
+    filesize_limit: u64,
+    file: Option<&FsnodeFile>,
+) -> Result<Option<Bytes>, Error> {
+    let file = match file {
+        None => return Ok(Some(Bytes::new())),
+        Some(file) => file,
+    };
+    if file.size() > filesize_limit {
+        return Ok(None);
+    }
+
+    let key = FetchKey::Canonical(*file.content_id());
+    let start = filestore::peek(blobstore, ctx.clone(), &key, BINARY_PEEK_SIZE)
+        .compat()
+        .await?
+        .ok_or_else(|| format_err!("Missing content: {}", file.content_id()))?;
+    if start.contains(&0) {
+        return Ok(None);
+    }
+    if start.len() as u64 >= file.size() {
+        return Ok(Some(start));
+    }
+
+    let content = filestore::fetch_concat(blobstore, ctx.clone(), key)
+        .compat()
+        .await?;
+    Ok(Some(content))
+}
+
+#[derive(Clone)]
+pub struct ChangesetDiffstatMapping {
+    blobstore: Arc<dyn Blobstore>,
+}
+
+impl ChangesetDiffstatMapping {
+    pub fn new(blobstore: Arc<dyn Blobstore>) -> Self {
+        Self { blobstore }
+    }
+
+    fn format_key(&self, csid: &ChangesetId) -> String {
+        format!("diffstat.blake2.{}", csid)
+    }
+}
+
+impl BonsaiDerivedMapping for ChangesetDiffstatMapping {
+    type Value = ChangesetDiffstat;
+
+    fn get(
+        &self,
+        ctx: CoreContext,
+        csids: Vec<ChangesetId>,
+    ) -> BoxFuture<HashMap<ChangesetId, Self::Value>, Error> {
+        let futs = csids.into_iter().map(|csid| {
+            self.blobstore
+                .get(ctx.clone(), self.format_key(&csid))
+                .compat()
+                .map(move |value| {
+                    value.map(|bytes| {
+                        let diffstat = ChangesetDiffstat::from_bytes(bytes.as_raw_bytes())?;
+                        Ok((csid, diffstat))
+                    })
+                })
+        });
+        FuturesUnordered::from_iter(futs)
+            .filter_map(|maybe_diffstat| maybe_diffstat)
+            .collect()
+            .and_then(move |diffstats| {
+                diffstats
+                    .into_iter()
+                    .collect::<Result<HashMap<_, _>, Error>>()
+            })
+            .boxify()
+    }
+
+    fn put(
+        &self,
+        ctx: CoreContext,
+        csid: ChangesetId,
+        diffstat: Self::Value,
+    ) -> BoxFuture<(), Error> {
+        let data = {
+            let data = compact_protocol::serialize(&diffstat.into_thrift());
+            BlobstoreBytes::from_bytes(data)
+        };
+        self.blobstore
+            .put(ctx, self.format_key(&csid), data)
+            .compat()
+            .boxify()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use blobstore::Loadable;
+    use fbinit::FacebookInit;
+    use tests_utils::CreateCommitContext;
+
+    use crate::DIFFSTAT_MAX_FILES;
+
+    fn file(path: &str, lines_added: u64, lines_removed: u64, is_binary: bool) -> FileDiffstat {
+        FileDiffstat {
+            path: MPath::new(path).unwrap(),
+            lines_added,
+            lines_removed,
+            is_binary,
+        }
+    }
+
+    #[fbinit::compat_test]
+    async fn derive_diffstat_test(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = blobrepo_factory::new_memblob_empty(None)?;
+
+        let root = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("a", "one\ntwo\n")
+            .add_file("dir/bin", "bin\0ary")
+            .commit()
+            .await?;
+        let child = CreateCommitContext::new(&ctx, &repo, vec![root])
+            .add_file("a", "one\nthree\nfour\n")
+            .add_file("b", "x\n")
+            .delete_file("dir/bin")
+            .commit()
+            .await?;
+
+        let diffstat = ChangesetDiffstat::derive(ctx.clone(), repo.clone(), root)
+            .compat()
+            .await?;
+        assert_eq!(
+            diffstat.files(),
+            &[file("a", 2, 0, false), file("dir/bin", 0, 0, true)][..]
+        );
+
+        let diffstat = ChangesetDiffstat::derive(ctx.clone(), repo.clone(), child)
+            .compat()
+            .await?;
+        assert_eq!(
+            diffstat.files(),
+            &[
+                file("a", 2, 1, false),
+                file("b", 1, 0, false),
+                file("dir/bin", 0, 0, true),
+            ][..]
+        );
+        assert_eq!(diffstat.files_changed(), 3);
+        assert_eq!(diffstat.lines_added(), 3);
+        assert_eq!(diffstat.lines_removed(), 1);
+
+        Ok(())
+    }
+
+    #[fbinit::compat_test]
+    async fn diffstat_filesize_limit_test(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = blobrepo_factory::new_memblob_empty(None)?;
+
+        let root = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("small", "one\n")
+            .add_file("large", "one\ntwo\nthree\n")
+            .commit()
+            .await?;
+        let child = CreateCommitContext::new(&ctx, &repo, vec![root])
+            .add_file("small", "one\ntwo\n")
+            .add_file("large", "one\n")
+            .commit()
+            .await?;
+
+        // Files over the limit on either side are reported as binary without being diffed
+        let bonsai = child.load(ctx.clone(), repo.blobstore()).await?;
+        let diffstat = derive_diffstat(&ctx, &repo, bonsai, 8).await?;
+        assert_eq!(
+            diffstat.files(),
+            &[file("large", 0, 0, true), file("small", 1, 0, false)][..]
+        );
+
+        Ok(())
+    }
+    #[test]
+    fn diffstat_max_files_test() -> Result<(), Error> {
+        let csid = ChangesetId::from_bytes([1; 32])?;
+        let files = (0..DIFFSTAT_MAX_FILES + 1)
+            .map(|i| file(&format!("file{:06}", i), 2, 1, false))
+            .collect();
+
+        // The totals cover every file, but stats are only kept for the first files
+        let diffstat = ChangesetDiffstat::new(csid, files);
+        assert_eq!(diffstat.files_changed(), DIFFSTAT_MAX_FILES as u64 + 1);
+        assert_eq!(diffstat.lines_added(), 2 * (DIFFSTAT_MAX_FILES as u64 + 1));
+        assert_eq!(diffstat.lines_removed(), DIFFSTAT_MAX_FILES as u64 + 1);
+        assert_eq!(diffstat.files().len(), DIFFSTAT_MAX_FILES);
+        assert_eq!(diffstat.files()[0], file("file000000", 2, 1, false));
+
+        // The stored diffstat keeps the totals
+        let bytes = compact_protocol::serialize(&diffstat.clone().into_thrift());
+        assert_eq!(ChangesetDiffstat::from_bytes(&bytes)?, diffstat);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{Context, Result};
+use fbthrift::compact_protocol;
+
+use diffstat_thrift as thrift;
+use mononoke_types::{errors::ErrorKind, ChangesetId, MPath};
+
+/// Stats are only kept for this many files, so that a changeset that changes a huge number of
+/// files doesn't make a huge diffstat. The totals still cover every file.
+pub const DIFFSTAT_MAX_FILES: usize = 10_000;
+
+/// Changeset Diffstat is a derived data structure that stores how many lines
+/// were added and removed by a changeset in each of the files it changed.
+///
+/// It is computed against the first parent of the changeset (or an empty
+/// tree for root changesets), so for merges it describes everything that was
+/// brought in relative to the first parent. Copies and renames are not
+/// tracked: a renamed file shows up as one removed and one added file.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ChangesetDiffstat {
+    /// changeset id of the source Bonsai changeset
+    changeset_id: ChangesetId,
+    /// per-file stats of the first `DIFFSTAT_MAX_FILES` files, sorted by path
+    files: Vec<FileDiffstat>,
+    /// totals over every changed file
+    files_changed: u64,
+    lines_added: u64,
+    lines_removed: u64,
+}
+
+/// Number of lines added and removed in a single file.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct FileDiffstat {
+    pub path: MPath,
+    pub lines_added: u64,
+    pub lines_removed: u64,
+    /// Lines are not counted for binary files, so both counts are zero. Files that are too large
+    /// to diff are also reported as binary.
+    pub is_binary: bool,
+}
+
+impl FileDiffstat {
+    pub(crate) fn from_thrift(tf: thrift::FileDiffstat) -> Result<Self> {
+        Ok(FileDiffstat {
+            path: MPath::from_thrift(tf.path)?,
+            lines_added: tf.lines_added as u64,
+            lines_removed: tf.lines_removed as u64,
+            is_binary: tf.is_binary,
+        })
+    }
+
+    pub(crate) fn into_thrift(self) -> thrift::FileDiffstat {
+        thrift::FileDiffstat {
+            path: self.path.into_thrift(),
+            lines_added: self.lines_added as i64,
+            lines_removed: self.lines_removed as i64,
+            is_binary: self.is_binary,
+        }
+    }
+}
+
+impl ChangesetDiffstat {
+    pub fn new(changeset_id: ChangesetId, mut files: Vec<FileDiffstat>) -> Self {
+        let files_changed = files.len() as u64;
+        let lines_added = files.iter().map(|file| file.lines_added).sum();
+        let lines_removed = files.iter().map(|file| file.lines_removed).sum();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files.truncate(DIFFSTAT_MAX_FILES);
+        Self {
+            changeset_id,
+            files,
+            files_changed,
+            lines_added,
+            lines_removed,
+        }
+    }
+
+    /// Get id of the source Bonsai changeset.
+    pub fn changeset_id(&self) -> &ChangesetId {
+        &self.changeset_id
+    }
+
+    /// Get the stats of each changed file, sorted by path. Only the first `DIFFSTAT_MAX_FILES`
+    /// files are included, so this can have fewer files than `files_changed`.
+    pub fn files(&self) -> &[FileDiffstat] {
+        &self.files
+    }
+
+    /// Get the number of files changed.
+    pub fn files_changed(&self) -> u64 {
+        self.files_changed
+    }
+
+    /// Get the total number of lines added.
+    pub fn lines_added(&self) -> u64 {
+        self.lines_added
+    }
+
+    /// Get the total number of lines removed.
+    pub fn lines_removed(&self) -> u64 {
+        self.lines_removed
+    }
+
+    pub(crate) fn from_thrift(tc: thrift::ChangesetDiffstat) -> Result<Self> {
+        let catch_block = || -> Result<_> {
+            Ok(ChangesetDiffstat {
+                changeset_id: ChangesetId::from_thrift(tc.changeset_id)?,
+                files: tc
+                    .files
+                    .into_iter()
+                    .map(FileDiffstat::from_thrift)
+                    .collect::<Result<_>>()?,
+                files_changed: tc.files_changed as u64,
+                lines_added: tc.lines_added as u64,
+                lines_removed: tc.lines_removed as u64,
+            })
+        };
+
+        Ok(catch_block().with_context(|| {
+            ErrorKind::InvalidThrift(
+                "ChangesetDiffstat".into(),
+                "Invalid changeset diffstat".into(),
+            )
+        })?)
+    }
+
+    pub(crate) fn into_thrift(self) -> thrift::ChangesetDiffstat {
+        thrift::ChangesetDiffstat {
+            changeset_id: self.changeset_id.into_thrift(),
+            files: self
+                .files
+                .into_iter()
+                .map(FileDiffstat::into_thrift)
+                .collect(),
+            files_changed: self.files_changed as i64,
+            lines_added: self.lines_added as i64,
+            lines_removed: self.lines_removed as i64,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let thrift_tc = compact_protocol::deserialize(bytes)
+            .with_context(|| ErrorKind::BlobDeserializeError("ChangesetDiffstat".into()))?;
+        Self::from_thrift(thrift_tc)
+    }
+}
@@ -0,0 +1,30 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+include "eden/mononoke/mononoke_types/if/mononoke_types_thrift.thrift"
+
+// Derived data structure with the number of lines added and removed by a
+// changeset in each file it changed, compared to its first parent.
+struct ChangesetDiffstat {
+  // Changeset id of the source Bonsai changeset
+  1: mononoke_types_thrift.ChangesetId changeset_id,
+  // Stats of the first files changed, sorted by path. Changesets that change
+  // too many files only have some of them here.
+  2: list<FileDiffstat> files,
+  // Totals over every file changed, including those missing from `files`
+  3: i64 files_changed,
+  4: i64 lines_added,
+  5: i64 lines_removed,
+}
+
+struct FileDiffstat {
+  1: mononoke_types_thrift.MPath path,
+  2: i64 lines_added,
+  3: i64 lines_removed,
+  // Lines are not counted for binary files, or for files too large to diff
+  4: bool is_binary,
+}
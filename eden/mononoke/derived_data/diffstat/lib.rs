@@ -0,0 +1,14 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+mod derive;
+mod diffstat;
+
+pub use crate::derive::ChangesetDiffstatMapping;
+pub use crate::diffstat::{ChangesetDiffstat, FileDiffstat, DIFFSTAT_MAX_FILES};
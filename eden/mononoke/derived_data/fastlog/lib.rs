@@ -32,4 +32,4 @@ mod thrift {
 pub use mapping::{
     fetch_parent_root_unodes, ErrorKind, FastlogParent, RootFastlog, RootFastlogMapping,
 };
-pub use ops::{list_file_history, FastlogError, HistoryAcrossDeletions, Visitor};
+pub use ops::{list_file_history, FastlogError, HistoryAcrossDeletions, TraversalOptions, Visitor};
@@ -11,7 +11,7 @@ use blobstore::{Blobstore, BlobstoreBytes, Loadable};
 use bytes::Bytes;
 use cloned::cloned;
 use context::CoreContext;
-use derived_data::{BonsaiDerived, BonsaiDerivedMapping};
+use derived_data::{mapping_blobstore, BonsaiDerived, BonsaiDerivedMapping};
 use futures::{
     compat::{Future01CompatExt, Stream01CompatExt},
     future::{FutureExt as NewFutureExt, TryFutureExt},
@@ -48,15 +48,16 @@ pub enum ErrorKind {
     DeserializationError(String, String),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct RootFastlog(ChangesetId);
 
 impl BonsaiDerived for RootFastlog {
     const NAME: &'static str = "fastlog";
+    const DEPENDENCIES: &'static [&'static str] = &[RootUnodeManifestId::NAME];
     type Mapping = RootFastlogMapping;
 
     fn mapping(_ctx: &CoreContext, repo: &BlobRepo) -> Self::Mapping {
-        RootFastlogMapping::new(repo.blobstore().boxed())
+        RootFastlogMapping::new(mapping_blobstore(repo, Self::NAME))
     }
 
     fn derive_from_parents(
@@ -9,6 +9,7 @@ use anyhow::{format_err, Error};
 use async_trait::async_trait;
 use blobrepo::BlobRepo;
 use blobstore::{Blobstore, Loadable, LoadableError};
+use changeset_info::ChangesetInfo;
 use cloned::cloned;
 use context::CoreContext;
 use deleted_files_manifest::{resolve_path_state, PathState};
@@ -57,12 +58,65 @@ pub enum HistoryAcrossDeletions {
     DontTrack,
 }
 
+/// Options controlling how `list_file_history` traverses the history of a path.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TraversalOptions {
+    /// Whether to continue past the point where the path was created if it
+    /// existed before and was deleted (found using the deleted files manifest).
+    pub history_across_deletions: HistoryAcrossDeletions,
+    /// Only follow the first parent of merges in the history of the path.
+    pub first_parent_only: bool,
+    /// Whether to continue into the history of the source path when a file
+    /// was created as a copy or move of another file.
+    pub follow_renames: bool,
+    /// Stop traversing at changesets with an author date older than this
+    /// timestamp. The history of a path is not ordered by date, so this
+    /// prunes whole branches of the traversal.
+    pub after_timestamp: Option<i64>,
+    /// Don't return changesets with an author date newer than this timestamp.
+    /// Their ancestors are still traversed.
+    pub before_timestamp: Option<i64>,
+    /// Stop traversing at this BFS depth. The starting changesets are at
+    /// depth 0, and each step to the parents in the history of the path
+    /// increases the depth by one.
+    pub max_depth: Option<u64>,
+    /// Don't return changesets at a BFS depth lower than this. A traversal
+    /// limited by `max_depth` can be resumed by setting `skip_depth` to the
+    /// previous `max_depth + 1`.
+    pub skip_depth: u64,
+}
+
+impl Default for TraversalOptions {
+    fn default() -> Self {
+        Self {
+            history_across_deletions: HistoryAcrossDeletions::DontTrack,
+            first_parent_only: false,
+            follow_renames: false,
+            after_timestamp: None,
+            before_timestamp: None,
+            max_depth: None,
+            skip_depth: 0,
+        }
+    }
+}
+
+impl From<HistoryAcrossDeletions> for TraversalOptions {
+    fn from(history_across_deletions: HistoryAcrossDeletions) -> Self {
+        Self {
+            history_across_deletions,
+            ..Default::default()
+        }
+    }
+}
+
 /// Returns a full history of the given path starting from the given unode in BFS order.
 ///
 /// Accepts a `Visitor` object which controls the BFS flow by filtering out the unwanted changesets
 /// before they're added to the queue, see its docs for details. If you don't need to filter the
 /// history you can provide `()` instead for default implementation.
 ///
+/// `options` control the rest of the traversal, see `TraversalOptions`.
+///
 /// This is the public API of this crate i.e. what clients should use if they want to
 /// fetch the history.
 ///
@@ -102,13 +156,14 @@ pub async fn list_file_history(
     path: Option<MPath>,
     changeset_id: ChangesetId,
     mut visitor: impl Visitor,
-    history_across_deletions: HistoryAcrossDeletions,
+    options: TraversalOptions,
 ) -> Result<impl NewStream<Item = Result<ChangesetId, Error>>, FastlogError> {
     let mut top_history = vec![];
     // get unode entry
     let resolved = resolve_path_state(&ctx, &repo, changeset_id, &path).await?;
 
     let mut visited = HashSet::new();
+    let mut depths = HashMap::new();
     let mut history_graph = HashMap::new();
 
     // there might be more than one unode entry: if the given path was
@@ -131,10 +186,12 @@ pub async fn list_file_history(
         &ctx,
         &repo,
         &mut visitor,
+        &options,
         None,
         last_changesets.clone(),
         &mut bfs,
         &mut visited,
+        &mut depths,
         &mut top_history,
     )
     .await?;
@@ -148,6 +205,8 @@ pub async fn list_file_history(
                 TraversalState {
                     history_graph,
                     visited,
+                    depths,
+                    renamed_paths: HashMap::new(),
                     bfs,
                     prefetch: None,
                     visitor,
@@ -156,14 +215,8 @@ pub async fn list_file_history(
                 move |state| {
                     cloned!(ctx, repo, path);
                     async move {
-                        do_history_unfold(
-                            ctx.clone(),
-                            repo.clone(),
-                            path.clone(),
-                            state,
-                            history_across_deletions,
-                        )
-                        .await
+                        do_history_unfold(ctx.clone(), repo.clone(), path.clone(), state, options)
+                            .await
                     }
                 },
             )
@@ -225,22 +278,87 @@ async fn visit(
     ctx: &CoreContext,
     repo: &BlobRepo,
     visitor: &mut impl Visitor,
+    options: &TraversalOptions,
     cs_id: Option<ChangesetId>,
     ancestors: Vec<ChangesetId>,
     bfs: &mut VecDeque<ChangesetId>,
     visited: &mut HashSet<ChangesetId>,
+    depths: &mut HashMap<ChangesetId, u64>,
     history: &mut Vec<ChangesetId>,
 ) -> Result<(), FastlogError> {
+    let depth = match cs_id {
+        Some(cs_id) => depths.get(&cs_id).map_or(0, |depth| depth + 1),
+        None => 0,
+    };
+    match options.max_depth {
+        Some(max_depth) if depth > max_depth => return Ok(()),
+        _ => {}
+    }
+
     let ancestors = visitor.visit(ctx, repo, cs_id, ancestors).await?;
-    for ancestor in ancestors {
+    let ancestors = ancestors
+        .into_iter()
+        .filter(|ancestor| !visited.contains(ancestor))
+        .collect();
+    let ancestors = filter_by_time(ctx, repo, options, ancestors).await?;
+    for (ancestor, in_time_range) in ancestors {
         if visited.insert(ancestor) {
-            history.push(ancestor.clone());
+            depths.insert(ancestor, depth);
+            if in_time_range && depth >= options.skip_depth {
+                history.push(ancestor.clone());
+            }
             bfs.push_back(ancestor);
         }
     }
     Ok(())
 }
 
+// Drops the changesets that are older than `after_timestamp` and marks whether
+// the remaining ones should be returned according to `before_timestamp`.
+async fn filter_by_time(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    options: &TraversalOptions,
+    cs_ids: Vec<ChangesetId>,
+) -> Result<Vec<(ChangesetId, bool)>, Error> {
+    if options.after_timestamp.is_none() && options.before_timestamp.is_none() {
+        return Ok(cs_ids.into_iter().map(|cs_id| (cs_id, true)).collect());
+    }
+
+    let cs_info_enabled = repo
+        .get_derived_data_config()
+        .derived_data_types
+        .contains(ChangesetInfo::NAME);
+    let timestamps = future::try_join_all(cs_ids.iter().map(|cs_id| async move {
+        let info = if cs_info_enabled {
+            ChangesetInfo::derive(ctx.clone(), repo.clone(), *cs_id)
+                .compat()
+                .await?
+        } else {
+            let bonsai = cs_id.load(ctx.clone(), repo.blobstore()).await?;
+            ChangesetInfo::new(*cs_id, bonsai)
+        };
+        Ok::<_, Error>(info.author_date().timestamp_secs())
+    }))
+    .await?;
+
+    Ok(cs_ids
+        .into_iter()
+        .zip(timestamps)
+        .filter(|(_, timestamp)| {
+            options
+                .after_timestamp
+                .map_or(true, |after| *timestamp >= after)
+        })
+        .map(|(cs_id, timestamp)| {
+            let in_time_range = options
+                .before_timestamp
+                .map_or(true, |before| timestamp <= before);
+            (cs_id, in_time_range)
+        })
+        .collect())
+}
+
 type UnodeEntry = Entry<ManifestUnodeId, FileUnodeId>;
 
 // Resolves the deletion nodes and inserts them into history as-if they were normal
@@ -344,6 +462,10 @@ type CommitGraph = HashMap<ChangesetId, Option<Vec<ChangesetId>>>;
 struct TraversalState<V: Visitor> {
     history_graph: CommitGraph,
     visited: HashSet<ChangesetId>,
+    depths: HashMap<ChangesetId, u64>,
+    // paths for the changesets in the history of a rename source, all other
+    // changesets are in the history of the path the traversal started from
+    renamed_paths: HashMap<ChangesetId, Option<MPath>>,
     bfs: VecDeque<ChangesetId>,
     prefetch: Option<ChangesetId>,
     visitor: V,
@@ -354,7 +476,7 @@ async fn do_history_unfold<V>(
     repo: BlobRepo,
     path: Option<MPath>,
     state: TraversalState<V>,
-    history_across_deletions: HistoryAcrossDeletions,
+    options: TraversalOptions,
 ) -> Result<Option<(Vec<ChangesetId>, TraversalState<V>)>, Error>
 where
     V: Visitor,
@@ -362,17 +484,20 @@ where
     let TraversalState {
         mut history_graph,
         mut visited,
+        mut depths,
+        mut renamed_paths,
         mut bfs,
         prefetch,
         mut visitor,
     } = state;
 
     if let Some(prefetch) = prefetch {
+        let prefetch_path = renamed_paths.get(&prefetch).unwrap_or(&path);
         prefetch_and_process_history(
             &ctx,
             &repo,
             &mut visitor,
-            &path,
+            prefetch_path,
             prefetch.clone(),
             &mut history_graph,
         )
@@ -383,32 +508,60 @@ where
     // process nodes to yield
     let mut next_to_fetch = None;
     while let Some(cs_id) = bfs.pop_front() {
+        let cs_path = renamed_paths.get(&cs_id).unwrap_or(&path).clone();
         match history_graph.get(&cs_id) {
             Some(Some(parents)) => {
                 // parents are fetched, ready to process
-                let ancestors = if parents.is_empty()
-                    && history_across_deletions == HistoryAcrossDeletions::Track
+                let mut ancestors = if options.first_parent_only {
+                    parents.iter().take(1).cloned().collect()
+                } else {
+                    parents.clone()
+                };
+                if ancestors.is_empty() && options.follow_renames {
+                    if let Some((source_cs_id, source_path)) =
+                        find_rename_source(&ctx, &repo, &mut history_graph, cs_id, &cs_path).await?
+                    {
+                        renamed_paths.insert(source_cs_id, source_path);
+                        ancestors.push(source_cs_id);
+                    }
+                }
+                if ancestors.is_empty()
+                    && options.history_across_deletions == HistoryAcrossDeletions::Track
                 {
-                    let (stats, deletion_nodes) =
-                        find_where_file_was_deleted(&ctx, &repo, cs_id, &path)
-                            .timed()
-                            .await;
+                    let (stats, deletion_nodes) = find_where_file_was_deleted(
+                        &ctx,
+                        &repo,
+                        cs_id,
+                        &cs_path,
+                        options.first_parent_only,
+                    )
+                    .timed()
+                    .await;
                     STATS::find_where_file_was_deleted_ms
                         .add_value(stats.completion_time.as_millis_unchecked() as i64);
                     let deletion_nodes = deletion_nodes?;
-                    process_deletion_nodes(&ctx, &repo, &mut history_graph, deletion_nodes).await?
-                } else {
-                    parents.clone()
-                };
+                    ancestors =
+                        process_deletion_nodes(&ctx, &repo, &mut history_graph, deletion_nodes)
+                            .await?;
+                }
+                if renamed_paths.contains_key(&cs_id) {
+                    for ancestor in &ancestors {
+                        renamed_paths
+                            .entry(*ancestor)
+                            .or_insert_with(|| cs_path.clone());
+                    }
+                }
 
                 visit(
                     &ctx,
                     &repo,
                     &mut visitor,
+                    &options,
                     Some(cs_id),
                     ancestors,
                     &mut bfs,
                     &mut visited,
+                    &mut depths,
                     &mut history,
                 )
                 .await?;
@@ -419,7 +572,7 @@ where
                 if Some(cs_id) == prefetch {
                     return Err(format_err!(
                         "internal error: infinite loop while traversing history for {:?}",
-                        path
+                        cs_path
                     ));
                 }
                 next_to_fetch = Some(cs_id);
@@ -443,6 +596,8 @@ where
         TraversalState {
             history_graph,
             visited,
+            depths,
+            renamed_paths,
             bfs,
             prefetch: next_to_fetch,
             visitor,
@@ -463,11 +618,15 @@ async fn find_where_file_was_deleted(
     repo: &BlobRepo,
     commit_no_more_history: ChangesetId,
     path: &Option<MPath>,
+    first_parent_only: bool,
 ) -> Result<Vec<(ChangesetId, UnodeEntry)>, Error> {
-    let parents = repo
+    let mut parents = repo
         .get_changeset_parents_by_bonsai(ctx.clone(), commit_no_more_history)
         .compat()
         .await?;
+    if first_parent_only {
+        parents.truncate(1);
+    }
 
     let resolved_path_states = future::try_join_all(
         parents
@@ -495,6 +654,51 @@ async fn find_where_file_was_deleted(
     Ok(all_deletion_nodes)
 }
 
+// If the file at `path` was created in this changeset as a copy or a move of
+// another file, returns the changeset where the source file was last changed
+// along with the source path, and adds that changeset to the history graph.
+async fn find_rename_source(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    history_graph: &mut CommitGraph,
+    cs_id: ChangesetId,
+    path: &Option<MPath>,
+) -> Result<Option<(ChangesetId, Option<MPath>)>, Error> {
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let bonsai = cs_id.load(ctx.clone(), repo.blobstore()).await?;
+    let copy_from = bonsai
+        .file_changes_map()
+        .get(path)
+        .and_then(|file_change| file_change.as_ref()?.copy_from().cloned());
+    let (source_path, source_cs_id) = match copy_from {
+        Some(copy_from) => copy_from,
+        None => return Ok(None),
+    };
+
+    let source_path = Some(source_path);
+    let source_entry = derive_unode_entry(ctx, repo, source_cs_id, &source_path)
+        .await?
+        .ok_or_else(|| {
+            format_err!(
+                "Copy source {:?} is not found in {}",
+                source_path,
+                source_cs_id
+            )
+        })?;
+    let linknode = match source_entry
+        .load(ctx.clone(), &repo.get_blobstore())
+        .await?
+    {
+        Entry::Tree(mf_unode) => mf_unode.linknode().clone(),
+        Entry::Leaf(file_unode) => file_unode.linknode().clone(),
+    };
+    history_graph.entry(linknode).or_insert(None);
+    Ok(Some((linknode, source_path)))
+}
+
 /// prefetches and processes fastlog batch for the given changeset id
 async fn prefetch_and_process_history(
     ctx: &CoreContext,
@@ -601,6 +805,7 @@ mod test {
     use context::CoreContext;
     use fbinit::FacebookInit;
     use futures::future::TryFutureExt;
+    use mononoke_types::DateTime;
     use tests_utils::CreateCommitContext;
 
     #[fbinit::compat_test]
@@ -639,7 +844,7 @@ mod test {
             path(filename),
             top,
             (),
-            HistoryAcrossDeletions::Track,
+            HistoryAcrossDeletions::Track.into(),
         )
         .await?;
         let history = history.try_collect::<Vec<_>>().await?;
@@ -712,7 +917,7 @@ mod test {
             path(filename),
             top,
             (),
-            HistoryAcrossDeletions::Track,
+            HistoryAcrossDeletions::Track.into(),
         )
         .await?;
         let history = history.try_collect::<Vec<_>>().await?;
@@ -777,7 +982,7 @@ mod test {
             path(filename),
             prev_id,
             (),
-            HistoryAcrossDeletions::Track,
+            HistoryAcrossDeletions::Track.into(),
         )
         .await?;
         let history = history.try_collect::<Vec<_>>().await?;
@@ -849,7 +1054,7 @@ mod test {
             filepath.clone(),
             top.clone(),
             NothingVisitor {},
-            HistoryAcrossDeletions::Track,
+            HistoryAcrossDeletions::Track.into(),
         )
         .await?;
         let history = history.try_collect::<Vec<_>>().await?;
@@ -877,7 +1082,7 @@ mod test {
             filepath,
             top,
             SingleBranchOfHistoryVisitor {},
-            HistoryAcrossDeletions::Track,
+            HistoryAcrossDeletions::Track.into(),
         )
         .await?;
         let history = history.try_collect::<Vec<_>>().await?;
@@ -940,7 +1145,7 @@ mod test {
                     path,
                     cs_id,
                     (),
-                    HistoryAcrossDeletions::Track,
+                    HistoryAcrossDeletions::Track.into(),
                 )
                 .await?;
                 history_stream.try_collect::<Vec<_>>().await
@@ -1067,7 +1272,7 @@ mod test {
                     path,
                     cs_id,
                     (),
-                    HistoryAcrossDeletions::Track,
+                    HistoryAcrossDeletions::Track.into(),
                 )
                 .await?;
                 history_stream.try_collect::<Vec<_>>().await
@@ -1135,7 +1340,7 @@ mod test {
             MPath::new_opt(filename)?,
             bcs_id,
             (),
-            HistoryAcrossDeletions::Track,
+            HistoryAcrossDeletions::Track.into(),
         )
         .await?;
         let expected = expected.into_iter().rev().collect::<Vec<_>>();
@@ -1149,7 +1354,7 @@ mod test {
             MPath::new_opt(filename)?,
             bcs_id,
             (),
-            HistoryAcrossDeletions::DontTrack,
+            HistoryAcrossDeletions::DontTrack.into(),
         )
         .await?;
         let actual = history_stream.try_collect::<Vec<_>>().await?;
@@ -1212,7 +1417,7 @@ mod test {
             MPath::new_opt(filename)?,
             bcs_id,
             (),
-            HistoryAcrossDeletions::Track,
+            HistoryAcrossDeletions::Track.into(),
         )
         .await?;
         let mut expected = expected.into_iter().rev().collect::<Vec<_>>();
@@ -1227,7 +1432,7 @@ mod test {
             MPath::new_opt(filename)?,
             merge,
             (),
-            HistoryAcrossDeletions::Track,
+            HistoryAcrossDeletions::Track.into(),
         )
         .await?;
         expected.remove(0);
@@ -1237,6 +1442,140 @@ mod test {
         Ok(())
     }
 
+    #[fbinit::compat_test]
+    async fn test_list_history_traversal_options(fb: FacebookInit) -> Result<(), Error> {
+        let repo = new_memblob_empty(None).unwrap();
+        let ctx = CoreContext::test_mock(fb);
+
+        //    O top   - 5000
+        //    |
+        //    O m     - 4000
+        //   / \
+        //  a O O b   - 2000, 3000
+        //   \ /
+        //    O r     - 1000
+        let commit = |parents: Vec<ChangesetId>, content: &'static str, date: i64| {
+            let ctx = &ctx;
+            let repo = &repo;
+            async move {
+                CreateCommitContext::new(ctx, repo, parents)
+                    .add_file("f", content)
+                    .set_author_date(DateTime::from_timestamp(date, 0)?)
+                    .commit()
+                    .await
+            }
+        };
+        let r = commit(vec![], "r", 1000).await?;
+        let a = commit(vec![r], "a", 2000).await?;
+        let b = commit(vec![r], "b", 3000).await?;
+        let m = commit(vec![a, b], "m", 4000).await?;
+        let top = commit(vec![m], "top", 5000).await?;
+
+        let history = |options: TraversalOptions| {
+            cloned!(ctx, repo);
+            async move {
+                list_file_history(ctx, repo, path("f"), top, (), options)
+                    .await?
+                    .try_collect::<Vec<_>>()
+                    .await
+            }
+        };
+
+        assert_eq!(history(Default::default()).await?, vec![top, m, a, b, r]);
+        assert_eq!(
+            history(TraversalOptions {
+                first_parent_only: true,
+                ..Default::default()
+            })
+            .await?,
+            vec![top, m, a, r]
+        );
+        assert_eq!(
+            history(TraversalOptions {
+                max_depth: Some(1),
+                ..Default::default()
+            })
+            .await?,
+            vec![top, m]
+        );
+        assert_eq!(
+            history(TraversalOptions {
+                skip_depth: 2,
+                ..Default::default()
+            })
+            .await?,
+            vec![a, b, r]
+        );
+        assert_eq!(
+            history(TraversalOptions {
+                after_timestamp: Some(2500),
+                ..Default::default()
+            })
+            .await?,
+            vec![top, m, b]
+        );
+        assert_eq!(
+            history(TraversalOptions {
+                before_timestamp: Some(4500),
+                ..Default::default()
+            })
+            .await?,
+            vec![m, a, b, r]
+        );
+
+        Ok(())
+    }
+
+    #[fbinit::compat_test]
+    async fn test_list_history_follow_renames(fb: FacebookInit) -> Result<(), Error> {
+        let repo = new_memblob_empty(None).unwrap();
+        let ctx = CoreContext::test_mock(fb);
+
+        let a1 = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("a", "1")
+            .commit()
+            .await?;
+        let a2 = CreateCommitContext::new(&ctx, &repo, vec![a1])
+            .add_file("a", "2")
+            .commit()
+            .await?;
+        let unrelated = CreateCommitContext::new(&ctx, &repo, vec![a2])
+            .add_file("unrelated", "1")
+            .commit()
+            .await?;
+        let moved = CreateCommitContext::new(&ctx, &repo, vec![unrelated])
+            .add_file_with_copy_info("b", "2", (unrelated, "a"))?
+            .delete_file("a")
+            .commit()
+            .await?;
+        let b2 = CreateCommitContext::new(&ctx, &repo, vec![moved])
+            .add_file("b", "3")
+            .commit()
+            .await?;
+
+        let history = |options: TraversalOptions| {
+            cloned!(ctx, repo);
+            async move {
+                list_file_history(ctx, repo, path("b"), b2, (), options)
+                    .await?
+                    .try_collect::<Vec<_>>()
+                    .await
+            }
+        };
+
+        assert_eq!(history(Default::default()).await?, vec![b2, moved]);
+        assert_eq!(
+            history(TraversalOptions {
+                follow_renames: true,
+                ..Default::default()
+            })
+            .await?,
+            vec![b2, moved, a2, a1]
+        );
+
+        Ok(())
+    }
+
     type TestCommitGraph = HashMap<ChangesetId, Vec<ChangesetId>>;
 
     async fn create_branch(
@@ -0,0 +1,403 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{bail, Context, Result};
+use fbthrift::compact_protocol;
+
+use file_classification_thrift as thrift;
+use mononoke_types::{errors::ErrorKind, ContentId, MPath, MPathElement};
+
+/// Maximum number of bytes at the start of a file that are used to classify it.
+pub const CLASSIFICATION_SAMPLE_SIZE: usize = 1024 * 1024;
+
+/// Marker used to flag generated files.
+const GENERATED_MARKER: &[u8] = b"@generated";
+
+/// Directory names that contain vendored third-party code.
+const VENDORED_DIRS: &[&str] = &["node_modules", "third-party", "third_party", "vendor"];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum TextEncoding {
+    Binary,
+    Ascii,
+    Utf8,
+    Utf16,
+    /// Text that is neither ASCII, UTF-8 nor UTF-16, e.g. Latin-1.
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum LineEndings {
+    /// Binary files, and text without any line breaks.
+    None,
+    Lf,
+    Crlf,
+    Mixed,
+}
+
+/// File Classification is a derived data structure that describes the kind
+/// of a file based on its content.
+///
+/// It is stored once for each file content, so anything that depends on the
+/// path of the file is not part of it. Use `language` to combine the
+/// language detected from the shebang line with the file extension.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct FileClassification {
+    content_id: ContentId,
+    mime_type: String,
+    encoding: TextEncoding,
+    line_endings: LineEndings,
+    generated: bool,
+    shebang_language: Option<String>,
+}
+
+impl FileClassification {
+    /// Classify a file from its content. Only the first
+    /// `CLASSIFICATION_SAMPLE_SIZE` bytes of `content` are inspected.
+    pub fn classify(content_id: ContentId, content: &[u8]) -> Self {
+        let truncated = content.len() > CLASSIFICATION_SAMPLE_SIZE;
+        let content = &content[..content.len().min(CLASSIFICATION_SAMPLE_SIZE)];
+        let encoding = detect_encoding(content, truncated);
+        let line_endings = match encoding {
+            TextEncoding::Binary | TextEncoding::Utf16 => LineEndings::None,
+            _ => detect_line_endings(content),
+        };
+        let shebang_language = match encoding {
+            TextEncoding::Binary => None,
+            _ => shebang_interpreter(content).and_then(language_for_interpreter),
+        };
+        let generated = encoding != TextEncoding::Binary
+            && content
+                .windows(GENERATED_MARKER.len())
+                .any(|window| window == GENERATED_MARKER);
+        let mime_type = detect_mime_type(content, encoding, shebang_language.is_some());
+
+        Self {
+            content_id,
+            mime_type: mime_type.to_string(),
+            encoding,
+            line_endings,
+            generated,
+            shebang_language: shebang_language.map(String::from),
+        }
+    }
+
+    /// Get the id of the classified content.
+    pub fn content_id(&self) -> &ContentId {
+        &self.content_id
+    }
+
+    /// Get the detected MIME type, e.g. `text/plain` or `image/png`.
+    pub fn mime_type(&self) -> &str {
+        &self.mime_type
+    }
+
+    pub fn encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
+    pub fn line_endings(&self) -> LineEndings {
+        self.line_endings
+    }
+
+    /// Whether the file is binary.
+    pub fn is_binary(&self) -> bool {
+        self.encoding == TextEncoding::Binary
+    }
+
+    /// Whether the content contains an `@generated` marker.
+    pub fn is_generated(&self) -> bool {
+        self.generated
+    }
+
+    /// Get the language of the interpreter named in the shebang line.
+    pub fn shebang_language(&self) -> Option<&str> {
+        self.shebang_language.as_deref()
+    }
+
+    /// Get the language of this content at the given path. The file
+    /// extension takes priority over the shebang line.
+    pub fn language(&self, path: Option<&MPath>) -> Option<&str> {
+        path.and_then(language_for_path)
+            .or_else(|| self.shebang_language())
+    }
+
+    pub(crate) fn from_thrift(tc: thrift::FileClassification) -> Result<Self> {
+        let catch_block = || -> Result<_> {
+            Ok(FileClassification {
+                content_id: ContentId::from_thrift(tc.content_id)?,
+                mime_type: tc.mime_type,
+                encoding: match tc.encoding {
+                    thrift::TextEncoding::BINARY => TextEncoding::Binary,
+                    thrift::TextEncoding::ASCII => TextEncoding::Ascii,
+                    thrift::TextEncoding::UTF8 => TextEncoding::Utf8,
+                    thrift::TextEncoding::UTF16 => TextEncoding::Utf16,
+                    thrift::TextEncoding::OTHER => TextEncoding::Other,
+                    other => bail!("unknown text encoding {}", other),
+                },
+                line_endings: match tc.line_endings {
+                    thrift::LineEndings::NONE => LineEndings::None,
+                    thrift::LineEndings::LF => LineEndings::Lf,
+                    thrift::LineEndings::CRLF => LineEndings::Crlf,
+                    thrift::LineEndings::MIXED => LineEndings::Mixed,
+                    other => bail!("unknown line endings {}", other),
+                },
+                generated: tc.generated,
+                shebang_language: tc.shebang_language,
+            })
+        };
+
+        Ok(catch_block().with_context(|| {
+            ErrorKind::InvalidThrift(
+                "FileClassification".into(),
+                "Invalid file classification".into(),
+            )
+        })?)
+    }
+
+    pub(crate) fn into_thrift(self) -> thrift::FileClassification {
+        thrift::FileClassification {
+            content_id: self.content_id.into_thrift(),
+            mime_type: self.mime_type,
+            encoding: match self.encoding {
+                TextEncoding::Binary => thrift::TextEncoding::BINARY,
+                TextEncoding::Ascii => thrift::TextEncoding::ASCII,
+                TextEncoding::Utf8 => thrift::TextEncoding::UTF8,
+                TextEncoding::Utf16 => thrift::TextEncoding::UTF16,
+                TextEncoding::Other => thrift::TextEncoding::OTHER,
+            },
+            line_endings: match self.line_endings {
+                LineEndings::None => thrift::LineEndings::NONE,
+                LineEndings::Lf => thrift::LineEndings::LF,
+                LineEndings::Crlf => thrift::LineEndings::CRLF,
+                LineEndings::Mixed => thrift::LineEndings::MIXED,
+            },
+            generated: self.generated,
+            shebang_language: self.shebang_language,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let thrift_tc = compact_protocol::deserialize(bytes)
+            .with_context(|| ErrorKind::BlobDeserializeError("FileClassification".into()))?;
+        Self::from_thrift(thrift_tc)
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        compact_protocol::serialize(&self.into_thrift())
+    }
+}
+
+fn detect_encoding(content: &[u8], truncated: bool) -> TextEncoding {
+    if content.starts_with(b"\xff\xfe") || content.starts_with(b"\xfe\xff") {
+        return TextEncoding::Utf16;
+    }
+    if content.contains(&0) {
+        return TextEncoding::Binary;
+    }
+    if content.is_ascii() {
+        return TextEncoding::Ascii;
+    }
+    match std::str::from_utf8(content) {
+        Ok(_) => TextEncoding::Utf8,
+        // The sample may end in the middle of a character.
+        Err(e) if truncated && e.error_len().is_none() => TextEncoding::Utf8,
+        Err(_) => TextEncoding::Other,
+    }
+}
+
+fn detect_line_endings(content: &[u8]) -> LineEndings {
+    let mut lf = false;
+    let mut crlf = false;
+    for (index, _) in content.iter().enumerate().filter(|(_, c)| **c == b'\n') {
+        if index > 0 && content[index - 1] == b'\r' {
+            crlf = true;
+        } else {
+            lf = true;
+        }
+    }
+    match (lf, crlf) {
+        (false, false) => LineEndings::None,
+        (true, false) => LineEndings::Lf,
+        (false, true) => LineEndings::Crlf,
+        (true, true) => LineEndings::Mixed,
+    }
+}
+
+fn detect_mime_type(content: &[u8], encoding: TextEncoding, has_shebang: bool) -> &'static str {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\x28\xb5\x2f\xfd", "application/zstd"),
+        (b"BZh", "application/x-bzip2"),
+        (b"\x7fELF", "application/x-executable"),
+    ];
+    for (magic, mime_type) in MAGIC {
+        if content.starts_with(magic) {
+            return mime_type;
+        }
+    }
+    match encoding {
+        TextEncoding::Binary => "application/octet-stream",
+        _ if has_shebang => "text/x-script",
+        _ => "text/plain",
+    }
+}
+
+/// Returns the name of the interpreter in the shebang line, skipping
+/// `/usr/bin/env` and its options.
+fn shebang_interpreter(content: &[u8]) -> Option<&str> {
+    if !content.starts_with(b"#!") {
+        return None;
+    }
+    let line = content[2..].split(|c| *c == b'\n').next()?;
+    let line = std::str::from_utf8(line).ok()?;
+    let mut words = line.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+    Some(interpreter)
+}
+
+fn language_for_interpreter(interpreter: &str) -> Option<&'static str> {
+    // Drop version suffixes like in python3.8
+    let name = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let language = match name {
+        "sh" | "bash" | "dash" | "ksh" | "zsh" | "fish" => "Shell",
+        "python" | "pypy" => "Python",
+        "perl" => "Perl",
+        "ruby" => "Ruby",
+        "node" | "nodejs" => "JavaScript",
+        "php" => "PHP",
+        "lua" => "Lua",
+        "tclsh" => "Tcl",
+        "Rscript" => "R",
+        "awk" | "gawk" => "Awk",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Returns the language of a file based on its name and extension.
+pub fn language_for_path(path: &MPath) -> Option<&'static str> {
+    let basename = String::from_utf8_lossy(path.basename().as_ref());
+    let language = match basename.as_ref() {
+        "BUCK" | "TARGETS" | "BUILD" | "WORKSPACE" => "Starlark",
+        "CMakeLists.txt" => "CMake",
+        "Dockerfile" => "Dockerfile",
+        "Makefile" | "GNUmakefile" => "Makefile",
+        _ => {
+            let extension = &basename[basename.rfind('.')? + 1..];
+            match extension {
+                "c" | "h" => "C",
+                "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "C++",
+                "cs" => "C#",
+                "css" => "CSS",
+                "go" => "Go",
+                "hs" => "Haskell",
+                "html" | "htm" => "HTML",
+                "java" => "Java",
+                "js" | "jsx" | "mjs" => "JavaScript",
+                "json" => "JSON",
+                "kt" | "kts" => "Kotlin",
+                "lua" => "Lua",
+                "m" | "mm" => "Objective-C",
+                "md" => "Markdown",
+                "ml" | "mli" => "OCaml",
+                "php" => "PHP",
+                "pl" | "pm" => "Perl",
+                "py" | "pyi" => "Python",
+                "rb" => "Ruby",
+                "rs" => "Rust",
+                "scala" => "Scala",
+                "sh" | "bash" | "zsh" => "Shell",
+                "sql" => "SQL",
+                "swift" => "Swift",
+                "thrift" => "Thrift",
+                "toml" => "TOML",
+                "ts" | "tsx" => "TypeScript",
+                "xml" => "XML",
+                "yaml" | "yml" => "YAML",
+                "bzl" | "star" => "Starlark",
+                _ => return None,
+            }
+        }
+    };
+    Some(language)
+}
+
+/// Returns true if the path is inside a directory of vendored third-party
+/// code, like `vendor` or `third-party`.
+pub fn is_vendored_path(path: &MPath) -> bool {
+    let elements: &[MPathElement] = path.as_ref();
+    let dirs = &elements[..elements.len() - 1];
+    dirs.iter().any(|dir| {
+        VENDORED_DIRS
+            .iter()
+            .any(|vendored| dir.as_ref() == vendored.as_bytes())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use mononoke_types_mocks::contentid::ONES_CTID;
+
+    #[test]
+    fn classify_text() {
+        let classification = FileClassification::classify(
+            ONES_CTID,
+            b"#!/usr/bin/env -S python3 -u\n# @generated\r\nprint('\xc3\xa9')\n",
+        );
+        assert_eq!(classification.mime_type(), "text/x-script");
+        assert_eq!(classification.encoding(), TextEncoding::Utf8);
+        assert_eq!(classification.line_endings(), LineEndings::Mixed);
+        assert!(classification.is_generated());
+        assert_eq!(classification.shebang_language(), Some("Python"));
+        assert_eq!(classification.language(None), Some("Python"));
+        assert_eq!(
+            classification.language(Some(&MPath::new("bin/tool.sh").unwrap())),
+            Some("Shell")
+        );
+        assert_eq!(
+            classification.language(Some(&MPath::new("bin/tool").unwrap())),
+            Some("Python")
+        );
+    }
+
+    #[test]
+    fn classify_binary() {
+        let classification = FileClassification::classify(ONES_CTID, b"\x89PNG\r\n\x1a\n\0\0");
+        assert_eq!(classification.mime_type(), "image/png");
+        assert_eq!(classification.encoding(), TextEncoding::Binary);
+        assert_eq!(classification.line_endings(), LineEndings::None);
+        assert!(!classification.is_generated());
+        assert!(classification.is_binary());
+
+        let classification = FileClassification::classify(ONES_CTID, b"caf\xe9\r\n");
+        assert_eq!(classification.mime_type(), "text/plain");
+        assert_eq!(classification.encoding(), TextEncoding::Other);
+        assert_eq!(classification.line_endings(), LineEndings::Crlf);
+    }
+
+    #[test]
+    fn vendored_paths() {
+        assert!(is_vendored_path(
+            &MPath::new("third-party/foo/lib.rs").unwrap()
+        ));
+        assert!(is_vendored_path(&MPath::new("a/vendor/b").unwrap()));
+        assert!(!is_vendored_path(&MPath::new("a/vendor").unwrap()));
+        assert!(!is_vendored_path(&MPath::new("src/lib.rs").unwrap()));
+    }
+}
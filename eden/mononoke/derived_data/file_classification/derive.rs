@@ -0,0 +1,235 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{format_err, Error};
+use bytes::Bytes;
+use std::{collections::HashMap, iter::FromIterator, sync::Arc};
+
+use blobrepo::BlobRepo;
+use blobstore::{Blobstore, BlobstoreBytes};
+use context::CoreContext;
+use derived_data::{mapping_blobstore, BonsaiDerived, BonsaiDerivedMapping};
+use filestore::FetchKey;
+use futures::{
+    compat::Future01CompatExt,
+    future::{FutureExt as NewFutureExt, TryFutureExt},
+    stream::{self, StreamExt as NewStreamExt, TryStreamExt},
+};
+use futures_ext::{BoxFuture, FutureExt};
+use futures_old::{stream::FuturesUnordered, Future, Stream};
+use mononoke_types::{BonsaiChangeset, ChangesetId, ContentId};
+
+use crate::classification::{FileClassification, CLASSIFICATION_SAMPLE_SIZE};
+
+/// Number of file contents that are classified concurrently.
+const CONCURRENT_CLASSIFICATIONS: usize = 100;
+
+fn classification_key(content_id: &ContentId) -> String {
+    format!("file_classification.blake2.{}", content_id)
+}
+
+/// Returns the classification of a file content.
+///
+/// Classifications are stored per file content, and like the filestore
+/// metadata they are computed and stored on the fly if they are missing.
+pub async fn fetch_file_classification<B: Blobstore + Clone>(
+    ctx: &CoreContext,
+    blobstore: &B,
+    content_id: ContentId,
+) -> Result<FileClassification, Error> {
+    if let Some(classification) =
+        fetch_stored_file_classification(ctx, blobstore, content_id).await?
+    {
+        return Ok(classification);
+    }
+
+    // One byte more than the sample size tells whether the sample is the
+    // complete content.
+    let sample = filestore::peek(
+        blobstore,
+        ctx.clone(),
+        &FetchKey::Canonical(content_id),
+        CLASSIFICATION_SAMPLE_SIZE + 1,
+    )
+    .compat()
+    .await?
+    .ok_or_else(|| format_err!("Content {} is not found", content_id))?;
+    let classification = FileClassification::classify(content_id, &sample);
+    blobstore
+        .put(
+            ctx.clone(),
+            classification_key(&content_id),
+            BlobstoreBytes::from_bytes(classification.clone().into_bytes()),
+        )
+        .await?;
+    Ok(classification)
+}
+
+/// Returns the classification of a file content if it has already been
+/// stored, without computing it.
+///
+/// Classifications are stored for all the file contents of changesets that
+/// have `RootFileClassification` derived.
+pub async fn fetch_stored_file_classification<B: Blobstore>(
+    ctx: &CoreContext,
+    blobstore: &B,
+    content_id: ContentId,
+) -> Result<Option<FileClassification>, Error> {
+    match blobstore
+        .get(ctx.clone(), classification_key(&content_id))
+        .await?
+    {
+        Some(bytes) => Ok(Some(FileClassification::from_bytes(bytes.as_raw_bytes())?)),
+        None => Ok(None),
+    }
+}
+
+/// Derived data marker for a changeset whose new file contents have all been
+/// classified.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RootFileClassification(ChangesetId);
+
+impl BonsaiDerived for RootFileClassification {
+    const NAME: &'static str = "file_classification";
+    type Mapping = RootFileClassificationMapping;
+
+    fn mapping(_ctx: &CoreContext, repo: &BlobRepo) -> Self::Mapping {
+        RootFileClassificationMapping::new(mapping_blobstore(repo, Self::NAME))
+    }
+
+    fn derive_from_parents(
+        ctx: CoreContext,
+        repo: BlobRepo,
+        bonsai: BonsaiChangeset,
+        _parents: Vec<Self>,
+    ) -> BoxFuture<Self, Error> {
+        let csid = bonsai.get_changeset_id();
+        let content_ids: Vec<_> = bonsai
+            .file_changes()
+            .filter_map(|(_path, file_change)| Some(file_change?.content_id()))
+            .collect();
+        async move {
+            let blobstore = repo.get_blobstore();
+            stream::iter(content_ids)
+                .map(|content_id| fetch_file_classification(&ctx, &blobstore, content_id))
+                .buffer_unordered(CONCURRENT_CLASSIFICATIONS)
+                .try_for_each(|_| async { Ok(()) })
+                .await?;
+            Ok(RootFileClassification(csid))
+        }
+        .boxed()
+        .compat()
+        .boxify()
+    }
+}
+
+#[derive(Clone)]
+pub struct RootFileClassificationMapping {
+    blobstore: Arc<dyn Blobstore>,
+}
+
+impl RootFileClassificationMapping {
+    pub fn new(blobstore: Arc<dyn Blobstore>) -> Self {
+        Self { blobstore }
+    }
+
+    fn format_key(&self, csid: &ChangesetId) -> String {
+        format!("derived_root_file_classification.v1.{}", csid)
+    }
+}
+
+impl BonsaiDerivedMapping for RootFileClassificationMapping {
+    type Value = RootFileClassification;
+
+    fn get(
+        &self,
+        ctx: CoreContext,
+        csids: Vec<ChangesetId>,
+    ) -> BoxFuture<HashMap<ChangesetId, Self::Value>, Error> {
+        let futs = csids.into_iter().map(|csid| {
+            self.blobstore
+                .get(ctx.clone(), self.format_key(&csid))
+                .compat()
+                .map(move |val| val.map(|_| (csid, RootFileClassification(csid))))
+        });
+        FuturesUnordered::from_iter(futs)
+            .filter_map(|v| v)
+            .collect()
+            .map(|entries| entries.into_iter().collect())
+            .boxify()
+    }
+
+    fn put(&self, ctx: CoreContext, csid: ChangesetId, _id: Self::Value) -> BoxFuture<(), Error> {
+        self.blobstore
+            .put(
+                ctx,
+                self.format_key(&csid),
+                BlobstoreBytes::from_bytes(Bytes::new()),
+            )
+            .compat()
+            .boxify()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use blobstore::Loadable;
+    use fbinit::FacebookInit;
+    use tests_utils::CreateCommitContext;
+
+    use crate::classification::TextEncoding;
+
+    #[fbinit::compat_test]
+    async fn derive_file_classification_test(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = blobrepo_factory::new_memblob_empty(None)?;
+
+        let csid = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("script", "#!/bin/bash\necho hi\n")
+            .add_file("bin", "bin\0ary")
+            .commit()
+            .await?;
+        RootFileClassification::derive(ctx.clone(), repo.clone(), csid)
+            .compat()
+            .await?;
+
+        let bonsai = csid.load(ctx.clone(), repo.blobstore()).await?;
+        let blobstore = repo.get_blobstore();
+        for (path, file_change) in bonsai.file_changes() {
+            let content_id = file_change.unwrap().content_id();
+            // The classification was stored when deriving
+            let bytes = blobstore
+                .get(ctx.clone(), classification_key(&content_id))
+                .await?
+                .expect("classification is stored");
+            let classification = FileClassification::from_bytes(bytes.as_raw_bytes())?;
+            assert_eq!(
+                Some(classification.clone()),
+                fetch_stored_file_classification(&ctx, &blobstore, content_id).await?
+            );
+            assert_eq!(
+                classification,
+                fetch_file_classification(&ctx, &blobstore, content_id).await?
+            );
+            match path.to_string().as_str() {
+                "script" => {
+                    assert_eq!(classification.encoding(), TextEncoding::Ascii);
+                    assert_eq!(classification.language(Some(path)), Some("Shell"));
+                }
+                "bin" => {
+                    assert!(classification.is_binary());
+                    assert_eq!(classification.mime_type(), "application/octet-stream");
+                }
+                _ => panic!("unexpected path {}", path),
+            }
+        }
+
+        Ok(())
+    }
+}
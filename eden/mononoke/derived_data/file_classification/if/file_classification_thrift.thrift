@@ -0,0 +1,39 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+include "eden/mononoke/mononoke_types/if/mononoke_types_thrift.thrift"
+
+enum TextEncoding {
+  BINARY = 0,
+  ASCII = 1,
+  UTF8 = 2,
+  UTF16 = 3,
+  // Text that is neither ASCII, UTF-8 nor UTF-16, e.g. Latin-1
+  OTHER = 4,
+}
+
+enum LineEndings {
+  // Binary files, and text without any line breaks
+  NONE = 0,
+  LF = 1,
+  CRLF = 2,
+  MIXED = 3,
+}
+
+// Derived data structure describing the kind of a file, based only on its
+// content. It is stored for each file content, so anything that depends on
+// the path of the file (e.g. its extension) is not part of it.
+struct FileClassification {
+  1: mononoke_types_thrift.ContentId content_id,
+  2: string mime_type,
+  3: TextEncoding encoding,
+  4: LineEndings line_endings,
+  // Whether the content contains an @generated marker
+  5: bool generated,
+  // Language of the interpreter named in the shebang line, if any
+  6: optional string shebang_language,
+}
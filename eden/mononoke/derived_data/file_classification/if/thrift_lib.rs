@@ -0,0 +1,2 @@
+// @generated
+::codegen_includer_proc_macro::include!();
@@ -0,0 +1,20 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+mod classification;
+mod derive;
+
+pub use crate::classification::{
+    is_vendored_path, language_for_path, FileClassification, LineEndings, TextEncoding,
+    CLASSIFICATION_SAMPLE_SIZE,
+};
+pub use crate::derive::{
+    fetch_file_classification, fetch_stored_file_classification, RootFileClassification,
+    RootFileClassificationMapping,
+};
@@ -13,7 +13,7 @@ use blobrepo::BlobRepo;
 use blobstore::{Blobstore, BlobstoreGetData};
 use bytes::Bytes;
 use context::CoreContext;
-use derived_data::{BonsaiDerived, BonsaiDerivedMapping};
+use derived_data::{mapping_blobstore, BonsaiDerived, BonsaiDerivedMapping};
 use futures::{
     compat::Future01CompatExt, stream as new_stream, StreamExt as NewStreamExt, TryFutureExt,
     TryStreamExt,
@@ -26,11 +26,11 @@ use futures_old::{
 use mononoke_types::{
     BlobstoreBytes, BonsaiChangeset, ChangesetId, ContentId, FileType, FsnodeId, MPath,
 };
-use repo_blobstore::RepoBlobstore;
 use std::{
     collections::HashMap,
     convert::{TryFrom, TryInto},
     iter::FromIterator,
+    sync::Arc,
 };
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -73,7 +73,7 @@ impl BonsaiDerived for RootFsnodeId {
     type Mapping = RootFsnodeMapping;
 
     fn mapping(_ctx: &CoreContext, repo: &BlobRepo) -> Self::Mapping {
-        RootFsnodeMapping::new(repo.blobstore().clone())
+        RootFsnodeMapping::new(mapping_blobstore(repo, Self::NAME))
     }
 
     fn derive_from_parents(
@@ -129,11 +129,11 @@ impl BonsaiDerived for RootFsnodeId {
 // TODO(mbthomas): this is copy-pasted from unodes
 #[derive(Clone)]
 pub struct RootFsnodeMapping {
-    blobstore: RepoBlobstore,
+    blobstore: Arc<dyn Blobstore>,
 }
 
 impl RootFsnodeMapping {
-    pub fn new(blobstore: RepoBlobstore) -> Self {
+    pub fn new(blobstore: Arc<dyn Blobstore>) -> Self {
         Self { blobstore }
     }
 
@@ -0,0 +1,238 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{Context, Error};
+use blobrepo::BlobRepo;
+use blobstore::{Blobstore, Loadable};
+use cloned::cloned;
+use context::CoreContext;
+use futures::future::{try_join_all, FutureExt as NewFutureExt, TryFutureExt};
+use futures_ext::FutureExt;
+use futures_old::{future, Future};
+use manifest::{derive_manifest, Entry, LeafInfo, TreeInfo};
+use mononoke_types::skeleton_manifest::{
+    SkeletonManifest, SkeletonManifestDirectory, SkeletonManifestEntry, SkeletonManifestSummary,
+};
+use mononoke_types::{BlobstoreValue, MPath, MPathElement, MononokeId, SkeletonManifestId};
+use repo_blobstore::RepoBlobstore;
+
+use crate::ErrorKind;
+
+/// Derives skeleton manifests for bonsai_changeset `cs_id` given parent
+/// skeleton manifests. Only the paths that are changed matter, so `changes`
+/// says whether each path is added or modified (`Some(())`) or deleted
+/// (`None`).
+pub(crate) fn derive_skeleton_manifest(
+    ctx: CoreContext,
+    repo: BlobRepo,
+    parents: Vec<SkeletonManifestId>,
+    changes: Vec<(MPath, Option<()>)>,
+) -> impl Future<Item = SkeletonManifestId, Error = Error> {
+    let blobstore = repo.get_blobstore();
+    derive_manifest(
+        ctx.clone(),
+        blobstore.clone(),
+        parents.clone(),
+        changes,
+        {
+            cloned!(ctx, blobstore);
+            move |tree_info| {
+                create_skeleton_manifest(ctx.clone(), blobstore.clone(), tree_info)
+                    .boxed()
+                    .compat()
+            }
+        },
+        |leaf_info| check_skeleton_manifest_leaf(leaf_info),
+    )
+    .and_then(move |maybe_tree_id| match maybe_tree_id {
+        Some(tree_id) => future::ok(tree_id).left_future(),
+        None => {
+            // All files have been deleted, generate empty skeleton manifest
+            let tree_info = TreeInfo {
+                path: None,
+                parents,
+                subentries: Default::default(),
+            };
+            create_skeleton_manifest(ctx, blobstore, tree_info)
+                .boxed()
+                .compat()
+                .map(|(_, tree_id)| tree_id)
+                .right_future()
+        }
+    })
+}
+
+/// Collect all the subentries for a new skeleton manifest, re-using entries
+/// from the parent skeleton manifests to avoid fetching too much.
+async fn collect_skeleton_subentries(
+    ctx: &CoreContext,
+    blobstore: &RepoBlobstore,
+    parents: &[SkeletonManifestId],
+    subentries: BTreeMap<
+        MPathElement,
+        (
+            Option<Option<SkeletonManifestSummary>>,
+            Entry<SkeletonManifestId, ()>,
+        ),
+    >,
+) -> Result<Vec<(MPathElement, SkeletonManifestEntry)>, Error> {
+    // Load the parent skeleton manifests and collect their directories as a cache
+    let parent_skeletons = try_join_all(parents.iter().map(|skeleton_id| async move {
+        skeleton_id
+            .load(ctx.clone(), blobstore)
+            .await
+            .context(ErrorKind::MissingParent(*skeleton_id))
+    }))
+    .await?;
+
+    let mut dir_cache = HashMap::new();
+    for parent_skeleton in parent_skeletons {
+        for (_elem, entry) in parent_skeleton.into_subentries() {
+            if let SkeletonManifestEntry::Directory(dir) = entry {
+                dir_cache.entry(*dir.id()).or_insert(dir);
+            }
+        }
+    }
+
+    try_join_all(subentries.into_iter().map(|(elem, (summary, entry))| {
+        let dir_cache = &dir_cache;
+        async move {
+            match entry {
+                Entry::Leaf(()) => Ok((elem, SkeletonManifestEntry::File)),
+                Entry::Tree(skeleton_id) => {
+                    let dir = if let Some(Some(summary)) = summary {
+                        // The subdirectory was just created. Use the
+                        // summary we just calculated.
+                        SkeletonManifestDirectory::new(skeleton_id, summary)
+                    } else if let Some(dir) = dir_cache.get(&skeleton_id) {
+                        // The subdirectory was already in this
+                        // directory. Use the cached entry.
+                        dir.clone()
+                    } else {
+                        // Some other directory is being used. Fetch its
+                        // summary from the blobstore.
+                        let skeleton = skeleton_id
+                            .load(ctx.clone(), blobstore)
+                            .await
+                            .with_context(|| {
+                                ErrorKind::MissingSubentry(
+                                    String::from_utf8_lossy(elem.as_ref()).to_string(),
+                                    skeleton_id,
+                                )
+                            })?;
+                        SkeletonManifestDirectory::new(skeleton_id, skeleton.summary().clone())
+                    };
+                    Ok((elem, SkeletonManifestEntry::Directory(dir)))
+                }
+            }
+        }
+    }))
+    .await
+}
+
+/// Create a new skeleton manifest for the tree described by `tree_info`.
+async fn create_skeleton_manifest(
+    ctx: CoreContext,
+    blobstore: RepoBlobstore,
+    tree_info: TreeInfo<SkeletonManifestId, (), Option<SkeletonManifestSummary>>,
+) -> Result<(Option<SkeletonManifestSummary>, SkeletonManifestId), Error> {
+    let entries =
+        collect_skeleton_subentries(&ctx, &blobstore, &tree_info.parents, tree_info.subentries)
+            .await?;
+
+    let skeleton = SkeletonManifest::new(entries.into_iter().collect());
+    let summary = skeleton.summary().clone();
+    let blob = skeleton.into_blob();
+    let skeleton_id = *blob.id();
+    blobstore
+        .put(ctx, skeleton_id.blobstore_key(), blob.into())
+        .await?;
+    Ok((Some(summary), skeleton_id))
+}
+
+/// Skeleton manifest leaves carry no information, so any merge of files is
+/// valid: the file exists in the merge whatever its contents in the parents.
+fn check_skeleton_manifest_leaf(
+    _leaf_info: LeafInfo<(), ()>,
+) -> impl Future<Item = (Option<SkeletonManifestSummary>, ()), Error = Error> {
+    future::ok((None, ()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapping::get_file_changes;
+    use fbinit::FacebookInit;
+    use fixtures::many_files_dirs;
+    use futures_old::Stream;
+    use test_utils::{get_bonsai_changeset, iterate_all_entries};
+    use tokio_compat::runtime::Runtime;
+
+    #[fbinit::test]
+    fn nested_directories_test(fb: FacebookInit) {
+        let mut runtime = Runtime::new().unwrap();
+        let repo = runtime.block_on_std(many_files_dirs::getrepo(fb));
+        let ctx = CoreContext::test_mock(fb);
+
+        // Derive skeleton manifests for the first two commits in the fixture
+        let parent_skeleton_id = {
+            let parent_hg_cs = "5a28e25f924a5d209b82ce0713d8d83e68982bc8";
+            let (_bcs_id, bcs) =
+                get_bonsai_changeset(ctx.clone(), repo.clone(), &mut runtime, parent_hg_cs);
+
+            let f =
+                derive_skeleton_manifest(ctx.clone(), repo.clone(), vec![], get_file_changes(&bcs));
+            runtime.block_on(f).unwrap()
+        };
+
+        let child_hg_cs = "2f866e7e549760934e31bf0420a873f65100ad63";
+        let (_bcs_id, bcs) =
+            get_bonsai_changeset(ctx.clone(), repo.clone(), &mut runtime, child_hg_cs);
+        let f = derive_skeleton_manifest(
+            ctx.clone(),
+            repo.clone(),
+            vec![parent_skeleton_id],
+            get_file_changes(&bcs),
+        );
+        let root_skeleton_id = runtime.block_on(f).unwrap();
+
+        // Make sure it's saved in the blobstore
+        let root_skeleton = runtime
+            .block_on_std(root_skeleton_id.load(ctx.clone(), repo.blobstore()))
+            .unwrap();
+
+        let all_entries: BTreeMap<_, _> = runtime
+            .block_on(
+                iterate_all_entries(ctx.clone(), repo.clone(), Entry::Tree(root_skeleton_id))
+                    .collect(),
+            )
+            .unwrap()
+            .into_iter()
+            .collect();
+        let files = all_entries
+            .values()
+            .filter(|entry| entry.into_leaf().is_some())
+            .count() as u64;
+        let dirs = all_entries.len() as u64 - files - 1;
+
+        // The summary counts agree with a full walk of the manifest
+        let summary = root_skeleton.summary();
+        assert_eq!(summary.descendant_files_count, files);
+        assert_eq!(summary.descendant_dirs_count, dirs);
+        assert_eq!(
+            summary.max_path_len,
+            all_entries
+                .keys()
+                .filter_map(|path| path.as_ref().map(|path| path.to_string().len() as u64))
+                .max()
+                .unwrap()
+        );
+        assert!(!summary.has_case_conflicts());
+    }
+}
@@ -0,0 +1,24 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![deny(warnings)]
+
+use mononoke_types::SkeletonManifestId;
+use thiserror::Error;
+
+mod derive;
+mod mapping;
+
+pub use mapping::{RootSkeletonManifestId, RootSkeletonManifestMapping};
+
+#[derive(Debug, Error)]
+pub enum ErrorKind {
+    #[error("Missing skeleton manifest parent: {0}")]
+    MissingParent(SkeletonManifestId),
+    #[error("Missing skeleton manifest subentry for '{0}': {1}")]
+    MissingSubentry(String, SkeletonManifestId),
+}
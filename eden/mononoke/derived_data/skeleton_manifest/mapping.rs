@@ -0,0 +1,277 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::derive::derive_skeleton_manifest;
+use anyhow::{Error, Result};
+use blobrepo::BlobRepo;
+use blobstore::{Blobstore, BlobstoreGetData};
+use bytes::Bytes;
+use context::CoreContext;
+use derived_data::{mapping_blobstore, BonsaiDerived, BonsaiDerivedMapping};
+use futures::TryFutureExt;
+use futures_ext::{BoxFuture, FutureExt, StreamExt};
+use futures_old::{
+    stream::{self, FuturesUnordered},
+    Future, Stream,
+};
+use mononoke_types::{BlobstoreBytes, BonsaiChangeset, ChangesetId, MPath, SkeletonManifestId};
+use std::{
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+    iter::FromIterator,
+    sync::Arc,
+};
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct RootSkeletonManifestId(SkeletonManifestId);
+
+impl RootSkeletonManifestId {
+    pub fn skeleton_manifest_id(&self) -> &SkeletonManifestId {
+        &self.0
+    }
+    pub fn into_skeleton_manifest_id(self) -> SkeletonManifestId {
+        self.0
+    }
+}
+
+impl TryFrom<BlobstoreBytes> for RootSkeletonManifestId {
+    type Error = Error;
+
+    fn try_from(blob_bytes: BlobstoreBytes) -> Result<Self> {
+        SkeletonManifestId::from_bytes(&blob_bytes.into_bytes()).map(RootSkeletonManifestId)
+    }
+}
+
+impl TryFrom<BlobstoreGetData> for RootSkeletonManifestId {
+    type Error = Error;
+
+    fn try_from(blob_get_data: BlobstoreGetData) -> Result<Self> {
+        blob_get_data.into_bytes().try_into()
+    }
+}
+
+impl From<RootSkeletonManifestId> for BlobstoreBytes {
+    fn from(root_skeleton_manifest_id: RootSkeletonManifestId) -> Self {
+        BlobstoreBytes::from_bytes(Bytes::copy_from_slice(
+            root_skeleton_manifest_id.0.blake2().as_ref(),
+        ))
+    }
+}
+
+impl BonsaiDerived for RootSkeletonManifestId {
+    const NAME: &'static str = "skeleton_manifests";
+    type Mapping = RootSkeletonManifestMapping;
+
+    fn mapping(_ctx: &CoreContext, repo: &BlobRepo) -> Self::Mapping {
+        RootSkeletonManifestMapping::new(mapping_blobstore(repo, Self::NAME))
+    }
+
+    fn derive_from_parents(
+        ctx: CoreContext,
+        repo: BlobRepo,
+        bonsai: BonsaiChangeset,
+        parents: Vec<Self>,
+    ) -> BoxFuture<Self, Error> {
+        derive_skeleton_manifest(
+            ctx,
+            repo,
+            parents
+                .into_iter()
+                .map(RootSkeletonManifestId::into_skeleton_manifest_id)
+                .collect(),
+            get_file_changes(&bonsai),
+        )
+        .map(RootSkeletonManifestId)
+        .boxify()
+    }
+}
+
+#[derive(Clone)]
+pub struct RootSkeletonManifestMapping {
+    blobstore: Arc<dyn Blobstore>,
+}
+
+impl RootSkeletonManifestMapping {
+    pub fn new(blobstore: Arc<dyn Blobstore>) -> Self {
+        Self { blobstore }
+    }
+
+    fn format_key(&self, cs_id: ChangesetId) -> String {
+        format!("derived_root_skeletonmanifest.{}", cs_id)
+    }
+
+    fn fetch_skeleton_manifest(
+        &self,
+        ctx: CoreContext,
+        cs_id: ChangesetId,
+    ) -> impl Future<Item = Option<(ChangesetId, RootSkeletonManifestId)>, Error = Error> {
+        self.blobstore
+            .get(ctx, self.format_key(cs_id))
+            .compat()
+            .and_then(|opt_blob| opt_blob.map(TryInto::try_into).transpose())
+            .map(move |maybe_root_id| maybe_root_id.map(|root_id| (cs_id, root_id)))
+    }
+}
+
+impl BonsaiDerivedMapping for RootSkeletonManifestMapping {
+    type Value = RootSkeletonManifestId;
+
+    fn get(
+        &self,
+        ctx: CoreContext,
+        csids: Vec<ChangesetId>,
+    ) -> BoxFuture<HashMap<ChangesetId, Self::Value>, Error> {
+        let gets = csids.into_iter().map(|cs_id| {
+            self.fetch_skeleton_manifest(ctx.clone(), cs_id)
+                .map(|maybe_root_id| stream::iter_ok(maybe_root_id.into_iter()))
+        });
+        FuturesUnordered::from_iter(gets)
+            .flatten()
+            .collect_to()
+            .boxify()
+    }
+
+    fn put(&self, ctx: CoreContext, csid: ChangesetId, id: Self::Value) -> BoxFuture<(), Error> {
+        self.blobstore
+            .put(ctx, self.format_key(csid), id.into())
+            .compat()
+            .boxify()
+    }
+}
+
+// Skeleton manifests only care whether a path exists, not what it contains
+pub(crate) fn get_file_changes(bcs: &BonsaiChangeset) -> Vec<(MPath, Option<()>)> {
+    bcs.file_changes()
+        .map(|(mpath, file_change)| (mpath.clone(), file_change.map(|_| ())))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blobrepo_hg::BlobRepoHg;
+    use blobstore::Loadable;
+    use bookmarks::BookmarkName;
+    use cloned::cloned;
+    use fbinit::FacebookInit;
+    use fixtures::{
+        branch_even, branch_uneven, branch_wide, linear, many_diamonds, many_files_dirs,
+        merge_even, merge_uneven, unshared_merge_even, unshared_merge_uneven,
+    };
+    use futures::future::{Future as NewFuture, TryFutureExt};
+    use manifest::Entry;
+    use mercurial_types::{HgChangesetId, HgManifestId};
+    use revset::AncestorsNodeStream;
+    use test_utils::iterate_all_entries;
+    use tokio_compat::runtime::Runtime;
+
+    fn fetch_manifest_by_cs_id(
+        ctx: CoreContext,
+        repo: BlobRepo,
+        hg_cs_id: HgChangesetId,
+    ) -> impl Future<Item = HgManifestId, Error = Error> {
+        hg_cs_id
+            .load(ctx, repo.blobstore())
+            .compat()
+            .from_err()
+            .map(|hg_cs| hg_cs.manifestid())
+    }
+
+    fn verify_skeleton_manifest(
+        ctx: CoreContext,
+        repo: BlobRepo,
+        bcs_id: ChangesetId,
+        hg_cs_id: HgChangesetId,
+    ) -> impl Future<Item = (), Error = Error> {
+        let skeleton_entries = RootSkeletonManifestId::derive(ctx.clone(), repo.clone(), bcs_id)
+            .from_err()
+            .map(RootSkeletonManifestId::into_skeleton_manifest_id)
+            .and_then({
+                cloned!(ctx, repo);
+                move |skeleton_id| {
+                    iterate_all_entries(ctx, repo, Entry::Tree(skeleton_id))
+                        .map(|(path, _)| path)
+                        .collect()
+                        .map(|mut paths| {
+                            paths.sort();
+                            paths
+                        })
+                }
+            });
+
+        let filenode_entries = fetch_manifest_by_cs_id(ctx.clone(), repo.clone(), hg_cs_id)
+            .and_then({
+                cloned!(ctx, repo);
+                move |root_mf_id| {
+                    iterate_all_entries(ctx, repo, Entry::Tree(root_mf_id))
+                        .map(|(path, _)| path)
+                        .collect()
+                        .map(|mut paths| {
+                            paths.sort();
+                            paths
+                        })
+                }
+            });
+
+        skeleton_entries
+            .join(filenode_entries)
+            .map(|(skeleton_entries, filenode_entries)| {
+                assert_eq!(skeleton_entries, filenode_entries);
+            })
+    }
+
+    fn all_commits(
+        ctx: CoreContext,
+        repo: BlobRepo,
+    ) -> impl Stream<Item = (ChangesetId, HgChangesetId), Error = Error> {
+        let master_book = BookmarkName::new("master").unwrap();
+        repo.get_bonsai_bookmark(ctx.clone(), &master_book)
+            .map(move |maybe_bcs_id| {
+                let bcs_id = maybe_bcs_id.unwrap();
+                AncestorsNodeStream::new(ctx.clone(), &repo.get_changeset_fetcher(), bcs_id.clone())
+                    .and_then(move |new_bcs_id| {
+                        repo.get_hg_from_bonsai_changeset(ctx.clone(), new_bcs_id)
+                            .map(move |hg_cs_id| (new_bcs_id, hg_cs_id))
+                    })
+            })
+            .flatten_stream()
+    }
+
+    fn verify_repo<F>(fb: FacebookInit, repo: F, runtime: &mut Runtime)
+    where
+        F: NewFuture<Output = BlobRepo>,
+    {
+        let ctx = CoreContext::test_mock(fb);
+
+        let repo = runtime.block_on_std(repo);
+
+        runtime
+            .block_on(
+                all_commits(ctx.clone(), repo.clone())
+                    .and_then(move |(bcs_id, hg_cs_id)| {
+                        verify_skeleton_manifest(ctx.clone(), repo.clone(), bcs_id, hg_cs_id)
+                    })
+                    .collect(),
+            )
+            .unwrap();
+    }
+
+    #[fbinit::test]
+    fn test_derive_data(fb: FacebookInit) {
+        let mut runtime = Runtime::new().unwrap();
+        verify_repo(fb, linear::getrepo(fb), &mut runtime);
+        verify_repo(fb, branch_even::getrepo(fb), &mut runtime);
+        verify_repo(fb, branch_uneven::getrepo(fb), &mut runtime);
+        verify_repo(fb, branch_wide::getrepo(fb), &mut runtime);
+        verify_repo(fb, many_diamonds::getrepo(fb), &mut runtime);
+        verify_repo(fb, many_files_dirs::getrepo(fb), &mut runtime);
+        verify_repo(fb, merge_even::getrepo(fb), &mut runtime);
+        verify_repo(fb, merge_uneven::getrepo(fb), &mut runtime);
+        verify_repo(fb, unshared_merge_even::getrepo(fb), &mut runtime);
+        verify_repo(fb, unshared_merge_uneven::getrepo(fb), &mut runtime);
+    }
+}
@@ -28,7 +28,10 @@ use slog::warn;
 use stats::prelude::*;
 use std::{
     collections::HashSet,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 use time_ext::DurationExt;
@@ -183,6 +186,34 @@ pub(crate) async fn find_topo_sorted_underived<
     limit: Option<u64>,
     mode: Mode,
 ) -> Result<Vec<ChangesetId>, Error> {
+    let (underived, _truncated) = find_topo_sorted_underived_with_truncation(
+        ctx,
+        repo,
+        derived_mapping,
+        start_csids,
+        limit,
+        mode,
+    )
+    .await?;
+    Ok(underived)
+}
+
+/// Returns the ancestors of `start_csids` (including themselves) that don't have derived
+/// data yet, sorted so that parents come before their children. If `limit` is set, the
+/// traversal stops once more than `limit` commits were visited, and the returned flag is set
+/// if any commit was left unchecked because of that.
+pub async fn find_topo_sorted_underived_with_truncation<
+    Derived: BonsaiDerived,
+    Mapping: BonsaiDerivedMapping<Value = Derived> + Send + Sync + Clone + 'static,
+    Changesets: IntoIterator<Item = ChangesetId>,
+>(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    derived_mapping: &Mapping,
+    start_csids: Changesets,
+    limit: Option<u64>,
+    mode: Mode,
+) -> Result<(Vec<ChangesetId>, bool), Error> {
     if mode == Mode::OnlyIfEnabled {
         fail_if_disabled::<Derived>(repo)?;
     }
@@ -190,9 +221,11 @@ pub(crate) async fn find_topo_sorted_underived<
     let changeset_fetcher = repo.get_changeset_fetcher();
     // This is necessary to avoid visiting the same commit a lot of times in mergy repos
     let visited: Arc<Mutex<HashSet<ChangesetId>>> = Arc::new(Mutex::new(HashSet::new()));
+    let truncated = AtomicBool::new(false);
 
     let changeset_fetcher = &changeset_fetcher;
     let visited = &visited;
+    let truncated = &truncated;
     let commits_not_derived_to_parents =
         bounded_traversal::bounded_traversal_stream(100, start_csids, {
             move |cs_id| {
@@ -200,6 +233,7 @@ pub(crate) async fn find_topo_sorted_underived<
                     if let Some(limit) = limit {
                         let visited = visited.lock().unwrap();
                         if visited.len() as u64 > limit {
+                            truncated.store(true, Ordering::Relaxed);
                             return Result::<_, Error>::Ok((None, vec![]));
                         }
                     }
@@ -251,7 +285,7 @@ pub(crate) async fn find_topo_sorted_underived<
         .filter(move |cs_id| commits_not_derived_to_parents.contains_key(cs_id))
         .collect();
 
-    Ok(all_csids)
+    Ok((all_csids, truncated.load(Ordering::Relaxed)))
 }
 
 // Panics if any of the parents is not derived yet
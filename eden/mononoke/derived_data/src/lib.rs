@@ -10,11 +10,17 @@
 use anyhow::Error;
 use async_trait::async_trait;
 use blobrepo::BlobRepo;
+use blobstore::{Blobstore, BlobstoreGetData};
 use context::CoreContext;
-use futures::{compat::Future01CompatExt, stream, StreamExt, TryStreamExt};
+use futures::{
+    compat::Future01CompatExt,
+    future::{BoxFuture as NewBoxFuture, FutureExt as NewFutureExt},
+    stream, StreamExt, TryStreamExt,
+};
 use futures_ext::{BoxFuture, FutureExt as OldFutureExt};
 use lock_ext::LockExt;
-use mononoke_types::{BonsaiChangeset, ChangesetId, RepositoryId};
+use mononoke_types::{BlobstoreBytes, BonsaiChangeset, ChangesetId, RepositoryId};
+use prefixblob::PrefixBlobstore;
 use std::{
     collections::{HashMap, HashSet},
     sync::{Arc, Mutex},
@@ -50,6 +56,11 @@ pub trait BonsaiDerived: Sized + 'static + Send + Sync + Clone {
     /// name data (for example lease keys) assoicated with particular derived data type.
     const NAME: &'static str;
 
+    /// Names of the derived data types that are used to derive this type.
+    /// They have to be derived for a changeset before this type can be
+    /// derived for it.
+    const DEPENDENCIES: &'static [&'static str] = &[];
+
     type Mapping: BonsaiDerivedMapping<Value = Self>;
 
     /// Get mapping associated with this derived data type.
@@ -209,6 +220,115 @@ impl<Mapping: BonsaiDerivedMapping> BonsaiDerivedMapping for Arc<Mapping> {
     }
 }
 
+/// Returns the version of the mapping that derived data type `name` uses in
+/// this repo.
+pub fn mapping_version(repo: &BlobRepo, name: &str) -> u64 {
+    repo.get_derived_data_config()
+        .mapping_versions
+        .get(name)
+        .copied()
+        .unwrap_or(0)
+}
+
+/// Returns the blobstore that the mapping of derived data type `name` should
+/// be stored in, according to the mapping version configured for this repo.
+pub fn mapping_blobstore(repo: &BlobRepo, name: &str) -> Arc<dyn Blobstore> {
+    versioned_mapping_blobstore(repo.get_blobstore().boxed(), mapping_version(repo, name))
+}
+
+/// Wraps a blobstore so that mapping keys are read from and written to the
+/// given version of the mapping.
+///
+/// Version 0 uses the original keys. Every other version adds a prefix to
+/// the keys, so that derived data can be re-derived into a new version while
+/// readers keep using the old one, and readers are switched over by bumping
+/// the version in the config. Keys that are missing from a version are read
+/// from the version before it, so changesets that were not re-derived keep
+/// their derived data when the version is bumped.
+pub fn versioned_mapping_blobstore(
+    blobstore: Arc<dyn Blobstore>,
+    version: u64,
+) -> Arc<dyn Blobstore> {
+    if version == 0 {
+        blobstore
+    } else {
+        Arc::new(FallbackMappingBlobstore {
+            current: exact_versioned_mapping_blobstore(blobstore.clone(), version),
+            previous: versioned_mapping_blobstore(blobstore, version - 1),
+        })
+    }
+}
+
+/// As `versioned_mapping_blobstore`, but only reads keys that are in the
+/// given version of the mapping. This is what derived data must be
+/// re-derived into, so that derivation doesn't stop at stale entries of an
+/// older version.
+pub fn exact_versioned_mapping_blobstore(
+    blobstore: Arc<dyn Blobstore>,
+    version: u64,
+) -> Arc<dyn Blobstore> {
+    if version == 0 {
+        blobstore
+    } else {
+        Arc::new(PrefixBlobstore::new(
+            blobstore,
+            format!("mapping_v{}.", version),
+        ))
+    }
+}
+
+/// Writes to the current version of a mapping, and reads from the previous
+/// version for keys that the current version doesn't have.
+#[derive(Debug)]
+struct FallbackMappingBlobstore {
+    current: Arc<dyn Blobstore>,
+    previous: Arc<dyn Blobstore>,
+}
+
+impl Blobstore for FallbackMappingBlobstore {
+    fn get(
+        &self,
+        ctx: CoreContext,
+        key: String,
+    ) -> NewBoxFuture<'static, Result<Option<BlobstoreGetData>, Error>> {
+        let current = self.current.clone();
+        let previous = self.previous.clone();
+        async move {
+            match current.get(ctx.clone(), key.clone()).await? {
+                Some(value) => Ok(Some(value)),
+                None => previous.get(ctx, key).await,
+            }
+        }
+        .boxed()
+    }
+
+    fn put(
+        &self,
+        ctx: CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> NewBoxFuture<'static, Result<(), Error>> {
+        self.current.put(ctx, key, value)
+    }
+
+    fn is_present(
+        &self,
+        ctx: CoreContext,
+        key: String,
+    ) -> NewBoxFuture<'static, Result<bool, Error>> {
+        let current = self.current.clone();
+        let previous = self.previous.clone();
+        async move {
+            if current.is_present(ctx.clone(), key.clone()).await? {
+                Ok(true)
+            } else {
+                previous.is_present(ctx, key).await
+            }
+        }
+        .boxed()
+    }
+}
+
 /// This mapping can be used when we want to ignore values before it was put
 /// again for some specific set of commits. It is useful when we want either
 /// re-backfill derived data or investigate performance problems.
@@ -11,7 +11,7 @@ use blobrepo::BlobRepo;
 use blobstore::{Blobstore, BlobstoreGetData};
 use bytes::Bytes;
 use context::CoreContext;
-use derived_data::{BonsaiDerived, BonsaiDerivedMapping};
+use derived_data::{mapping_blobstore, BonsaiDerived, BonsaiDerivedMapping};
 use futures::future::TryFutureExt;
 use futures_ext::{BoxFuture, FutureExt, StreamExt};
 use futures_old::{
@@ -22,11 +22,11 @@ use metaconfig_types::UnodeVersion;
 use mononoke_types::{
     BlobstoreBytes, BonsaiChangeset, ChangesetId, ContentId, FileType, MPath, ManifestUnodeId,
 };
-use repo_blobstore::RepoBlobstore;
 use std::{
     collections::HashMap,
     convert::{TryFrom, TryInto},
     iter::FromIterator,
+    sync::Arc,
 };
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -66,7 +66,7 @@ impl BonsaiDerived for RootUnodeManifestId {
 
     fn mapping(_ctx: &CoreContext, repo: &BlobRepo) -> Self::Mapping {
         RootUnodeManifestMapping::new(
-            repo.blobstore().clone(),
+            mapping_blobstore(repo, Self::NAME),
             repo.get_derived_data_config().unode_version,
         )
     }
@@ -96,12 +96,12 @@ impl BonsaiDerived for RootUnodeManifestId {
 // TODO(stash): have a generic version of blobstore derived data mapping?
 #[derive(Clone)]
 pub struct RootUnodeManifestMapping {
-    blobstore: RepoBlobstore,
+    blobstore: Arc<dyn Blobstore>,
     unode_version: UnodeVersion,
 }
 
 impl RootUnodeManifestMapping {
-    pub fn new(blobstore: RepoBlobstore, unode_version: UnodeVersion) -> Self {
+    pub fn new(blobstore: Arc<dyn Blobstore>, unode_version: UnodeVersion) -> Self {
         Self {
             blobstore,
             unode_version,
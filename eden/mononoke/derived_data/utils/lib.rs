@@ -17,24 +17,29 @@ use cloned::cloned;
 use context::CoreContext;
 use deleted_files_manifest::{RootDeletedManifestId, RootDeletedManifestMapping};
 use derived_data::{
-    derive_impl::derive_impl, BonsaiDerived, BonsaiDerivedMapping, DeriveError, Mode as DeriveMode,
-    RegenerateMapping,
+    derive_impl::{derive_impl, find_topo_sorted_underived_with_truncation},
+    exact_versioned_mapping_blobstore, mapping_blobstore, versioned_mapping_blobstore,
+    BonsaiDerived, BonsaiDerivedMapping, DeriveError, Mode as DeriveMode, RegenerateMapping,
 };
 use derived_data_filenodes::{FilenodesOnlyPublic, FilenodesOnlyPublicMapping};
+use diffstat::{ChangesetDiffstat, ChangesetDiffstatMapping};
 use fastlog::{RootFastlog, RootFastlogMapping};
+use file_classification::{RootFileClassification, RootFileClassificationMapping};
 use fsnodes::{RootFsnodeId, RootFsnodeMapping};
 use futures::{
     compat::Future01CompatExt,
-    future::ready,
+    future::{ready, try_join},
     stream::{self, futures_unordered::FuturesUnordered},
     Future, StreamExt, TryStreamExt,
 };
 use futures_ext::{BoxFuture, FutureExt as OldFutureExt};
 use futures_old::{future, stream as stream_old, Future as OldFuture, Stream};
+use git_types::{CommitHandle, CommitMapping, TreeHandle, TreeMapping};
 use mercurial_derived_data::{HgChangesetIdMapping, MappedHgChangesetId};
 use mononoke_types::{BonsaiChangeset, ChangesetId};
+use skeleton_manifest::{RootSkeletonManifestId, RootSkeletonManifestMapping};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{Arc, Mutex},
 };
 use unodes::{RootUnodeManifestId, RootUnodeManifestMapping};
@@ -48,8 +53,165 @@ pub const POSSIBLE_DERIVED_TYPES: &[&str] = &[
     ChangesetInfo::NAME,
     RootDeletedManifestId::NAME,
     FilenodesOnlyPublic::NAME,
+    TreeHandle::NAME,
+    CommitHandle::NAME,
+    RootSkeletonManifestId::NAME,
+    ChangesetDiffstat::NAME,
+    RootFileClassification::NAME,
 ];
 
+/// Returns the derived data types that derivation of `name` relies on. They
+/// have to be derived for a changeset before `name` can be safely backfilled
+/// for it.
+pub fn derived_data_dependencies(name: &str) -> Result<&'static [&'static str], Error> {
+    match name {
+        RootUnodeManifestId::NAME => Ok(RootUnodeManifestId::DEPENDENCIES),
+        RootFastlog::NAME => Ok(RootFastlog::DEPENDENCIES),
+        MappedHgChangesetId::NAME => Ok(MappedHgChangesetId::DEPENDENCIES),
+        RootFsnodeId::NAME => Ok(RootFsnodeId::DEPENDENCIES),
+        BlameRoot::NAME => Ok(BlameRoot::DEPENDENCIES),
+        ChangesetInfo::NAME => Ok(ChangesetInfo::DEPENDENCIES),
+        RootDeletedManifestId::NAME => Ok(RootDeletedManifestId::DEPENDENCIES),
+        FilenodesOnlyPublic::NAME => Ok(FilenodesOnlyPublic::DEPENDENCIES),
+        TreeHandle::NAME => Ok(TreeHandle::DEPENDENCIES),
+        CommitHandle::NAME => Ok(CommitHandle::DEPENDENCIES),
+        RootSkeletonManifestId::NAME => Ok(RootSkeletonManifestId::DEPENDENCIES),
+        ChangesetDiffstat::NAME => Ok(ChangesetDiffstat::DEPENDENCIES),
+        RootFileClassification::NAME => Ok(RootFileClassification::DEPENDENCIES),
+        name => Err(format_err!("Unsupported derived data type: {}", name)),
+    }
+}
+
+/// Returns `name` together with all the derived data types it transitively
+/// depends on, ordered so that every type comes after its dependencies.
+pub fn derived_data_with_dependencies(name: &str) -> Result<Vec<&'static str>, Error> {
+    fn visit(name: &str, order: &mut Vec<&'static str>) -> Result<(), Error> {
+        for dependency in derived_data_dependencies(name)? {
+            visit(dependency, order)?;
+        }
+        let name = POSSIBLE_DERIVED_TYPES
+            .iter()
+            .find(|ty| **ty == name)
+            .ok_or_else(|| format_err!("Unsupported derived data type: {}", name))?;
+        if !order.contains(name) {
+            order.push(name);
+        }
+        Ok(())
+    }
+
+    let mut order = Vec::new();
+    visit(name, &mut order)?;
+    Ok(order)
+}
+
+/// Derivation state of one derived data type for a changeset.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DerivedDataStatus {
+    pub name: &'static str,
+    /// Whether the type is enabled in the repo config.
+    pub enabled: bool,
+    /// The closest ancestor (by generation number) that has the data derived,
+    /// or `None` if there is none or the search limit was reached first.
+    pub latest_derived_ancestor: Option<ChangesetId>,
+    /// Number of ancestors of the changeset that don't have the data derived.
+    pub underived_count: u64,
+    /// Whether the search for underived ancestors of this type or of its
+    /// dependencies stopped at the limit, in which case the counts are lower
+    /// bounds.
+    pub limit_reached: bool,
+    /// Number of derivations needed to derive this type for the changeset,
+    /// including those of the types it depends on.
+    pub estimated_derivations: u64,
+}
+
+/// Reports the derivation state of each of `types` for changeset `csid`.
+///
+/// At most roughly `limit` ancestors are inspected for each type, which
+/// keeps the report cheap for changesets that are far from being derived.
+pub async fn derived_data_status(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    csid: ChangesetId,
+    types: &[String],
+    limit: Option<u64>,
+) -> Result<Vec<DerivedDataStatus>, Error> {
+    let mut underived: HashMap<&'static str, (Vec<ChangesetId>, bool)> = HashMap::new();
+    for ty in types {
+        for name in derived_data_with_dependencies(ty)? {
+            if !underived.contains_key(name) {
+                let utils = derived_data_utils_unsafe(repo.clone(), name)?;
+                let found = utils.find_underived(ctx, repo, vec![csid], limit).await?;
+                underived.insert(name, found);
+            }
+        }
+    }
+
+    let config = repo.get_derived_data_config();
+    let mut statuses = Vec::new();
+    for ty in types {
+        let with_dependencies = derived_data_with_dependencies(ty)?;
+        // The type itself always comes last, after its dependencies.
+        let name = *with_dependencies
+            .last()
+            .ok_or_else(|| format_err!("Unsupported derived data type: {}", ty))?;
+        let (csids, truncated) = &underived[name];
+        // Only a complete set of underived ancestors tells where derivation stopped
+        let latest_derived_ancestor = if *truncated {
+            None
+        } else {
+            find_latest_derived_ancestor(ctx, repo, csid, csids).await?
+        };
+        let limit_reached = with_dependencies.iter().any(|name| underived[name].1);
+        let estimated_derivations = with_dependencies
+            .iter()
+            .map(|name| underived[name].0.len() as u64)
+            .sum();
+        statuses.push(DerivedDataStatus {
+            name,
+            enabled: config.derived_data_types.contains(name),
+            latest_derived_ancestor,
+            underived_count: csids.len() as u64,
+            limit_reached,
+            estimated_derivations,
+        });
+    }
+    Ok(statuses)
+}
+
+/// Given the complete set of underived ancestors of `csid`, returns the
+/// derived ancestor with the highest generation number.
+async fn find_latest_derived_ancestor(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    csid: ChangesetId,
+    underived: &[ChangesetId],
+) -> Result<Option<ChangesetId>, Error> {
+    if underived.is_empty() {
+        return Ok(Some(csid));
+    }
+
+    let changeset_fetcher = repo.get_changeset_fetcher();
+    let underived: HashSet<_> = underived.iter().copied().collect();
+    let mut derived_parents = HashSet::new();
+    for csid in &underived {
+        let parents = changeset_fetcher
+            .get_parents(ctx.clone(), *csid)
+            .compat()
+            .await?;
+        derived_parents.extend(parents.into_iter().filter(|p| !underived.contains(p)));
+    }
+
+    let mut generations = Vec::new();
+    for parent in derived_parents {
+        let generation = changeset_fetcher
+            .get_generation_number(ctx.clone(), parent)
+            .compat()
+            .await?;
+        generations.push((generation, parent));
+    }
+    Ok(generations.into_iter().max().map(|(_, csid)| csid))
+}
+
 pub fn derive_data_for_csids(
     ctx: &CoreContext,
     repo: &BlobRepo,
@@ -123,6 +285,18 @@ pub trait DerivedUtils: Send + Sync + 'static {
         repo: &'a BlobRepo,
         csids: &'a Vec<ChangesetId>,
     ) -> Result<Option<BonsaiChangeset>, Error>;
+
+    /// Find all ancestors of `csids` (including themselves) which are not
+    /// derived yet, in topological order. If `limit` is set, the search stops
+    /// after visiting roughly `limit` changesets, and the returned flag is
+    /// set if that left any ancestors unchecked.
+    async fn find_underived<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        repo: &'a BlobRepo,
+        csids: Vec<ChangesetId>,
+        limit: Option<u64>,
+    ) -> Result<(Vec<ChangesetId>, bool), Error>;
 }
 
 #[derive(Clone)]
@@ -283,6 +457,24 @@ where
             .min_by_key(|bcs| *bcs.author_date()))
     }
 
+    async fn find_underived<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        repo: &'a BlobRepo,
+        csids: Vec<ChangesetId>,
+        limit: Option<u64>,
+    ) -> Result<(Vec<ChangesetId>, bool), Error> {
+        find_topo_sorted_underived_with_truncation(
+            ctx,
+            repo,
+            &self.mapping,
+            csids,
+            limit,
+            self.mode,
+        )
+        .await
+    }
+
     fn regenerate(&self, csids: &Vec<ChangesetId>) {
         self.mapping.regenerate(csids.iter().copied())
     }
@@ -370,16 +562,18 @@ fn derived_data_utils_impl(
     name: impl AsRef<str>,
     mode: DeriveMode,
 ) -> Result<Arc<dyn DerivedUtils>, Error> {
-    match name.as_ref() {
+    let name = name.as_ref();
+    let blobstore = mapping_blobstore(&repo, name);
+    match name {
         RootUnodeManifestId::NAME => {
             let mapping = RootUnodeManifestMapping::new(
-                repo.get_blobstore(),
+                blobstore,
                 repo.get_derived_data_config().unode_version,
             );
             Ok(Arc::new(DerivedUtilsFromMapping::new(mapping, mode)))
         }
         RootFastlog::NAME => {
-            let mapping = RootFastlogMapping::new(repo.get_blobstore().boxed());
+            let mapping = RootFastlogMapping::new(blobstore);
             Ok(Arc::new(DerivedUtilsFromMapping::new(mapping, mode)))
         }
         MappedHgChangesetId::NAME => {
@@ -387,25 +581,335 @@ fn derived_data_utils_impl(
             Ok(Arc::new(DerivedUtilsFromMapping::new(mapping, mode)))
         }
         RootFsnodeId::NAME => {
-            let mapping = RootFsnodeMapping::new(repo.get_blobstore());
+            let mapping = RootFsnodeMapping::new(blobstore);
             Ok(Arc::new(DerivedUtilsFromMapping::new(mapping, mode)))
         }
         BlameRoot::NAME => {
-            let mapping = BlameRootMapping::new(repo.get_blobstore().boxed());
+            let mapping = BlameRootMapping::new(blobstore);
             Ok(Arc::new(DerivedUtilsFromMapping::new(mapping, mode)))
         }
         ChangesetInfo::NAME => {
-            let mapping = ChangesetInfoMapping::new(repo.get_blobstore().boxed());
+            let mapping = ChangesetInfoMapping::new(blobstore);
             Ok(Arc::new(DerivedUtilsFromMapping::new(mapping, mode)))
         }
         RootDeletedManifestId::NAME => {
-            let mapping = RootDeletedManifestMapping::new(repo.get_blobstore());
+            let mapping = RootDeletedManifestMapping::new(blobstore);
             Ok(Arc::new(DerivedUtilsFromMapping::new(mapping, mode)))
         }
         FilenodesOnlyPublic::NAME => {
             let mapping = FilenodesOnlyPublicMapping::new(repo);
             Ok(Arc::new(DerivedUtilsFromMapping::new(mapping, mode)))
         }
+        TreeHandle::NAME => {
+            let mapping = TreeMapping::new(blobstore);
+            Ok(Arc::new(DerivedUtilsFromMapping::new(mapping, mode)))
+        }
+        CommitHandle::NAME => {
+            let mapping = CommitMapping::new(blobstore);
+            Ok(Arc::new(DerivedUtilsFromMapping::new(mapping, mode)))
+        }
+        RootSkeletonManifestId::NAME => {
+            let mapping = RootSkeletonManifestMapping::new(blobstore);
+            Ok(Arc::new(DerivedUtilsFromMapping::new(mapping, mode)))
+        }
+        ChangesetDiffstat::NAME => {
+            let mapping = ChangesetDiffstatMapping::new(blobstore);
+            Ok(Arc::new(DerivedUtilsFromMapping::new(mapping, mode)))
+        }
+        RootFileClassification::NAME => {
+            let mapping = RootFileClassificationMapping::new(blobstore);
+            Ok(Arc::new(DerivedUtilsFromMapping::new(mapping, mode)))
+        }
         name => Err(format_err!("Unsupported derived data type: {}", name)),
     }
 }
+
+/// Moves derived data of one type from one version of its mapping to another.
+///
+/// This is used when derived data was generated incorrectly for some
+/// changesets: the invalid ones are derived again into the new version,
+/// starting from copies of the mapping entries of their valid parents, and
+/// once the new version has been verified against the old one readers are
+/// switched over by bumping the mapping version in the repo config. Readers
+/// of the new version fall back to the old one for all other changesets.
+#[async_trait]
+pub trait MappingMigration: Send + Sync + 'static {
+    /// Copy mapping entries for changesets from the old version of the
+    /// mapping to the new one. Returns the number of changesets that had an
+    /// entry in the old version.
+    async fn copy(&self, ctx: &CoreContext, csids: Vec<ChangesetId>) -> Result<usize, Error>;
+
+    /// Derive data for a changeset into the new version of the mapping,
+    /// together with any of its ancestors that are not in the new version.
+    /// Entries of the old version are never used for this.
+    async fn rederive(
+        &self,
+        ctx: &CoreContext,
+        repo: &BlobRepo,
+        csid: ChangesetId,
+    ) -> Result<(), Error>;
+
+    /// Compare the old and the new version of the mapping, returning the
+    /// changesets for which they differ.
+    async fn verify(
+        &self,
+        ctx: &CoreContext,
+        csids: Vec<ChangesetId>,
+    ) -> Result<Vec<MappingDifference>, Error>;
+
+    /// Get a name for this type of derived data
+    fn name(&self) -> &'static str;
+}
+
+/// Derived data of a changeset that differs between two versions of a mapping.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MappingDifference {
+    pub csid: ChangesetId,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+struct MigrationFromMappings<M> {
+    old: M,
+    new: M,
+}
+
+#[async_trait]
+impl<M> MappingMigration for MigrationFromMappings<M>
+where
+    M: BonsaiDerivedMapping + Clone + 'static,
+    M::Value: BonsaiDerived + PartialEq + std::fmt::Debug,
+{
+    async fn copy(&self, ctx: &CoreContext, csids: Vec<ChangesetId>) -> Result<usize, Error> {
+        let derived = self.old.get(ctx.clone(), csids).compat().await?;
+        let count = derived.len();
+        stream::iter(derived)
+            .map(|(csid, value)| self.new.put(ctx.clone(), csid, value).compat())
+            .buffer_unordered(100)
+            .try_for_each(|_| ready(Ok(())))
+            .await?;
+        Ok(count)
+    }
+
+    async fn rederive(
+        &self,
+        ctx: &CoreContext,
+        repo: &BlobRepo,
+        csid: ChangesetId,
+    ) -> Result<(), Error> {
+        derive_impl::<M::Value, _>(
+            ctx.clone(),
+            repo.clone(),
+            self.new.clone(),
+            csid,
+            DeriveMode::OnlyIfEnabled,
+        )
+        .compat()
+        .await?;
+        Ok(())
+    }
+
+    async fn verify(
+        &self,
+        ctx: &CoreContext,
+        csids: Vec<ChangesetId>,
+    ) -> Result<Vec<MappingDifference>, Error> {
+        let (old, new) = try_join(
+            self.old.get(ctx.clone(), csids.clone()).compat(),
+            self.new.get(ctx.clone(), csids.clone()).compat(),
+        )
+        .await?;
+        Ok(csids
+            .into_iter()
+            .filter_map(|csid| {
+                let old = old.get(&csid);
+                let new = new.get(&csid);
+                if old == new {
+                    None
+                } else {
+                    Some(MappingDifference {
+                        csid,
+                        old: old.map(|value| format!("{:?}", value)),
+                        new: new.map(|value| format!("{:?}", value)),
+                    })
+                }
+            })
+            .collect())
+    }
+
+    fn name(&self) -> &'static str {
+        M::Value::NAME
+    }
+}
+
+/// Returns a migration of derived data type `name` from one version of its
+/// mapping to the next.
+///
+/// Only types whose data is entirely reachable from the mapping can be
+/// migrated. Types like blame and fastlog store their data under keys of
+/// their own (e.g. unode ids), so re-deriving them would overwrite the data
+/// that readers of the old version use, and comparing the mappings would
+/// not compare that data.
+///
+/// Types that other enabled types are derived from can't be migrated either:
+/// the data of their dependents was derived from the invalid data too, and
+/// would not be re-derived.
+pub fn mapping_migration(
+    repo: &BlobRepo,
+    name: impl AsRef<str>,
+    from_version: u64,
+    to_version: u64,
+) -> Result<Arc<dyn MappingMigration>, Error> {
+    // Readers of a version fall back to the version before it, so versions
+    // can't be skipped
+    if to_version != from_version + 1 {
+        return Err(format_err!(
+            "Can only migrate mapping version {} to version {}, not {}",
+            from_version,
+            from_version + 1,
+            to_version
+        ));
+    }
+    let derived_data_types = &repo.get_derived_data_config().derived_data_types;
+    let mut dependents = vec![];
+    for dependent in POSSIBLE_DERIVED_TYPES {
+        if derived_data_types.contains(*dependent)
+            && derived_data_dependencies(dependent)?
+                .iter()
+                .any(|dependency| *dependency == name.as_ref())
+        {
+            dependents.push(*dependent);
+        }
+    }
+    if !dependents.is_empty() {
+        return Err(format_err!(
+            "{} can't be migrated because it is a dependency of {}",
+            name.as_ref(),
+            dependents.join(", ")
+        ));
+    }
+
+    let blobstore = repo.get_blobstore().boxed();
+    let old = versioned_mapping_blobstore(blobstore.clone(), from_version);
+    let new = exact_versioned_mapping_blobstore(blobstore, to_version);
+    match name.as_ref() {
+        RootUnodeManifestId::NAME => {
+            let unode_version = repo.get_derived_data_config().unode_version;
+            Ok(Arc::new(MigrationFromMappings {
+                old: RootUnodeManifestMapping::new(old, unode_version),
+                new: RootUnodeManifestMapping::new(new, unode_version),
+            }))
+        }
+        RootFsnodeId::NAME => Ok(Arc::new(MigrationFromMappings {
+            old: RootFsnodeMapping::new(old),
+            new: RootFsnodeMapping::new(new),
+        })),
+        ChangesetInfo::NAME => Ok(Arc::new(MigrationFromMappings {
+            old: ChangesetInfoMapping::new(old),
+            new: ChangesetInfoMapping::new(new),
+        })),
+        RootDeletedManifestId::NAME => Ok(Arc::new(MigrationFromMappings {
+            old: RootDeletedManifestMapping::new(old),
+            new: RootDeletedManifestMapping::new(new),
+        })),
+        TreeHandle::NAME => Ok(Arc::new(MigrationFromMappings {
+            old: TreeMapping::new(old),
+            new: TreeMapping::new(new),
+        })),
+        CommitHandle::NAME => Ok(Arc::new(MigrationFromMappings {
+            old: CommitMapping::new(old),
+            new: CommitMapping::new(new),
+        })),
+        RootSkeletonManifestId::NAME => Ok(Arc::new(MigrationFromMappings {
+            old: RootSkeletonManifestMapping::new(old),
+            new: RootSkeletonManifestMapping::new(new),
+        })),
+        ChangesetDiffstat::NAME => Ok(Arc::new(MigrationFromMappings {
+            old: ChangesetDiffstatMapping::new(old),
+            new: ChangesetDiffstatMapping::new(new),
+        })),
+        BlameRoot::NAME | RootFastlog::NAME | RootFileClassification::NAME => Err(format_err!(
+            "{} data is not stored in its mapping, so it can't be migrated",
+            name.as_ref()
+        )),
+        name => Err(format_err!("Mapping of {} is not versioned", name)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fbinit::FacebookInit;
+    use tests_utils::CreateCommitContext;
+
+    #[fbinit::compat_test]
+    async fn test_derived_data_status(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = blobrepo_factory::new_memblob_empty(None)?;
+
+        // root - y - x - p1 - m
+        //    \               /|
+        //     +---- p2 -----+ |
+        //      \              |
+        //       +-- p3 -------+
+        let root = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("root", "root")
+            .commit()
+            .await?;
+        let mut parent = root;
+        let mut stack = vec![];
+        for name in &["y", "x", "p1"] {
+            parent = CreateCommitContext::new(&ctx, &repo, vec![parent])
+                .add_file(*name, *name)
+                .commit()
+                .await?;
+            stack.push(parent);
+        }
+        let (x, p1) = (stack[1], stack[2]);
+        let p2 = CreateCommitContext::new(&ctx, &repo, vec![root])
+            .add_file("p2", "p2")
+            .commit()
+            .await?;
+        let p3 = CreateCommitContext::new(&ctx, &repo, vec![root])
+            .add_file("p3", "p3")
+            .commit()
+            .await?;
+        let m = CreateCommitContext::new(&ctx, &repo, vec![p1, p2, p3])
+            .commit()
+            .await?;
+
+        // Only p1 and m are left underived
+        for csid in vec![x, p2, p3] {
+            RootUnodeManifestId::derive(ctx.clone(), repo.clone(), csid)
+                .compat()
+                .await?;
+        }
+
+        let types = vec![RootUnodeManifestId::NAME.to_string()];
+        let status = |csid, limit| derived_data_status(&ctx, &repo, csid, &types, limit);
+        let expected = |latest_derived_ancestor, underived_count, limit_reached| {
+            vec![DerivedDataStatus {
+                name: RootUnodeManifestId::NAME,
+                enabled: true,
+                latest_derived_ancestor,
+                underived_count,
+                limit_reached,
+                estimated_derivations: underived_count,
+            }]
+        };
+
+        assert_eq!(status(x, None).await?, expected(Some(x), 0, false));
+        assert_eq!(status(m, None).await?, expected(Some(x), 2, false));
+        assert_eq!(
+            find_latest_derived_ancestor(&ctx, &repo, m, &[p1, m]).await?,
+            Some(x)
+        );
+
+        // Checking m's parents goes over the limit, so p1 is never checked.
+        // The count is a lower bound, and the latest derived ancestor can't
+        // be told (from what was visited it would look like p1).
+        assert_eq!(status(m, Some(2)).await?, expected(None, 1, true));
+
+        Ok(())
+    }
+}
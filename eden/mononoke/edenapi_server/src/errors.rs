@@ -64,6 +64,12 @@ pub enum ErrorKind {
     CommitRevlogDataRequestFailed,
     #[error("HgId not found: {0}")]
     HgIdNotFound(HgId),
+    #[error("Invalid commit id: {0}")]
+    InvalidCommitId(String),
+    #[error("Commit not found: {0}")]
+    CommitNotFound(String),
+    #[error("Archive request failed")]
+    ArchiveRequestFailed,
 }
 
 /// Extension trait for converting `MononokeError`s into `HttpErrors`.
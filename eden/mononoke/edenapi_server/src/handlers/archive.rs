@@ -0,0 +1,105 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use anyhow::{Context, Error};
+use futures::TryStreamExt;
+use gotham::state::{FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use mime::Mime;
+use serde::Deserialize;
+use slog::error;
+
+use gotham_ext::{
+    content::ContentStream,
+    error::HttpError,
+    response::{StreamBody, TryIntoResponse},
+    stream_ext::GothamTryStreamExt,
+};
+use mercurial_types::HgChangesetId;
+use mononoke_api::{ArchiveFormat, ChangesetSpecifier, MononokePath};
+
+use crate::context::ServerContext;
+use crate::errors::{ErrorKind, MononokeErrorExt};
+use crate::middleware::RequestContext;
+use crate::utils::get_repo_context;
+
+use super::{EdenApiMethod, HandlerInfo};
+
+#[derive(Debug, Deserialize, StateData, StaticResponseExtender)]
+pub struct ArchiveParams {
+    repo: String,
+    commit: String,
+}
+
+#[derive(Debug, Deserialize, StateData, StaticResponseExtender)]
+pub struct ArchiveQueryString {
+    /// Archive format, as a file extension, e.g. "tar.gz". Defaults to
+    /// an uncompressed tar archive.
+    format: Option<String>,
+    /// Only include the files under this path.
+    path: Option<String>,
+}
+
+/// Stream an archive of the files in a commit, identified by its Mercurial
+/// changeset id.
+pub async fn archive(state: &mut State) -> Result<impl TryIntoResponse, HttpError> {
+    let params = ArchiveParams::take_from(state);
+    let query = ArchiveQueryString::take_from(state);
+
+    state.put(HandlerInfo::new(&params.repo, EdenApiMethod::Archive));
+
+    let sctx = ServerContext::borrow_from(state);
+    let rctx = RequestContext::borrow_from(state).clone();
+
+    let format = match &query.format {
+        Some(format) => ArchiveFormat::from_str(format)
+            .map_err(|e| e.into_http_error(ErrorKind::ArchiveRequestFailed))?,
+        None => ArchiveFormat::Tar,
+    };
+    let path_prefix = match &query.path {
+        Some(path) => Some(
+            MononokePath::try_from(path)
+                .with_context(|| ErrorKind::InvalidPath(path.as_bytes().to_vec()))
+                .map_err(HttpError::e400)?,
+        ),
+        None => None,
+    };
+    let hg_cs_id = HgChangesetId::from_str(&params.commit)
+        .with_context(|| ErrorKind::InvalidCommitId(params.commit.clone()))
+        .map_err(HttpError::e400)?;
+
+    let repo = get_repo_context(&sctx, &rctx, &params.repo).await?;
+    let changeset = repo
+        .changeset(ChangesetSpecifier::Hg(hg_cs_id))
+        .await
+        .map_err(|e| e.into_http_error(ErrorKind::ArchiveRequestFailed))?
+        .with_context(|| ErrorKind::CommitNotFound(params.commit.clone()))
+        .map_err(HttpError::e404)?;
+
+    let mime: Mime = format
+        .mime_type()
+        .parse()
+        .context(ErrorKind::ArchiveRequestFailed)
+        .map_err(HttpError::e500)?;
+    let stream = changeset
+        .archive(format, path_prefix)
+        .await
+        .map_err(|e| e.into_http_error(ErrorKind::ArchiveRequestFailed))?
+        .map_err(Error::from);
+
+    // An archive that is cut short is corrupt, so end the response on the
+    // first error rather than skipping over it.
+    let logger = rctx.logger.clone();
+    let stream = ContentStream::new(stream).end_on_err(move |e| {
+        error!(&logger, "Error during streaming archive: {:?}", &e);
+    });
+
+    Ok(StreamBody::new(stream, mime))
+}
@@ -26,6 +26,7 @@ define_stats! {
     history_duration: dynamic_histogram("{}.history_ms", (repo: String); 100, 0, 5000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     commit_location_to_hash_duration: dynamic_histogram("{}.commit_location_to_hash_ms", (repo: String); 10, 0, 500, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
     commit_revlog_data_duration: dynamic_histogram("{}.commit_revlog_data_ms", (repo: String); 10, 0, 500, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
+    archive_duration: dynamic_histogram("{}.archive_ms", (repo: String); 1000, 0, 100_000, Average, Sum, Count; P 5; P 25; P 50; P 75; P 95; P 97; P 99),
 }
 
 fn log_stats(state: &mut State, status: StatusCode) -> Option<()> {
@@ -58,6 +59,7 @@ fn log_stats(state: &mut State, status: StatusCode) -> Option<()> {
                     STATS::commit_location_to_hash_duration.add_value(dur_ms, (repo,))
                 }
                 CommitRevlogData => STATS::commit_revlog_data_duration.add_value(dur_ms, (repo,)),
+                Archive => STATS::archive_duration.add_value(dur_ms, (repo,)),
             }
         }
 
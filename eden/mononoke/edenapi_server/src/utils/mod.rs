@@ -13,6 +13,7 @@ use hyper::Body;
 
 use gotham_ext::{body_ext::BodyExt, error::HttpError};
 use mononoke_api::hg::HgRepoContext;
+use mononoke_api::RepoContext;
 
 use crate::context::ServerContext;
 use crate::errors::{ErrorKind, MononokeErrorExt};
@@ -29,12 +30,19 @@ pub async fn get_repo(
     rctx: &RequestContext,
     name: impl AsRef<str>,
 ) -> Result<HgRepoContext, HttpError> {
+    Ok(get_repo_context(sctx, rctx, name).await?.hg())
+}
+
+pub async fn get_repo_context(
+    sctx: &ServerContext,
+    rctx: &RequestContext,
+    name: impl AsRef<str>,
+) -> Result<RepoContext, HttpError> {
     let name = name.as_ref();
     sctx.mononoke_api()
         .repo(rctx.ctx.clone(), name)
         .await
         .map_err(|e| e.into_http_error(ErrorKind::RepoLoadFailed(name.to_string())))?
-        .map(|repo| repo.hg())
         .with_context(|| ErrorKind::RepoDoesNotExist(name.to_string()))
         .map_err(HttpError::e404)
 }
@@ -27,3 +27,16 @@ struct Tree {
   1: TreeHandle handle,
   2: map<mononoke_types_thrift.MPathElement, TreeMember> members,
 }
+
+struct CommitHandle {
+  1: mononoke_types_thrift.GitSha1 oid,
+  2: i64 size,
+}
+
+struct Commit {
+  1: CommitHandle handle,
+  2: TreeHandle tree,
+  3: list<CommitHandle> parents,
+  // The git commit object, without the "commit <size>\0" prefix
+  4: binary object,
+}
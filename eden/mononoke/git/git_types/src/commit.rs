@@ -0,0 +1,300 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use std::convert::{TryFrom, TryInto};
+use std::io::Write;
+
+use mononoke_types::{hash::RichGitSha1, BonsaiChangeset, DateTime};
+
+use crate::errors::ErrorKind;
+use crate::thrift;
+use crate::{ObjectKind, TreeHandle};
+
+/// Bonsai extras with this prefix are written to the git commit as extra headers, after the
+/// committer, in key order. For example `git-extra-header:gpgsig` becomes a `gpgsig` header.
+pub const GIT_EXTRA_HEADER_PREFIX: &str = "git-extra-header:";
+
+// Headers that are always generated from the bonsai changeset itself
+const RESERVED_HEADERS: &[&str] = &["tree", "parent", "author", "committer"];
+
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub struct CommitHandle {
+    oid: RichGitSha1,
+}
+
+impl CommitHandle {
+    pub fn oid(&self) -> &RichGitSha1 {
+        &self.oid
+    }
+
+    pub fn blobstore_key(&self) -> String {
+        format!("git.commit.{}", self.oid)
+    }
+}
+
+impl TryFrom<thrift::CommitHandle> for CommitHandle {
+    type Error = Error;
+
+    fn try_from(t: thrift::CommitHandle) -> Result<Self, Error> {
+        let size = t.size.try_into()?;
+        let oid = RichGitSha1::from_bytes(&t.oid.0, ObjectKind::Commit.as_str(), size)?;
+        Ok(Self { oid })
+    }
+}
+
+impl Into<thrift::CommitHandle> for CommitHandle {
+    fn into(self) -> thrift::CommitHandle {
+        let size = self.oid.size();
+
+        thrift::CommitHandle {
+            oid: self.oid.into_thrift(),
+            size: size.try_into().expect("Commit size must fit in a i64"),
+        }
+    }
+}
+
+/// A git commit object, generated from a bonsai changeset, its git tree and its parents' git
+/// commits. The same inputs always produce the same bytes, and so the same commit hash.
+#[derive(Debug, Clone)]
+pub struct Commit {
+    handle: CommitHandle,
+    tree: TreeHandle,
+    parents: Vec<CommitHandle>,
+    object: Vec<u8>,
+}
+
+impl Commit {
+    pub fn new(
+        bcs: &BonsaiChangeset,
+        tree: TreeHandle,
+        parents: Vec<CommitHandle>,
+    ) -> Result<Self, Error> {
+        let mut object = Vec::new();
+        write_commit_object(&mut object, bcs, &tree, &parents)?;
+        let oid = ObjectKind::Commit.create_oid(&object);
+
+        Ok(Self {
+            handle: CommitHandle { oid },
+            tree,
+            parents,
+            object,
+        })
+    }
+
+    pub fn handle(&self) -> &CommitHandle {
+        &self.handle
+    }
+
+    pub fn tree(&self) -> &TreeHandle {
+        &self.tree
+    }
+
+    pub fn parents(&self) -> &[CommitHandle] {
+        &self.parents
+    }
+
+    /// The serialized git commit object, without the `commit <size>\0` prefix
+    pub fn object(&self) -> &[u8] {
+        &self.object
+    }
+}
+
+impl TryFrom<thrift::Commit> for Commit {
+    type Error = Error;
+
+    fn try_from(t: thrift::Commit) -> Result<Self, Error> {
+        let parents = t
+            .parents
+            .into_iter()
+            .map(CommitHandle::try_from)
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(Self {
+            handle: t.handle.try_into()?,
+            tree: t.tree.try_into()?,
+            parents,
+            object: t.object,
+        })
+    }
+}
+
+impl Into<thrift::Commit> for Commit {
+    fn into(self) -> thrift::Commit {
+        let Commit {
+            handle,
+            tree,
+            parents,
+            object,
+        } = self;
+
+        thrift::Commit {
+            handle: handle.into(),
+            tree: tree.into(),
+            parents: parents.into_iter().map(Into::into).collect(),
+            object,
+        }
+    }
+}
+
+fn write_commit_object(
+    writer: &mut impl Write,
+    bcs: &BonsaiChangeset,
+    tree: &TreeHandle,
+    parents: &[CommitHandle],
+) -> Result<(), Error> {
+    writeln!(writer, "tree {}", tree.oid())?;
+    for parent in parents {
+        writeln!(writer, "parent {}", parent.oid())?;
+    }
+
+    write_signature(writer, "author", bcs.author(), bcs.author_date())?;
+    write_signature(
+        writer,
+        "committer",
+        bcs.committer().unwrap_or_else(|| bcs.author()),
+        bcs.committer_date().unwrap_or_else(|| bcs.author_date()),
+    )?;
+
+    for (key, value) in bcs.extra() {
+        if let Some(name) = key.strip_prefix(GIT_EXTRA_HEADER_PREFIX) {
+            write_header(writer, name, value)?;
+        }
+    }
+
+    writer.write_all(b"\n")?;
+    writer.write_all(bcs.message().as_bytes())?;
+    Ok(())
+}
+
+// Bonsai signatures are usually `Name <email>`, which is what git expects. Anything else is
+// taken as a name without an email.
+fn write_signature(
+    writer: &mut impl Write,
+    header: &str,
+    who: &str,
+    date: &DateTime,
+) -> Result<(), Error> {
+    if who.contains('\n') {
+        return Err(ErrorKind::InvalidCommitSignature(who.to_string()).into());
+    }
+    let who = if who.ends_with('>') && who.contains(" <") {
+        who.to_string()
+    } else {
+        format!("{} <>", who)
+    };
+
+    // Bonsai offsets are seconds west of UTC, git offsets are hours and minutes east of it
+    let offset = -date.tz_offset_secs();
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    writeln!(
+        writer,
+        "{} {} {} {}{:02}{:02}",
+        header,
+        who,
+        date.timestamp_secs(),
+        sign,
+        offset / 3600,
+        (offset % 3600) / 60
+    )?;
+    Ok(())
+}
+
+// Multi-line values, such as signatures, continue on lines starting with a space
+fn write_header(writer: &mut impl Write, name: &str, value: &[u8]) -> Result<(), Error> {
+    if name.is_empty()
+        || name.contains(|c: char| c == ' ' || c == '\n')
+        || RESERVED_HEADERS.contains(&name)
+    {
+        return Err(ErrorKind::InvalidCommitHeader(name.to_string()).into());
+    }
+
+    writer.write_all(name.as_bytes())?;
+    writer.write_all(b" ")?;
+    for (i, line) in value.split(|b| *b == b'\n').enumerate() {
+        if i > 0 {
+            writer.write_all(b"\n ")?;
+        }
+        writer.write_all(line)?;
+    }
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Tree;
+    use crate::TreeBuilder;
+    use mononoke_types::BonsaiChangesetMut;
+    use std::collections::BTreeMap;
+
+    fn empty_tree() -> TreeHandle {
+        let tree: Tree = TreeBuilder::default().into();
+        *tree.handle()
+    }
+
+    #[test]
+    fn test_commit_object() -> Result<(), Error> {
+        let mut extra = BTreeMap::new();
+        extra.insert(
+            format!("{}gpgsig", GIT_EXTRA_HEADER_PREFIX),
+            b"-----BEGIN PGP SIGNATURE-----\nabc\n-----END PGP SIGNATURE-----".to_vec(),
+        );
+        extra.insert("other".to_string(), b"ignored".to_vec());
+        let bcs = BonsaiChangesetMut {
+            parents: vec![],
+            author: "Jane Doe <jane@example.com>".to_string(),
+            author_date: DateTime::from_timestamp(1500000000, -3600)?,
+            committer: Some("committer".to_string()),
+            committer_date: Some(DateTime::from_timestamp(1500000060, 5 * 3600 + 30 * 60)?),
+            message: "message\n".to_string(),
+            extra,
+            file_changes: BTreeMap::new(),
+        }
+        .freeze()?;
+
+        let commit = Commit::new(&bcs, empty_tree(), vec![])?;
+        let expected = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+                        author Jane Doe <jane@example.com> 1500000000 +0100\n\
+                        committer committer <> 1500000060 -0530\n\
+                        gpgsig -----BEGIN PGP SIGNATURE-----\n abc\n -----END PGP SIGNATURE-----\n\
+                        \n\
+                        message\n";
+        assert_eq!(String::from_utf8_lossy(commit.object()), expected);
+
+        // The hash is the git hash of the object
+        let git_oid = git2::Oid::hash_object(git2::ObjectType::Commit, expected.as_bytes())?;
+        assert_eq!(commit.handle().oid().as_ref(), git_oid.as_bytes());
+
+        let child = Commit::new(&bcs, empty_tree(), vec![*commit.handle()])?;
+        assert!(String::from_utf8_lossy(child.object())
+            .contains(&format!("\nparent {}\n", commit.handle().oid())));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reserved_header() -> Result<(), Error> {
+        let mut extra = BTreeMap::new();
+        extra.insert(format!("{}tree", GIT_EXTRA_HEADER_PREFIX), b"x".to_vec());
+        let bcs = BonsaiChangesetMut {
+            parents: vec![],
+            author: "author".to_string(),
+            author_date: DateTime::from_timestamp(0, 0)?,
+            committer: None,
+            committer_date: None,
+            message: "message".to_string(),
+            extra,
+            file_changes: BTreeMap::new(),
+        }
+        .freeze()?;
+
+        assert!(Commit::new(&bcs, empty_tree(), vec![]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use context::CoreContext;
+use futures::{
+    compat::Future01CompatExt,
+    future::{FutureExt as NewFutureExt, TryFutureExt},
+};
+use futures_ext::{BoxFuture, FutureExt, StreamExt};
+use futures_old::{stream::futures_unordered, Future, Stream};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::sync::Arc;
+
+use blobrepo::BlobRepo;
+use blobstore::{Blobstore, Storable};
+use bonsai_git_mapping::{AddGitMappingErrorKind, BonsaiGitMapping, BonsaiGitMappingEntry};
+use derived_data::{mapping_blobstore, BonsaiDerived, BonsaiDerivedMapping};
+use mononoke_types::{BonsaiChangeset, ChangesetId};
+use slog::warn;
+
+use crate::{Commit, CommitHandle, TreeHandle};
+
+#[derive(Clone)]
+pub struct CommitMapping {
+    blobstore: Arc<dyn Blobstore>,
+}
+
+impl CommitMapping {
+    pub fn new(blobstore: Arc<dyn Blobstore>) -> Self {
+        Self { blobstore }
+    }
+
+    fn root_key(&self, cs_id: ChangesetId) -> String {
+        format!("git.derived_commit.{}", cs_id)
+    }
+
+    fn fetch_commit(
+        &self,
+        ctx: CoreContext,
+        cs_id: ChangesetId,
+    ) -> impl Future<Item = Option<(ChangesetId, CommitHandle)>, Error = Error> {
+        self.blobstore
+            .get(ctx, self.root_key(cs_id))
+            .compat()
+            .and_then(move |bytes| match bytes {
+                Some(bytes) => bytes.try_into().map(|handle| Some((cs_id, handle))),
+                None => Ok(None),
+            })
+    }
+}
+
+impl BonsaiDerivedMapping for CommitMapping {
+    type Value = CommitHandle;
+
+    fn get(
+        &self,
+        ctx: CoreContext,
+        csids: Vec<ChangesetId>,
+    ) -> BoxFuture<HashMap<ChangesetId, Self::Value>, Error> {
+        let gets = csids
+            .into_iter()
+            .map(|cs_id| self.fetch_commit(ctx.clone(), cs_id));
+
+        futures_unordered(gets)
+            .filter_map(|maybe_handle| maybe_handle)
+            .collect_to()
+            .boxify()
+    }
+
+    fn put(&self, ctx: CoreContext, csid: ChangesetId, root: Self::Value) -> BoxFuture<(), Error> {
+        self.blobstore
+            .put(ctx, self.root_key(csid), root.into())
+            .compat()
+            .boxify()
+    }
+}
+
+impl BonsaiDerived for CommitHandle {
+    const NAME: &'static str = "git_commits";
+    const DEPENDENCIES: &'static [&'static str] = &[TreeHandle::NAME];
+    type Mapping = CommitMapping;
+
+    fn mapping(_ctx: &CoreContext, repo: &BlobRepo) -> Self::Mapping {
+        CommitMapping::new(mapping_blobstore(repo, Self::NAME))
+    }
+
+    fn derive_from_parents(
+        ctx: CoreContext,
+        repo: BlobRepo,
+        bonsai: BonsaiChangeset,
+        parents: Vec<Self>,
+    ) -> BoxFuture<Self, Error> {
+        async move {
+            let cs_id = bonsai.get_changeset_id();
+            let tree = TreeHandle::derive(ctx.clone(), repo.clone(), cs_id)
+                .compat()
+                .await?;
+
+            let commit = Commit::new(&bonsai, tree, parents)?;
+            let handle = commit.store(ctx.clone(), &repo.get_blobstore()).await?;
+            record_git_mapping(&ctx, &repo, cs_id, &handle).await?;
+            Ok(handle)
+        }
+        .boxed()
+        .compat()
+        .boxify()
+    }
+}
+
+// Commits imported from git already have a mapping to their original hash, which is kept. Bonsais
+// that only differ in what git doesn't store (e.g. non-git extras) have the same git hash, which
+// stays mapped to the first of them.
+async fn record_git_mapping(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    cs_id: ChangesetId,
+    handle: &CommitHandle,
+) -> Result<(), Error> {
+    let mapping = repo.bonsai_git_mapping();
+    if mapping
+        .get_git_sha1_from_bonsai(ctx, cs_id)
+        .await?
+        .is_none()
+    {
+        let entry = BonsaiGitMappingEntry::new(handle.oid().sha1(), cs_id);
+        match mapping.bulk_add(ctx, &[entry]).await {
+            Ok(()) => {}
+            Err(AddGitMappingErrorKind::Conflict(entries)) => {
+                warn!(
+                    ctx.logger(),
+                    "Not mapping {} to git commit {}, which is already mapped: {:?}",
+                    cs_id,
+                    handle.oid(),
+                    entries
+                );
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::format_err;
+    use blobstore::Loadable;
+    use fbinit::FacebookInit;
+    use tests_utils::CreateCommitContext;
+
+    async fn run_commit_derivation_for_fixture(
+        fb: FacebookInit,
+        repo: BlobRepo,
+    ) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+
+        let bcs_id = repo
+            .get_bonsai_bookmark(ctx.clone(), &("master".try_into()?))
+            .compat()
+            .await?
+            .ok_or(format_err!("no master"))?;
+
+        let handle = CommitHandle::derive(ctx.clone(), repo.clone(), bcs_id)
+            .compat()
+            .await?;
+        let commit = handle.load(ctx.clone(), repo.blobstore()).await?;
+        assert_eq!(commit.handle(), &handle);
+
+        let tree = TreeHandle::derive(ctx.clone(), repo.clone(), bcs_id)
+            .compat()
+            .await?;
+        assert_eq!(commit.tree(), &tree);
+
+        let bcs = bcs_id.load(ctx.clone(), repo.blobstore()).await?;
+        let mut parents = vec![];
+        for parent in bcs.parents() {
+            parents.push(
+                CommitHandle::derive(ctx.clone(), repo.clone(), parent)
+                    .compat()
+                    .await?,
+            );
+        }
+        assert_eq!(commit.parents(), &parents[..]);
+
+        let git_sha1 = repo
+            .bonsai_git_mapping()
+            .get_git_sha1_from_bonsai(&ctx, bcs_id)
+            .await?;
+        assert_eq!(git_sha1, Some(handle.oid().sha1()));
+
+        Ok(())
+    }
+
+    macro_rules! impl_test {
+        ($fixture:ident) => {
+            #[fbinit::test]
+            fn $fixture(fb: FacebookInit) -> Result<(), Error> {
+                let mut runtime = tokio_compat::runtime::Runtime::new()?;
+                runtime.block_on_std(async move {
+                    let repo = fixtures::$fixture::getrepo(fb).await;
+                    run_commit_derivation_for_fixture(fb, repo).await
+                })
+            }
+        };
+    }
+
+    impl_test!(linear);
+    impl_test!(branch_even);
+    impl_test!(merge_even);
+    impl_test!(many_diamonds);
+
+    #[fbinit::compat_test]
+    async fn same_git_commit(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = blobrepo_factory::new_memblob_empty(None)?;
+
+        // Git doesn't store these extras, so both bonsais have the same git commit
+        let first = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("file", "content")
+            .add_extra("origin".to_string(), b"first".to_vec())
+            .commit()
+            .await?;
+        let second = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("file", "content")
+            .add_extra("origin".to_string(), b"second".to_vec())
+            .commit()
+            .await?;
+        assert_ne!(first, second);
+
+        let first_handle = CommitHandle::derive(ctx.clone(), repo.clone(), first)
+            .compat()
+            .await?;
+        let second_handle = CommitHandle::derive(ctx.clone(), repo.clone(), second)
+            .compat()
+            .await?;
+        assert_eq!(first_handle, second_handle);
+
+        // The git commit stays mapped to the first bonsai
+        let mapping = repo.bonsai_git_mapping();
+        assert_eq!(
+            mapping.get_git_sha1_from_bonsai(&ctx, first).await?,
+            Some(first_handle.oid().sha1())
+        );
+        assert_eq!(mapping.get_git_sha1_from_bonsai(&ctx, second).await?, None);
+
+        Ok(())
+    }
+}
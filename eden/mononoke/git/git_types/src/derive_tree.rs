@@ -18,7 +18,7 @@ use std::sync::Arc;
 
 use blobrepo::BlobRepo;
 use blobstore::{Blobstore, Storable};
-use derived_data::{BonsaiDerived, BonsaiDerivedMapping};
+use derived_data::{mapping_blobstore, BonsaiDerived, BonsaiDerivedMapping};
 use filestore::{self, FetchKey};
 use mononoke_types::{BonsaiChangeset, ChangesetId, MPath};
 
@@ -87,7 +87,7 @@ impl BonsaiDerived for TreeHandle {
     type Mapping = TreeMapping;
 
     fn mapping(_ctx: &CoreContext, repo: &BlobRepo) -> Self::Mapping {
-        TreeMapping::new(repo.blobstore().boxed())
+        TreeMapping::new(mapping_blobstore(repo, Self::NAME))
     }
 
     fn derive_from_parents(
@@ -16,4 +16,8 @@ pub enum ErrorKind {
     TreeDerivationFailed,
     #[error("Invalid Thrift")]
     InvalidThrift,
+    #[error("Invalid git commit signature: {0:?}")]
+    InvalidCommitSignature(String),
+    #[error("Invalid git commit extra header: {0:?}")]
+    InvalidCommitHeader(String),
 }
@@ -12,6 +12,8 @@ mod thrift {
 }
 
 mod blob;
+mod commit;
+mod derive_commit;
 mod derive_tree;
 mod errors;
 mod manifest;
@@ -20,6 +22,8 @@ mod store;
 mod tree;
 
 pub use crate::blob::BlobHandle;
+pub use crate::commit::{Commit, CommitHandle, GIT_EXTRA_HEADER_PREFIX};
 pub use crate::tree::{Tree, TreeBuilder, TreeHandle, TreeMember, Treeish};
+pub use derive_commit::CommitMapping;
 pub use derive_tree::TreeMapping;
 pub use object::ObjectKind;
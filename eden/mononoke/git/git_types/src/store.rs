@@ -13,7 +13,7 @@ use futures::future::{BoxFuture, FutureExt};
 use std::convert::TryFrom;
 use std::convert::TryInto;
 
-use crate::{thrift, Tree, TreeHandle};
+use crate::{thrift, Commit, CommitHandle, Tree, TreeHandle};
 
 macro_rules! impl_blobstore_conversions {
     ($ty:ident) => {
@@ -98,3 +98,4 @@ macro_rules! impl_loadable_storable {
 }
 
 impl_loadable_storable!(TreeHandle, Tree);
+impl_loadable_storable!(CommitHandle, Commit);
@@ -13,8 +13,10 @@ use futures_ext::FutureExt as _;
 use futures_old::Future as _;
 use mononoke_types::{
     fsnode::{Fsnode, FsnodeEntry, FsnodeFile},
+    skeleton_manifest::{SkeletonManifest, SkeletonManifestEntry},
     unode::{ManifestUnode, UnodeEntry},
     ContentId, FileType, FileUnodeId, FsnodeId, MPath, MPathElement, ManifestUnodeId,
+    SkeletonManifestId,
 };
 use serde_derive::{Deserialize, Serialize};
 use std::{
@@ -79,6 +81,35 @@ fn convert_fsnode(fsnode_entry: &FsnodeEntry) -> Entry<FsnodeId, FsnodeFile> {
     }
 }
 
+// Skeleton manifests only record that a file exists, so their leaves carry no data
+impl Manifest for SkeletonManifest {
+    type TreeId = SkeletonManifestId;
+    type LeafId = ();
+
+    fn lookup(&self, name: &MPathElement) -> Option<Entry<Self::TreeId, Self::LeafId>> {
+        self.lookup(name).map(convert_skeleton_manifest)
+    }
+
+    fn list(&self) -> Box<dyn Iterator<Item = (MPathElement, Entry<Self::TreeId, Self::LeafId>)>> {
+        let v: Vec<_> = self
+            .list()
+            .map(|(basename, entry)| (basename.clone(), convert_skeleton_manifest(entry)))
+            .collect();
+        Box::new(v.into_iter())
+    }
+}
+
+fn convert_skeleton_manifest(
+    skeleton_entry: &SkeletonManifestEntry,
+) -> Entry<SkeletonManifestId, ()> {
+    match skeleton_entry {
+        SkeletonManifestEntry::File => Entry::Leaf(()),
+        SkeletonManifestEntry::Directory(skeleton_directory) => {
+            Entry::Tree(skeleton_directory.id().clone())
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub enum Entry<T, L> {
     Tree(T),
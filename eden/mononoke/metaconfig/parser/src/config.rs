@@ -431,13 +431,13 @@ mod test {
         DefaultSmallToLargeCommitSyncPathAction, DerivedDataConfig, FilestoreParams, HookBypass,
         HookConfig, HookManagerParams, HookParams, InfinitepushNamespace, InfinitepushParams,
         LfsParams, LocalDatabaseConfig, MetadataDatabaseConfig, MultiplexId, MultiplexedStoreType,
-        PushParams, PushrebaseFlags, PushrebaseParams, RemoteDatabaseConfig,
-        RemoteMetadataDatabaseConfig, RepoClientKnobs, SegmentedChangelogConfig,
-        ShardableRemoteDatabaseConfig, ShardedRemoteDatabaseConfig, SmallRepoCommitSyncConfig,
-        SourceControlServiceMonitoring, SourceControlServiceParams, UnodeVersion,
-        WireprotoLoggingConfig,
+        PushParams, PushrebaseFlags, PushrebaseParams, ReadVerification, ReadVerificationMode,
+        RemoteDatabaseConfig, RemoteMetadataDatabaseConfig, RepoClientKnobs, S3Credentials,
+        ScrubAction, SegmentedChangelogConfig, ShardableRemoteDatabaseConfig,
+        ShardedRemoteDatabaseConfig, SmallRepoCommitSyncConfig, SourceControlServiceMonitoring,
+        SourceControlServiceParams, UnodeVersion, WireprotoLoggingConfig,
     };
-    use mononoke_types::MPath;
+    use mononoke_types::{ChangesetId, MPath};
     use nonzero_ext::nonzero;
     use pretty_assertions::assert_eq;
     use regex::Regex;
@@ -905,6 +905,8 @@ mod test {
             [derived_data_config]
             derived_data_types=["fsnodes"]
             override_blame_filesize_limit=101
+            blame_ignore_revs=["1111111111111111111111111111111111111111111111111111111111111111"]
+            mapping_versions={ fsnodes = 2 }
 
             [derived_data_config.raw_unode_version]
             unode_version_v2 = {}
@@ -970,6 +972,7 @@ mod test {
             [infinitepush]
             allow_writes = true
             namespace_pattern = "foobar/.+"
+            bundle_ttl_secs = 86400
 
             [filestore]
             chunk_size = 768
@@ -1050,6 +1053,7 @@ mod test {
             queue_db: DatabaseConfig::Remote(RemoteDatabaseConfig {
                 db_address: "queue_db_address".into(),
             }),
+            read_verification: None,
         };
         let main_storage_config = StorageConfig {
             blobstore: multiplex,
@@ -1180,6 +1184,7 @@ mod test {
                     hydrate_getbundle_response: false,
                     populate_reverse_filler_queue: false,
                     commit_scribe_category: None,
+                    bundle_ttl: Some(Duration::from_secs(86400)),
                 },
                 list_keys_patterns_max: 123,
                 hook_max_file_size: 456,
@@ -1206,6 +1211,11 @@ mod test {
                     scuba_table: None,
                     unode_version: UnodeVersion::V2,
                     override_blame_filesize_limit: Some(101),
+                    blame_ignore_revs: btreeset![ChangesetId::from_str(
+                        "1111111111111111111111111111111111111111111111111111111111111111"
+                    )
+                    .unwrap()],
+                    mapping_versions: btreemap! {String::from("fsnodes") => 2},
                 },
                 hgsql_name: HgsqlName("fbsource".to_string()),
                 hgsql_globalrevs_name: HgsqlGlobalrevsName("fbsource".to_string()),
@@ -1512,6 +1522,7 @@ mod test {
                                 db_address: "queue_db_address".into(),
                             }
                         ),
+                        read_verification: None,
                     },
                     metadata: MetadataDatabaseConfig::Remote(RemoteMetadataDatabaseConfig {
                         primary: RemoteDatabaseConfig {
@@ -1711,4 +1722,161 @@ mod test {
             panic!("Multiplexed config is not a multiplexed blobstore");
         }
     }
+
+    #[fbinit::test]
+    fn test_multiplexed_read_verification(fb: FacebookInit) {
+        const STORAGE: &str = r#"
+        [quorum_store.metadata.local]
+        local_db_path = "/tmp/quorum"
+
+        [quorum_store.blobstore.multiplexed]
+        multiplex_id = 1
+        components = [
+            { blobstore_id = 1, blobstore = { blob_files = { path = "/tmp/foo1" } } },
+            { blobstore_id = 2, blobstore = { blob_files = { path = "/tmp/foo2" } } },
+        ]
+        queue_db = { remote = { db_address = "queue_db_address" } }
+        read_verification = { mode = { quorum = 2 }, repair = true }
+
+        [hash_store.metadata.local]
+        local_db_path = "/tmp/hash"
+
+        [hash_store.blobstore.multiplexed]
+        multiplex_id = 2
+        components = [
+            { blobstore_id = 1, blobstore = { blob_files = { path = "/tmp/foo1" } } },
+        ]
+        queue_db = { remote = { db_address = "queue_db_address" } }
+        read_verification = { mode = { content_hash = {} } }
+        "#;
+
+        const QUORUM_REPO: &str = r#"
+        repoid = 123
+        storage_config = "quorum_store"
+        "#;
+
+        const HASH_REPO: &str = r#"
+        repoid = 124
+        storage_config = "hash_store"
+        "#;
+
+        let paths = btreemap! {
+            "common/storage.toml" => STORAGE,
+            "common/commitsyncmap.toml" => "",
+            "repos/quorum/server.toml" => QUORUM_REPO,
+            "repos/hash/server.toml" => HASH_REPO,
+        };
+
+        let tmp_dir = write_files(&paths);
+        let res = load_repo_configs(fb, tmp_dir.path()).expect("Read configs failed");
+
+        let read_verification = |repo: &str| match &res.repos[repo].storage_config.blobstore {
+            BlobConfig::Multiplexed {
+                read_verification, ..
+            } => *read_verification,
+            _ => panic!("Multiplexed config is not a multiplexed blobstore"),
+        };
+        assert_eq!(
+            read_verification("quorum"),
+            Some(ReadVerification {
+                mode: ReadVerificationMode::Quorum(nonzero!(2usize)),
+                action: ScrubAction::Repair,
+            })
+        );
+        assert_eq!(
+            read_verification("hash"),
+            Some(ReadVerification {
+                mode: ReadVerificationMode::ContentHash,
+                action: ScrubAction::ReportOnly,
+            })
+        );
+
+        // A quorum can't be bigger than the multiplex
+        let storage = STORAGE.replace("quorum = 2", "quorum = 3");
+        let paths = btreemap! {
+            "common/storage.toml" => storage.as_str(),
+            "common/commitsyncmap.toml" => "",
+            "repos/quorum/server.toml" => QUORUM_REPO,
+        };
+        let tmp_dir = write_files(&paths);
+        let res = load_repo_configs(fb, tmp_dir.path());
+        assert!(res.is_err());
+
+        // Content hash verification under encryption would only ever see ciphertext
+        let storage = format!(
+            "{}\n{}",
+            STORAGE,
+            r#"
+        [encrypted_store.metadata.local]
+        local_db_path = "/tmp/encrypted"
+
+        [encrypted_store.blobstore.encrypted]
+        key_file = "/tmp/keys"
+        current_key_id = "key1"
+
+        [encrypted_store.blobstore.encrypted.blobstore.multiplexed]
+        multiplex_id = 3
+        components = [
+            { blobstore_id = 1, blobstore = { blob_files = { path = "/tmp/foo1" } } },
+        ]
+        queue_db = { remote = { db_address = "queue_db_address" } }
+        read_verification = { mode = { content_hash = {} } }
+        "#
+        );
+        const ENCRYPTED_REPO: &str = r#"
+        repoid = 125
+        storage_config = "encrypted_store"
+        "#;
+        let paths = btreemap! {
+            "common/storage.toml" => storage.as_str(),
+            "common/commitsyncmap.toml" => "",
+            "repos/encrypted/server.toml" => ENCRYPTED_REPO,
+        };
+        let tmp_dir = write_files(&paths);
+        let res = load_repo_configs(fb, tmp_dir.path());
+        assert!(res.is_err());
+    }
+
+    #[fbinit::test]
+    fn test_s3_blobstore(fb: FacebookInit) {
+        const STORAGE: &str = r#"
+        [minio.metadata.local]
+        local_db_path = "/tmp/minio"
+
+        [minio.blobstore.s3]
+        bucket = "mononoke"
+        prefix = "repo123."
+        region_name = "us-east-1"
+        endpoint = "http://localhost:9000"
+        credentials = { profile = { profile = "minio" } }
+        "#;
+
+        const REPO: &str = r#"
+        repoid = 123
+        storage_config = "minio"
+        "#;
+
+        let paths = btreemap! {
+            "common/storage.toml" => STORAGE,
+            "common/commitsyncmap.toml" => "",
+            "repos/test/server.toml" => REPO,
+        };
+
+        let tmp_dir = write_files(&paths);
+        let res = load_repo_configs(fb, tmp_dir.path()).expect("Read configs failed");
+
+        assert_eq!(
+            res.repos["test"].storage_config.blobstore,
+            BlobConfig::S3 {
+                bucket: "mononoke".into(),
+                prefix: "repo123.".into(),
+                region_name: "us-east-1".into(),
+                endpoint: "http://localhost:9000".into(),
+                credentials: S3Credentials::Profile {
+                    path: None,
+                    profile: Some("minio".into()),
+                },
+            }
+        );
+    }
 }
@@ -7,6 +7,8 @@
 
 use std::collections::HashMap;
 use std::convert::TryInto;
+use std::str::FromStr;
+use std::time::Duration;
 
 use anyhow::{anyhow, Context, Result};
 use bookmarks_types::BookmarkName;
@@ -18,7 +20,7 @@ use metaconfig_types::{
     SourceControlServiceMonitoring, SourceControlServiceParams, StorageConfig, UnodeVersion,
     WireprotoLoggingConfig,
 };
-use mononoke_types::{MPath, PrefixTrie};
+use mononoke_types::{ChangesetId, MPath, PrefixTrie};
 use regex::Regex;
 use repos::{
     RawBookmarkConfig, RawBundle2ReplayParams, RawCacheWarmupConfig, RawDerivedDataConfig,
@@ -267,6 +269,10 @@ impl Convert for RawInfinitepushParams {
             hydrate_getbundle_response: self.hydrate_getbundle_response.unwrap_or(false),
             populate_reverse_filler_queue: self.populate_reverse_filler_queue.unwrap_or(false),
             commit_scribe_category: self.commit_scribe_category,
+            bundle_ttl: self
+                .bundle_ttl_secs
+                .map(|ttl| -> Result<_> { Ok(Duration::from_secs(ttl.try_into()?)) })
+                .transpose()?,
         })
     }
 }
@@ -366,6 +372,28 @@ impl Convert for RawDerivedDataConfig {
             UnodeVersion::default()
         };
 
+        let blame_ignore_revs = self
+            .blame_ignore_revs
+            .unwrap_or_default()
+            .into_iter()
+            .map(|csid| {
+                ChangesetId::from_str(&csid)
+                    .with_context(|| format!("invalid blame_ignore_revs changeset id: {}", csid))
+            })
+            .collect::<Result<_>>()?;
+
+        let mapping_versions = self
+            .mapping_versions
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, version)| {
+                let version = version.try_into().with_context(|| {
+                    format!("invalid mapping version for {}: {}", name, version)
+                })?;
+                Ok((name, version))
+            })
+            .collect::<Result<_>>()?;
+
         Ok(DerivedDataConfig {
             scuba_table: self.scuba_table,
             derived_data_types: self.derived_data_types.unwrap_or_default(),
@@ -373,6 +401,8 @@ impl Convert for RawDerivedDataConfig {
             override_blame_filesize_limit: self
                 .override_blame_filesize_limit
                 .map(|limit| limit as u64),
+            blame_ignore_revs,
+            mapping_versions,
         })
     }
 }
@@ -13,14 +13,15 @@ use std::time::Duration;
 use anyhow::{anyhow, Result};
 use metaconfig_types::{
     BlobConfig, BlobstoreId, DatabaseConfig, FilestoreParams, LocalDatabaseConfig,
-    MetadataDatabaseConfig, MultiplexId, MultiplexedStoreType, RemoteDatabaseConfig,
-    RemoteMetadataDatabaseConfig, ShardableRemoteDatabaseConfig, ShardedRemoteDatabaseConfig,
-    StorageConfig,
+    MetadataDatabaseConfig, MultiplexId, MultiplexedStoreType, ReadVerification,
+    ReadVerificationMode, RemoteDatabaseConfig, RemoteMetadataDatabaseConfig, S3Credentials,
+    ScrubAction, ShardableRemoteDatabaseConfig, ShardedRemoteDatabaseConfig, StorageConfig,
 };
 use nonzero_ext::nonzero;
 use repos::{
     RawBlobstoreConfig, RawDbConfig, RawDbLocal, RawDbRemote, RawDbShardableRemote,
-    RawDbShardedRemote, RawFilestoreParams, RawMetadataConfig, RawMultiplexedStoreType,
+    RawDbShardedRemote, RawFilestoreParams, RawMetadataConfig, RawMultiplexedReadVerification,
+    RawMultiplexedReadVerificationMode, RawMultiplexedStoreType, RawS3Credentials,
     RawStorageConfig,
 };
 
@@ -73,6 +74,21 @@ impl Convert for RawBlobstoreConfig {
                         anyhow!("Must require at least 1 successful write to make a put succeed")
                     })?;
 
+                let read_verification = raw.read_verification.convert()?;
+                if let Some(ReadVerification {
+                    mode: ReadVerificationMode::Quorum(quorum),
+                    ..
+                }) = read_verification
+                {
+                    if quorum.get() > raw.components.len() {
+                        return Err(anyhow!(
+                            "Not enough blobstores for a read quorum of {} (have {})",
+                            quorum,
+                            raw.components.len()
+                        ));
+                    }
+                }
+
                 BlobConfig::Multiplexed {
                     multiplex_id: raw
                         .multiplex_id
@@ -98,6 +114,7 @@ impl Convert for RawBlobstoreConfig {
                         .queue_db
                         .ok_or_else(|| anyhow!("missing queue_db from configuration"))?
                         .convert()?,
+                    read_verification,
                 }
             }
             RawBlobstoreConfig::manifold_with_ttl(raw) => {
@@ -116,11 +133,48 @@ impl Convert for RawBlobstoreConfig {
             RawBlobstoreConfig::pack(raw) => BlobConfig::Pack {
                 blobconfig: Box::new(raw.blobstore.convert()?),
             },
-            RawBlobstoreConfig::s3(raw) => BlobConfig::S3 {
-                bucket: raw.bucket,
-                keychain_group: raw.keychain_group,
-                region_name: raw.region_name,
-                endpoint: raw.endpoint,
+            RawBlobstoreConfig::s3(raw) => {
+                let credentials = match (raw.keychain_group, raw.credentials) {
+                    (Some(_), Some(_)) => {
+                        return Err(anyhow!(
+                            "s3 blobstore can't have both keychain_group and credentials"
+                        ));
+                    }
+                    (Some(group), None) => S3Credentials::Keychain { group },
+                    (None, Some(credentials)) => credentials.convert()?,
+                    (None, None) => S3Credentials::default(),
+                };
+                BlobConfig::S3 {
+                    bucket: raw.bucket,
+                    prefix: raw.prefix.unwrap_or_default(),
+                    region_name: raw.region_name,
+                    endpoint: raw.endpoint,
+                    credentials,
+                }
+            }
+            RawBlobstoreConfig::encrypted(raw) => {
+                let blobconfig = raw.blobstore.convert()?;
+                // A multiplex under the encryption layer sees ciphertext, which never
+                // matches the content hash in the key.
+                if verifies_content_hash(&blobconfig) {
+                    return Err(anyhow!(
+                        "content_hash read verification can't be used inside an encrypted blobstore"
+                    ));
+                }
+                BlobConfig::Encrypted {
+                    blobconfig: Box::new(blobconfig),
+                    key_file: PathBuf::from(raw.key_file),
+                    current_key_id: raw.current_key_id,
+                }
+            }
+            RawBlobstoreConfig::disk_cache(raw) => BlobConfig::DiskCache {
+                blobconfig: Box::new(raw.blobstore.convert()?),
+                path: PathBuf::from(raw.path),
+                max_bytes: raw.max_bytes.try_into()?,
+            },
+            RawBlobstoreConfig::tiered(raw) => BlobConfig::Tiered {
+                hot: Box::new(raw.hot.convert()?),
+                cold: Box::new(raw.cold.convert()?),
             },
             RawBlobstoreConfig::UnknownField(f) => {
                 return Err(anyhow!("unsupported blobstore configuration ({})", f));
@@ -142,6 +196,45 @@ fn parse_scuba_sample_rate(sample_rate: Option<i64>) -> Result<NonZeroU64> {
     Ok(rate)
 }
 
+/// Return true if any multiplex in this blobstore verifies reads against the content hash in
+/// the key.
+fn verifies_content_hash(config: &BlobConfig) -> bool {
+    use BlobConfig::*;
+
+    match config {
+        Disabled
+        | Files { .. }
+        | Sqlite { .. }
+        | Manifold { .. }
+        | Mysql { .. }
+        | ManifoldWithTtl { .. }
+        | S3 { .. } => false,
+        Multiplexed {
+            blobstores,
+            read_verification,
+            ..
+        } => {
+            matches!(
+                read_verification,
+                Some(ReadVerification {
+                    mode: ReadVerificationMode::ContentHash,
+                    ..
+                })
+            ) || blobstores
+                .iter()
+                .any(|(_, _, config)| verifies_content_hash(config))
+        }
+        Scrub { blobstores, .. } => blobstores
+            .iter()
+            .any(|(_, _, config)| verifies_content_hash(config)),
+        Logging { blobconfig, .. }
+        | Pack { blobconfig, .. }
+        | Encrypted { blobconfig, .. }
+        | DiskCache { blobconfig, .. } => verifies_content_hash(blobconfig),
+        Tiered { hot, cold } => verifies_content_hash(hot) || verifies_content_hash(cold),
+    }
+}
+
 impl Convert for RawDbLocal {
     type Output = LocalDatabaseConfig;
 
@@ -240,6 +333,51 @@ impl Convert for RawFilestoreParams {
     }
 }
 
+impl Convert for RawS3Credentials {
+    type Output = S3Credentials;
+
+    fn convert(self) -> Result<Self::Output> {
+        match self {
+            RawS3Credentials::environment(_) => Ok(S3Credentials::Environment),
+            RawS3Credentials::profile(raw) => Ok(S3Credentials::Profile {
+                path: raw.path.map(PathBuf::from),
+                profile: raw.profile,
+            }),
+            RawS3Credentials::keychain(raw) => Ok(S3Credentials::Keychain { group: raw.group }),
+            RawS3Credentials::UnknownField(f) => {
+                Err(anyhow!("unsupported s3 credentials configuration ({})", f))
+            }
+        }
+    }
+}
+
+impl Convert for RawMultiplexedReadVerification {
+    type Output = ReadVerification;
+
+    fn convert(self) -> Result<Self::Output> {
+        let mode = match self.mode {
+            RawMultiplexedReadVerificationMode::quorum(quorum) => {
+                let quorum: usize = quorum.try_into()?;
+                ReadVerificationMode::Quorum(NonZeroUsize::new(quorum).ok_or_else(|| {
+                    anyhow!("Read verification quorum must be at least 1 blobstore")
+                })?)
+            }
+            RawMultiplexedReadVerificationMode::content_hash(_) => {
+                ReadVerificationMode::ContentHash
+            }
+            RawMultiplexedReadVerificationMode::UnknownField(field) => {
+                return Err(anyhow!("unknown read verification mode {}", field));
+            }
+        };
+        let action = if self.repair.unwrap_or(false) {
+            ScrubAction::Repair
+        } else {
+            ScrubAction::ReportOnly
+        };
+        Ok(ReadVerification { mode, action })
+    }
+}
+
 impl Convert for RawMultiplexedStoreType {
     type Output = MultiplexedStoreType;
 
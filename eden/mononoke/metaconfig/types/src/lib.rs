@@ -13,7 +13,7 @@
 
 use anyhow::{anyhow, Error, Result};
 use std::{
-    collections::{BTreeSet, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fmt, mem,
     num::{NonZeroU64, NonZeroUsize},
     ops::Deref,
@@ -26,7 +26,7 @@ use std::{
 
 use ascii::AsciiString;
 use bookmarks_types::BookmarkName;
-use mononoke_types::{BonsaiChangeset, MPath, PrefixTrie, RepositoryId};
+use mononoke_types::{BonsaiChangeset, ChangesetId, MPath, PrefixTrie, RepositoryId};
 use regex::Regex;
 use scuba::ScubaValue;
 use serde_derive::Deserialize;
@@ -222,6 +222,14 @@ pub struct DerivedDataConfig {
     /// size is above the limit. NOTE: if `override_blame_filesize_limit` is None
     /// then a default limit will be used!
     pub override_blame_filesize_limit: Option<u64>,
+    /// Changesets that blame should look through, attributing the lines
+    /// they changed to the previous changes of those lines instead
+    /// (e.g. mass reformatting changesets).
+    pub blame_ignore_revs: BTreeSet<ChangesetId>,
+    /// Version of the changeset to derived data mapping for each derived
+    /// data type. Types that are not listed use version 0, i.e. the
+    /// original mapping keys.
+    pub mapping_versions: BTreeMap<String, u64>,
 }
 
 /// What type of unode derived data to generate
@@ -483,6 +491,11 @@ pub struct PushParams {
     pub pure_push_allowed: bool,
     /// Scribe category we log new commits to
     pub commit_scribe_category: Option<String>,
+
+    /// How long saved infinitepush bundles are kept before they expire. If None, they are kept
+    /// forever. The repo's blobstore must support TTLs, and this must be longer than the reverse
+    /// filler queue takes to replay the bundles.
+    pub bundle_ttl: Option<Duration>,
 }
 
 impl Default for PushParams {
@@ -685,6 +698,24 @@ impl FromStr for ScrubAction {
     }
 }
 
+/// How a multiplexed blobstore checks a blob before returning it from a `get`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ReadVerificationMode {
+    /// Read from this many blobstores and compare the values they return
+    Quorum(NonZeroUsize),
+    /// Check the blob against the content hash in its key, for keys that name one
+    ContentHash,
+}
+
+/// Read verification for a multiplexed blobstore
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ReadVerification {
+    /// How blobs are checked
+    pub mode: ReadVerificationMode,
+    /// What to do with blobstores that return a bad blob
+    pub action: ScrubAction,
+}
+
 /// Whether we should read from this blobstore normally in a Multiplex,
 /// or only read from it in Scrub or when it's our last chance to find the blob
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
@@ -739,6 +770,8 @@ pub enum BlobConfig {
         scuba_sample_rate: NonZeroU64,
         /// DB config to use for the sync queue
         queue_db: DatabaseConfig,
+        /// Whether to check blobs before returning them from a `get`
+        read_verification: Option<ReadVerification>,
     },
     /// Multiplex across multiple blobstores scrubbing for errors
     Scrub {
@@ -784,13 +817,65 @@ pub enum BlobConfig {
     S3 {
         /// Bucket to connect to
         bucket: String,
-        /// Name of keychain group, to retrieve access key
-        keychain_group: String,
+        /// Prefix to be prepended to all the keys
+        prefix: String,
         /// Name of the region, currently arbitrary
         region_name: String,
         /// S3 host:port to connect to
         endpoint: String,
+        /// Where to get the access key from
+        credentials: S3Credentials,
     },
+    /// A blobstore that encrypts values before passing them to the blobstore it wraps
+    Encrypted {
+        /// The config for the blobstore that is wrapped.
+        blobconfig: Box<BlobConfig>,
+        /// JSON file mapping key ids to hex encoded keys
+        key_file: PathBuf,
+        /// Id of the key used to encrypt new values
+        current_key_id: String,
+    },
+    /// A blobstore with a persistent local cache of the blobs read from the blobstore it wraps
+    DiskCache {
+        /// The config for the blobstore that is wrapped.
+        blobconfig: Box<BlobConfig>,
+        /// Directory for the cached blobs
+        path: PathBuf,
+        /// Maximum total size of the cached blobs
+        max_bytes: u64,
+    },
+    /// A blobstore split into a hot tier for new blobs and a cold tier for old blobs
+    Tiered {
+        /// The tier that new blobs are written to and that is read first
+        hot: Box<BlobConfig>,
+        /// The tier that old blobs are moved to
+        cold: Box<BlobConfig>,
+    },
+}
+
+/// Source of the access key for an S3 compatible blobstore
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum S3Credentials {
+    /// Read from the AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY environment variables
+    Environment,
+    /// Read from an AWS credentials file
+    Profile {
+        /// Path to the credentials file. Defaults to ~/.aws/credentials
+        path: Option<PathBuf>,
+        /// Profile to use. Defaults to AWS_PROFILE, or "default"
+        profile: Option<String>,
+    },
+    /// Retrieve from a keychain group (not supported by s3blob yet)
+    Keychain {
+        /// Name of keychain group
+        group: String,
+    },
+}
+
+impl Default for S3Credentials {
+    fn default() -> Self {
+        S3Credentials::Environment
+    }
 }
 
 impl BlobConfig {
@@ -808,6 +893,9 @@ impl BlobConfig {
                 .all(BlobConfig::is_local),
             Logging { blobconfig, .. } => blobconfig.is_local(),
             Pack { blobconfig, .. } => blobconfig.is_local(),
+            Encrypted { blobconfig, .. } => blobconfig.is_local(),
+            DiskCache { blobconfig, .. } => blobconfig.is_local(),
+            Tiered { hot, cold } => hot.is_local() && cold.is_local(),
         }
     }
 
@@ -824,6 +912,8 @@ impl BlobConfig {
             blobstores,
             minimum_successful_writes,
             queue_db,
+            // Scrub reads and compares every blobstore already
+            read_verification: _,
         } = self
         {
             let scuba_table = mem::replace(scuba_table, None);
@@ -1007,6 +1097,7 @@ impl Default for InfinitepushParams {
             hydrate_getbundle_response: false,
             populate_reverse_filler_queue: false,
             commit_scribe_category: None,
+            bundle_ttl: None,
         }
     }
 }
@@ -10,6 +10,7 @@ use std::convert::TryInto;
 use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
 
 use anyhow::anyhow;
 use blobrepo_hg::BlobRepoHg;
@@ -19,6 +20,10 @@ use chrono::{DateTime, FixedOffset};
 use cloned::cloned;
 use context::CoreContext;
 use derived_data::BonsaiDerived;
+pub use diffstat::{ChangesetDiffstat, FileDiffstat};
+use file_classification::{
+    fetch_stored_file_classification, is_vendored_path, RootFileClassification,
+};
 use fsnodes::RootFsnodeId;
 use futures::compat::{Future01CompatExt, Stream01CompatExt};
 use futures::future::{self, try_join, try_join_all, FutureExt, Shared};
@@ -27,8 +32,12 @@ use manifest::{Diff as ManifestDiff, Entry as ManifestEntry, ManifestOps, PathOr
 use maplit::hashset;
 use mercurial_types::Globalrev;
 pub use mononoke_types::Generation;
-use mononoke_types::{BonsaiChangeset, FileChange, MPath, MPathElement};
+use mononoke_types::{
+    fsnode::FsnodeFile, skeleton_manifest::SkeletonManifestEntry, BonsaiChangeset, FileChange,
+    MPath, MPathElement,
+};
 use reachabilityindex::ReachabilityIndex;
+use skeleton_manifest::RootSkeletonManifestId;
 use unodes::RootUnodeManifestId;
 
 use crate::changeset_path::ChangesetPathContext;
@@ -50,6 +59,8 @@ pub struct ChangesetContext {
         Shared<Pin<Box<dyn Future<Output = Result<RootFsnodeId, MononokeError>> + Send>>>,
     root_unode_manifest_id:
         Shared<Pin<Box<dyn Future<Output = Result<RootUnodeManifestId, MononokeError>> + Send>>>,
+    root_skeleton_manifest_id:
+        Shared<Pin<Box<dyn Future<Output = Result<RootSkeletonManifestId, MononokeError>> + Send>>>,
 }
 
 #[derive(Default)]
@@ -59,6 +70,30 @@ pub struct ChangesetHistoryOptions {
     pub exclude_changeset_and_ancestors: Option<ChangesetId>,
 }
 
+/// Filters on the kind of files returned by `find_files_filtered`.
+#[derive(Default)]
+pub struct FindFilesFilter {
+    /// Only include files in one of these languages, as detected from the
+    /// file extension or shebang line, e.g. "Rust" or "Python".  Languages
+    /// are matched case-insensitively.
+    pub languages: Option<Vec<String>>,
+    /// Exclude files that contain an `@generated` marker.
+    pub exclude_generated: bool,
+    /// Exclude files in directories of vendored third-party code.
+    pub exclude_vendored: bool,
+    /// Exclude binary files.
+    pub exclude_binary: bool,
+}
+
+impl FindFilesFilter {
+    fn needs_classification(&self) -> bool {
+        self.languages.is_some() || self.exclude_generated || self.exclude_binary
+    }
+}
+
+/// Number of files that are classified concurrently when filtering files.
+const FIND_FILES_CLASSIFICATION_CONCURRENCY: usize = 100;
+
 #[derive(PartialEq, Eq, PartialOrd, Ord)]
 pub enum ChangesetDiffItem {
     TREES,
@@ -120,6 +155,16 @@ impl ChangesetContext {
             }
         };
         let root_unode_manifest_id = root_unode_manifest_id.boxed().shared();
+        let root_skeleton_manifest_id = {
+            cloned!(repo);
+            async move {
+                RootSkeletonManifestId::derive(repo.ctx().clone(), repo.blob_repo().clone(), id)
+                    .compat()
+                    .await
+                    .map_err(MononokeError::from)
+            }
+        };
+        let root_skeleton_manifest_id = root_skeleton_manifest_id.boxed().shared();
         Self {
             repo,
             id,
@@ -127,6 +172,7 @@ impl ChangesetContext {
             bonsai_changeset,
             root_fsnode_id,
             root_unode_manifest_id,
+            root_skeleton_manifest_id,
         }
     }
 
@@ -193,6 +239,84 @@ impl ChangesetContext {
         self.root_unode_manifest_id.clone().await
     }
 
+    pub(crate) async fn root_skeleton_manifest_id(
+        &self,
+    ) -> Result<RootSkeletonManifestId, MononokeError> {
+        self.root_skeleton_manifest_id.clone().await
+    }
+
+    /// Returns `true` if the path exists in the repository at this changeset
+    /// revision, as either a file or a directory. This only needs skeleton
+    /// manifests, so is cheaper than querying the path itself.
+    pub async fn path_exists_fast<P>(&self, path: P) -> Result<bool, MononokeError>
+    where
+        P: TryInto<MononokePath>,
+        MononokeError: From<P::Error>,
+    {
+        let path: MononokePath = path.try_into()?;
+        let entry = self
+            .root_skeleton_manifest_id()
+            .await?
+            .skeleton_manifest_id()
+            .find_entry(
+                self.ctx().clone(),
+                self.repo().blob_repo().get_blobstore(),
+                path.into_mpath(),
+            )
+            .compat()
+            .await?;
+        Ok(entry.is_some())
+    }
+
+    /// Returns `true` if any two paths in the repository at this changeset
+    /// revision differ only by case.
+    pub async fn has_case_conflicts(&self) -> Result<bool, MononokeError> {
+        let root = self
+            .root_skeleton_manifest_id()
+            .await?
+            .skeleton_manifest_id()
+            .load(self.ctx().clone(), self.repo().blob_repo().blobstore())
+            .await?;
+        Ok(root.summary().has_case_conflicts())
+    }
+
+    /// Find all pairs of paths in the repository at this changeset revision
+    /// that differ only by case. Each pair is of sibling paths, so if two
+    /// directories conflict, the paths below them are not reported.
+    pub async fn find_case_conflicts(
+        &self,
+    ) -> Result<Vec<(MononokePath, MononokePath)>, MononokeError> {
+        let blobstore = self.repo().blob_repo().blobstore();
+        let root_id = self
+            .root_skeleton_manifest_id()
+            .await?
+            .into_skeleton_manifest_id();
+        let mut conflicts = Vec::new();
+        let mut queue = VecDeque::new();
+        queue.push_back((None, root_id));
+        while let Some((path, skeleton_id)) = queue.pop_front() {
+            let skeleton = skeleton_id.load(self.ctx().clone(), blobstore).await?;
+            let join = |name: &MPathElement| {
+                MononokePath::new(Some(MPath::join_opt_element(path.as_ref(), name)))
+            };
+            for (first, second) in skeleton.case_conflicts() {
+                conflicts.push((join(first), join(second)));
+            }
+            // Only descend into directories that have conflicts below them
+            for (name, entry) in skeleton.list() {
+                if let SkeletonManifestEntry::Directory(dir) = entry {
+                    if dir.summary().has_case_conflicts() {
+                        queue.push_back((
+                            Some(MPath::join_opt_element(path.as_ref(), name)),
+                            *dir.id(),
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(conflicts)
+    }
+
     /// Query the root directory in the repository at this changeset revision.
     pub fn root(&self) -> ChangesetPathContext {
         ChangesetPathContext::new(self.clone(), None)
@@ -325,6 +449,18 @@ impl ChangesetContext {
         Ok(bonsai.file_changes)
     }
 
+    /// Number of lines added and removed in each file changed by the commit,
+    /// compared to its first parent.
+    pub async fn diffstat(&self) -> Result<ChangesetDiffstat, MononokeError> {
+        Ok(ChangesetDiffstat::derive(
+            self.ctx().clone(),
+            self.repo().blob_repo().clone(),
+            self.id(),
+        )
+        .compat()
+        .await?)
+    }
+
     /// Returns `true` if this commit is an ancestor of `other_commit`.  A commit is considered its
     /// own ancestor for the purpose of this call.
     pub async fn is_ancestor_of(&self, other_commit: ChangesetId) -> Result<bool, MononokeError> {
@@ -643,6 +779,95 @@ impl ChangesetContext {
         prefixes: Option<Vec<MononokePath>>,
         basenames: Option<Vec<String>>,
     ) -> Result<impl Stream<Item = Result<MononokePath, MononokeError>>, MononokeError> {
+        Ok(self
+            .find_file_entries(prefixes, basenames)
+            .await?
+            .map_ok(|(mpath, _file)| MononokePath::new(Some(mpath)))
+            .map_err(MononokeError::from))
+    }
+
+    /// Find files like `find_files`, and only include those that match
+    /// `filter` based on their path and the classification of their content.
+    pub async fn find_files_filtered(
+        &self,
+        prefixes: Option<Vec<MononokePath>>,
+        basenames: Option<Vec<String>>,
+        filter: FindFilesFilter,
+    ) -> Result<impl Stream<Item = Result<MononokePath, MononokeError>>, MononokeError> {
+        if filter.needs_classification() {
+            self.ensure_file_classification_derived().await?;
+        }
+        let ctx = self.ctx().clone();
+        let blobstore = self.repo().blob_repo().get_blobstore();
+        let filter = Arc::new(filter);
+        let entries = self
+            .find_file_entries(prefixes, basenames)
+            .await?
+            .try_filter({
+                cloned!(filter);
+                move |(mpath, _file)| {
+                    future::ready(!(filter.exclude_vendored && is_vendored_path(mpath)))
+                }
+            })
+            .map_ok(move |(mpath, file)| {
+                cloned!(ctx, blobstore, filter);
+                async move {
+                    if !filter.needs_classification() {
+                        return Ok(Some(mpath));
+                    }
+                    let classification =
+                        fetch_stored_file_classification(&ctx, &blobstore, *file.content_id())
+                            .await?
+                            .ok_or_else(|| {
+                                anyhow!("File classification missing for {}", file.content_id())
+                            })?;
+                    let excluded = (filter.exclude_generated && classification.is_generated())
+                        || (filter.exclude_binary && classification.is_binary());
+                    let language_matches = match &filter.languages {
+                        Some(languages) => {
+                            classification
+                                .language(Some(&mpath))
+                                .map_or(false, |language| {
+                                    languages
+                                        .iter()
+                                        .any(|wanted| wanted.eq_ignore_ascii_case(language))
+                                })
+                        }
+                        None => true,
+                    };
+                    Ok((!excluded && language_matches).then_some(mpath))
+                }
+            })
+            .try_buffered(FIND_FILES_CLASSIFICATION_CONCURRENCY)
+            .try_filter_map(|mpath| future::ready(Ok(mpath)));
+        Ok(entries
+            .map_ok(|mpath| MononokePath::new(Some(mpath)))
+            .map_err(MononokeError::from))
+    }
+
+    /// Check that file classifications have already been derived for this
+    /// changeset, so that the classifications of all its files are stored.
+    /// Classifications are never computed while reading.
+    pub(crate) async fn ensure_file_classification_derived(&self) -> Result<(), MononokeError> {
+        let derived =
+            RootFileClassification::is_derived(self.ctx(), self.repo().blob_repo(), &self.id)
+                .await?;
+        if !derived {
+            return Err(MononokeError::NotAvailable(format!(
+                "File classifications have not been derived for {}",
+                self.id
+            )));
+        }
+        Ok(())
+    }
+
+    /// Find the files matching the prefixes and basenames, along with their
+    /// fsnode entries.
+    pub(crate) async fn find_file_entries(
+        &self,
+        prefixes: Option<Vec<MononokePath>>,
+        basenames: Option<Vec<String>>,
+    ) -> Result<impl Stream<Item = Result<(MPath, FsnodeFile), anyhow::Error>>, MononokeError> {
         let root = self.root_fsnode_id().await?;
         let prefixes = match prefixes {
             Some(prefixes) => prefixes
@@ -651,7 +876,7 @@ impl ChangesetContext {
                 .collect(),
             None => vec![PathOrPrefix::Prefix(None)],
         };
-        let mpaths = root
+        let entries = root
             .fsnode_id()
             .find_entries(
                 self.ctx().clone(),
@@ -661,27 +886,27 @@ impl ChangesetContext {
             .compat()
             .try_filter_map(|(path, entry)| async move {
                 match (path, entry) {
-                    (Some(mpath), ManifestEntry::Leaf(_)) => Ok(Some(mpath)),
+                    (Some(mpath), ManifestEntry::Leaf(file)) => Ok(Some((mpath, file))),
                     _ => Ok(None),
                 }
             });
-        let mpaths = match basenames {
+        let entries = match basenames {
             Some(basenames) => {
                 let basename_set = basenames
                     .into_iter()
                     .map(|basename| MPathElement::new(basename.into()))
                     .collect::<Result<HashSet<_>, _>>()
                     .map_err(MononokeError::from)?;
-                mpaths
-                    .try_filter(move |mpath| future::ready(basename_set.contains(mpath.basename())))
+                entries
+                    .try_filter(move |(mpath, _file)| {
+                        future::ready(basename_set.contains(mpath.basename()))
+                    })
                     .into_stream()
                     .left_stream()
             }
-            None => mpaths.into_stream().right_stream(),
+            None => entries.into_stream().right_stream(),
         };
-        Ok(mpaths
-            .map_ok(|mpath| MononokePath::new(Some(mpath)))
-            .map_err(MononokeError::from))
+        Ok(entries)
     }
 
     /// Returns a stream of `ChangesetContext` for the history of the repository from this commit.
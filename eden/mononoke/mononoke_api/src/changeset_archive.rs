@@ -0,0 +1,985 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use std::str::FromStr;
+
+use anyhow::{format_err, Context};
+use blobstore::Blobstore;
+use bytes::Bytes;
+use chrono::{DateTime, Datelike, FixedOffset, Timelike};
+use cloned::cloned;
+use context::CoreContext;
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::{Compression, Crc};
+use futures::compat::Future01CompatExt;
+use futures::future;
+use futures::stream::{self, BoxStream, Stream, StreamExt, TryStreamExt};
+use mononoke_types::{fsnode::FsnodeFile, FileType, MPath};
+
+use crate::changeset::ChangesetContext;
+use crate::errors::MononokeError;
+use crate::path::MononokePath;
+
+/// Number of file contents that are fetched ahead of the archive writer.
+const ARCHIVE_CONCURRENCY: usize = 20;
+
+/// The format of an archive of a changeset's files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGzip,
+    TarZstd,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// The conventional file extension for archives in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGzip => "tar.gz",
+            ArchiveFormat::TarZstd => "tar.zst",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    /// The MIME type of archives in this format.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "application/x-tar",
+            ArchiveFormat::TarGzip => "application/gzip",
+            ArchiveFormat::TarZstd => "application/zstd",
+            ArchiveFormat::Zip => "application/zip",
+        }
+    }
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = MononokeError;
+
+    /// Parse an archive format from its file extension.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tar" => Ok(ArchiveFormat::Tar),
+            "tar.gz" | "tgz" => Ok(ArchiveFormat::TarGzip),
+            "tar.zst" | "tzst" => Ok(ArchiveFormat::TarZstd),
+            "zip" => Ok(ArchiveFormat::Zip),
+            _ => Err(MononokeError::InvalidRequest(format!(
+                "unknown archive format: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Unix permissions of files in archives.
+fn file_mode(file_type: FileType) -> u32 {
+    match file_type {
+        FileType::Regular => 0o644,
+        FileType::Executable => 0o755,
+        FileType::Symlink => 0o777,
+    }
+}
+
+const ZIP_LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
+const ZIP_CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x02014b50;
+const ZIP_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const ZIP64_EXTRA_FIELD_ID: u16 = 0x0001;
+/// Version 2.0 of the format supports deflate, version 4.5 zip64.
+const ZIP_VERSION: u16 = 20;
+const ZIP64_VERSION: u16 = 45;
+/// Entries are made by Unix, so that external attributes hold Unix modes.
+const ZIP_MADE_BY_UNIX: u16 = 3 << 8;
+const ZIP_FLAG_UTF8: u16 = 1 << 11;
+const ZIP_METHOD_STORED: u16 = 0;
+const ZIP_METHOD_DEFLATED: u16 = 8;
+const ZIP_UNIX_FILE: u32 = 0o100000;
+const ZIP_UNIX_SYMLINK: u32 = 0o120000;
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+/// The parts of the central directory record of a zip entry that depend on
+/// the file's content, so that the record can be written again without
+/// fetching the content.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ZipEntrySummary {
+    crc: u32,
+    compressed_size: u32,
+}
+
+/// Writer of zip archives that produces its output incrementally.
+///
+/// Each file is compressed before its local header is written, so no
+/// seeking back is needed to fill in sizes.  Offsets and entry counts that
+/// don't fit the classic format are written as zip64 records.
+struct ZipWriter {
+    output: Vec<u8>,
+    offset: u64,
+    central_directory: Vec<u8>,
+    summaries: Vec<ZipEntrySummary>,
+    dos_time: u16,
+    dos_date: u16,
+}
+
+impl ZipWriter {
+    fn new(mtime: &DateTime<FixedOffset>) -> Self {
+        // MS-DOS dates can't represent anything before 1980.
+        let (dos_time, dos_date) = if mtime.year() < 1980 {
+            (0, (1 << 5) | 1)
+        } else {
+            (
+                ((mtime.hour() << 11) | (mtime.minute() << 5) | (mtime.second() / 2)) as u16,
+                (((mtime.year() - 1980) as u32) << 9 | (mtime.month() << 5) | mtime.day()) as u16,
+            )
+        };
+        ZipWriter {
+            output: Vec::new(),
+            offset: 0,
+            central_directory: Vec::new(),
+            summaries: Vec::new(),
+            dos_time,
+            dos_date,
+        }
+    }
+
+    fn add_file(&mut self, path: &MPath, file_type: FileType, content: &[u8]) -> io::Result<()> {
+        let name = path.to_vec();
+        let mut crc = Crc::new();
+        crc.update(content);
+        let (method, data) = match file_type {
+            FileType::Symlink => (ZIP_METHOD_STORED, content.to_vec()),
+            FileType::Regular | FileType::Executable => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(content)?;
+                (ZIP_METHOD_DEFLATED, encoder.finish()?)
+            }
+        };
+        let summary = ZipEntrySummary {
+            crc: crc.sum(),
+            compressed_size: u32::try_from(data.len()).map_err(|_| zip_too_large(path))?,
+        };
+        let size = u32::try_from(content.len()).map_err(|_| zip_too_large(path))?;
+        let name_len = u16::try_from(name.len()).map_err(|_| zip_too_large(path))?;
+
+        put_u32(&mut self.output, ZIP_LOCAL_FILE_HEADER_SIGNATURE);
+        put_u16(&mut self.output, ZIP_VERSION);
+        put_u16(&mut self.output, zip_flags(&name));
+        put_u16(&mut self.output, method);
+        put_u16(&mut self.output, self.dos_time);
+        put_u16(&mut self.output, self.dos_date);
+        put_u32(&mut self.output, summary.crc);
+        put_u32(&mut self.output, summary.compressed_size);
+        put_u32(&mut self.output, size);
+        put_u16(&mut self.output, name_len);
+        put_u16(&mut self.output, 0);
+        self.output.extend_from_slice(&name);
+        self.output.extend_from_slice(&data);
+        self.add_entry(path, file_type, content.len() as u64, summary)
+    }
+
+    /// List a file in the central directory at the end of the archive.
+    /// This is all that is needed for files whose output was produced by an
+    /// earlier writer.
+    fn add_entry(
+        &mut self,
+        path: &MPath,
+        file_type: FileType,
+        size: u64,
+        summary: ZipEntrySummary,
+    ) -> io::Result<()> {
+        let name = path.to_vec();
+        let size = u32::try_from(size).map_err(|_| zip_too_large(path))?;
+        let name_len = u16::try_from(name.len()).map_err(|_| zip_too_large(path))?;
+        let (method, kind) = match file_type {
+            FileType::Symlink => (ZIP_METHOD_STORED, ZIP_UNIX_SYMLINK),
+            FileType::Regular | FileType::Executable => (ZIP_METHOD_DEFLATED, ZIP_UNIX_FILE),
+        };
+        let external_attributes = (kind | file_mode(file_type)) << 16;
+
+        let header_offset = self.offset;
+        self.offset += 30 + name.len() as u64 + summary.compressed_size as u64;
+
+        let needs_zip64 = header_offset >= u32::MAX as u64;
+        let cd = &mut self.central_directory;
+        put_u32(cd, ZIP_CENTRAL_DIRECTORY_HEADER_SIGNATURE);
+        if needs_zip64 {
+            put_u16(cd, ZIP_MADE_BY_UNIX | ZIP64_VERSION);
+            put_u16(cd, ZIP64_VERSION);
+        } else {
+            put_u16(cd, ZIP_MADE_BY_UNIX | ZIP_VERSION);
+            put_u16(cd, ZIP_VERSION);
+        }
+        put_u16(cd, zip_flags(&name));
+        put_u16(cd, method);
+        put_u16(cd, self.dos_time);
+        put_u16(cd, self.dos_date);
+        put_u32(cd, summary.crc);
+        put_u32(cd, summary.compressed_size);
+        put_u32(cd, size);
+        put_u16(cd, name_len);
+        put_u16(cd, if needs_zip64 { 12 } else { 0 });
+        // Comment length, disk number and internal attributes.
+        put_u16(cd, 0);
+        put_u16(cd, 0);
+        put_u16(cd, 0);
+        put_u32(cd, external_attributes);
+        put_u32(cd, header_offset.min(u32::MAX as u64) as u32);
+        cd.extend_from_slice(&name);
+        if needs_zip64 {
+            put_u16(cd, ZIP64_EXTRA_FIELD_ID);
+            put_u16(cd, 8);
+            put_u64(cd, header_offset);
+        }
+        self.summaries.push(summary);
+        Ok(())
+    }
+
+    fn take_output(&mut self) -> Vec<u8> {
+        mem::take(&mut self.output)
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let cd_offset = self.offset;
+        let cd_size = self.central_directory.len() as u64;
+        let entries = self.summaries.len() as u64;
+        let mut output = mem::take(&mut self.output);
+        output.append(&mut self.central_directory);
+
+        let out = &mut output;
+        if entries >= u16::MAX as u64 || cd_offset >= u32::MAX as u64 || cd_size >= u32::MAX as u64
+        {
+            let zip64_eocd_offset = cd_offset + cd_size;
+            put_u32(out, ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+            // Size of the rest of the record.
+            put_u64(out, 44);
+            put_u16(out, ZIP_MADE_BY_UNIX | ZIP64_VERSION);
+            put_u16(out, ZIP64_VERSION);
+            put_u32(out, 0);
+            put_u32(out, 0);
+            put_u64(out, entries);
+            put_u64(out, entries);
+            put_u64(out, cd_size);
+            put_u64(out, cd_offset);
+
+            put_u32(out, ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE);
+            put_u32(out, 0);
+            put_u64(out, zip64_eocd_offset);
+            put_u32(out, 1);
+        }
+        let entries = entries.min(u16::MAX as u64) as u16;
+        put_u32(out, ZIP_END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        put_u16(out, 0);
+        put_u16(out, 0);
+        put_u16(out, entries);
+        put_u16(out, entries);
+        put_u32(out, cd_size.min(u32::MAX as u64) as u32);
+        put_u32(out, cd_offset.min(u32::MAX as u64) as u32);
+        put_u16(out, 0);
+        output
+    }
+}
+
+fn zip_flags(name: &[u8]) -> u16 {
+    if std::str::from_utf8(name).is_ok() {
+        ZIP_FLAG_UTF8
+    } else {
+        0
+    }
+}
+
+fn zip_too_large(path: &MPath) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Other,
+        format!("{} is too large for a zip archive", path),
+    )
+}
+
+/// Compress a part of a tar archive independently of the other parts.
+///
+/// Concatenated gzip members and zstd frames decompress to the
+/// concatenation of their contents, so compressing each file separately
+/// still produces a valid archive.
+fn compress(format: ArchiveFormat, data: Vec<u8>) -> io::Result<Vec<u8>> {
+    match format {
+        ArchiveFormat::Tar | ArchiveFormat::Zip => Ok(data),
+        ArchiveFormat::TarGzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&data)?;
+            encoder.finish()
+        }
+        ArchiveFormat::TarZstd => zstd::stream::encode_all(&data[..], 0 /* use default */),
+    }
+}
+
+/// Writer of an archive in any of the supported formats.
+///
+/// The output for each file doesn't depend on the files before it, so an
+/// archive can be continued from any file without producing the files
+/// before it again.  Only the end of a zip archive, which lists all of its
+/// files, needs all of them to have been added.
+enum ArchiveWriter {
+    Tar {
+        format: ArchiveFormat,
+        builder: tar::Builder<Vec<u8>>,
+        mtime: u64,
+    },
+    Zip(ZipWriter),
+}
+
+impl ArchiveWriter {
+    fn new(format: ArchiveFormat, mtime: &DateTime<FixedOffset>) -> Self {
+        match format {
+            ArchiveFormat::Zip => ArchiveWriter::Zip(ZipWriter::new(mtime)),
+            ArchiveFormat::Tar | ArchiveFormat::TarGzip | ArchiveFormat::TarZstd => {
+                ArchiveWriter::Tar {
+                    format,
+                    builder: tar::Builder::new(Vec::new()),
+                    mtime: mtime.timestamp().max(0) as u64,
+                }
+            }
+        }
+    }
+
+    /// Add a file to the archive, and return its output.
+    fn add_file(&mut self, path: &MPath, file_type: FileType, content: &[u8]) -> io::Result<Bytes> {
+        match self {
+            ArchiveWriter::Tar {
+                format,
+                builder,
+                mtime,
+            } => {
+                let mut header = tar::Header::new_gnu();
+                header.set_mtime(*mtime);
+                header.set_uid(0);
+                header.set_gid(0);
+                header.set_mode(file_mode(file_type));
+                match file_type {
+                    FileType::Symlink => {
+                        let target = String::from_utf8_lossy(content);
+                        header.set_entry_type(tar::EntryType::Symlink);
+                        header.set_size(0);
+                        if target.len() > TAR_MAX_LINK_NAME_LEN {
+                            append_long_link_name(builder, target.as_bytes())?;
+                        } else {
+                            header.set_link_name(&*target)?;
+                        }
+                        builder.append_data(&mut header, path.to_string(), io::empty())?;
+                    }
+                    FileType::Regular | FileType::Executable => {
+                        header.set_entry_type(tar::EntryType::Regular);
+                        header.set_size(content.len() as u64);
+                        builder.append_data(&mut header, path.to_string(), content)?;
+                    }
+                }
+                let data = mem::take(builder.get_mut());
+                Ok(Bytes::from(compress(*format, data)?))
+            }
+            ArchiveWriter::Zip(writer) => {
+                writer.add_file(path, file_type, content)?;
+                Ok(Bytes::from(writer.take_output()))
+            }
+        }
+    }
+
+    /// Add a file that an earlier writer produced the output for, without
+    /// producing it again.  Only zip archives need the file's summary, to
+    /// list it at the end of the archive.
+    fn add_previous_file(
+        &mut self,
+        path: &MPath,
+        file: &FsnodeFile,
+        summary: Option<ZipEntrySummary>,
+    ) -> io::Result<()> {
+        match (self, summary) {
+            (ArchiveWriter::Tar { .. }, None) => Ok(()),
+            (ArchiveWriter::Zip(writer), Some(summary)) => {
+                writer.add_entry(path, *file.file_type(), file.size(), summary)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "archive cursor doesn't match the archive format",
+            )),
+        }
+    }
+
+    /// The summaries of the first `count` zip entries, for a cursor after
+    /// them.
+    fn zip_summaries(&self, count: u64) -> Vec<ZipEntrySummary> {
+        match self {
+            ArchiveWriter::Tar { .. } => Vec::new(),
+            ArchiveWriter::Zip(writer) => writer.summaries[..count as usize].to_vec(),
+        }
+    }
+
+    /// Finish the archive, and return the remaining output.
+    fn finish(&mut self) -> io::Result<Bytes> {
+        match self {
+            ArchiveWriter::Tar {
+                format, builder, ..
+            } => {
+                builder.finish()?;
+                let data = mem::take(builder.get_mut());
+                Ok(Bytes::from(compress(*format, data)?))
+            }
+            ArchiveWriter::Zip(writer) => Ok(Bytes::from(writer.finish())),
+        }
+    }
+}
+
+/// Link names longer than this don't fit in a tar header.
+const TAR_MAX_LINK_NAME_LEN: usize = 100;
+
+/// Append a GNU long link name entry, which sets the link name of the entry
+/// after it.  This is how `tar::Builder` stores long paths as well.
+fn append_long_link_name(builder: &mut tar::Builder<Vec<u8>>, target: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    let name = b"././@LongLink";
+    let gnu = header
+        .as_gnu_mut()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "tar header is not a GNU header"))?;
+    gnu.name[..name.len()].copy_from_slice(name);
+    header.set_mode(0o644);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(0);
+    // The name is NUL-terminated.
+    header.set_size(target.len() as u64 + 1);
+    header.set_entry_type(tar::EntryType::GNULongLink);
+    header.set_cksum();
+    builder.append(&header, io::Read::chain(target, &[0u8][..]))
+}
+
+/// Position in an archive from which to continue producing it.
+///
+/// Archives are produced one entry at a time: one for each file, and a
+/// last one for the end of the archive.  The cursor records the entry to
+/// continue from, and how much of its output was already returned.  For zip
+/// archives it also records the checksum and compressed size of the files
+/// before it, which the end of the archive lists.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ArchiveCursor {
+    /// Index of the entry to continue from.
+    entry: u64,
+    /// Number of bytes of the entry's output that were already returned.
+    entry_offset: u64,
+    /// Number of bytes of the archive that were already returned.
+    offset: u64,
+    /// Checksum of the part of the entry's output that was already
+    /// returned.  If the entry's output is different when the archive is
+    /// continued, e.g. because the server now compresses it differently,
+    /// the archive can't be continued.
+    checksum: u32,
+    /// Summaries of the zip entries of the files before `entry`.  Empty for
+    /// other formats.
+    zip_entries: Vec<ZipEntrySummary>,
+}
+
+impl ArchiveCursor {
+    /// Number of bytes of the archive before the cursor.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl fmt::Display for ArchiveCursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}.{:08x}",
+            self.entry, self.entry_offset, self.offset, self.checksum
+        )?;
+        for (index, summary) in self.zip_entries.iter().enumerate() {
+            let separator = if index == 0 { '.' } else { ',' };
+            write!(
+                f,
+                "{}{:08x}{:x}",
+                separator, summary.crc, summary.compressed_size
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ArchiveCursor {
+    type Err = MononokeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || MononokeError::InvalidRequest(format!("invalid archive cursor: {}", s));
+        let parts: Vec<_> = s.split('.').collect();
+        let (entry, entry_offset, offset, checksum, zip_entries) = match parts.as_slice() {
+            [entry, entry_offset, offset, checksum] => {
+                (entry, entry_offset, offset, checksum, Vec::new())
+            }
+            [entry, entry_offset, offset, checksum, zip_entries] => {
+                let zip_entries = zip_entries
+                    .split(',')
+                    .map(|summary| {
+                        if summary.len() <= 8 || !summary.is_char_boundary(8) {
+                            return None;
+                        }
+                        Some(ZipEntrySummary {
+                            crc: u32::from_str_radix(&summary[..8], 16).ok()?,
+                            compressed_size: u32::from_str_radix(&summary[8..], 16).ok()?,
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(invalid)?;
+                (entry, entry_offset, offset, checksum, zip_entries)
+            }
+            _ => return Err(invalid()),
+        };
+        let cursor = ArchiveCursor {
+            entry: entry.parse().map_err(|_| invalid())?,
+            entry_offset: entry_offset.parse().map_err(|_| invalid())?,
+            offset: offset.parse().map_err(|_| invalid())?,
+            checksum: u32::from_str_radix(checksum, 16).map_err(|_| invalid())?,
+            zip_entries,
+        };
+        if cursor.entry_offset > cursor.offset
+            || (!cursor.zip_entries.is_empty() && cursor.zip_entries.len() as u64 != cursor.entry)
+        {
+            return Err(invalid());
+        }
+        Ok(cursor)
+    }
+}
+
+fn archive_changed() -> MononokeError {
+    MononokeError::InvalidRequest(String::from(
+        "archive has changed since the previous chunk, fetch it again from the start",
+    ))
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+/// Fetch the contents of the files to archive, in order.
+fn fetch_contents<B: Blobstore + Clone>(
+    ctx: CoreContext,
+    blobstore: B,
+    files: Vec<(MPath, FsnodeFile)>,
+) -> BoxStream<'static, Result<(MPath, FileType, Bytes), anyhow::Error>> {
+    stream::iter(files)
+        .map(move |(mpath, file)| {
+            cloned!(ctx, blobstore);
+            async move {
+                let content = filestore::fetch_concat(&blobstore, ctx, *file.content_id())
+                    .compat()
+                    .await?;
+                Ok::<_, anyhow::Error>((mpath, *file.file_type(), content))
+            }
+        })
+        .buffered(ARCHIVE_CONCURRENCY)
+        .boxed()
+}
+
+impl ChangesetContext {
+    /// The files to archive, in path order.
+    async fn archive_files(
+        &self,
+        path_prefix: Option<MononokePath>,
+    ) -> Result<Vec<(MPath, FsnodeFile)>, MononokeError> {
+        let mut files: Vec<_> = self
+            .find_file_entries(path_prefix.map(|prefix| vec![prefix]), None)
+            .await?
+            .try_collect()
+            .await?;
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(files)
+    }
+
+    /// Produce an archive of the files in this changeset, optionally
+    /// restricted to the files under a path, as a stream of chunks.
+    ///
+    /// Files keep their path in the repo, and are stored with Unix
+    /// permissions matching their file type: executable files as 0755,
+    /// and symlinks as symlinks.  The author date of the changeset is used
+    /// as the modification time of all files, and files are stored in path
+    /// order, so archives of the same changeset are identical.
+    ///
+    /// Compressed tar archives compress each file separately, as a gzip
+    /// member or a zstd frame, so that `archive_chunk` can continue them
+    /// from any file.
+    pub async fn archive(
+        &self,
+        format: ArchiveFormat,
+        path_prefix: Option<MononokePath>,
+    ) -> Result<impl Stream<Item = Result<Bytes, MononokeError>>, MononokeError> {
+        let mtime = self.author_date().await?;
+        let writer = ArchiveWriter::new(format, &mtime);
+        let files = self.archive_files(path_prefix).await?;
+        let contents = fetch_contents(
+            self.ctx().clone(),
+            self.repo().blob_repo().get_blobstore(),
+            files,
+        );
+
+        let chunks = stream::try_unfold(
+            (Some(writer), contents),
+            |(writer, mut contents)| async move {
+                let mut writer = match writer {
+                    Some(writer) => writer,
+                    None => return Ok(None),
+                };
+                let chunk = match contents.try_next().await? {
+                    Some((mpath, file_type, content)) => {
+                        let chunk = writer
+                            .add_file(&mpath, file_type, &content)
+                            .with_context(|| format!("Failed to archive {}", mpath))?;
+                        return Ok(Some((chunk, (Some(writer), contents))));
+                    }
+                    None => writer.finish().context("Failed to finish archive")?,
+                };
+                Ok::<_, anyhow::Error>(Some((chunk, (None, contents))))
+            },
+        )
+        .map_err(MononokeError::from)
+        .try_filter(|chunk| future::ready(!chunk.is_empty()));
+        Ok(chunks)
+    }
+
+    /// Produce up to `size` bytes of an archive of the files in this
+    /// changeset, starting at `cursor`, or at the start of the archive if
+    /// there is no cursor.
+    ///
+    /// Returns the data, and the cursor to continue from, or `None` if the
+    /// data reaches the end of the archive.  Only the files from the cursor
+    /// onwards are fetched.  The archive is the same as the one produced by
+    /// `archive`.
+    pub async fn archive_chunk(
+        &self,
+        format: ArchiveFormat,
+        path_prefix: Option<MononokePath>,
+        cursor: Option<ArchiveCursor>,
+        size: u64,
+    ) -> Result<(Bytes, Option<ArchiveCursor>), MononokeError> {
+        if size == 0 {
+            return Err(MononokeError::InvalidRequest(String::from(
+                "archive chunk size must be at least 1 byte",
+            )));
+        }
+        let cursor = cursor.unwrap_or_default();
+        let mtime = self.author_date().await?;
+        let files = self.archive_files(path_prefix).await?;
+        let file_count = files.len() as u64;
+        if cursor.entry > file_count {
+            return Err(MononokeError::InvalidRequest(format!(
+                "archive cursor {} is past the end of the archive",
+                cursor
+            )));
+        }
+        let zip_entries = match format {
+            ArchiveFormat::Zip => cursor.entry,
+            ArchiveFormat::Tar | ArchiveFormat::TarGzip | ArchiveFormat::TarZstd => 0,
+        };
+        if cursor.zip_entries.len() as u64 != zip_entries {
+            return Err(MononokeError::InvalidRequest(format!(
+                "archive cursor is not a cursor of a {} archive",
+                format
+            )));
+        }
+
+        let mut writer = ArchiveWriter::new(format, &mtime);
+        let mut entry_start = cursor.offset - cursor.entry_offset;
+        // The files before the cursor are only listed at the end of zip
+        // archives, which the summaries in the cursor are enough for.
+        for (index, (mpath, file)) in files[..cursor.entry as usize].iter().enumerate() {
+            writer
+                .add_previous_file(mpath, file, cursor.zip_entries.get(index).copied())
+                .with_context(|| format!("Failed to archive {}", mpath))?;
+        }
+        if let ArchiveWriter::Zip(zip) = &writer {
+            if zip.offset != entry_start {
+                return Err(archive_changed());
+            }
+        }
+        let mut contents = fetch_contents(
+            self.ctx().clone(),
+            self.repo().blob_repo().get_blobstore(),
+            files[cursor.entry as usize..].to_vec(),
+        );
+
+        let mut data = Vec::new();
+        let mut entry = cursor.entry;
+        let mut entry_offset = cursor.entry_offset as usize;
+        loop {
+            let output = if entry < file_count {
+                let (mpath, file_type, content) = contents
+                    .try_next()
+                    .await?
+                    .ok_or_else(|| format_err!("Missing content for archive entry"))?;
+                writer
+                    .add_file(&mpath, file_type, &content)
+                    .with_context(|| format!("Failed to archive {}", mpath))?
+            } else {
+                writer.finish().context("Failed to finish archive")?
+            };
+
+            if entry == cursor.entry
+                && (output.len() < entry_offset
+                    || checksum(&output[..entry_offset]) != cursor.checksum)
+            {
+                return Err(archive_changed());
+            }
+            let remaining = &output[entry_offset..];
+            let take = remaining.len().min(size as usize - data.len());
+            data.extend_from_slice(&remaining[..take]);
+            if take < remaining.len() {
+                let entry_offset = entry_offset + take;
+                let next = ArchiveCursor {
+                    entry,
+                    entry_offset: entry_offset as u64,
+                    offset: entry_start + entry_offset as u64,
+                    checksum: checksum(&output[..entry_offset]),
+                    zip_entries: writer.zip_summaries(entry),
+                };
+                return Ok((Bytes::from(data), Some(next)));
+            }
+
+            entry_start += output.len() as u64;
+            if entry == file_count {
+                return Ok((Bytes::from(data), None));
+            }
+            entry += 1;
+            entry_offset = 0;
+            if data.len() as u64 == size {
+                let next = ArchiveCursor {
+                    entry,
+                    entry_offset: 0,
+                    offset: entry_start,
+                    checksum: checksum(&[]),
+                    zip_entries: writer.zip_summaries(entry),
+                };
+                return Ok((Bytes::from(data), Some(next)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn zip_dos_date() {
+        let mtime = DateTime::parse_from_rfc3339("2020-06-15T10:20:30+01:00").unwrap();
+        let writer = ZipWriter::new(&mtime);
+        assert_eq!(writer.dos_time, (10 << 11) | (20 << 5) | 15);
+        assert_eq!(writer.dos_date, (40 << 9) | (6 << 5) | 15);
+
+        let mtime = DateTime::parse_from_rfc3339("1970-01-01T00:00:00+00:00").unwrap();
+        let writer = ZipWriter::new(&mtime);
+        assert_eq!((writer.dos_time, writer.dos_date), (0, (1 << 5) | 1));
+    }
+
+    /// List the entries of a zip archive as their path, Unix mode and
+    /// content.
+    fn zip_entries(data: &[u8]) -> Vec<(String, u32, Vec<u8>)> {
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(data)).unwrap();
+        (0..archive.len())
+            .map(|index| {
+                let mut file = archive.by_index(index).unwrap();
+                let mut content = Vec::new();
+                io::Read::read_to_end(&mut file, &mut content).unwrap();
+                (file.name().to_string(), file.unix_mode().unwrap(), content)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn zip_round_trip() {
+        let mtime = DateTime::parse_from_rfc3339("2020-06-15T10:20:30+00:00").unwrap();
+        let mut writer = ZipWriter::new(&mtime);
+        let files = [
+            ("a/file", FileType::Regular, &b"content"[..]),
+            ("a/tool", FileType::Executable, &b"#!/bin/sh\n"[..]),
+            ("link", FileType::Symlink, &b"a/file"[..]),
+        ];
+        for (path, file_type, content) in files.iter() {
+            writer
+                .add_file(&MPath::new(path).unwrap(), *file_type, content)
+                .unwrap();
+        }
+        let mut data = writer.take_output();
+        data.extend(writer.finish());
+
+        assert_eq!(
+            zip_entries(&data),
+            vec![
+                (String::from("a/file"), 0o100644, b"content".to_vec()),
+                (String::from("a/tool"), 0o100755, b"#!/bin/sh\n".to_vec()),
+                (String::from("link"), 0o120777, b"a/file".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn zip64_round_trip() {
+        // Too many entries for the classic end of central directory record.
+        let mtime = DateTime::parse_from_rfc3339("2020-06-15T10:20:30+00:00").unwrap();
+        let mut writer = ZipWriter::new(&mtime);
+        let count = u16::MAX as usize + 1;
+        let mut data = Vec::new();
+        for index in 0..count {
+            let path = MPath::new(format!("{:05x}", index)).unwrap();
+            writer
+                .add_file(&path, FileType::Regular, index.to_string().as_bytes())
+                .unwrap();
+            data.extend(writer.take_output());
+        }
+        data.extend(writer.finish());
+
+        assert!(data
+            .windows(4)
+            .any(|window| window == ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes()));
+        let entries = zip_entries(&data);
+        assert_eq!(entries.len(), count);
+        assert_eq!(
+            entries[count - 1],
+            (
+                format!("{:05x}", count - 1),
+                0o100644,
+                (count - 1).to_string().into_bytes()
+            )
+        );
+    }
+
+    #[test]
+    fn archive_cursor() {
+        let cursor = ArchiveCursor {
+            entry: 3,
+            entry_offset: 100,
+            offset: 5000,
+            checksum: 0xdeadbeef,
+            zip_entries: Vec::new(),
+        };
+        assert_eq!(cursor.to_string(), "3.100.5000.deadbeef");
+        assert_eq!(
+            ArchiveCursor::from_str("3.100.5000.deadbeef").unwrap(),
+            cursor
+        );
+        assert!(ArchiveCursor::from_str("3.100").is_err());
+        assert!(ArchiveCursor::from_str("3.5000.100.deadbeef").is_err());
+
+        let cursor = ArchiveCursor {
+            entry: 2,
+            entry_offset: 0,
+            offset: 300,
+            checksum: 0,
+            zip_entries: vec![
+                ZipEntrySummary {
+                    crc: 0x12345678,
+                    compressed_size: 0x100,
+                },
+                ZipEntrySummary {
+                    crc: 0,
+                    compressed_size: 0,
+                },
+            ],
+        };
+        assert_eq!(cursor.to_string(), "2.0.300.00000000.12345678100,000000000");
+        assert_eq!(
+            ArchiveCursor::from_str(&cursor.to_string()).unwrap(),
+            cursor
+        );
+        // The summaries must be those of the entries before the cursor.
+        assert!(ArchiveCursor::from_str("3.0.300.00000000.12345678100,000000000").is_err());
+        assert!(ArchiveCursor::from_str("1.0.300.00000000.1234").is_err());
+    }
+
+    #[test]
+    fn zip_continued() {
+        // A writer that continues an archive from the summaries of the
+        // entries before it ends the archive in the same way.
+        let mtime = DateTime::parse_from_rfc3339("2020-06-15T10:20:30+00:00").unwrap();
+        let files = [
+            ("a/file", FileType::Regular, &b"content"[..]),
+            ("link", FileType::Symlink, &b"a/file"[..]),
+            ("z", FileType::Executable, &b"#!/bin/sh\n"[..]),
+        ];
+        let mut writer = ZipWriter::new(&mtime);
+        for (path, file_type, content) in files.iter() {
+            writer
+                .add_file(&MPath::new(path).unwrap(), *file_type, content)
+                .unwrap();
+        }
+        let summaries = writer.summaries.clone();
+        let mut data = writer.take_output();
+        let end = writer.finish();
+
+        let mut continued = ZipWriter::new(&mtime);
+        for ((path, file_type, content), summary) in files[..2].iter().zip(summaries) {
+            continued
+                .add_entry(
+                    &MPath::new(path).unwrap(),
+                    *file_type,
+                    content.len() as u64,
+                    summary,
+                )
+                .unwrap();
+        }
+        assert!(continued.take_output().is_empty());
+        let (path, file_type, content) = files[2];
+        continued
+            .add_file(&MPath::new(path).unwrap(), file_type, content)
+            .unwrap();
+        let last = continued.take_output();
+        assert!(data.ends_with(&last));
+        assert_eq!(continued.finish(), end);
+
+        data.extend(end);
+        assert_eq!(zip_entries(&data).len(), files.len());
+    }
+
+    #[test]
+    fn tar_long_symlink() {
+        let mtime = DateTime::parse_from_rfc3339("2020-06-15T10:20:30+00:00").unwrap();
+        let mut writer = ArchiveWriter::new(ArchiveFormat::Tar, &mtime);
+        let target = format!("{}/file", "dir".repeat(50));
+        let mut data = writer
+            .add_file(
+                &MPath::new("link").unwrap(),
+                FileType::Symlink,
+                target.as_bytes(),
+            )
+            .unwrap()
+            .to_vec();
+        data.extend(writer.finish().unwrap());
+
+        let mut archive = tar::Archive::new(&data[..]);
+        let mut entries = archive.entries().unwrap();
+        let entry = entries.next().unwrap().unwrap();
+        assert_eq!(entry.path().unwrap().to_str(), Some("link"));
+        assert_eq!(entry.link_name_bytes().as_deref(), Some(target.as_bytes()));
+        assert!(entries.next().is_none());
+    }
+}
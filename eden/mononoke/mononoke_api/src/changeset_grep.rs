@@ -0,0 +1,250 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::fmt::Write;
+use std::sync::Arc;
+
+use cloned::cloned;
+use file_classification::{fetch_stored_file_classification, FileClassification};
+use futures::compat::Future01CompatExt;
+use futures::future;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use mononoke_types::{FileType, MPath};
+use regex::bytes::{Regex, RegexBuilder};
+use regex_syntax::hir::literal::Literals;
+use regex_syntax::ParserBuilder;
+
+use crate::changeset::ChangesetContext;
+use crate::errors::MononokeError;
+use crate::path::MononokePath;
+
+/// Number of files that are fetched and searched concurrently.
+const GREP_CONCURRENCY: usize = 100;
+
+/// Files larger than this are not searched unless the caller asks for a
+/// different limit.
+const GREP_DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Options for searching the contents of the files in a changeset.
+pub struct ChangesetGrepOptions {
+    /// Only search files under these paths.
+    pub prefixes: Option<Vec<MononokePath>>,
+    /// Match the pattern case-insensitively.
+    pub case_insensitive: bool,
+    /// Stop after this many matching lines in total.
+    pub max_matches: Option<usize>,
+    /// Stop searching a file after this many matching lines in it.
+    pub max_matches_per_file: Option<usize>,
+    /// Skip files larger than this many bytes.
+    pub max_file_size: u64,
+}
+
+impl Default for ChangesetGrepOptions {
+    fn default() -> Self {
+        ChangesetGrepOptions {
+            prefixes: None,
+            case_insensitive: false,
+            max_matches: None,
+            max_matches_per_file: None,
+            max_file_size: GREP_DEFAULT_MAX_FILE_SIZE,
+        }
+    }
+}
+
+/// A line of a file that matches a grep pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrepMatch {
+    /// The path of the file.
+    pub path: MononokePath,
+    /// The number of the matching line, starting from 1.
+    pub line_number: u64,
+    /// The content of the matching line, without its line ending.  Invalid
+    /// UTF-8 is replaced.
+    pub line: String,
+}
+
+/// A compiled grep pattern.
+struct GrepPattern {
+    regex: Regex,
+    /// Pattern matching any of the literals that all matches of `regex`
+    /// must start with.  Files that don't contain one of them can be skipped
+    /// without running `regex` on each of their lines.
+    prefilter: Option<Regex>,
+}
+
+impl GrepPattern {
+    fn new(pattern: &str, case_insensitive: bool) -> Result<Self, MononokeError> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .build()
+            .map_err(|e| {
+                MononokeError::InvalidRequest(format!("invalid pattern '{}': {}", pattern, e))
+            })?;
+        let hir = ParserBuilder::new()
+            .case_insensitive(case_insensitive)
+            .allow_invalid_utf8(true)
+            .build()
+            .parse(pattern)
+            .map_err(|e| {
+                MononokeError::InvalidRequest(format!("invalid pattern '{}': {}", pattern, e))
+            })?;
+        let prefilter = literal_prefilter(&Literals::prefixes(&hir));
+        Ok(GrepPattern { regex, prefilter })
+    }
+
+    /// Returns the matching lines of a file's content.
+    fn matching_lines(&self, path: &MPath, content: &[u8], limit: Option<usize>) -> Vec<GrepMatch> {
+        if let Some(prefilter) = &self.prefilter {
+            if !prefilter.is_match(content) {
+                return Vec::new();
+            }
+        }
+        let mut content = content;
+        if content.last() == Some(&b'\n') {
+            content = &content[..content.len() - 1];
+        }
+        let path = MononokePath::new(Some(path.clone()));
+        content
+            .split(|b| *b == b'\n')
+            .enumerate()
+            .filter_map(|(index, line)| {
+                let line = match line.last() {
+                    Some(b'\r') => &line[..line.len() - 1],
+                    _ => line,
+                };
+                if self.regex.is_match(line) {
+                    Some(GrepMatch {
+                        path: path.clone(),
+                        line_number: index as u64 + 1,
+                        line: String::from_utf8_lossy(line).into_owned(),
+                    })
+                } else {
+                    None
+                }
+            })
+            .take(limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+/// Build a pattern matching any of a set of literals.  Returns `None` if
+/// the set can't be used to exclude content, i.e. if it is empty or
+/// contains the empty string, which matches everything.
+fn literal_prefilter(literals: &Literals) -> Option<Regex> {
+    if literals.literals().is_empty() || literals.literals().iter().any(|lit| lit.is_empty()) {
+        return None;
+    }
+    let mut alternation = String::from("(?-u:");
+    for (index, literal) in literals.literals().iter().enumerate() {
+        if index > 0 {
+            alternation.push('|');
+        }
+        for byte in literal.iter() {
+            write!(alternation, "\\x{:02x}", byte).ok()?;
+        }
+    }
+    alternation.push(')');
+    Regex::new(&alternation).ok()
+}
+
+impl ChangesetContext {
+    /// Search the contents of the files in this changeset for lines that
+    /// match a regular expression.
+    ///
+    /// Symlinks, binary files and files larger than the maximum file size
+    /// are skipped. Binary files that already have a stored classification
+    /// are skipped without fetching their content, and other files are
+    /// classified from the content that is fetched to search them.
+    /// Classifications are never stored by this method. Files are searched
+    /// in no particular order, but the matches of each file are returned
+    /// together, in line order.
+    pub async fn grep(
+        &self,
+        pattern: &str,
+        options: ChangesetGrepOptions,
+    ) -> Result<impl Stream<Item = Result<GrepMatch, MononokeError>>, MononokeError> {
+        let pattern = Arc::new(GrepPattern::new(pattern, options.case_insensitive)?);
+        let ctx = self.ctx().clone();
+        let blobstore = self.repo().blob_repo().get_blobstore();
+        let max_file_size = options.max_file_size;
+        let max_matches_per_file = options.max_matches_per_file;
+        let matches = self
+            .find_file_entries(options.prefixes, None)
+            .await?
+            .try_filter(move |(_mpath, file)| {
+                future::ready(
+                    *file.file_type() != FileType::Symlink && file.size() <= max_file_size,
+                )
+            })
+            .map_ok(move |(mpath, file)| {
+                cloned!(ctx, blobstore, pattern);
+                async move {
+                    let content_id = *file.content_id();
+                    let stored =
+                        fetch_stored_file_classification(&ctx, &blobstore, content_id).await?;
+                    if stored
+                        .as_ref()
+                        .map_or(false, |classification| classification.is_binary())
+                    {
+                        return Ok(Vec::new());
+                    }
+                    let content = filestore::fetch_concat(&blobstore, ctx, content_id)
+                        .compat()
+                        .await?;
+                    if stored.is_none()
+                        && FileClassification::classify(content_id, &content).is_binary()
+                    {
+                        return Ok(Vec::new());
+                    }
+                    Ok::<_, anyhow::Error>(pattern.matching_lines(
+                        &mpath,
+                        &content,
+                        max_matches_per_file,
+                    ))
+                }
+            })
+            .try_buffered(GREP_CONCURRENCY)
+            .map_ok(|matches| stream::iter(matches.into_iter().map(Ok)))
+            .try_flatten()
+            .map_err(MononokeError::from)
+            .take(options.max_matches.unwrap_or(usize::MAX));
+        Ok(matches)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prefilter_literals() {
+        let pattern = GrepPattern::new("foo(bar|baz)", false).unwrap();
+        let prefilter = pattern.prefilter.expect("pattern has literal prefixes");
+        assert!(prefilter.is_match(b"xx foobaz yy"));
+        assert!(!prefilter.is_match(b"xx fob yy"));
+
+        let pattern = GrepPattern::new("\\w+bar", false).unwrap();
+        assert!(pattern.prefilter.is_none());
+    }
+
+    #[test]
+    fn matching_lines() {
+        let pattern = GrepPattern::new("needle", false).unwrap();
+        let path = MPath::new("dir/file").unwrap();
+        let content = b"hay\r\nneedle one\nhay\nneedle two\n";
+        let matches = pattern.matching_lines(&path, content, None);
+        assert_eq!(
+            matches
+                .iter()
+                .map(|m| (m.line_number, m.line.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(2, "needle one"), (4, "needle two")]
+        );
+        assert_eq!(pattern.matching_lines(&path, content, Some(1)).len(), 1);
+        assert!(pattern.matching_lines(&path, b"hay\n", None).is_empty());
+    }
+}
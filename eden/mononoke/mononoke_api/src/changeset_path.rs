@@ -13,15 +13,12 @@ use std::sync::Arc;
 
 use anyhow::{format_err, Error};
 use async_trait::async_trait;
-use blame::{fetch_blame, BlameError};
+use blame::{fetch_blame_with_ignored, BlameError};
 use blobrepo::BlobRepo;
-use blobstore::Loadable;
 use bytes::Bytes;
-use changeset_info::ChangesetInfo;
 use cloned::cloned;
 use context::CoreContext;
-use derived_data::BonsaiDerived;
-use fastlog::{list_file_history, FastlogError, HistoryAcrossDeletions, Visitor};
+use fastlog::{list_file_history, FastlogError, HistoryAcrossDeletions, TraversalOptions, Visitor};
 use filestore::FetchKey;
 use futures::compat::Future01CompatExt;
 use futures::future::{try_join_all, FutureExt, Shared, TryFutureExt};
@@ -34,7 +31,7 @@ use mononoke_types::{
 };
 use reachabilityindex::ReachabilityIndex;
 use skiplist::SkiplistIndex;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use xdiff;
 
 pub use xdiff::CopyInfo;
@@ -53,10 +50,25 @@ pub struct HistoryEntry {
 
 #[derive(Default)]
 pub struct ChangesetPathHistoryOptions {
+    /// Stop at changesets older than this timestamp.
     pub until_timestamp: Option<i64>,
+    /// Don't return changesets newer than this timestamp, but continue
+    /// through them.
+    pub before_timestamp: Option<i64>,
     pub descendants_of: Option<ChangesetId>,
     pub exclude_changeset_and_ancestors: Option<ChangesetId>,
     pub follow_history_across_deletions: bool,
+    /// Only follow the first parent of merges in the history of the path.
+    pub first_parent_only: bool,
+    /// Continue into the history of the source file when a file was copied
+    /// or moved.
+    pub follow_renames: bool,
+    /// Stop after this many steps back in the history of the path.
+    pub max_depth: Option<u64>,
+    /// Don't return changesets fewer than this many steps back in the
+    /// history of the path. Use the previous `max_depth + 1` to resume a
+    /// history listing that was limited by `max_depth`.
+    pub skip_depth: u64,
 }
 
 pub enum PathEntry {
@@ -264,16 +276,37 @@ impl ChangesetPathContext {
         Ok(entry)
     }
 
+    /// Returns the content and blame of the file at this path.
+    ///
+    /// Changesets in the repo's `blame_ignore_revs` config are looked
+    /// through, so lines they changed are attributed to the previous change
+    /// of each line.
     pub async fn blame(&self) -> Result<(Bytes, Blame), MononokeError> {
+        self.blame_ignoring(Vec::new()).await
+    }
+
+    /// Returns the content and blame of the file at this path, looking
+    /// through `ignore_revs` in addition to the repo's `blame_ignore_revs`
+    /// config.
+    pub async fn blame_ignoring(
+        &self,
+        ignore_revs: impl IntoIterator<Item = ChangesetId>,
+    ) -> Result<(Bytes, Blame), MononokeError> {
         let ctx = self.changeset.ctx().clone();
         let repo = self.changeset.repo().blob_repo().clone();
         let csid = self.changeset.id();
         let mpath = self.path.as_mpath().ok_or_else(|| {
             MononokeError::InvalidRequest(format!("Blame is not available for directory: `/`"))
         })?;
-
-        fetch_blame(ctx, repo, csid, mpath.clone())
-            .compat()
+        let ignore: HashSet<_> = repo
+            .get_derived_data_config()
+            .blame_ignore_revs
+            .iter()
+            .cloned()
+            .chain(ignore_revs)
+            .collect();
+
+        fetch_blame_with_ignored(ctx, repo, csid, mpath.clone(), &ignore)
             .map_err(|error| match error {
                 BlameError::NoSuchPath(_)
                 | BlameError::IsDirectory(_)
@@ -318,8 +351,6 @@ impl ChangesetPathContext {
         };
 
         struct FilterVisitor {
-            cs_info_enabled: bool,
-            until_timestamp: Option<i64>,
             descendants_of: Option<(ChangesetId, Generation)>,
             exclude_changeset_and_ancestors: Option<(ChangesetId, Generation)>,
             cache: HashMap<(Option<ChangesetId>, Vec<ChangesetId>), Vec<ChangesetId>>,
@@ -333,26 +364,7 @@ impl ChangesetPathContext {
                 descendant_cs_id: Option<ChangesetId>,
                 mut cs_ids: Vec<ChangesetId>,
             ) -> Result<Vec<ChangesetId>, Error> {
-                let cs_info_enabled = self.cs_info_enabled;
                 let skiplist_index = self.skiplist_index.clone();
-                if let Some(until_ts) = self.until_timestamp {
-                    cs_ids = try_join_all(cs_ids.into_iter().map(|cs_id| async move {
-                        let info = if cs_info_enabled {
-                            ChangesetInfo::derive(ctx.clone(), repo.clone(), cs_id)
-                                .compat()
-                                .await
-                        } else {
-                            let bonsai = cs_id.load(ctx.clone(), repo.blobstore()).await?;
-                            Ok(ChangesetInfo::new(cs_id, bonsai))
-                        }?;
-                        let timestamp = info.author_date().as_chrono().timestamp();
-                        Ok::<_, Error>((timestamp >= until_ts).then_some(cs_id))
-                    }))
-                    .await?
-                    .into_iter()
-                    .filter_map(identity)
-                    .collect();
-                }
                 if let Some((descendants_of, descendants_of_gen)) = self.descendants_of {
                     cs_ids = try_join_all(cs_ids.into_iter().map(|cs_id| {
                         cloned!(skiplist_index);
@@ -507,27 +519,32 @@ impl ChangesetPathContext {
                 Ok(())
             }
         }
-        let cs_info_enabled = self.repo().derive_changeset_info_enabled();
-
         let history_across_deletions = if opts.follow_history_across_deletions {
             HistoryAcrossDeletions::Track
         } else {
             HistoryAcrossDeletions::DontTrack
         };
+        let traversal_options = TraversalOptions {
+            history_across_deletions,
+            first_parent_only: opts.first_parent_only,
+            follow_renames: opts.follow_renames,
+            after_timestamp: opts.until_timestamp,
+            before_timestamp: opts.before_timestamp,
+            max_depth: opts.max_depth,
+            skip_depth: opts.skip_depth,
+        };
         let history = list_file_history(
             ctx,
             repo,
             mpath.cloned(),
             self.changeset.id(),
             FilterVisitor {
-                cs_info_enabled,
-                until_timestamp: opts.until_timestamp,
                 descendants_of,
                 exclude_changeset_and_ancestors,
                 cache: HashMap::new(),
                 skiplist_index: self.repo().skiplist_index().clone(),
             },
-            history_across_deletions,
+            traversal_options,
         )
         .await
         .map_err(|error| match error {
@@ -14,6 +14,7 @@ use anyhow::format_err;
 use bytes::{Bytes, BytesMut};
 use cloned::cloned;
 use context::CoreContext;
+use file_classification::fetch_file_classification;
 use filestore::{self, get_metadata, FetchKey};
 use futures::compat::{Future01CompatExt, Stream01CompatExt};
 use futures::future::{FutureExt, Shared};
@@ -31,6 +32,9 @@ pub use mononoke_types::FileType;
 /// Metadata about a file.
 pub use mononoke_types::ContentMetadata as FileMetadata;
 
+/// Classification of a file's content.
+pub use file_classification::{FileClassification, LineEndings, TextEncoding};
+
 #[derive(Clone)]
 pub struct FileContext {
     repo: RepoContext,
@@ -126,6 +130,16 @@ impl FileContext {
         self.metadata.clone().await
     }
 
+    /// Return the classification of the file's content: its MIME type,
+    /// encoding, line endings, and whether it is generated.
+    pub async fn classification(&self) -> Result<FileClassification, MononokeError> {
+        let content_id = self.id().await?;
+        let classification =
+            fetch_file_classification(self.ctx(), self.repo().blob_repo().blobstore(), content_id)
+                .await?;
+        Ok(classification)
+    }
+
     /// Return the content for the file.
     ///
     /// This method buffers the full file content in memory, which may
@@ -28,11 +28,14 @@ use metaconfig_parser::RepoConfigs;
 use crate::repo::Repo;
 
 pub mod changeset;
+pub mod changeset_archive;
+pub mod changeset_grep;
 pub mod changeset_path;
 pub mod changeset_path_diff;
 pub mod errors;
 pub mod file;
 pub mod hg;
+pub mod merge;
 pub mod path;
 pub mod repo;
 pub mod repo_write;
@@ -44,19 +47,26 @@ mod xrepo;
 mod test;
 
 pub use crate::changeset::{
-    ChangesetContext, ChangesetDiffItem, ChangesetHistoryOptions, Generation,
+    ChangesetContext, ChangesetDiffItem, ChangesetDiffstat, ChangesetHistoryOptions, FileDiffstat,
+    FindFilesFilter, Generation,
 };
+pub use crate::changeset_archive::{ArchiveCursor, ArchiveFormat};
+pub use crate::changeset_grep::{ChangesetGrepOptions, GrepMatch};
 pub use crate::changeset_path::{
     unified_diff, ChangesetPathContext, ChangesetPathHistoryOptions, CopyInfo, PathEntry,
     UnifiedDiff, UnifiedDiffMode,
 };
 pub use crate::changeset_path_diff::ChangesetPathDiffContext;
 pub use crate::errors::MononokeError;
-pub use crate::file::{FileContext, FileId, FileMetadata, FileType};
+pub use crate::file::{
+    FileClassification, FileContext, FileId, FileMetadata, FileType, LineEndings, TextEncoding,
+};
+pub use crate::merge::{ConflictHunk, MergeConflict, MergeConflictKind, MergePreview};
 pub use crate::path::MononokePath;
-pub use crate::repo::{BookmarkFreshness, RepoContext};
+pub use crate::repo::{BookmarkFreshness, CommitComparison, RepoContext};
 pub use crate::repo_write::create_changeset::{CreateChange, CreateCopyInfo};
 pub use crate::repo_write::land_stack::PushrebaseOutcome;
+pub use crate::repo_write::rebase::{CherryPickOutcome, RebaseOutcome, RebasedChangeset};
 pub use crate::repo_write::RepoWriteContext;
 pub use crate::specifiers::{
     ChangesetId, ChangesetIdPrefix, ChangesetPrefixSpecifier, ChangesetSpecifier,
@@ -0,0 +1,563 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Three-way merging of files and their contents.
+
+use std::cmp::max;
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use bytes::Bytes;
+use filestore::FetchKey;
+use futures::compat::Stream01CompatExt;
+use futures::future;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use futures::try_join;
+use manifest::{Diff as ManifestDiff, Entry as ManifestEntry, ManifestOps};
+use mononoke_types::{ChangesetId, MPath};
+
+use crate::changeset::ChangesetContext;
+use crate::changeset_path::PathEntry;
+use crate::errors::MononokeError;
+use crate::file::{FileContext, FileId, FileType};
+use crate::path::MononokePath;
+use crate::repo::RepoContext;
+use crate::specifiers::ChangesetSpecifier;
+
+/// Number of paths that are merged concurrently when previewing a merge.
+const CONCURRENT_FILE_MERGES: usize = 100;
+
+/// Files larger than this are not merged line by line, and conflict as if
+/// they were binary.
+pub const MERGE_FILESIZE_LIMIT: u64 = 10 * 1024 * 1024;
+
+/// A range of lines that the two sides of a merge changed differently.
+///
+/// Line numbers are zero-based and the ranges are half-open.  An empty range
+/// is the position at which lines were inserted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConflictHunk {
+    pub base: Range<usize>,
+    pub local: Range<usize>,
+    pub other: Range<usize>,
+}
+
+/// The reason a path could not be merged automatically.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeConflictKind {
+    /// Both sides changed the same lines of a text file.
+    Content(Vec<ConflictHunk>),
+
+    /// Both sides changed a binary file or a symlink differently.  Files
+    /// that are too large to merge line by line are treated as binary.
+    Binary,
+
+    /// One side modified the file while the other side deleted it.
+    ModifyDelete,
+
+    /// Both sides changed the type of the file differently.
+    FileType,
+
+    /// One side has a file where the other side has a directory.
+    FileDirectory,
+}
+
+/// A path that could not be merged automatically.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergeConflict {
+    pub path: MononokePath,
+    pub kind: MergeConflictKind,
+}
+
+/// The outcome of merging two changesets, as previewed by
+/// `ChangesetContext::merge_preview`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergePreview {
+    /// The merge base of the two changesets.
+    pub base: ChangesetId,
+
+    /// Paths changed by either side that merge cleanly, in path order.
+    pub clean: Vec<MononokePath>,
+
+    /// Paths that can't be merged automatically, in path order.
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl MergePreview {
+    /// Returns true if the changesets can be merged without conflicts.
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// The state of a path in one of the changesets taking part in a merge.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum PathState {
+    Absent,
+    Directory,
+    File(FileId, FileType),
+}
+
+impl PathState {
+    pub(crate) async fn of(
+        changeset: &ChangesetContext,
+        path: &MononokePath,
+    ) -> Result<Self, MononokeError> {
+        let state = match changeset.path(path.clone())?.entry().await? {
+            PathEntry::NotPresent => PathState::Absent,
+            PathEntry::Tree(_) => PathState::Directory,
+            PathEntry::File(file, file_type) => PathState::File(file.id().await?, file_type),
+        };
+        Ok(state)
+    }
+
+    pub(crate) fn is_file(&self) -> bool {
+        match self {
+            PathState::File(..) => true,
+            _ => false,
+        }
+    }
+}
+
+/// How a path is resolved by a three-way merge.
+pub(crate) enum FileResolution {
+    /// The path is as it is on the local side.
+    Local,
+
+    /// The path is as it is on the other side.
+    Other,
+
+    /// The path is an existing file content, with a possibly merged file
+    /// type.
+    File(FileId, FileType),
+
+    /// The path is a new file content, created by merging the contents of
+    /// both sides.
+    Merged(Bytes, FileType),
+
+    /// The path can't be merged automatically.
+    Conflict(MergeConflictKind),
+}
+
+/// Merges the state of a path on the local and other side of a merge,
+/// relative to its state in the merge base.
+///
+/// File contents are only fetched if both sides modified the file and none
+/// of the versions is larger than `MERGE_FILESIZE_LIMIT`, and nothing is
+/// written to the blobstore.
+pub(crate) async fn merge_file(
+    repo: &RepoContext,
+    base: PathState,
+    local: PathState,
+    other: PathState,
+) -> Result<FileResolution, MononokeError> {
+    if other == base || local == other {
+        return Ok(FileResolution::Local);
+    }
+    if local == base {
+        return Ok(FileResolution::Other);
+    }
+
+    let (local_id, local_type, other_id, other_type) = match (base, local, other) {
+        (_, PathState::File(local_id, local_type), PathState::File(other_id, other_type)) => {
+            (local_id, local_type, other_id, other_type)
+        }
+        (PathState::File(..), PathState::File(..), PathState::Absent)
+        | (PathState::File(..), PathState::Absent, PathState::File(..)) => {
+            return Ok(FileResolution::Conflict(MergeConflictKind::ModifyDelete));
+        }
+        (_, PathState::File(..), PathState::Directory)
+        | (_, PathState::Directory, PathState::File(..)) => {
+            return Ok(FileResolution::Conflict(MergeConflictKind::FileDirectory));
+        }
+        // Neither side has a file at this path.  A directory on one side
+        // wins over the path being absent on the other.
+        (_, _, PathState::Absent) => return Ok(FileResolution::Local),
+        (_, PathState::Absent, _) => return Ok(FileResolution::Other),
+        (_, PathState::Directory, PathState::Directory) => return Ok(FileResolution::Local),
+    };
+
+    let base_file = match base {
+        PathState::File(base_id, base_type) => Some((base_id, base_type)),
+        _ => None,
+    };
+    let file_type = match merge_file_type(base_file.map(|(_, ty)| ty), local_type, other_type) {
+        Some(file_type) => file_type,
+        None => return Ok(FileResolution::Conflict(MergeConflictKind::FileType)),
+    };
+    if local_id == other_id {
+        return Ok(FileResolution::File(local_id, file_type));
+    }
+    if let Some((base_id, _)) = base_file {
+        if local_id == base_id {
+            return Ok(FileResolution::File(other_id, file_type));
+        }
+        if other_id == base_id {
+            return Ok(FileResolution::File(local_id, file_type));
+        }
+    }
+    if file_type == FileType::Symlink
+        || local_type == FileType::Symlink
+        || other_type == FileType::Symlink
+    {
+        return Ok(FileResolution::Conflict(MergeConflictKind::Binary));
+    }
+
+    let file =
+        |id: Option<FileId>| id.map(|id| FileContext::new(repo.clone(), FetchKey::Canonical(id)));
+    let files = [
+        file(base_file.map(|(id, _)| id)),
+        file(Some(local_id)),
+        file(Some(other_id)),
+    ];
+    let size = |file: &Option<FileContext>| {
+        let file = file.clone();
+        async move {
+            match file {
+                Some(file) => Ok(file.metadata().await?.total_size),
+                None => Ok::<_, MononokeError>(0),
+            }
+        }
+    };
+    let (base_size, local_size, other_size) =
+        try_join!(size(&files[0]), size(&files[1]), size(&files[2]))?;
+    if max(base_size, max(local_size, other_size)) > MERGE_FILESIZE_LIMIT {
+        return Ok(FileResolution::Conflict(MergeConflictKind::Binary));
+    }
+
+    let fetch = |file: &Option<FileContext>| {
+        let file = file.clone();
+        async move {
+            match file {
+                Some(file) => file.content_concat().await,
+                None => Ok(Bytes::new()),
+            }
+        }
+    };
+    let (base_content, local_content, other_content) =
+        try_join!(fetch(&files[0]), fetch(&files[1]), fetch(&files[2]))?;
+    if is_binary(&base_content) || is_binary(&local_content) || is_binary(&other_content) {
+        return Ok(FileResolution::Conflict(MergeConflictKind::Binary));
+    }
+
+    match merge_text(&base_content, &local_content, &other_content) {
+        Ok(merged) => Ok(FileResolution::Merged(Bytes::from(merged), file_type)),
+        Err(hunks) => Ok(FileResolution::Conflict(MergeConflictKind::Content(hunks))),
+    }
+}
+
+impl ChangesetContext {
+    /// Preview merging another changeset into this one.
+    ///
+    /// Every path that either changeset changed relative to their merge base
+    /// is merged with the same three-way merge that rebases use, with this
+    /// changeset as the local side.  The merged contents are discarded, so
+    /// no file contents are written to the blobstore, and files larger than
+    /// `MERGE_FILESIZE_LIMIT` are reported as binary conflicts without
+    /// fetching them.
+    ///
+    /// The changed paths are found by comparing fsnodes, so fsnodes are
+    /// derived for the two changesets and their merge base if they haven't
+    /// been yet, which writes them to the blobstore.  If fsnodes are not
+    /// enabled for the repo, this fails with `NotAvailable`.
+    pub async fn merge_preview(&self, other: ChangesetId) -> Result<MergePreview, MononokeError> {
+        let other = self
+            .repo()
+            .changeset(ChangesetSpecifier::Bonsai(other))
+            .await?
+            .ok_or_else(|| {
+                MononokeError::InvalidRequest(format!("Commit {} does not exist", other))
+            })?;
+        let base = self.common_base_with(other.id()).await?.ok_or_else(|| {
+            MononokeError::InvalidRequest(format!(
+                "Commits {} and {} have no common base",
+                self.id(),
+                other.id()
+            ))
+        })?;
+
+        let (local_paths, other_paths) = try_join!(
+            changed_file_paths(&base, self),
+            changed_file_paths(&base, &other),
+        )?;
+        let resolutions: Vec<_> = stream::iter(local_paths.union(&other_paths))
+            .map(|path| {
+                let (base, other) = (&base, &other);
+                async move {
+                    let path = MononokePath::from(path.clone());
+                    let (base_state, local_state, other_state) = try_join!(
+                        PathState::of(base, &path),
+                        PathState::of(self, &path),
+                        PathState::of(other, &path),
+                    )?;
+                    let resolution =
+                        merge_file(self.repo(), base_state, local_state, other_state).await?;
+                    Ok::<_, MononokeError>((path, resolution))
+                }
+            })
+            .buffered(CONCURRENT_FILE_MERGES)
+            .try_collect()
+            .await?;
+
+        let mut clean = Vec::new();
+        let mut conflicts = Vec::new();
+        for (path, resolution) in resolutions {
+            match resolution {
+                FileResolution::Conflict(kind) => conflicts.push(MergeConflict { path, kind }),
+                _ => clean.push(path),
+            }
+        }
+        Ok(MergePreview {
+            base: base.id(),
+            clean,
+            conflicts,
+        })
+    }
+}
+
+/// Returns the paths of the files that differ between the base and a side
+/// of a merge.
+async fn changed_file_paths(
+    base: &ChangesetContext,
+    side: &ChangesetContext,
+) -> Result<BTreeSet<MPath>, MononokeError> {
+    let (base_root, side_root) = try_join!(base.root_fsnode_id(), side.root_fsnode_id())?;
+    let paths = base_root
+        .fsnode_id()
+        .diff(
+            side.ctx().clone(),
+            side.repo().blob_repo().get_blobstore(),
+            side_root.fsnode_id().clone(),
+        )
+        .compat()
+        .try_filter_map(|diff| {
+            future::ok(match diff {
+                ManifestDiff::Added(path, ManifestEntry::Leaf(_))
+                | ManifestDiff::Removed(path, ManifestEntry::Leaf(_))
+                | ManifestDiff::Changed(path, ManifestEntry::Leaf(_), _)
+                | ManifestDiff::Changed(path, _, ManifestEntry::Leaf(_)) => path,
+                _ => None,
+            })
+        })
+        .try_collect()
+        .await?;
+    Ok(paths)
+}
+
+fn merge_file_type(base: Option<FileType>, local: FileType, other: FileType) -> Option<FileType> {
+    if local == other || base == Some(other) {
+        Some(local)
+    } else if base == Some(local) {
+        Some(other)
+    } else {
+        None
+    }
+}
+
+fn is_binary(content: &[u8]) -> bool {
+    content.contains(&0)
+}
+
+/// Splits text into lines, keeping the line terminators.  This matches how
+/// xdiff numbers lines.
+fn split_lines(text: &[u8]) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (index, byte) in text.iter().enumerate() {
+        if *byte == b'\n' {
+            lines.push(&text[start..=index]);
+            start = index + 1;
+        }
+    }
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+    lines
+}
+
+/// Maps a range of lines in the base through the changes one side made
+/// within it.
+struct SideChanges {
+    hunks: Vec<xdiff::Hunk>,
+    next: usize,
+    /// Difference between line numbers on this side and in the base, for
+    /// lines after the hunks that have been consumed.
+    delta: isize,
+}
+
+impl SideChanges {
+    fn new(hunks: Vec<xdiff::Hunk>) -> Self {
+        Self {
+            hunks,
+            next: 0,
+            delta: 0,
+        }
+    }
+
+    fn next_start(&self) -> Option<usize> {
+        self.hunks.get(self.next).map(|hunk| hunk.remove.start)
+    }
+
+    /// Consumes the next hunk if it starts before or at `end`, returning the
+    /// end of the hunk in the base.
+    fn consume_until(&mut self, end: usize) -> Option<usize> {
+        let hunk = self.hunks.get(self.next)?;
+        if hunk.remove.start > end {
+            return None;
+        }
+        self.next += 1;
+        Some(hunk.remove.end)
+    }
+
+    /// Returns the range on this side corresponding to the base range
+    /// `start..end`, where the hunks from `first` onwards are within it.
+    fn side_range(&mut self, first: usize, start: usize, end: usize) -> Range<usize> {
+        let side_start = (start as isize + self.delta) as usize;
+        for hunk in &self.hunks[first..self.next] {
+            self.delta += hunk.add.len() as isize - hunk.remove.len() as isize;
+        }
+        let side_end = (end as isize + self.delta) as usize;
+        side_start..side_end
+    }
+}
+
+/// Performs a three-way merge of text contents, line by line.
+///
+/// Changes made by only one side are applied.  Regions that both sides
+/// changed, including adjacent changes, merge cleanly only if both sides
+/// made the same change.  Otherwise the conflicting regions are returned.
+pub(crate) fn merge_text(
+    base: &[u8],
+    local: &[u8],
+    other: &[u8],
+) -> Result<Vec<u8>, Vec<ConflictHunk>> {
+    let base_lines = split_lines(base);
+    let local_lines = split_lines(local);
+    let other_lines = split_lines(other);
+    let mut local_changes = SideChanges::new(xdiff::diff_hunks(base, local));
+    let mut other_changes = SideChanges::new(xdiff::diff_hunks(base, other));
+
+    let mut merged = Vec::with_capacity(max(local.len(), other.len()));
+    let mut conflicts = Vec::new();
+    let mut base_pos = 0;
+    loop {
+        let start = match (local_changes.next_start(), other_changes.next_start()) {
+            (None, None) => break,
+            (Some(start), None) | (None, Some(start)) => start,
+            (Some(local_start), Some(other_start)) => local_start.min(other_start),
+        };
+
+        // Grow the region until no hunk on either side overlaps or touches it.
+        let (first_local, first_other) = (local_changes.next, other_changes.next);
+        let mut end = start;
+        loop {
+            let mut grown = false;
+            while let Some(hunk_end) = local_changes.consume_until(end) {
+                end = max(end, hunk_end);
+                grown = true;
+            }
+            while let Some(hunk_end) = other_changes.consume_until(end) {
+                end = max(end, hunk_end);
+                grown = true;
+            }
+            if !grown {
+                break;
+            }
+        }
+
+        for line in &base_lines[base_pos..start] {
+            merged.extend_from_slice(line);
+        }
+        let local_changed = local_changes.next > first_local;
+        let other_changed = other_changes.next > first_other;
+        let local_range = local_changes.side_range(first_local, start, end);
+        let other_range = other_changes.side_range(first_other, start, end);
+        let local_region = &local_lines[local_range.clone()];
+        let other_region = &other_lines[other_range.clone()];
+        if !other_changed || (local_changed && local_region == other_region) {
+            for line in local_region {
+                merged.extend_from_slice(line);
+            }
+        } else if !local_changed {
+            for line in other_region {
+                merged.extend_from_slice(line);
+            }
+        } else {
+            conflicts.push(ConflictHunk {
+                base: start..end,
+                local: local_range,
+                other: other_range,
+            });
+        }
+        base_pos = end;
+    }
+    for line in &base_lines[base_pos..] {
+        merged.extend_from_slice(line);
+    }
+
+    if conflicts.is_empty() {
+        Ok(merged)
+    } else {
+        Err(conflicts)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_text_clean() {
+        let base = b"a\nb\nc\nd\ne\n";
+        let local = b"A\nb\nc\nd\ne\n";
+        let other = b"a\nb\nc\nd\nE\nf\n";
+        assert_eq!(
+            merge_text(base, local, other),
+            Ok(b"A\nb\nc\nd\nE\nf\n".to_vec())
+        );
+
+        // The same change on both sides merges cleanly.
+        assert_eq!(merge_text(base, local, local), Ok(local.to_vec()));
+
+        // Unchanged sides.
+        assert_eq!(merge_text(base, base, other), Ok(other.to_vec()));
+        assert_eq!(merge_text(base, local, base), Ok(local.to_vec()));
+    }
+
+    #[test]
+    fn merge_text_conflict() {
+        let base = b"a\nb\nc\nd\n";
+        let local = b"a\nB\nc\nd\n";
+        let other = b"a\nx\ny\nc\nD\n";
+        assert_eq!(
+            merge_text(base, local, other),
+            Err(vec![ConflictHunk {
+                base: 1..2,
+                local: 1..2,
+                other: 1..3,
+            }])
+        );
+
+        // Both sides adding to an empty base conflicts.
+        assert_eq!(
+            merge_text(b"", b"a\n", b"b\n"),
+            Err(vec![ConflictHunk {
+                base: 0..0,
+                local: 0..1,
+                other: 0..1,
+            }])
+        );
+    }
+
+    #[test]
+    fn merge_text_missing_newline() {
+        let base = b"a\nb\nc";
+        let local = b"A\nb\nc";
+        let other = b"a\nb\nc\nd";
+        assert_eq!(merge_text(base, local, other), Ok(b"A\nb\nc\nd".to_vec()));
+    }
+}
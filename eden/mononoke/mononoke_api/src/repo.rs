@@ -51,7 +51,7 @@ use permission_checker::{ArcPermissionChecker, PermissionCheckerBuilder};
 use reachabilityindex::LeastCommonAncestorsHint;
 #[cfg(test)]
 use regex::Regex;
-use revset::AncestorsNodeStream;
+use revset::{AncestorsNodeStream, DifferenceOfUnionsOfAncestorsNodeStream};
 use scuba_ext::ScubaSampleBuilderExt;
 use segmented_changelog::SegmentedChangelog;
 use skiplist::{fetch_skiplist_index, SkiplistIndex};
@@ -620,6 +620,39 @@ pub struct Stack {
     pub leftover_heads: Vec<ChangesetId>,
 }
 
+/// A page of the commits that are ancestors of one commit but not of
+/// another, and their number.
+struct AncestorsDifference {
+    page: Vec<ChangesetId>,
+    count: u64,
+    count_is_lower_bound: bool,
+}
+
+/// The result of comparing two commits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitComparison {
+    /// The merge base of the two commits, if they have one.
+    pub merge_base: Option<ChangesetId>,
+    /// A page of the commits that are ancestors of the first commit but not
+    /// of the second, newest first.
+    pub only_in_a: Vec<ChangesetId>,
+    /// The total number of commits that are ancestors of the first commit
+    /// but not of the second, or a lower bound of it if counting was
+    /// stopped.
+    pub only_in_a_count: u64,
+    /// Whether `only_in_a_count` is a lower bound.
+    pub only_in_a_count_is_lower_bound: bool,
+    /// A page of the commits that are ancestors of the second commit but
+    /// not of the first, newest first.
+    pub only_in_b: Vec<ChangesetId>,
+    /// The total number of commits that are ancestors of the second commit
+    /// but not of the first, or a lower bound of it if counting was
+    /// stopped.
+    pub only_in_b_count: u64,
+    /// Whether `only_in_b_count` is a lower bound.
+    pub only_in_b_count_is_lower_bound: bool,
+}
+
 /// A context object representing a query to a particular repo.
 impl RepoContext {
     pub(crate) async fn new(ctx: CoreContext, repo: Arc<Repo>) -> Result<Self, MononokeError> {
@@ -1028,6 +1061,89 @@ impl RepoContext {
         })
     }
 
+    /// Compare two commits, e.g. a branch and the branch it should merge
+    /// into: find the commits that are only ancestors of one of them, and
+    /// their merge base.
+    ///
+    /// The commits of each side are ordered newest first, by generation
+    /// number, and paginated using `skip` and `limit`.  The counts are of
+    /// all the commits of each side, regardless of pagination, but counting
+    /// stops after `count_limit` commits, in which case the count is a lower
+    /// bound.  Pages end at the last counted commit, so the commits after
+    /// it can't be listed.
+    pub async fn compare_commits(
+        &self,
+        a: ChangesetId,
+        b: ChangesetId,
+        skip: usize,
+        limit: usize,
+        count_limit: usize,
+    ) -> Result<CommitComparison, MononokeError> {
+        let missing =
+            |cs_id| MononokeError::InvalidRequest(format!("Commit {} does not exist", cs_id));
+        let (changeset_a, changeset_b) = try_join!(
+            self.changeset(ChangesetSpecifier::Bonsai(a)),
+            self.changeset(ChangesetSpecifier::Bonsai(b)),
+        )?;
+        let changeset_a = changeset_a.ok_or_else(|| missing(a))?;
+        changeset_b.ok_or_else(|| missing(b))?;
+
+        let (merge_base, only_in_a, only_in_b) = try_join!(
+            changeset_a.common_base_with(b),
+            self.ancestors_difference(a, b, skip, limit, count_limit),
+            self.ancestors_difference(b, a, skip, limit, count_limit),
+        )?;
+        Ok(CommitComparison {
+            merge_base: merge_base.map(|changeset| changeset.id()),
+            only_in_a: only_in_a.page,
+            only_in_a_count: only_in_a.count,
+            only_in_a_count_is_lower_bound: only_in_a.count_is_lower_bound,
+            only_in_b: only_in_b.page,
+            only_in_b_count: only_in_b.count,
+            only_in_b_count_is_lower_bound: only_in_b.count_is_lower_bound,
+        })
+    }
+
+    /// Returns a page of the ancestors of `include` that are not ancestors
+    /// of `exclude`, along with the number of them.  Counting stops after
+    /// `count_limit` commits, and the page ends there too.
+    async fn ancestors_difference(
+        &self,
+        include: ChangesetId,
+        exclude: ChangesetId,
+        skip: usize,
+        limit: usize,
+        count_limit: usize,
+    ) -> Result<AncestorsDifference, MononokeError> {
+        let lca_hint: Arc<dyn LeastCommonAncestorsHint> = self.skiplist_index().clone();
+        let end = skip.saturating_add(limit).min(count_limit);
+        let (page, count) = DifferenceOfUnionsOfAncestorsNodeStream::new_with_excludes(
+            self.ctx.clone(),
+            &self.blob_repo().get_changeset_fetcher(),
+            lca_hint,
+            vec![include],
+            vec![exclude],
+        )
+        .compat()
+        // One more commit than the limit tells whether the count is complete.
+        .take(count_limit.saturating_add(1))
+        .try_fold(
+            (Vec::new(), 0usize),
+            |(mut page, count), cs_id| async move {
+                if count >= skip && count < end {
+                    page.push(cs_id);
+                }
+                Ok((page, count + 1))
+            },
+        )
+        .await?;
+        Ok(AncestorsDifference {
+            page,
+            count: count.min(count_limit) as u64,
+            count_is_lower_bound: count > count_limit,
+        })
+    }
+
     /// Get a Tree by id.  Returns `None` if the tree doesn't exist.
     pub async fn tree(&self, tree_id: TreeId) -> Result<Option<TreeContext>, MononokeError> {
         TreeContext::new_check_exists(self.clone(), tree_id).await
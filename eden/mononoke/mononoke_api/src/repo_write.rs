@@ -15,6 +15,7 @@ pub mod create_changeset;
 pub mod delete_bookmark;
 pub mod land_stack;
 pub mod move_bookmark;
+pub mod rebase;
 
 /// Describes the permissions model that is being used to determine if a write is
 /// permitted or not.
@@ -0,0 +1,340 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::format_err;
+use blobstore::Loadable;
+use filestore::StoreRequest;
+use futures::compat::Future01CompatExt;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use futures::try_join;
+use futures_old::stream as old_stream;
+use mononoke_types::{ChangesetId, FileChange, MPath};
+
+use crate::changeset::ChangesetContext;
+use crate::errors::MononokeError;
+use crate::merge::{merge_file, FileResolution, MergeConflict, MergeConflictKind, PathState};
+use crate::path::MononokePath;
+use crate::repo_write::RepoWriteContext;
+use crate::specifiers::ChangesetSpecifier;
+
+/// Number of paths of a changeset that are merged concurrently.
+const CONCURRENT_FILE_MERGES: usize = 100;
+
+/// A changeset that was rebased, and the new changeset it was rebased to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RebasedChangeset {
+    pub id_old: ChangesetId,
+    pub id_new: ChangesetId,
+}
+
+/// Outcome of rebasing a stack of changesets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RebaseOutcome {
+    /// All changesets of the stack were rebased, in stack order.
+    Rebased(Vec<RebasedChangeset>),
+
+    /// Rebasing stopped at `changeset`, as its changes conflict with the
+    /// new base.
+    Conflicts {
+        changeset: ChangesetId,
+        conflicts: Vec<MergeConflict>,
+    },
+}
+
+/// Outcome of cherry-picking a changeset.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CherryPickOutcome {
+    /// The changeset was picked as this new changeset.
+    Picked(ChangesetId),
+
+    /// The changes of the changeset conflict with the new base.
+    Conflicts(Vec<MergeConflict>),
+}
+
+/// How a file change of the rebased changeset applies to the new base.
+enum RebasedFileChange {
+    /// The new base already matches the result of the change.
+    Unchanged,
+    Changed(Option<FileChange>),
+    Conflict(MergeConflictKind),
+}
+
+impl RepoWriteContext {
+    /// Rebase a stack of changesets onto a new base.
+    ///
+    /// The stack is given from bottom to top, and must be linear: each
+    /// changeset's only parent must be the changeset before it.  The changes
+    /// of each changeset are applied with a file-level three-way merge, and
+    /// the rebased changesets keep their author, dates and message.
+    ///
+    /// If the changes of a changeset can't be merged, rebasing stops and the
+    /// conflicts are returned.  Changesets of the stack that were already
+    /// rebased are stored in the repo, but nothing refers to them.
+    pub async fn rebase_stack(
+        &self,
+        stack: Vec<ChangesetId>,
+        onto: ChangesetId,
+    ) -> Result<RebaseOutcome, MononokeError> {
+        self.check_method_permitted("rebase_stack")?;
+
+        if stack.is_empty() {
+            return Err(MononokeError::InvalidRequest(String::from(
+                "Cannot rebase an empty stack",
+            )));
+        }
+        let mut changesets = Vec::with_capacity(stack.len());
+        for cs_id in stack {
+            changesets.push(self.changeset_to_rewrite(cs_id).await?);
+        }
+        for pair in changesets.windows(2) {
+            if pair[1].parents().await? != vec![pair[0].id()] {
+                return Err(MononokeError::InvalidRequest(format!(
+                    "Not a stack: the parent of commit {} is not {}",
+                    pair[1].id(),
+                    pair[0].id(),
+                )));
+            }
+        }
+
+        let mut onto = self.changeset_to_rewrite(onto).await?;
+        let mut rebased = Vec::with_capacity(changesets.len());
+        for changeset in changesets {
+            match self.rebase_changeset(&changeset, &onto).await? {
+                Ok(id_new) => {
+                    rebased.push(RebasedChangeset {
+                        id_old: changeset.id(),
+                        id_new,
+                    });
+                    onto = ChangesetContext::new(self.repo.clone(), id_new);
+                }
+                Err(conflicts) => {
+                    return Ok(RebaseOutcome::Conflicts {
+                        changeset: changeset.id(),
+                        conflicts,
+                    });
+                }
+            }
+        }
+        Ok(RebaseOutcome::Rebased(rebased))
+    }
+
+    /// Cherry-pick a changeset onto another changeset.
+    ///
+    /// The changes the changeset made relative to its parent are applied on
+    /// top of `onto` with a file-level three-way merge, creating a new
+    /// changeset with the same author, dates and message.
+    pub async fn cherry_pick(
+        &self,
+        changeset: ChangesetId,
+        onto: ChangesetId,
+    ) -> Result<CherryPickOutcome, MononokeError> {
+        self.check_method_permitted("cherry_pick")?;
+
+        let changeset = self.changeset_to_rewrite(changeset).await?;
+        let onto = self.changeset_to_rewrite(onto).await?;
+        match self.rebase_changeset(&changeset, &onto).await? {
+            Ok(id_new) => Ok(CherryPickOutcome::Picked(id_new)),
+            Err(conflicts) => Ok(CherryPickOutcome::Conflicts(conflicts)),
+        }
+    }
+
+    async fn changeset_to_rewrite(
+        &self,
+        cs_id: ChangesetId,
+    ) -> Result<ChangesetContext, MononokeError> {
+        self.changeset(ChangesetSpecifier::Bonsai(cs_id))
+            .await?
+            .ok_or_else(|| {
+                MononokeError::InvalidRequest(format!("Commit {} does not exist", cs_id))
+            })
+    }
+
+    /// Create a copy of `changeset` with `onto` as its parent, merging its
+    /// changes into `onto`.  Returns the conflicts if the changes can't be
+    /// merged.
+    async fn rebase_changeset(
+        &self,
+        changeset: &ChangesetContext,
+        onto: &ChangesetContext,
+    ) -> Result<Result<ChangesetId, Vec<MergeConflict>>, MononokeError> {
+        let bonsai = changeset
+            .id()
+            .load(self.ctx().clone(), self.blob_repo().blobstore())
+            .await?;
+        let parent = match bonsai.parents().collect::<Vec<_>>().as_slice() {
+            [parent] => ChangesetContext::new(self.repo.clone(), *parent),
+            _ => {
+                return Err(MononokeError::InvalidRequest(format!(
+                    "Commit {} is a merge or root commit, which cannot be rebased",
+                    changeset.id()
+                )));
+            }
+        };
+
+        let deleted: HashSet<MPath> = bonsai
+            .file_changes()
+            .filter(|(_path, change)| change.is_none())
+            .map(|(path, _change)| path.clone())
+            .collect();
+        let rebased_changes: Vec<_> = stream::iter(bonsai.file_changes())
+            .map(|(path, change)| {
+                let (parent, deleted) = (&parent, &deleted);
+                async move {
+                    let rebased = self
+                        .rebase_file_change(parent, onto, path, change, deleted)
+                        .await?;
+                    Ok::<_, MononokeError>((path.clone(), rebased))
+                }
+            })
+            .buffered(CONCURRENT_FILE_MERGES)
+            .try_collect()
+            .await?;
+
+        let mut file_changes = BTreeMap::new();
+        let mut conflicts = Vec::new();
+        for (path, rebased) in rebased_changes {
+            match rebased {
+                RebasedFileChange::Unchanged => {}
+                RebasedFileChange::Changed(change) => {
+                    file_changes.insert(path, change);
+                }
+                RebasedFileChange::Conflict(kind) => conflicts.push(MergeConflict {
+                    path: MononokePath::from(path),
+                    kind,
+                }),
+            }
+        }
+        if !conflicts.is_empty() {
+            return Ok(Err(conflicts));
+        }
+
+        let mut new_changeset = bonsai.into_mut();
+        new_changeset.parents = vec![onto.id()];
+        new_changeset.file_changes = file_changes;
+        let new_changeset = new_changeset.freeze().map_err(|e| {
+            MononokeError::InvalidRequest(format!("Rebase creates invalid bonsai changeset: {}", e))
+        })?;
+        let new_changeset_id = new_changeset.get_changeset_id();
+        blobrepo::save_bonsai_changesets(
+            vec![new_changeset],
+            self.ctx().clone(),
+            self.blob_repo().clone(),
+        )
+        .compat()
+        .await?;
+        Ok(Ok(new_changeset_id))
+    }
+
+    async fn rebase_file_change(
+        &self,
+        parent: &ChangesetContext,
+        onto: &ChangesetContext,
+        path: &MPath,
+        change: Option<&FileChange>,
+        deleted: &HashSet<MPath>,
+    ) -> Result<RebasedFileChange, MononokeError> {
+        let mononoke_path = MononokePath::from(path.clone());
+        let other = match change {
+            Some(change) => PathState::File(change.content_id(), change.file_type()),
+            None => PathState::Absent,
+        };
+        let (base, local) = try_join!(
+            PathState::of(parent, &mononoke_path),
+            PathState::of(onto, &mononoke_path),
+        )?;
+
+        let (content_id, file_type, size) = match merge_file(&self.repo, base, local, other).await?
+        {
+            FileResolution::Local => return Ok(RebasedFileChange::Unchanged),
+            FileResolution::Conflict(kind) => return Ok(RebasedFileChange::Conflict(kind)),
+            FileResolution::Other => match change {
+                Some(change) => (change.content_id(), change.file_type(), change.size()),
+                // Only files can be deleted.
+                None if local.is_file() => return Ok(RebasedFileChange::Changed(None)),
+                None => return Ok(RebasedFileChange::Unchanged),
+            },
+            FileResolution::File(content_id, file_type) => {
+                let size = match change {
+                    Some(change) if change.content_id() == content_id => change.size(),
+                    _ => {
+                        self.file(content_id)
+                            .await?
+                            .ok_or_else(|| {
+                                MononokeError::from(format_err!(
+                                    "Content {} is not found",
+                                    content_id
+                                ))
+                            })?
+                            .metadata()
+                            .await?
+                            .total_size
+                    }
+                };
+                (content_id, file_type, size)
+            }
+            FileResolution::Merged(content, file_type) => {
+                let meta = filestore::store(
+                    self.blob_repo().get_blobstore(),
+                    self.blob_repo().filestore_config(),
+                    self.ctx().clone(),
+                    &StoreRequest::new(content.len() as u64),
+                    old_stream::once(Ok(content)),
+                )
+                .compat()
+                .await?;
+                (meta.content_id, file_type, meta.total_size)
+            }
+        };
+
+        // A file can only be added if no prefix of its path is a file on the
+        // new base, unless the changeset deletes it.
+        if !local.is_file() {
+            let prefix_files: Vec<_> = onto
+                .paths(mononoke_path.prefixes())
+                .await?
+                .try_filter_map(|prefix| async move {
+                    if prefix.is_file().await? {
+                        Ok(Some(prefix.path().clone()))
+                    } else {
+                        Ok(None)
+                    }
+                })
+                .try_collect()
+                .await?;
+            let prefix_file_remains = prefix_files.into_iter().any(|prefix| {
+                prefix
+                    .into_mpath()
+                    .map_or(false, |mpath| !deleted.contains(&mpath))
+            });
+            if prefix_file_remains {
+                return Ok(RebasedFileChange::Conflict(
+                    MergeConflictKind::FileDirectory,
+                ));
+            }
+        }
+
+        // Copy information refers to the parent of the changeset, so it is
+        // kept only if the source still exists in the new parent.
+        let copy_from = match change.and_then(|change| change.copy_from()) {
+            Some((from_path, _)) => {
+                let from = PathState::of(onto, &MononokePath::from(from_path.clone())).await?;
+                if from.is_file() {
+                    Some((from_path.clone(), onto.id()))
+                } else {
+                    None
+                }
+            }
+            None => None,
+        };
+
+        Ok(RebasedFileChange::Changed(Some(FileChange::new(
+            content_id, file_type, size, copy_from,
+        ))))
+    }
+}
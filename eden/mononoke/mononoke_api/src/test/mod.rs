@@ -11,3 +11,5 @@ mod test_repo_bookmarks;
 mod test_repo_create_changeset;
 mod test_repo_land_stack;
 mod test_repo_modify_bookmarks;
+mod test_repo_rebase;
+mod test_skeleton_manifests;
@@ -361,6 +361,69 @@ async fn commit_path_history(fb: FacebookInit) -> Result<()> {
         vec![changesets["a4"], changesets["m1"], changesets["a3"]]
     );
 
+    // Following only first parents skips the merged-in branches.
+    let dir3_first_parent_history: Vec<_> = dir3_path
+        .history(ChangesetPathHistoryOptions {
+            follow_history_across_deletions: true,
+            first_parent_only: true,
+            ..Default::default()
+        })
+        .await?
+        .and_then(|cs| async move { Ok(cs.id()) })
+        .try_collect()
+        .await?;
+    assert_eq!(
+        dir3_first_parent_history,
+        vec![
+            changesets["c2"],
+            changesets["m2"],
+            changesets["a4"],
+            changesets["c1"],
+            changesets["m1"],
+            changesets["b2"],
+        ]
+    );
+
+    // History limited by depth can be resumed by skipping the same depth.
+    let dir3_history_page1: Vec<_> = dir3_path
+        .history(ChangesetPathHistoryOptions {
+            follow_history_across_deletions: true,
+            max_depth: Some(2),
+            ..Default::default()
+        })
+        .await?
+        .and_then(|cs| async move { Ok(cs.id()) })
+        .try_collect()
+        .await?;
+    assert_eq!(
+        dir3_history_page1,
+        vec![
+            changesets["c2"],
+            changesets["m2"],
+            changesets["a4"],
+            changesets["b3"],
+        ]
+    );
+    let dir3_history_page2: Vec<_> = dir3_path
+        .history(ChangesetPathHistoryOptions {
+            follow_history_across_deletions: true,
+            skip_depth: 3,
+            ..Default::default()
+        })
+        .await?
+        .and_then(|cs| async move { Ok(cs.id()) })
+        .try_collect()
+        .await?;
+    assert_eq!(
+        dir3_history_page2,
+        vec![
+            changesets["c1"],
+            changesets["m1"],
+            changesets["b2"],
+            changesets["a3"],
+        ]
+    );
+
     Ok(())
 }
 
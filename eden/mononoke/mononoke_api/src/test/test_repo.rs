@@ -11,19 +11,24 @@ use std::str::FromStr;
 use std::sync::Arc;
 
 use anyhow::{anyhow, Error};
+use assert_matches::assert_matches;
 use blobrepo_factory::new_memblob_empty;
 use blobstore::Loadable;
 use bytes::Bytes;
 use chrono::{FixedOffset, TimeZone};
+use derived_data::BonsaiDerived;
 use fbinit::FacebookInit;
+use file_classification::{fetch_stored_file_classification, RootFileClassification};
 use fixtures::{branch_uneven, linear, many_files_dirs};
+use futures::compat::Future01CompatExt;
 use futures::stream::TryStreamExt;
 use maplit::{btreeset, hashmap};
 
 use crate::{
-    BookmarkFreshness, ChangesetDiffItem, ChangesetId, ChangesetIdPrefix, ChangesetPathDiffContext,
-    ChangesetPrefixSpecifier, ChangesetSpecifier, ChangesetSpecifierPrefixResolution, CoreContext,
-    FileId, FileMetadata, FileType, HgChangesetId, HgChangesetIdPrefix, Mononoke, MononokePath,
+    ArchiveFormat, BookmarkFreshness, ChangesetDiffItem, ChangesetGrepOptions, ChangesetId,
+    ChangesetIdPrefix, ChangesetPathDiffContext, ChangesetPrefixSpecifier, ChangesetSpecifier,
+    ChangesetSpecifierPrefixResolution, CoreContext, FileId, FileMetadata, FileType,
+    FindFilesFilter, HgChangesetId, HgChangesetIdPrefix, Mononoke, MononokeError, MononokePath,
     TreeEntry, TreeId,
 };
 use cross_repo_sync::{update_mapping_with_version, CommitSyncRepos, CommitSyncer};
@@ -302,6 +307,383 @@ async fn commit_find_files(fb: FacebookInit) -> Result<(), Error> {
     Ok(())
 }
 
+#[fbinit::compat_test]
+async fn commit_find_files_filtered(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blobrepo = new_memblob_empty(None)?;
+    let root = CreateCommitContext::new_root(&ctx, &blobrepo)
+        .add_file("src/lib.rs", "fn main() {}\n")
+        .add_file("src/gen.rs", "// @generated\n")
+        .add_file("bin/tool", "#!/usr/bin/env python3\nprint()\n")
+        .add_file("vendor/dep/lib.rs", "fn dep() {}\n")
+        .add_file("image.png", "\u{89}PNG\0")
+        .commit()
+        .await?;
+    let mononoke =
+        Mononoke::new_test(ctx.clone(), vec![("test".to_string(), blobrepo.clone())]).await?;
+    let repo = mononoke
+        .repo(ctx.clone(), "test")
+        .await?
+        .expect("repo exists");
+    let cs = repo
+        .changeset(ChangesetSpecifier::Bonsai(root))
+        .await?
+        .expect("changeset exists");
+
+    // Classifications are only read, so they must have been derived.
+    assert_matches!(
+        cs.find_files_filtered(
+            None,
+            None,
+            FindFilesFilter {
+                exclude_binary: true,
+                ..Default::default()
+            }
+        )
+        .await,
+        Err(MononokeError::NotAvailable(_))
+    );
+    RootFileClassification::derive(ctx.clone(), blobrepo.clone(), root)
+        .compat()
+        .await?;
+
+    let find = |filter: FindFilesFilter| {
+        let cs = &cs;
+        async move {
+            let mut files: Vec<_> = cs
+                .find_files_filtered(None, None, filter)
+                .await?
+                .map_ok(|path| path.to_string())
+                .try_collect()
+                .await?;
+            files.sort();
+            Ok::<_, Error>(files)
+        }
+    };
+
+    assert_eq!(
+        find(FindFilesFilter {
+            languages: Some(vec![String::from("rust")]),
+            ..Default::default()
+        })
+        .await?,
+        vec!["src/gen.rs", "src/lib.rs", "vendor/dep/lib.rs"]
+    );
+    assert_eq!(
+        find(FindFilesFilter {
+            languages: Some(vec![String::from("Python")]),
+            ..Default::default()
+        })
+        .await?,
+        vec!["bin/tool"]
+    );
+    assert_eq!(
+        find(FindFilesFilter {
+            exclude_generated: true,
+            exclude_vendored: true,
+            exclude_binary: true,
+            ..Default::default()
+        })
+        .await?,
+        vec!["bin/tool", "src/lib.rs"]
+    );
+
+    Ok(())
+}
+
+#[fbinit::compat_test]
+async fn commit_grep(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blobrepo = new_memblob_empty(None)?;
+    let root = CreateCommitContext::new_root(&ctx, &blobrepo)
+        .add_file("src/lib.rs", "fn main() {\r\n    todo!()\r\n}\r\n")
+        .add_file("src/util.rs", "// TODO: one\nfn util() {}\n// TODO: two\n")
+        .add_file("docs/README", "Nothing to do\n")
+        .add_file("image.png", "\u{89}PNG\0TODO")
+        .commit()
+        .await?;
+    let mononoke =
+        Mononoke::new_test(ctx.clone(), vec![("test".to_string(), blobrepo.clone())]).await?;
+    let repo = mononoke
+        .repo(ctx.clone(), "test")
+        .await?
+        .expect("repo exists");
+    let cs = repo
+        .changeset(ChangesetSpecifier::Bonsai(root))
+        .await?
+        .expect("changeset exists");
+
+    let grep = |pattern: &'static str, options: ChangesetGrepOptions| {
+        let cs = &cs;
+        async move {
+            let mut matches: Vec<_> = cs
+                .grep(pattern, options)
+                .await?
+                .map_ok(|m| (m.path.to_string(), m.line_number, m.line))
+                .try_collect()
+                .await?;
+            matches.sort();
+            Ok::<_, Error>(matches)
+        }
+    };
+
+    assert_eq!(
+        grep("TODO", Default::default()).await?,
+        vec![
+            (String::from("src/util.rs"), 1, String::from("// TODO: one")),
+            (String::from("src/util.rs"), 3, String::from("// TODO: two")),
+        ]
+    );
+    assert_eq!(
+        grep(
+            "todo",
+            ChangesetGrepOptions {
+                case_insensitive: true,
+                max_matches_per_file: Some(1),
+                ..Default::default()
+            }
+        )
+        .await?,
+        vec![
+            (String::from("src/lib.rs"), 2, String::from("    todo!()")),
+            (String::from("src/util.rs"), 1, String::from("// TODO: one")),
+        ]
+    );
+    assert_eq!(
+        grep(
+            "(?i)to ?do",
+            ChangesetGrepOptions {
+                prefixes: Some(vec![MononokePath::try_from("docs")?]),
+                ..Default::default()
+            }
+        )
+        .await?,
+        vec![(
+            String::from("docs/README"),
+            1,
+            String::from("Nothing to do")
+        )]
+    );
+    assert_eq!(
+        grep(
+            "TODO",
+            ChangesetGrepOptions {
+                max_matches: Some(1),
+                ..Default::default()
+            }
+        )
+        .await?
+        .len(),
+        1
+    );
+    assert!(cs.grep("(unclosed", Default::default()).await.is_err());
+
+    // Searching doesn't store the classifications of the files it fetched
+    let image = cs.path("image.png")?.file().await?.expect("file exists");
+    assert!(
+        fetch_stored_file_classification(&ctx, blobrepo.blobstore(), image.id().await?)
+            .await?
+            .is_none()
+    );
+
+    Ok(())
+}
+
+#[fbinit::compat_test]
+async fn commit_archive(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blobrepo = new_memblob_empty(None)?;
+    let root = CreateCommitContext::new_root(&ctx, &blobrepo)
+        .add_file("dir/file", "content\n")
+        .add_file_with_type("dir/tool", "#!/bin/sh\n", FileType::Executable)
+        .add_file_with_type("link", "dir/file", FileType::Symlink)
+        .add_file("other/file", "other\n")
+        .commit()
+        .await?;
+    let mononoke =
+        Mononoke::new_test(ctx.clone(), vec![("test".to_string(), blobrepo.clone())]).await?;
+    let repo = mononoke
+        .repo(ctx.clone(), "test")
+        .await?
+        .expect("repo exists");
+    let cs = repo
+        .changeset(ChangesetSpecifier::Bonsai(root))
+        .await?
+        .expect("changeset exists");
+
+    let archive = |format: ArchiveFormat, prefix: Option<&'static str>| {
+        let cs = &cs;
+        async move {
+            let prefix = prefix.map(MononokePath::try_from).transpose()?;
+            let chunks: Vec<_> = cs.archive(format, prefix).await?.try_collect().await?;
+            Ok::<_, Error>(chunks.concat())
+        }
+    };
+    let tar_entries = |data: &[u8]| -> Result<Vec<(String, u32, Option<String>)>, Error> {
+        let mut entries = Vec::new();
+        for entry in tar::Archive::new(data).entries()? {
+            let entry = entry?;
+            let header = entry.header();
+            let link = header
+                .link_name()?
+                .map(|link| link.to_string_lossy().into_owned());
+            entries.push((
+                entry.path()?.to_string_lossy().into_owned(),
+                header.mode()?,
+                link,
+            ));
+        }
+        Ok(entries)
+    };
+    let expected = vec![
+        (String::from("dir/file"), 0o644, None),
+        (String::from("dir/tool"), 0o755, None),
+        (String::from("link"), 0o777, Some(String::from("dir/file"))),
+        (String::from("other/file"), 0o644, None),
+    ];
+
+    let tar = archive(ArchiveFormat::Tar, None).await?;
+    assert_eq!(tar_entries(&tar)?, expected);
+    // Archives of the same commit are identical.
+    assert_eq!(archive(ArchiveFormat::Tar, None).await?, tar);
+
+    let tar_gz = archive(ArchiveFormat::TarGzip, None).await?;
+    let mut tar = Vec::new();
+    // Each file is a separate gzip member.
+    std::io::Read::read_to_end(
+        &mut flate2::read::MultiGzDecoder::new(&tar_gz[..]),
+        &mut tar,
+    )?;
+    assert_eq!(tar_entries(&tar)?, expected);
+
+    let tar_zst = archive(ArchiveFormat::TarZstd, Some("dir")).await?;
+    assert_eq!(
+        tar_entries(&zstd::decode_all(&tar_zst[..])?)?,
+        expected[..2]
+    );
+
+    let zip = archive(ArchiveFormat::Zip, None).await?;
+    let mut zip_archive = zip::ZipArchive::new(std::io::Cursor::new(&zip[..]))?;
+    let mut zip_entries = Vec::new();
+    for index in 0..zip_archive.len() {
+        let file = zip_archive.by_index(index)?;
+        zip_entries.push((file.name().to_string(), file.unix_mode()));
+    }
+    assert_eq!(
+        zip_entries,
+        vec![
+            (String::from("dir/file"), Some(0o100644)),
+            (String::from("dir/tool"), Some(0o100755)),
+            (String::from("link"), Some(0o120777)),
+            (String::from("other/file"), Some(0o100644)),
+        ]
+    );
+
+    // Fetching the archive in chunks from cursors produces the same archive,
+    // for chunks that end both within files and at their boundaries.
+    for format in [ArchiveFormat::TarGzip, ArchiveFormat::Zip].iter() {
+        let expected = archive(*format, None).await?;
+        for size in [1, 100, 512, 1 << 20].iter() {
+            let mut data = Vec::new();
+            let mut cursor = None;
+            loop {
+                let (chunk, next) = cs.archive_chunk(*format, None, cursor, *size).await?;
+                assert!(chunk.len() as u64 <= *size);
+                data.extend_from_slice(&chunk);
+                match next {
+                    Some(next) => {
+                        assert_eq!(next.offset(), data.len() as u64);
+                        cursor = Some(next);
+                    }
+                    None => break,
+                }
+            }
+            assert_eq!(data, expected);
+        }
+    }
+    // Empty chunks would never reach the end of the archive.
+    assert!(cs
+        .archive_chunk(ArchiveFormat::Zip, None, None, 0)
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[fbinit::compat_test]
+async fn repo_compare_commits(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let blobrepo = new_memblob_empty(None)?;
+    let root = CreateCommitContext::new_root(&ctx, &blobrepo)
+        .add_file("file", "root")
+        .commit()
+        .await?;
+    let base = CreateCommitContext::new(&ctx, &blobrepo, vec![root])
+        .add_file("file", "base")
+        .commit()
+        .await?;
+    let a1 = CreateCommitContext::new(&ctx, &blobrepo, vec![base])
+        .add_file("a", "1")
+        .commit()
+        .await?;
+    let a2 = CreateCommitContext::new(&ctx, &blobrepo, vec![a1])
+        .add_file("a", "2")
+        .commit()
+        .await?;
+    let a3 = CreateCommitContext::new(&ctx, &blobrepo, vec![a2])
+        .add_file("a", "3")
+        .commit()
+        .await?;
+    let b1 = CreateCommitContext::new(&ctx, &blobrepo, vec![base])
+        .add_file("b", "1")
+        .commit()
+        .await?;
+    let mononoke =
+        Mononoke::new_test(ctx.clone(), vec![("test".to_string(), blobrepo.clone())]).await?;
+    let repo = mononoke
+        .repo(ctx.clone(), "test")
+        .await?
+        .expect("repo exists");
+
+    let comparison = repo.compare_commits(a3, b1, 0, 10, 100).await?;
+    assert_eq!(comparison.merge_base, Some(base));
+    assert_eq!(comparison.only_in_a, vec![a3, a2, a1]);
+    assert_eq!(comparison.only_in_a_count, 3);
+    assert!(!comparison.only_in_a_count_is_lower_bound);
+    assert_eq!(comparison.only_in_b, vec![b1]);
+    assert_eq!(comparison.only_in_b_count, 1);
+    assert!(!comparison.only_in_b_count_is_lower_bound);
+
+    // Pagination applies to both sides, but the counts are complete.
+    let comparison = repo.compare_commits(a3, b1, 1, 1, 100).await?;
+    assert_eq!(comparison.only_in_a, vec![a2]);
+    assert_eq!(comparison.only_in_a_count, 3);
+    assert!(comparison.only_in_b.is_empty());
+    assert_eq!(comparison.only_in_b_count, 1);
+
+    // A commit compared with its ancestor is only ahead.
+    let comparison = repo.compare_commits(a2, base, 0, 10, 100).await?;
+    assert_eq!(comparison.merge_base, Some(base));
+    assert_eq!(comparison.only_in_a, vec![a2, a1]);
+    assert_eq!(comparison.only_in_b_count, 0);
+
+    // Counting stops at the count limit, and so do pages.
+    let comparison = repo.compare_commits(a3, b1, 0, 1, 2).await?;
+    assert_eq!(comparison.only_in_a, vec![a3]);
+    assert_eq!(comparison.only_in_a_count, 2);
+    assert!(comparison.only_in_a_count_is_lower_bound);
+    assert_eq!(comparison.only_in_b_count, 1);
+    assert!(!comparison.only_in_b_count_is_lower_bound);
+    let comparison = repo.compare_commits(a3, b1, 1, 2, 2).await?;
+    assert_eq!(comparison.only_in_a, vec![a2]);
+    assert_eq!(comparison.only_in_a_count, 2);
+    assert!(comparison.only_in_a_count_is_lower_bound);
+    let comparison = repo.compare_commits(a3, b1, 2, 2, 2).await?;
+    assert_eq!(comparison.only_in_a, vec![]);
+
+    Ok(())
+}
+
 #[fbinit::compat_test]
 async fn commit_path_exists_and_type(fb: FacebookInit) -> Result<(), Error> {
     let ctx = CoreContext::test_mock(fb);
@@ -0,0 +1,272 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use anyhow::Result;
+use assert_matches::assert_matches;
+use bytes::Bytes;
+use context::CoreContext;
+use fbinit::FacebookInit;
+use mononoke_types::ChangesetId;
+use tests_utils::CreateCommitContext;
+
+use crate::merge::MERGE_FILESIZE_LIMIT;
+use crate::{
+    ChangesetContext, ChangesetSpecifier, CherryPickOutcome, ConflictHunk, MergeConflict,
+    MergeConflictKind, MononokePath, RebaseOutcome, Repo, RepoContext,
+};
+
+struct TestRepo {
+    repo: RepoContext,
+    base: ChangesetId,
+    stack_bottom: ChangesetId,
+    stack_top: ChangesetId,
+    other_change: ChangesetId,
+    conflicting_change: ChangesetId,
+}
+
+async fn init_repo(ctx: &CoreContext) -> Result<TestRepo> {
+    let blob_repo = blobrepo_factory::new_memblob_empty(None)?;
+    let base = CreateCommitContext::new_root(ctx, &blob_repo)
+        .add_file("file", "1\n2\n3\n4\n5\n")
+        .add_file("unchanged", "unchanged\n")
+        .commit()
+        .await?;
+    let stack_bottom = CreateCommitContext::new(ctx, &blob_repo, vec![base])
+        .add_file("file", "one\n2\n3\n4\n5\n")
+        .add_file("new", "new\n")
+        .commit()
+        .await?;
+    let stack_top = CreateCommitContext::new(ctx, &blob_repo, vec![stack_bottom])
+        .add_file("new", "newer\n")
+        .delete_file("unchanged")
+        .set_message("top of the stack")
+        .commit()
+        .await?;
+    let other_change = CreateCommitContext::new(ctx, &blob_repo, vec![base])
+        .add_file("file", "1\n2\n3\n4\nfive\n")
+        .commit()
+        .await?;
+    let conflicting_change = CreateCommitContext::new(ctx, &blob_repo, vec![base])
+        .add_file("file", "uno\n2\n3\n4\n5\n")
+        .commit()
+        .await?;
+
+    let repo = Repo::new_test(ctx.clone(), blob_repo).await?;
+    let repo = RepoContext::new(ctx.clone(), Arc::new(repo)).await?;
+    Ok(TestRepo {
+        repo,
+        base,
+        stack_bottom,
+        stack_top,
+        other_change,
+        conflicting_change,
+    })
+}
+
+async fn file_content(cs: &ChangesetContext, path: &str) -> Result<Option<Bytes>> {
+    match cs.path(path)?.file().await? {
+        Some(file) => Ok(Some(file.content_concat().await?)),
+        None => Ok(None),
+    }
+}
+
+#[fbinit::compat_test]
+async fn rebase_stack(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let test = init_repo(&ctx).await?;
+    let repo = test.repo.write().await?;
+
+    let outcome = repo
+        .rebase_stack(vec![test.stack_bottom, test.stack_top], test.other_change)
+        .await?;
+    let rebased = match outcome {
+        RebaseOutcome::Rebased(rebased) => rebased,
+        outcome => panic!("unexpected outcome: {:?}", outcome),
+    };
+    assert_eq!(rebased.len(), 2);
+    assert_eq!(rebased[0].id_old, test.stack_bottom);
+    assert_eq!(rebased[1].id_old, test.stack_top);
+
+    let bottom = repo
+        .changeset(ChangesetSpecifier::Bonsai(rebased[0].id_new))
+        .await?
+        .expect("rebased changeset exists");
+    assert_eq!(bottom.parents().await?, vec![test.other_change]);
+    let top = repo
+        .changeset(ChangesetSpecifier::Bonsai(rebased[1].id_new))
+        .await?
+        .expect("rebased changeset exists");
+    assert_eq!(top.parents().await?, vec![bottom.id()]);
+    assert_eq!(top.message().await?, "top of the stack");
+
+    assert_eq!(
+        file_content(&top, "file").await?,
+        Some(Bytes::from("one\n2\n3\n4\nfive\n"))
+    );
+    assert_eq!(
+        file_content(&top, "new").await?,
+        Some(Bytes::from("newer\n"))
+    );
+    assert_eq!(file_content(&top, "unchanged").await?, None);
+
+    // Rebasing something that is not a stack fails.
+    assert!(repo
+        .rebase_stack(vec![test.stack_top, test.stack_bottom], test.base)
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+#[fbinit::compat_test]
+async fn cherry_pick(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let test = init_repo(&ctx).await?;
+    let repo = test.repo.write().await?;
+
+    // Picking the top of the stack onto the base conflicts, as the file it
+    // modifies doesn't exist there.
+    let outcome = repo.cherry_pick(test.stack_top, test.base).await?;
+    let conflicts = match outcome {
+        CherryPickOutcome::Conflicts(conflicts) => conflicts,
+        outcome => panic!("unexpected outcome: {:?}", outcome),
+    };
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].path.to_string(), "new");
+    assert_eq!(conflicts[0].kind, MergeConflictKind::ModifyDelete);
+
+    // Both sides changed the first line of the file.
+    let outcome = repo
+        .cherry_pick(test.stack_bottom, test.conflicting_change)
+        .await?;
+    let conflicts = match outcome {
+        CherryPickOutcome::Conflicts(conflicts) => conflicts,
+        outcome => panic!("unexpected outcome: {:?}", outcome),
+    };
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].path.to_string(), "file");
+    assert_eq!(
+        conflicts[0].kind,
+        MergeConflictKind::Content(vec![ConflictHunk {
+            base: 0..1,
+            local: 0..1,
+            other: 0..1,
+        }])
+    );
+
+    let outcome = repo.cherry_pick(test.other_change, test.stack_top).await?;
+    let picked = match outcome {
+        CherryPickOutcome::Picked(picked) => picked,
+        outcome => panic!("unexpected outcome: {:?}", outcome),
+    };
+    let picked = repo
+        .changeset(ChangesetSpecifier::Bonsai(picked))
+        .await?
+        .expect("picked changeset exists");
+    assert_eq!(picked.parents().await?, vec![test.stack_top]);
+    assert_eq!(
+        file_content(&picked, "file").await?,
+        Some(Bytes::from("one\n2\n3\n4\nfive\n"))
+    );
+    assert_matches!(
+        repo.cherry_pick(test.base, test.stack_top).await,
+        Err(crate::MononokeError::InvalidRequest(_))
+    );
+
+    Ok(())
+}
+
+#[fbinit::compat_test]
+async fn merge_preview(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let test = init_repo(&ctx).await?;
+    let repo = &test.repo;
+    let changeset = |cs_id| async move {
+        repo.changeset(ChangesetSpecifier::Bonsai(cs_id))
+            .await?
+            .ok_or_else(|| anyhow::format_err!("changeset {} not found", cs_id))
+    };
+    let paths = |paths: &[MononokePath]| -> Vec<String> {
+        paths.iter().map(|path| path.to_string()).collect()
+    };
+
+    let preview = changeset(test.stack_bottom)
+        .await?
+        .merge_preview(test.other_change)
+        .await?;
+    assert!(preview.is_clean());
+    assert_eq!(preview.base, test.base);
+    assert_eq!(paths(&preview.clean), vec!["file", "new"]);
+
+    let preview = changeset(test.stack_top)
+        .await?
+        .merge_preview(test.conflicting_change)
+        .await?;
+    assert!(!preview.is_clean());
+    assert_eq!(paths(&preview.clean), vec!["new", "unchanged"]);
+    assert_eq!(
+        preview.conflicts,
+        vec![MergeConflict {
+            path: MononokePath::try_from("file")?,
+            kind: MergeConflictKind::Content(vec![ConflictHunk {
+                base: 0..1,
+                local: 0..1,
+                other: 0..1,
+            }]),
+        }]
+    );
+
+    // Both sides changed a file to different binary contents.
+    let blob_repo = test.repo.blob_repo();
+    let binary_local = CreateCommitContext::new(&ctx, blob_repo, vec![test.base])
+        .add_file("unchanged", "local\0binary\n")
+        .commit()
+        .await?;
+    let binary_other = CreateCommitContext::new(&ctx, blob_repo, vec![test.base])
+        .add_file("unchanged", "other\0binary\n")
+        .commit()
+        .await?;
+    let preview = changeset(binary_local)
+        .await?
+        .merge_preview(binary_other)
+        .await?;
+    assert!(preview.clean.is_empty());
+    assert_eq!(
+        preview.conflicts,
+        vec![MergeConflict {
+            path: MononokePath::try_from("unchanged")?,
+            kind: MergeConflictKind::Binary,
+        }]
+    );
+
+    // Text files above the size limit are not merged line by line.
+    let large = "line\n".repeat(MERGE_FILESIZE_LIMIT as usize / 5 + 1);
+    let large_local = CreateCommitContext::new(&ctx, blob_repo, vec![test.base])
+        .add_file("unchanged", large.clone())
+        .commit()
+        .await?;
+    let large_other = CreateCommitContext::new(&ctx, blob_repo, vec![test.base])
+        .add_file("unchanged", format!("{}other\n", large))
+        .commit()
+        .await?;
+    let preview = changeset(large_local)
+        .await?
+        .merge_preview(large_other)
+        .await?;
+    assert_eq!(
+        preview.conflicts,
+        vec![MergeConflict {
+            path: MononokePath::try_from("unchanged")?,
+            kind: MergeConflictKind::Binary,
+        }]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use context::CoreContext;
+use fbinit::FacebookInit;
+use tests_utils::CreateCommitContext;
+
+use crate::{ChangesetContext, ChangesetSpecifier, Repo, RepoContext};
+
+async fn case_conflicts(cs: &ChangesetContext) -> Result<Vec<(String, String)>> {
+    Ok(cs
+        .find_case_conflicts()
+        .await?
+        .into_iter()
+        .map(|(first, second)| (first.to_string(), second.to_string()))
+        .collect())
+}
+
+#[fbinit::compat_test]
+async fn path_exists_and_case_conflicts(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let blob_repo = blobrepo_factory::new_memblob_empty(None)?;
+
+    let root = CreateCommitContext::new_root(&ctx, &blob_repo)
+        .add_file("a", "a")
+        .add_file("dir/b", "b")
+        .add_file("dir/sub/c", "c")
+        .commit()
+        .await?;
+    let conflicts = CreateCommitContext::new(&ctx, &blob_repo, vec![root])
+        .add_file("dir/sub/C", "C")
+        .add_file("DIR/d", "d")
+        .commit()
+        .await?;
+
+    let repo = Repo::new_test(ctx.clone(), blob_repo).await?;
+    let repo = RepoContext::new(ctx.clone(), Arc::new(repo)).await?;
+
+    let cs = repo
+        .changeset(ChangesetSpecifier::Bonsai(root))
+        .await?
+        .expect("changeset exists");
+    assert!(cs.path_exists_fast("a").await?);
+    assert!(cs.path_exists_fast("dir").await?);
+    assert!(cs.path_exists_fast("dir/sub/c").await?);
+    assert!(!cs.path_exists_fast("dir/sub/d").await?);
+    assert!(!cs.path_exists_fast("a/b").await?);
+    assert!(!cs.has_case_conflicts().await?);
+    assert!(case_conflicts(&cs).await?.is_empty());
+
+    let cs = repo
+        .changeset(ChangesetSpecifier::Bonsai(conflicts))
+        .await?
+        .expect("changeset exists");
+    assert!(cs.has_case_conflicts().await?);
+    assert_eq!(
+        case_conflicts(&cs).await?,
+        vec![
+            ("DIR".to_string(), "dir".to_string()),
+            ("dir/sub/C".to_string(), "dir/sub/c".to_string()),
+        ]
+    );
+
+    Ok(())
+}
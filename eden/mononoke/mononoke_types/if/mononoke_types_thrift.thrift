@@ -47,6 +47,7 @@ typedef IdType FileUnodeId (rust.newtype)
 typedef IdType ManifestUnodeId (rust.newtype)
 typedef IdType DeletedManifestId(rust.newtype)
 typedef IdType FsnodeId (rust.newtype)
+typedef IdType SkeletonManifestId (rust.newtype)
 typedef IdType MPathHash (rust.newtype)
 
 typedef IdType ContentMetadataId (rust.newtype)
@@ -268,6 +269,33 @@ struct Fsnode {
   2: FsnodeSummary summary,
 }
 
+struct SkeletonManifestDirectory {
+  1: SkeletonManifestId id,
+  2: SkeletonManifestSummary summary,
+}
+
+struct SkeletonManifestSummary {
+  // Counts and lengths are u64s stored as i64s
+  1: i64 child_files_count,
+  2: i64 child_dirs_count,
+  3: i64 descendant_files_count,
+  4: i64 descendant_dirs_count,
+  5: i64 max_path_len,
+  6: bool child_case_conflicts,
+  7: bool descendant_case_conflicts,
+}
+
+// A skeleton manifest entry is either a file, in which case `directory` is
+// not set, or a directory.
+struct SkeletonManifestEntry {
+  1: optional SkeletonManifestDirectory directory,
+}
+
+struct SkeletonManifest {
+  1: map<MPathElement, SkeletonManifestEntry> subentries,
+  2: SkeletonManifestSummary summary,
+}
+
 // Structure that holds a commit graph, usually a history of a file
 // or a directory hence the name. Semantically it stores list of
 // (commit hash, [parent commit hashes]), however it's stored in compressed form
@@ -439,6 +439,128 @@ impl Blame {
         Blame::new(ranges)
     }
 
+    /// Construct blame for the `content` introduced by `csid`, looking
+    /// through `csid` to the parents as if it had never been there.
+    ///
+    /// This is used for changesets that should be ignored by blame (e.g. mass
+    /// reformatting). Lines modified by `csid` are attributed to the lines
+    /// they replaced in the parent, and only the lines that were purely added
+    /// by `csid` remain attributed to it.
+    pub fn from_parents_ignoring<C>(
+        csid: ChangesetId,
+        content: C,
+        path: MPath,
+        parents: Vec<(C, Blame)>,
+    ) -> Result<Blame, Error>
+    where
+        C: AsRef<[u8]>,
+    {
+        if parents.is_empty() {
+            return Blame::from_no_parents(csid, content, path);
+        }
+        let mut blames = parents
+            .iter()
+            .map(|(parent_content, parent_blame)| {
+                Blame::from_single_parent_ignoring(
+                    csid,
+                    content.as_ref(),
+                    &path,
+                    parent_content.as_ref(),
+                    parent_blame,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if blames.len() == 1 {
+            if let Some(blame) = blames.pop() {
+                return Ok(blame);
+            }
+            unreachable!();
+        }
+
+        blame_merge(csid, blames)
+    }
+
+    fn from_single_parent_ignoring<'a>(
+        csid: ChangesetId,
+        content: &[u8],
+        path: &'a MPath,
+        parent_content: &[u8],
+        parent_blame: &'a Blame,
+    ) -> Result<Blame, Error> {
+        let parent_lines: Vec<_> = parent_blame.lines().collect();
+        let parent_line = |index: usize| {
+            parent_lines
+                .get(index)
+                .cloned()
+                .ok_or_else(|| Error::msg("not enough lines in a parent blame"))
+        };
+
+        let mut lines = Vec::new();
+        let mut parent_index = 0;
+        for Hunk { add, remove } in diff_hunks(parent_content, content) {
+            // add unaffected lines
+            while parent_index < remove.start {
+                lines.push(parent_line(parent_index)?);
+                parent_index += 1;
+            }
+
+            // replaced lines are attributed to the line they replaced (or the
+            // last of them, if the hunk grew), and added lines to `csid`
+            let removed = remove.end - remove.start;
+            for index in 0..(add.end - add.start) {
+                if removed > 0 {
+                    lines.push(parent_line(remove.start + index.min(removed - 1))?);
+                } else {
+                    lines.push((csid, path, (add.start + index) as u32));
+                }
+            }
+            parent_index = remove.end;
+        }
+        lines.extend(
+            parent_lines
+                .get(parent_index..)
+                .ok_or_else(|| Error::msg("not enough lines in a parent blame"))?,
+        );
+
+        blame_from_lines(lines)
+    }
+
+    /// Replace the attribution of lines that were introduced by a changeset
+    /// at a path with the attribution of the same line in a different blame
+    /// of that changeset's version of the file.
+    ///
+    /// `replacements` maps `(csid, path)` to the blame that should be used
+    /// for lines attributed to `csid` at `path`.
+    pub fn substitute(
+        &self,
+        replacements: &HashMap<(ChangesetId, MPath), Blame>,
+    ) -> Result<Blame, Error> {
+        let mut lines = Vec::new();
+        for range in self.ranges.iter() {
+            match replacements.get(&(range.csid, range.path.clone())) {
+                None => {
+                    lines.extend(
+                        (0..range.length)
+                            .map(|index| (range.csid, &range.path, range.origin_offset + index)),
+                    );
+                }
+                Some(replacement) => {
+                    let replacement_lines: Vec<_> = replacement
+                        .lines()
+                        .skip(range.origin_offset as usize)
+                        .take(range.length as usize)
+                        .collect();
+                    if replacement_lines.len() != range.length as usize {
+                        bail!("not enough lines in a replacement blame");
+                    }
+                    lines.extend(replacement_lines);
+                }
+            }
+        }
+        blame_from_lines(lines)
+    }
+
     pub fn lines<'a>(&'a self) -> BlameLines<'a> {
         BlameLines::new(&self.ranges)
     }
@@ -492,6 +614,38 @@ fn blame_ranges_split_at(
     return (left, ranges);
 }
 
+/// Convert a list of lines with associated changeset id, path and origin
+/// offset back to a `Blame` object, merging adjacent lines that come from
+/// consecutive lines of the same origin.
+fn blame_from_lines<'a>(
+    lines: impl IntoIterator<Item = (ChangesetId, &'a MPath, u32)>,
+) -> Result<Blame, Error> {
+    let mut offset = 0;
+    let mut ranges: Vec<BlameRange> = Vec::new();
+    for (csid, path, origin_offset) in lines {
+        match ranges.last_mut() {
+            Some(ref mut last)
+                if last.csid == csid
+                    && &last.path == path
+                    && last.origin_offset + last.length == origin_offset =>
+            {
+                last.length += 1;
+            }
+            _ => {
+                ranges.push(BlameRange {
+                    offset,
+                    length: 1,
+                    csid,
+                    path: path.clone(),
+                    origin_offset,
+                });
+            }
+        }
+        offset += 1;
+    }
+    Blame::new(ranges)
+}
+
 /// Merge multiple blames into a single.
 ///
 /// All blames are assumed to be generated by running `blame_single_parent`
@@ -907,4 +1061,73 @@ mod test {
         assert_eq!(b3_reference, b3);
         Ok(())
     }
+
+    #[test]
+    fn test_blame_ignoring() -> Result<(), Error> {
+        let path = MPath::new("path")?;
+
+        let c1 = "one\ntwo\nthree\n";
+        let c2 = "ONE\ntwo\nTHREE\n";
+        let c3 = "ONE\nsix\nTHREE\nfour\n";
+
+        let b1 = Blame::from_parents(ONES_CSID, c1, path.clone(), Vec::new())?;
+        let b2 = Blame::from_parents(TWOS_CSID, c2, path.clone(), vec![(c1, b1.clone())])?;
+        let b3 = Blame::from_parents(THREES_CSID, c3, path.clone(), vec![(c2, b2)])?;
+
+        // looking through the second changeset attributes everything to the first
+        let b2_ignoring =
+            Blame::from_parents_ignoring(TWOS_CSID, c2, path.clone(), vec![(c1, b1.clone())])?;
+        assert_eq!(b1, b2_ignoring);
+
+        let mut replacements = HashMap::new();
+        replacements.insert((TWOS_CSID, path.clone()), b2_ignoring);
+        let b3_ignoring = b3.substitute(&replacements)?;
+
+        let b3_reference = Blame::new(vec![
+            BlameRange {
+                offset: 0,
+                length: 1,
+                csid: ONES_CSID,
+                path: path.clone(),
+                origin_offset: 0,
+            },
+            BlameRange {
+                offset: 1,
+                length: 1,
+                csid: THREES_CSID,
+                path: path.clone(),
+                origin_offset: 1,
+            },
+            BlameRange {
+                offset: 2,
+                length: 1,
+                csid: ONES_CSID,
+                path: path.clone(),
+                origin_offset: 2,
+            },
+            BlameRange {
+                offset: 3,
+                length: 1,
+                csid: THREES_CSID,
+                path: path.clone(),
+                origin_offset: 3,
+            },
+        ])?;
+        assert_eq!(b3_reference, b3_ignoring);
+
+        // lines purely added by an ignored changeset stay attributed to it
+        let c4 = "ONE\nsix\nTHREE\nfour\nfive\n";
+        let b4_ignoring = Blame::from_parents_ignoring(
+            FOURS_CSID,
+            c4,
+            path.clone(),
+            vec![(c3, b3_ignoring.clone())],
+        )?;
+        let mut b4_lines = b4_ignoring.lines();
+        assert_eq!(b4_lines.nth(3), Some((THREES_CSID, &path, 3)));
+        assert_eq!(b4_lines.next(), Some((FOURS_CSID, &path, 4)));
+        assert_eq!(b4_lines.next(), None);
+
+        Ok(())
+    }
 }
@@ -28,6 +28,7 @@ pub mod hash;
 pub mod path;
 pub mod rawbundle2;
 pub mod repo;
+pub mod skeleton_manifest;
 pub mod sql_types;
 pub mod typed_hash;
 pub mod unode;
@@ -47,9 +48,9 @@ pub use path::{check_case_conflicts, MPath, MPathElement, MPathHash, PrefixTrie,
 pub use rawbundle2::RawBundle2;
 pub use repo::{RepositoryId, REPO_PREFIX_REGEX};
 pub use typed_hash::{
-    ChangesetId, ChangesetIdPrefix, ChangesetIdsResolvedFromPrefix, ContentChunkId, ContentId,
-    ContentMetadataId, DeletedManifestId, FastlogBatchId, FileUnodeId, FsnodeId, ManifestUnodeId,
-    MononokeId, RawBundle2Id,
+    verify_blob_id, ChangesetId, ChangesetIdPrefix, ChangesetIdsResolvedFromPrefix, ContentChunkId,
+    ContentId, ContentMetadataId, DeletedManifestId, FastlogBatchId, FileUnodeId, FsnodeId,
+    ManifestUnodeId, MononokeId, RawBundle2Id, SkeletonManifestId,
 };
 
 mod macros;
@@ -0,0 +1,366 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{Context, Result};
+
+use crate::blob::{Blob, BlobstoreValue, SkeletonManifestBlob};
+use crate::errors::ErrorKind;
+use crate::path::MPathElement;
+use crate::thrift;
+use crate::typed_hash::{SkeletonManifestId, SkeletonManifestIdContext};
+
+use fbthrift::compact_protocol;
+use sorted_vector_map::SortedVectorMap;
+use std::collections::{hash_map::Entry, BTreeMap, HashMap};
+
+// A skeleton manifest is a manifest node containing only the structure of
+// the repository: the names of files and directories, and nothing about
+// their contents. This makes them small and cheap to derive, which is useful
+// for answering questions about whether paths exist, or for finding case
+// conflicts, without loading anything heavier.
+//
+// Skeleton manifests only exist for trees, and each skeleton manifest is a
+// structure that contains:
+// * A list of its children, containing for each child:
+//   - Name
+//   - Whether the child is a file or a sub-directory
+//   - The skeleton manifest id and summary for sub-directories
+// * The summary for the directory itself
+//
+// The summary stored for each directory is:
+// * count of immediate child files
+// * count of immediate child sub-directories
+// * recursive count of descendant files
+// * recursive count of descendant sub-directories
+// * length of the longest path below this directory, relative to it
+// * whether any immediate children conflict with each other by case
+// * whether any descendants conflict with each other by case
+//
+// Like fsnodes, skeleton manifests are not repository-wide unique. Any
+// directories with the same structure share a skeleton manifest.
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct SkeletonManifest {
+    subentries: SortedVectorMap<MPathElement, SkeletonManifestEntry>,
+    summary: SkeletonManifestSummary,
+}
+
+impl SkeletonManifest {
+    /// Create a skeleton manifest for a directory, computing its summary
+    /// from its subentries.
+    pub fn new(subentries: SortedVectorMap<MPathElement, SkeletonManifestEntry>) -> Self {
+        let mut summary = SkeletonManifestSummary {
+            child_case_conflicts: first_case_conflict(subentries.keys()).is_some(),
+            ..Default::default()
+        };
+        for (elem, entry) in subentries.iter() {
+            match entry {
+                SkeletonManifestEntry::File => {
+                    summary.child_files_count += 1;
+                    summary.descendant_files_count += 1;
+                    summary.max_path_len = summary.max_path_len.max(elem.len() as u64);
+                }
+                SkeletonManifestEntry::Directory(dir) => {
+                    let subdir_summary = dir.summary();
+                    summary.child_dirs_count += 1;
+                    summary.descendant_files_count += subdir_summary.descendant_files_count;
+                    summary.descendant_dirs_count += subdir_summary.descendant_dirs_count + 1;
+                    // Paths below the sub-directory are joined to its name with a '/'
+                    let path_len = elem.len() as u64
+                        + if subdir_summary.max_path_len > 0 {
+                            subdir_summary.max_path_len + 1
+                        } else {
+                            0
+                        };
+                    summary.max_path_len = summary.max_path_len.max(path_len);
+                    summary.descendant_case_conflicts |= subdir_summary.child_case_conflicts
+                        || subdir_summary.descendant_case_conflicts;
+                }
+            }
+        }
+        Self {
+            subentries,
+            summary,
+        }
+    }
+
+    pub fn lookup(&self, basename: &MPathElement) -> Option<&SkeletonManifestEntry> {
+        self.subentries.get(basename)
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = (&MPathElement, &SkeletonManifestEntry)> {
+        self.subentries.iter()
+    }
+
+    pub fn into_subentries(self) -> SortedVectorMap<MPathElement, SkeletonManifestEntry> {
+        self.subentries
+    }
+
+    pub fn summary(&self) -> &SkeletonManifestSummary {
+        &self.summary
+    }
+
+    /// Returns the first pair of immediate children whose names differ only
+    /// by case, if there are any.
+    pub fn first_case_conflict(&self) -> Option<(&MPathElement, &MPathElement)> {
+        first_case_conflict(self.subentries.keys())
+    }
+
+    /// Returns all immediate children whose names conflict by case with an
+    /// earlier child, paired with that earlier child.
+    pub fn case_conflicts(&self) -> Vec<(&MPathElement, &MPathElement)> {
+        let mut lowercase = HashMap::new();
+        let mut conflicts = Vec::new();
+        for name in self.subentries.keys() {
+            if let Ok(utf8_name) = std::str::from_utf8(name.as_ref()) {
+                match lowercase.entry(utf8_name.to_lowercase()) {
+                    Entry::Occupied(first) => conflicts.push((*first.get(), name)),
+                    Entry::Vacant(vacant) => {
+                        vacant.insert(name);
+                    }
+                }
+            }
+        }
+        conflicts
+    }
+
+    pub(crate) fn from_thrift(t: thrift::SkeletonManifest) -> Result<SkeletonManifest> {
+        let subentries = t
+            .subentries
+            .into_iter()
+            .map(|(basename, entry)| {
+                let basename = MPathElement::from_thrift(basename)?;
+                let entry = SkeletonManifestEntry::from_thrift(entry)?;
+                Ok((basename, entry))
+            })
+            .collect::<Result<_>>()?;
+        let summary = SkeletonManifestSummary::from_thrift(t.summary)?;
+        Ok(SkeletonManifest {
+            subentries,
+            summary,
+        })
+    }
+
+    pub(crate) fn into_thrift(self) -> thrift::SkeletonManifest {
+        let subentries: BTreeMap<_, _> = self
+            .subentries
+            .into_iter()
+            .map(|(basename, entry)| (basename.into_thrift(), entry.into_thrift()))
+            .collect();
+        let summary = self.summary.into_thrift();
+        thrift::SkeletonManifest {
+            subentries,
+            summary,
+        }
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let thrift_tc = compact_protocol::deserialize(bytes)
+            .with_context(|| ErrorKind::BlobDeserializeError("SkeletonManifest".into()))?;
+        Self::from_thrift(thrift_tc)
+    }
+}
+
+// Names that are not valid UTF-8 are never considered to conflict, as in
+// `check_case_conflicts`.
+fn first_case_conflict<'a>(
+    names: impl IntoIterator<Item = &'a MPathElement>,
+) -> Option<(&'a MPathElement, &'a MPathElement)> {
+    let mut lowercase = HashMap::new();
+    for name in names {
+        if let Ok(utf8_name) = std::str::from_utf8(name.as_ref()) {
+            if let Some(other) = lowercase.insert(utf8_name.to_lowercase(), name) {
+                return Some((other, name));
+            }
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum SkeletonManifestEntry {
+    File,
+    Directory(SkeletonManifestDirectory),
+}
+
+impl SkeletonManifestEntry {
+    pub(crate) fn from_thrift(t: thrift::SkeletonManifestEntry) -> Result<SkeletonManifestEntry> {
+        match t.directory {
+            Some(dir) => Ok(SkeletonManifestEntry::Directory(
+                SkeletonManifestDirectory::from_thrift(dir)?,
+            )),
+            None => Ok(SkeletonManifestEntry::File),
+        }
+    }
+
+    pub(crate) fn into_thrift(self) -> thrift::SkeletonManifestEntry {
+        match self {
+            SkeletonManifestEntry::File => thrift::SkeletonManifestEntry { directory: None },
+            SkeletonManifestEntry::Directory(dir) => thrift::SkeletonManifestEntry {
+                directory: Some(dir.into_thrift()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct SkeletonManifestDirectory {
+    id: SkeletonManifestId,
+    summary: SkeletonManifestSummary,
+}
+
+impl SkeletonManifestDirectory {
+    pub fn new(id: SkeletonManifestId, summary: SkeletonManifestSummary) -> Self {
+        Self { id, summary }
+    }
+
+    pub fn id(&self) -> &SkeletonManifestId {
+        &self.id
+    }
+
+    pub fn summary(&self) -> &SkeletonManifestSummary {
+        &self.summary
+    }
+
+    pub(crate) fn from_thrift(
+        t: thrift::SkeletonManifestDirectory,
+    ) -> Result<SkeletonManifestDirectory> {
+        let id = SkeletonManifestId::from_thrift(t.id)?;
+        let summary = SkeletonManifestSummary::from_thrift(t.summary)?;
+        Ok(SkeletonManifestDirectory { id, summary })
+    }
+
+    pub(crate) fn into_thrift(self) -> thrift::SkeletonManifestDirectory {
+        thrift::SkeletonManifestDirectory {
+            id: self.id.into_thrift(),
+            summary: self.summary.into_thrift(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct SkeletonManifestSummary {
+    pub child_files_count: u64,
+    pub child_dirs_count: u64,
+    pub descendant_files_count: u64,
+    pub descendant_dirs_count: u64,
+    pub max_path_len: u64,
+    pub child_case_conflicts: bool,
+    pub descendant_case_conflicts: bool,
+}
+
+impl SkeletonManifestSummary {
+    /// Whether any two paths in or below this directory differ only by case.
+    pub fn has_case_conflicts(&self) -> bool {
+        self.child_case_conflicts || self.descendant_case_conflicts
+    }
+
+    pub(crate) fn from_thrift(
+        t: thrift::SkeletonManifestSummary,
+    ) -> Result<SkeletonManifestSummary> {
+        Ok(SkeletonManifestSummary {
+            child_files_count: t.child_files_count as u64,
+            child_dirs_count: t.child_dirs_count as u64,
+            descendant_files_count: t.descendant_files_count as u64,
+            descendant_dirs_count: t.descendant_dirs_count as u64,
+            max_path_len: t.max_path_len as u64,
+            child_case_conflicts: t.child_case_conflicts,
+            descendant_case_conflicts: t.descendant_case_conflicts,
+        })
+    }
+
+    pub(crate) fn into_thrift(self) -> thrift::SkeletonManifestSummary {
+        thrift::SkeletonManifestSummary {
+            child_files_count: self.child_files_count as i64,
+            child_dirs_count: self.child_dirs_count as i64,
+            descendant_files_count: self.descendant_files_count as i64,
+            descendant_dirs_count: self.descendant_dirs_count as i64,
+            max_path_len: self.max_path_len as i64,
+            child_case_conflicts: self.child_case_conflicts,
+            descendant_case_conflicts: self.descendant_case_conflicts,
+        }
+    }
+}
+
+impl BlobstoreValue for SkeletonManifest {
+    type Key = SkeletonManifestId;
+
+    fn into_blob(self) -> SkeletonManifestBlob {
+        let thrift = self.into_thrift();
+        let data = compact_protocol::serialize(&thrift);
+        let mut context = SkeletonManifestIdContext::new();
+        context.update(&data);
+        let id = context.finish();
+        Blob::new(id, data)
+    }
+
+    fn from_blob(blob: Blob<Self::Key>) -> Result<Self> {
+        Self::from_bytes(blob.data().as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn elem(name: &str) -> MPathElement {
+        MPathElement::new(name.as_bytes().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_summary() {
+        let subdir = SkeletonManifest::new(
+            vec![
+                (elem("File"), SkeletonManifestEntry::File),
+                (elem("file"), SkeletonManifestEntry::File),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(
+            subdir.first_case_conflict(),
+            Some((&elem("File"), &elem("file")))
+        );
+        let subdir_id = *subdir.clone().into_blob().id();
+
+        let root = SkeletonManifest::new(
+            vec![
+                (elem("a"), SkeletonManifestEntry::File),
+                (
+                    elem("dir"),
+                    SkeletonManifestEntry::Directory(SkeletonManifestDirectory::new(
+                        subdir_id,
+                        subdir.summary().clone(),
+                    )),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        assert_eq!(
+            subdir.case_conflicts(),
+            vec![(&elem("File"), &elem("file"))]
+        );
+        assert_eq!(root.first_case_conflict(), None);
+        assert_eq!(
+            root.summary(),
+            &SkeletonManifestSummary {
+                child_files_count: 1,
+                child_dirs_count: 1,
+                descendant_files_count: 3,
+                descendant_dirs_count: 1,
+                max_path_len: "dir/File".len() as u64,
+                child_case_conflicts: false,
+                descendant_case_conflicts: true,
+            }
+        );
+        assert!(root.summary().has_case_conflicts());
+
+        let blob = root.clone().into_blob();
+        assert_eq!(SkeletonManifest::from_blob(blob).unwrap(), root);
+    }
+}
@@ -12,6 +12,7 @@ use abomonation_derive::Abomonation;
 use anyhow::{bail, Error, Result};
 use ascii::{AsciiStr, AsciiString};
 use blobstore::{Blobstore, Loadable, LoadableError, Storable};
+use bytes::Bytes;
 use context::CoreContext;
 use futures::future::{BoxFuture, FutureExt};
 use quickcheck::{empty_shrinker, Arbitrary, Gen};
@@ -29,6 +30,7 @@ use crate::{
     fsnode::Fsnode,
     hash::{Blake2, Blake2Prefix, Context},
     rawbundle2::RawBundle2,
+    skeleton_manifest::SkeletonManifest,
     thrift,
     unode::{FileUnode, ManifestUnode},
 };
@@ -105,6 +107,10 @@ pub struct DeletedManifestId(Blake2);
 #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
 pub struct FsnodeId(Blake2);
 
+/// An identifier for a skeleton manifest
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
+pub struct SkeletonManifestId(Blake2);
+
 #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug, Hash)]
 pub struct FastlogBatchId(Blake2);
 
@@ -421,6 +427,13 @@ impl_typed_hash! {
     context_key => "fsnode",
 }
 
+impl_typed_hash! {
+    hash_type => SkeletonManifestId,
+    value_type => SkeletonManifest,
+    context_type => SkeletonManifestIdContext,
+    context_key => "skeletonmanifest",
+}
+
 impl_typed_hash_no_context! {
     hash_type => ContentMetadataId,
     value_type => ContentMetadata,
@@ -504,6 +517,46 @@ impl Display for ChangesetIdPrefix {
     }
 }
 
+macro_rules! hash_with_context {
+    ($context:ident, $data:expr) => {{
+        let mut context = $context::new();
+        context.update($data);
+        *context.finish().blake2()
+    }};
+}
+
+/// Check that the blob `data` stored under blobstore `key` hashes to the id in the key. Keys may
+/// have a repo prefix. Returns `None` when the id can't be checked from the blob alone: for keys
+/// that aren't typed hashes, and for chunked file contents, whose id is the hash of the content
+/// of all of their chunks.
+pub fn verify_blob_id(key: &str, data: &Bytes) -> Option<bool> {
+    let mut parts = key.rsplitn(3, '.');
+    let id = Blake2::from_str(parts.next()?).ok()?;
+    if parts.next()? != "blake2" {
+        return None;
+    }
+    let actual = match parts.next()?.rsplit('.').next()? {
+        "changeset" => hash_with_context!(ChangesetIdContext, data),
+        "fileunode" => hash_with_context!(FileUnodeIdContext, data),
+        "manifestunode" => hash_with_context!(ManifestUnodeIdContext, data),
+        "deletedmanifest" => hash_with_context!(DeletedManifestContext, data),
+        "fsnode" => hash_with_context!(FsnodeIdContext, data),
+        "skeletonmanifest" => hash_with_context!(SkeletonManifestIdContext, data),
+        "fastlogbatch" => hash_with_context!(FastlogBatchIdContext, data),
+        "content" => match FileContents::from_encoded_bytes(data.clone()) {
+            Ok(FileContents::Bytes(bytes)) => *FileContents::content_id_for_bytes(&bytes).blake2(),
+            Ok(FileContents::Chunked(_)) => return None,
+            Err(_) => return Some(false),
+        },
+        "chunk" => match ContentChunk::from_encoded_bytes(data.clone()) {
+            Ok(chunk) => hash_with_context!(ContentChunkIdContext, chunk.into_bytes()),
+            Err(_) => return Some(false),
+        },
+        _ => return None,
+    };
+    Some(actual == id)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -553,6 +606,12 @@ mod test {
         let id = FsnodeId::from_byte_array([1; 32]);
         assert_eq!(id.blobstore_key(), format!("fsnode.blake2.{}", id));
 
+        let id = SkeletonManifestId::from_byte_array([1; 32]);
+        assert_eq!(
+            id.blobstore_key(),
+            format!("skeletonmanifest.blake2.{}", id)
+        );
+
         let id = ContentMetadataId::from_byte_array([1; 32]);
         assert_eq!(
             id.blobstore_key(),
@@ -563,6 +622,32 @@ mod test {
         assert_eq!(id.blobstore_key(), format!("fastlogbatch.blake2.{}", id));
     }
 
+    #[test]
+    fn test_verify_blob_id() {
+        let blob = FileContents::new_bytes("hello world").into_blob();
+        let key = format!("repo0000.{}", blob.id().blobstore_key());
+        assert_eq!(verify_blob_id(&key, blob.data()), Some(true));
+        assert_eq!(
+            verify_blob_id(&key, &FileContents::new_bytes("hello").into_blob().data()),
+            Some(false)
+        );
+        assert_eq!(
+            verify_blob_id(&key, &Bytes::from("not thrift")),
+            Some(false)
+        );
+
+        let blob = ContentChunk::new_bytes("chunk").into_blob();
+        assert_eq!(
+            verify_blob_id(&blob.id().blobstore_key(), blob.data()),
+            Some(true)
+        );
+
+        // Keys that aren't typed hashes can't be checked
+        let alias = "repo0000.alias.sha256.0000000000000000000000000000000000000000000000000000000000000000";
+        assert_eq!(verify_blob_id(alias, blob.data()), None);
+        assert_eq!(verify_blob_id("bookmark", blob.data()), None);
+    }
+
     #[test]
     fn test_serialize_deserialize() {
         let id = ChangesetId::new(Blake2::from_byte_array([1; 32]));
@@ -1594,6 +1594,7 @@ impl HgCommands for RepoClient {
                         let lca_hint = client.repo.lca_hint().clone();
                         let infinitepush_params = client.repo.infinitepush().clone();
                         let infinitepush_writes_allowed = infinitepush_params.allow_writes;
+                        let infinitepush_bundle_ttl = infinitepush_params.bundle_ttl;
                         let pushrebase_params = client.repo.pushrebase_params().clone();
                         let push_params = client.repo.push_params().clone();
                         let pure_push_allowed = push_params.pure_push_allowed;
@@ -1604,6 +1605,7 @@ impl HgCommands for RepoClient {
                             &ctx,
                             &blobrepo,
                             infinitepush_writes_allowed,
+                            infinitepush_bundle_ttl,
                             stream,
                             read_write,
                             maybe_full_content,
@@ -47,13 +47,16 @@ use mercurial_types::{
     HgChangesetId, HgNodeKey, RepoPath,
 };
 use metaconfig_types::{PushrebaseFlags, RepoReadOnly};
-use mononoke_types::{BlobstoreValue, BonsaiChangeset, ChangesetId, RawBundle2, RawBundle2Id};
+use mononoke_types::{
+    BlobstoreValue, BonsaiChangeset, ChangesetId, MononokeId, RawBundle2, RawBundle2Id,
+};
 use pushrebase::HgReplayData;
 use scuba_ext::ScubaSampleBuilderExt;
 use slog::{debug, trace};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use topo_sort::sort_topological;
 use wirepack::{TreemanifestBundle2Parser, TreemanifestEntry};
 
@@ -259,6 +262,7 @@ pub async fn resolve<'a>(
     ctx: &'a CoreContext,
     repo: &'a BlobRepo,
     infinitepush_writes_allowed: bool,
+    infinitepush_bundle_ttl: Option<Duration>,
     bundle2: OldBoxStream<Bundle2Item, Error>,
     readonly: RepoReadOnly,
     maybe_full_content: Option<Arc<Mutex<BytesOld>>>,
@@ -266,7 +270,13 @@ pub async fn resolve<'a>(
     pushrebase_flags: PushrebaseFlags,
 ) -> Result<(PostResolveAction, bool), BundleResolverError> {
     UNBUNDLE_STATS::total_unbundles.add_value(1, (repo.name().to_string(),));
-    let resolver = Bundle2Resolver::new(ctx, repo, infinitepush_writes_allowed, pushrebase_flags);
+    let resolver = Bundle2Resolver::new(
+        ctx,
+        repo,
+        infinitepush_writes_allowed,
+        infinitepush_bundle_ttl,
+        pushrebase_flags,
+    );
     let (stream_header, bundle2) = resolver.resolve_stream_params(bundle2).await?;
     let bundle2 = resolver.resolve_replycaps(bundle2).await?;
 
@@ -454,8 +464,14 @@ async fn resolve_push<'r>(
         .maybe_resolve_infinitepush_bookmarks(bundle2)
         .await
         .context("While resolving B2xInfinitepushBookmarks")?;
+    // Infinitepush bundles are only kept until they have been replayed
+    let bundle_ttl = if is_infinitepush {
+        resolver.infinitepush_bundle_ttl
+    } else {
+        None
+    };
     let maybe_raw_bundle2_id = resolver
-        .ensure_stream_finished(bundle2, maybe_full_content)
+        .ensure_stream_finished(bundle2, maybe_full_content, bundle_ttl)
         .await?;
 
     let maybe_bonsai_bookmark_push = match maybe_hg_bookmark_push {
@@ -665,7 +681,7 @@ async fn resolve_pushrebase<'r>(
     };
 
     let maybe_raw_bundle2_id = resolver
-        .ensure_stream_finished(bundle2, maybe_full_content)
+        .ensure_stream_finished(bundle2, maybe_full_content, None)
         .await?;
     let bookmark_spec =
         hg_pushrebase_bookmark_spec_to_bonsai(ctx, &resolver.repo, bookmark_spec).await?;
@@ -720,7 +736,7 @@ async fn resolve_bookmark_only_pushrebase<'r>(
 
     let bookmark_push = bookmark_pushes.into_iter().next().unwrap();
     let maybe_raw_bundle2_id = resolver
-        .ensure_stream_finished(bundle2, maybe_full_content)
+        .ensure_stream_finished(bundle2, maybe_full_content, None)
         .await?;
     let bookmark_push =
         plain_hg_bookmark_push_to_bonsai(ctx, &resolver.repo, bookmark_push).await?;
@@ -806,6 +822,7 @@ pub struct Bundle2Resolver<'r> {
     ctx: &'r CoreContext,
     repo: &'r BlobRepo,
     infinitepush_writes_allowed: bool,
+    infinitepush_bundle_ttl: Option<Duration>,
     pushrebase_flags: PushrebaseFlags,
 }
 
@@ -814,12 +831,14 @@ impl<'r> Bundle2Resolver<'r> {
         ctx: &'r CoreContext,
         repo: &'r BlobRepo,
         infinitepush_writes_allowed: bool,
+        infinitepush_bundle_ttl: Option<Duration>,
         pushrebase_flags: PushrebaseFlags,
     ) -> Self {
         Self {
             ctx,
             repo,
             infinitepush_writes_allowed,
+            infinitepush_bundle_ttl,
             pushrebase_flags,
         }
     }
@@ -848,17 +867,29 @@ impl<'r> Bundle2Resolver<'r> {
         }
     }
 
-    /// Preserve the full raw content of the bundle2 for later replay
+    /// Preserve the full raw content of the bundle2 for later replay. With a TTL, the content
+    /// expires once the TTL has passed.
     async fn maybe_save_full_content_bundle2(
         &self,
         maybe_full_content: Option<Arc<Mutex<BytesOld>>>,
+        ttl: Option<Duration>,
     ) -> Result<Option<RawBundle2Id>, Error> {
         match maybe_full_content {
             Some(full_content) => {
                 let blob =
                     RawBundle2::new_bytes(Bytes::copy_from_slice(&full_content.lock().unwrap()))
                         .into_blob();
-                let id = blob.store(self.ctx.clone(), self.repo.blobstore()).await?;
+                let id = match ttl {
+                    Some(ttl) => {
+                        let id = *blob.id();
+                        self.repo
+                            .blobstore()
+                            .put_with_ttl(self.ctx.clone(), id.blobstore_key(), blob.into(), ttl)
+                            .await?;
+                        id
+                    }
+                    None => blob.store(self.ctx.clone(), self.repo.blobstore()).await?,
+                };
                 debug!(self.ctx.logger(), "Saved a raw bundle2 content: {:?}", id);
                 self.ctx
                     .scuba()
@@ -1250,10 +1281,11 @@ impl<'r> Bundle2Resolver<'r> {
         &self,
         bundle2: OldBoxStream<Bundle2Item, Error>,
         maybe_full_content: Option<Arc<Mutex<BytesOld>>>,
+        bundle_ttl: Option<Duration>,
     ) -> Result<Option<RawBundle2Id>, Error> {
         let (none, _bundle2) = next_item(bundle2).await?;
         ensure!(none.is_none(), "Expected end of Bundle2");
-        self.maybe_save_full_content_bundle2(maybe_full_content)
+        self.maybe_save_full_content_bundle2(maybe_full_content, bundle_ttl)
             .await
     }
 
@@ -26,7 +26,7 @@ mod tests {
     use derived_data_utils::derived_data_utils;
     use fbinit::FacebookInit;
     use futures::{compat::Future01CompatExt, stream::TryStreamExt};
-    use git_types::TreeHandle;
+    use git_types::{CommitHandle, TreeHandle};
     use live_commit_sync_config::{
         CfgrLiveCommitSyncConfig, LiveCommitSyncConfig, TestLiveCommitSyncConfig,
         CONFIGERATOR_ALL_COMMIT_SYNC_CONFIGS, CONFIGERATOR_CURRENT_COMMIT_SYNC_CONFIGS,
@@ -75,6 +75,9 @@ mod tests {
                 .derived_data_types
                 .remove(&TreeHandle::NAME.to_string());
             derived_data_config
+                .derived_data_types
+                .remove(&CommitHandle::NAME.to_string());
+            derived_data_config
         });
         Ok(repo)
     }
@@ -106,6 +106,8 @@ impl_into_thrift_error!(service::RepoCreateBookmarkExn);
 impl_into_thrift_error!(service::RepoMoveBookmarkExn);
 impl_into_thrift_error!(service::RepoDeleteBookmarkExn);
 impl_into_thrift_error!(service::RepoLandStackExn);
+impl_into_thrift_error!(service::RepoRebaseStackExn);
+impl_into_thrift_error!(service::RepoCompareCommitsExn);
 impl_into_thrift_error!(service::RepoStackInfoExn);
 impl_into_thrift_error!(service::CommitCommonBaseWithExn);
 impl_into_thrift_error!(service::CommitFileDiffsExn);
@@ -114,6 +116,9 @@ impl_into_thrift_error!(service::CommitInfoExn);
 impl_into_thrift_error!(service::CommitCompareExn);
 impl_into_thrift_error!(service::CommitIsAncestorOfExn);
 impl_into_thrift_error!(service::CommitFindFilesExn);
+impl_into_thrift_error!(service::CommitGrepExn);
+impl_into_thrift_error!(service::CommitArchiveChunkExn);
+impl_into_thrift_error!(service::CommitCherryPickExn);
 impl_into_thrift_error!(service::CommitHistoryExn);
 impl_into_thrift_error!(service::CommitListDescendantBookmarksExn);
 impl_into_thrift_error!(service::CommitPathInfoExn);
@@ -14,7 +14,7 @@ use chrono::{DateTime, FixedOffset, TimeZone};
 use faster_hex::hex_string;
 use mononoke_api::specifiers::{GitSha1, Globalrev};
 use mononoke_api::{
-    BookmarkName, CandidateSelectionHintArgs, ChangesetId, ChangesetIdPrefix,
+    ArchiveFormat, BookmarkName, CandidateSelectionHintArgs, ChangesetId, ChangesetIdPrefix,
     ChangesetPrefixSpecifier, ChangesetSpecifier, CopyInfo, CreateCopyInfo, FileId, FileType,
     HgChangesetId, HgChangesetIdPrefix, MononokePath, TreeId,
 };
@@ -220,6 +220,21 @@ impl FromRequest<thrift::RepoCreateCommitParamsFileType> for FileType {
     }
 }
 
+impl FromRequest<thrift::ArchiveFormat> for ArchiveFormat {
+    fn from_request(format: &thrift::ArchiveFormat) -> Result<Self, thrift::RequestError> {
+        match format {
+            &thrift::ArchiveFormat::TAR => Ok(ArchiveFormat::Tar),
+            &thrift::ArchiveFormat::TAR_GZIP => Ok(ArchiveFormat::TarGzip),
+            &thrift::ArchiveFormat::TAR_ZSTD => Ok(ArchiveFormat::TarZstd),
+            &thrift::ArchiveFormat::ZIP => Ok(ArchiveFormat::Zip),
+            &val => Err(errors::invalid_request(format!(
+                "unsupported archive format ({})",
+                val
+            ))),
+        }
+    }
+}
+
 impl FromRequest<thrift::RepoCreateCommitParamsFileCopyInfo> for CreateCopyInfo {
     fn from_request(
         copy_info: &thrift::RepoCreateCommitParamsFileCopyInfo,
@@ -13,8 +13,10 @@ use futures::try_join;
 use itertools::Itertools;
 use maplit::btreemap;
 use mononoke_api::{
-    ChangesetContext, ChangesetId, ChangesetPathContext, FileMetadata, FileType, MononokeError,
-    PushrebaseOutcome, RepoContext, TreeEntry, TreeId, TreeSummary, UnifiedDiff,
+    ChangesetContext, ChangesetDiffstat, ChangesetId, ChangesetPathContext, CherryPickOutcome,
+    CommitComparison, ConflictHunk, FileClassification, FileDiffstat, FileMetadata, FileType,
+    GrepMatch, LineEndings, MergeConflict, MergeConflictKind, MononokeError, PushrebaseOutcome,
+    RebaseOutcome, RepoContext, TextEncoding, TreeEntry, TreeId, TreeSummary, UnifiedDiff,
 };
 use source_control as thrift;
 use std::collections::{BTreeMap, BTreeSet};
@@ -59,6 +61,79 @@ impl IntoResponse<thrift::EntryType> for FileType {
     }
 }
 
+impl IntoResponse<thrift::FileDiffstat> for FileDiffstat {
+    fn into_response(self) -> thrift::FileDiffstat {
+        thrift::FileDiffstat {
+            path: self.path.to_string(),
+            lines_added: self.lines_added as i64,
+            lines_removed: self.lines_removed as i64,
+            is_binary: self.is_binary,
+        }
+    }
+}
+
+impl IntoResponse<thrift::CommitDiffstat> for ChangesetDiffstat {
+    fn into_response(self) -> thrift::CommitDiffstat {
+        thrift::CommitDiffstat {
+            files_changed: self.files_changed() as i64,
+            lines_added: self.lines_added() as i64,
+            lines_removed: self.lines_removed() as i64,
+            files: self
+                .files()
+                .iter()
+                .cloned()
+                .map(IntoResponse::into_response)
+                .collect(),
+        }
+    }
+}
+
+impl IntoResponse<thrift::TextEncoding> for TextEncoding {
+    fn into_response(self) -> thrift::TextEncoding {
+        match self {
+            TextEncoding::Binary => thrift::TextEncoding::BINARY,
+            TextEncoding::Ascii => thrift::TextEncoding::ASCII,
+            TextEncoding::Utf8 => thrift::TextEncoding::UTF8,
+            TextEncoding::Utf16 => thrift::TextEncoding::UTF16,
+            TextEncoding::Other => thrift::TextEncoding::OTHER,
+        }
+    }
+}
+
+impl IntoResponse<thrift::LineEndings> for LineEndings {
+    fn into_response(self) -> thrift::LineEndings {
+        match self {
+            LineEndings::None => thrift::LineEndings::NONE,
+            LineEndings::Lf => thrift::LineEndings::LF,
+            LineEndings::Crlf => thrift::LineEndings::CRLF,
+            LineEndings::Mixed => thrift::LineEndings::MIXED,
+        }
+    }
+}
+
+impl IntoResponse<thrift::FileClassification> for FileClassification {
+    fn into_response(self) -> thrift::FileClassification {
+        thrift::FileClassification {
+            mime_type: self.mime_type().to_string(),
+            encoding: self.encoding().into_response(),
+            line_endings: self.line_endings().into_response(),
+            is_binary: self.is_binary(),
+            is_generated: self.is_generated(),
+            shebang_language: self.shebang_language().map(String::from),
+        }
+    }
+}
+
+impl IntoResponse<thrift::GrepMatch> for GrepMatch {
+    fn into_response(self) -> thrift::GrepMatch {
+        thrift::GrepMatch {
+            path: self.path.to_string(),
+            line_number: self.line_number as i64,
+            line: self.line,
+        }
+    }
+}
+
 impl IntoResponse<thrift::TreeEntry> for (String, TreeEntry) {
     fn into_response(self) -> thrift::TreeEntry {
         let (name, entry) = self;
@@ -83,6 +158,7 @@ impl IntoResponse<thrift::TreeEntry> for (String, TreeEntry) {
                     file_size: file.size() as i64,
                     content_sha1: file.content_sha1().as_ref().to_vec(),
                     content_sha256: file.content_sha256().as_ref().to_vec(),
+                    classification: None,
                 };
                 (
                     file.file_type().into_response(),
@@ -101,6 +177,7 @@ impl IntoResponse<thrift::FileInfo> for FileMetadata {
             file_size: self.total_size as i64,
             content_sha1: self.sha1.as_ref().to_vec(),
             content_sha256: self.sha256.as_ref().to_vec(),
+            classification: None,
         }
     }
 }
@@ -221,6 +298,7 @@ impl AsyncIntoResponseWith<thrift::CommitInfo> for ChangesetContext {
             parents,
             extra: extra.into_iter().collect(),
             generation: generation.value() as i64,
+            diffstat: None,
         })
     }
 }
@@ -274,6 +352,22 @@ impl AsyncIntoResponseWith<Vec<BTreeMap<thrift::CommitIdentityScheme, thrift::Co
     }
 }
 
+/// Get the identities of a commit from a map fetched by
+/// `map_commit_identities`.  If the look-up couldn't be performed, then just
+/// return the bonsai ID only.
+fn try_get(
+    map: &BTreeMap<ChangesetId, BTreeMap<thrift::CommitIdentityScheme, thrift::CommitId>>,
+    cs_id: ChangesetId,
+) -> BTreeMap<thrift::CommitIdentityScheme, thrift::CommitId> {
+    match map.get(&cs_id) {
+        Some(ids) => ids.clone(),
+        None => btreemap! {
+            thrift::CommitIdentityScheme::BONSAI =>
+                thrift::CommitId::bonsai(cs_id.as_ref().into()),
+        },
+    }
+}
+
 #[async_trait]
 impl AsyncIntoResponseWith<thrift::PushrebaseOutcome> for PushrebaseOutcome {
     /// The additional data is the repo context, the set of commit identity
@@ -303,21 +397,7 @@ impl AsyncIntoResponseWith<thrift::PushrebaseOutcome> for PushrebaseOutcome {
             map_commit_identities(&repo, new_ids.into_iter().collect(), &identity_schemes),
         )?;
 
-        // Map IDs using one of the maps we just fetched.  If we couldn't
-        // perform the look-up then just return the bonsai ID only.
-        fn try_get(
-            map: &BTreeMap<ChangesetId, BTreeMap<thrift::CommitIdentityScheme, thrift::CommitId>>,
-            cs_id: ChangesetId,
-        ) -> BTreeMap<thrift::CommitIdentityScheme, thrift::CommitId> {
-            match map.get(&cs_id) {
-                Some(ids) => ids.clone(),
-                None => btreemap! {
-                    thrift::CommitIdentityScheme::BONSAI =>
-                        thrift::CommitId::bonsai(cs_id.as_ref().into()),
-                },
-            }
-        }
-
+        // Map IDs using one of the maps we just fetched.
         let head = try_get(&new_id_map, self.head);
         let rebased_commits: Vec<_> = self
             .rebased_changesets
@@ -335,3 +415,159 @@ impl AsyncIntoResponseWith<thrift::PushrebaseOutcome> for PushrebaseOutcome {
         })
     }
 }
+
+impl IntoResponse<thrift::MergeConflictHunk> for ConflictHunk {
+    fn into_response(self) -> thrift::MergeConflictHunk {
+        thrift::MergeConflictHunk {
+            base_start: self.base.start as i64,
+            base_count: self.base.len() as i64,
+            local_start: self.local.start as i64,
+            local_count: self.local.len() as i64,
+            other_start: self.other.start as i64,
+            other_count: self.other.len() as i64,
+        }
+    }
+}
+
+impl IntoResponse<thrift::MergeConflict> for MergeConflict {
+    fn into_response(self) -> thrift::MergeConflict {
+        let (kind, hunks) = match self.kind {
+            MergeConflictKind::Content(hunks) => (
+                thrift::MergeConflictKind::CONTENT,
+                hunks.into_iter().map(IntoResponse::into_response).collect(),
+            ),
+            MergeConflictKind::Binary => (thrift::MergeConflictKind::BINARY, Vec::new()),
+            MergeConflictKind::ModifyDelete => {
+                (thrift::MergeConflictKind::MODIFY_DELETE, Vec::new())
+            }
+            MergeConflictKind::FileType => (thrift::MergeConflictKind::FILE_TYPE, Vec::new()),
+            MergeConflictKind::FileDirectory => {
+                (thrift::MergeConflictKind::FILE_DIRECTORY, Vec::new())
+            }
+        };
+        thrift::MergeConflict {
+            path: self.path.to_string(),
+            kind,
+            hunks,
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncIntoResponseWith<thrift::RepoCompareCommitsResponse> for CommitComparison {
+    /// The additional data is the repo context and the set of commit
+    /// identity schemes to be returned in the response.
+    type Additional = (RepoContext, BTreeSet<thrift::CommitIdentityScheme>);
+
+    async fn into_response_with(
+        self,
+        additional: &Self::Additional,
+    ) -> Result<thrift::RepoCompareCommitsResponse, errors::ServiceError> {
+        let (repo, identity_schemes) = additional;
+        let ids = self
+            .merge_base
+            .iter()
+            .chain(self.only_in_a.iter())
+            .chain(self.only_in_b.iter())
+            .copied()
+            .collect();
+        let id_map = map_commit_identities(&repo, ids, &identity_schemes).await?;
+        Ok(thrift::RepoCompareCommitsResponse {
+            merge_base: self.merge_base.map(|cs_id| try_get(&id_map, cs_id)),
+            only_in_a: self
+                .only_in_a
+                .into_iter()
+                .map(|cs_id| try_get(&id_map, cs_id))
+                .collect(),
+            only_in_a_count: self.only_in_a_count as i64,
+            only_in_a_count_is_lower_bound: self.only_in_a_count_is_lower_bound,
+            only_in_b: self
+                .only_in_b
+                .into_iter()
+                .map(|cs_id| try_get(&id_map, cs_id))
+                .collect(),
+            only_in_b_count: self.only_in_b_count as i64,
+            only_in_b_count_is_lower_bound: self.only_in_b_count_is_lower_bound,
+        })
+    }
+}
+
+#[async_trait]
+impl AsyncIntoResponseWith<thrift::RepoRebaseStackResponse> for RebaseOutcome {
+    /// The additional data is the repo context and the set of commit
+    /// identity schemes to be returned in the response.
+    type Additional = (RepoContext, BTreeSet<thrift::CommitIdentityScheme>);
+
+    async fn into_response_with(
+        self,
+        additional: &Self::Additional,
+    ) -> Result<thrift::RepoRebaseStackResponse, errors::ServiceError> {
+        let (repo, identity_schemes) = additional;
+        match self {
+            RebaseOutcome::Rebased(rebased_changesets) => {
+                let ids = rebased_changesets
+                    .iter()
+                    .flat_map(|rebase| vec![rebase.id_old, rebase.id_new])
+                    .collect();
+                let id_map = map_commit_identities(&repo, ids, &identity_schemes).await?;
+                let rebased_commits = rebased_changesets
+                    .iter()
+                    .map(|rebase| thrift::PushrebaseRebasedCommit {
+                        old_ids: try_get(&id_map, rebase.id_old),
+                        new_ids: try_get(&id_map, rebase.id_new),
+                    })
+                    .collect();
+                Ok(thrift::RepoRebaseStackResponse {
+                    rebased_commits,
+                    conflicting_commit: None,
+                    conflicts: Vec::new(),
+                })
+            }
+            RebaseOutcome::Conflicts {
+                changeset,
+                conflicts,
+            } => {
+                let id_map =
+                    map_commit_identities(&repo, vec![changeset], &identity_schemes).await?;
+                Ok(thrift::RepoRebaseStackResponse {
+                    rebased_commits: Vec::new(),
+                    conflicting_commit: Some(try_get(&id_map, changeset)),
+                    conflicts: conflicts
+                        .into_iter()
+                        .map(IntoResponse::into_response)
+                        .collect(),
+                })
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncIntoResponseWith<thrift::CommitCherryPickResponse> for CherryPickOutcome {
+    /// The additional data is the repo context and the set of commit
+    /// identity schemes to be returned in the response.
+    type Additional = (RepoContext, BTreeSet<thrift::CommitIdentityScheme>);
+
+    async fn into_response_with(
+        self,
+        additional: &Self::Additional,
+    ) -> Result<thrift::CommitCherryPickResponse, errors::ServiceError> {
+        let (repo, identity_schemes) = additional;
+        match self {
+            CherryPickOutcome::Picked(cs_id) => {
+                let id_map = map_commit_identities(&repo, vec![cs_id], &identity_schemes).await?;
+                Ok(thrift::CommitCherryPickResponse {
+                    ids: Some(try_get(&id_map, cs_id)),
+                    conflicts: Vec::new(),
+                })
+            }
+            CherryPickOutcome::Conflicts(conflicts) => Ok(thrift::CommitCherryPickResponse {
+                ids: None,
+                conflicts: conflicts
+                    .into_iter()
+                    .map(IntoResponse::into_response)
+                    .collect(),
+            }),
+        }
+    }
+}
@@ -15,9 +15,10 @@ use futures::{future, try_join};
 use itertools::{Either, Itertools};
 use maplit::btreeset;
 use mononoke_api::{
-    unified_diff, CandidateSelectionHintArgs, ChangesetContext, ChangesetDiffItem,
-    ChangesetHistoryOptions, ChangesetId, ChangesetPathDiffContext, ChangesetSpecifier, CopyInfo,
-    MononokeError, MononokePath, UnifiedDiffMode,
+    unified_diff, ArchiveCursor, ArchiveFormat, CandidateSelectionHintArgs, ChangesetContext,
+    ChangesetDiffItem, ChangesetGrepOptions, ChangesetHistoryOptions, ChangesetId,
+    ChangesetPathDiffContext, ChangesetSpecifier, CopyInfo, FindFilesFilter, MononokeError,
+    MononokePath, UnifiedDiffMode,
 };
 use source_control as thrift;
 
@@ -32,6 +33,26 @@ use crate::specifiers::SpecifierExt;
 // Magic number used when we want to limit concurrency with buffer_unordered.
 const CONCURRENCY_LIMIT: usize = 100;
 
+/// Parse the path prefixes of a request that restricts which files it
+/// applies to.
+fn prefixes_from_request(
+    prefixes: Option<Vec<String>>,
+) -> Result<Option<Vec<MononokePath>>, errors::ServiceError> {
+    match prefixes {
+        Some(prefixes) => Ok(Some(
+            prefixes
+                .into_iter()
+                .map(|prefix| {
+                    MononokePath::try_from(&prefix).map_err(|e| {
+                        errors::invalid_request(format!("invalid prefix '{}': {}", prefix, e))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        )),
+        None => Ok(None),
+    }
+}
+
 enum CommitComparePath {
     File(thrift::CommitCompareFile),
     Tree(thrift::CommitCompareTree),
@@ -278,7 +299,16 @@ impl SourceControlServiceImpl {
         params: thrift::CommitInfoParams,
     ) -> Result<thrift::CommitInfo, errors::ServiceError> {
         let (_repo, changeset) = self.repo_changeset(ctx, &commit).await?;
-        changeset.into_response_with(&params.identity_schemes).await
+        let diffstat = if params.include_diffstat {
+            Some(changeset.diffstat().await?.into_response())
+        } else {
+            None
+        };
+        let mut info: thrift::CommitInfo = changeset
+            .into_response_with(&params.identity_schemes)
+            .await?;
+        info.diffstat = diffstat;
+        Ok(info)
     }
 
     /// Returns `true` if this commit is an ancestor of `other_commit`.
@@ -408,22 +438,15 @@ impl SourceControlServiceImpl {
             params.limit,
             0..=source_control::COMMIT_FIND_FILES_MAX_LIMIT,
         )?;
-        let prefixes: Option<Vec<_>> = match params.prefixes {
-            Some(prefixes) => Some(
-                prefixes
-                    .into_iter()
-                    .map(|prefix| {
-                        MononokePath::try_from(&prefix).map_err(|e| {
-                            errors::invalid_request(format!("invalid prefix '{}': {}", prefix, e))
-                        })
-                    })
-                    .collect::<Result<Vec<_>, _>>()?,
-            ),
-            None => None,
+        let prefixes = prefixes_from_request(params.prefixes)?;
+        let filter = FindFilesFilter {
+            languages: params.languages,
+            exclude_generated: params.exclude_generated,
+            exclude_vendored: params.exclude_vendored,
+            exclude_binary: params.exclude_binary,
         };
-
         let files: Vec<_> = changeset
-            .find_files(prefixes, params.basenames)
+            .find_files_filtered(prefixes, params.basenames, filter)
             .await?
             .take(limit)
             .map_ok(|path| path.to_string())
@@ -432,6 +455,102 @@ impl SourceControlServiceImpl {
         Ok(thrift::CommitFindFilesResponse { files })
     }
 
+    /// Search the contents of the files in a commit for lines matching a
+    /// regular expression.
+    pub(crate) async fn commit_grep(
+        &self,
+        ctx: CoreContext,
+        commit: thrift::CommitSpecifier,
+        params: thrift::CommitGrepParams,
+    ) -> Result<thrift::CommitGrepResponse, errors::ServiceError> {
+        let (_repo, changeset) = self.repo_changeset(ctx, &commit).await?;
+        let limit: usize = check_range_and_convert(
+            "limit",
+            params.limit,
+            0..=source_control::COMMIT_GREP_MAX_LIMIT,
+        )?;
+        let max_matches_per_file = match params.max_matches_per_file {
+            Some(max) => Some(check_range_and_convert(
+                "max_matches_per_file",
+                max,
+                1..=source_control::COMMIT_GREP_MAX_LIMIT,
+            )?),
+            None => None,
+        };
+        let mut options = ChangesetGrepOptions {
+            prefixes: prefixes_from_request(params.prefixes)?,
+            case_insensitive: params.case_insensitive,
+            max_matches: Some(limit),
+            max_matches_per_file,
+            ..Default::default()
+        };
+        if let Some(max_file_size) = params.max_file_size {
+            options.max_file_size = check_range_and_convert(
+                "max_file_size",
+                max_file_size,
+                0..=source_control::COMMIT_GREP_MAX_FILE_SIZE_LIMIT,
+            )?;
+        }
+        let matches: Vec<_> = changeset
+            .grep(&params.pattern, options)
+            .await?
+            .map_ok(IntoResponse::into_response)
+            .try_collect()
+            .await?;
+        Ok(thrift::CommitGrepResponse { matches })
+    }
+
+    /// Returns a chunk of an archive of the files in a commit.
+    ///
+    /// Archives of a commit are always identical, so clients fetch the
+    /// whole archive by requesting consecutive chunks until the last one,
+    /// passing the `continue_after` cursor of each chunk to the request for
+    /// the next one.  The cursor lets the server continue the archive from
+    /// the file the previous chunk ended in, rather than from the start.
+    pub(crate) async fn commit_archive_chunk(
+        &self,
+        ctx: CoreContext,
+        commit: thrift::CommitSpecifier,
+        params: thrift::CommitArchiveChunkParams,
+    ) -> Result<thrift::CommitArchiveChunk, errors::ServiceError> {
+        let (_repo, changeset) = self.repo_changeset(ctx, &commit).await?;
+        let format = ArchiveFormat::from_request(&params.format)?;
+        let path_prefix = match &params.path_prefix {
+            Some(prefix) => Some(MononokePath::try_from(prefix).map_err(|e| {
+                errors::invalid_request(format!("invalid path prefix '{}': {}", prefix, e))
+            })?),
+            None => None,
+        };
+        let offset: u64 = check_range_and_convert("offset", params.offset, 0..)?;
+        let size: u64 = check_range_and_convert(
+            "size",
+            params.size,
+            1..=source_control::COMMIT_ARCHIVE_CHUNK_SIZE_LIMIT,
+        )?;
+        let cursor = match &params.after {
+            Some(after) => Some(after.parse::<ArchiveCursor>()?),
+            None => None,
+        };
+        let cursor_offset = cursor.as_ref().map_or(0, ArchiveCursor::offset);
+        if offset != cursor_offset {
+            return Err(errors::invalid_request(format!(
+                "offset ({}) does not match the position of the archive cursor ({})",
+                offset, cursor_offset,
+            ))
+            .into());
+        }
+
+        let (data, continue_after) = changeset
+            .archive_chunk(format, path_prefix, cursor, size)
+            .await?;
+        Ok(thrift::CommitArchiveChunk {
+            offset: params.offset,
+            data: data.to_vec(),
+            is_last: continue_after.is_none(),
+            continue_after: continue_after.map(|cursor| cursor.to_string()),
+        })
+    }
+
     /// Returns the history of a commit
     pub(crate) async fn commit_history(
         &self,
@@ -594,6 +713,30 @@ impl SourceControlServiceImpl {
         })
     }
 
+    /// Cherry-pick a commit onto another commit.
+    ///
+    /// Returns the new commit, or the conflicts that prevented creating it.
+    pub(crate) async fn commit_cherry_pick(
+        &self,
+        ctx: CoreContext,
+        commit: thrift::CommitSpecifier,
+        params: thrift::CommitCherryPickParams,
+    ) -> Result<thrift::CommitCherryPickResponse, errors::ServiceError> {
+        let (repo, changeset) = self.repo_changeset(ctx, &commit).await?;
+        let repo = match params.service_identity {
+            Some(service_identity) => repo.service_write(service_identity).await?,
+            None => repo.write().await?,
+        };
+        let onto = self.changeset_id(&repo, &params.onto).await?;
+
+        let response = repo
+            .cherry_pick(changeset.id(), onto)
+            .await?
+            .into_response_with(&(repo.clone(), params.identity_schemes))
+            .await?;
+        Ok(response)
+    }
+
     /// Do a cross-repo lookup to see if a commit exists under a different hash in another repo
     pub(crate) async fn commit_lookup_xrepo(
         &self,
@@ -61,6 +61,7 @@ impl SourceControlServiceImpl {
                     file_size: metadata.total_size as i64,
                     content_sha1: metadata.sha1.as_ref().to_vec(),
                     content_sha256: metadata.sha256.as_ref().to_vec(),
+                    classification: None,
                 };
                 thrift::CommitPathInfoResponse {
                     exists: true,
@@ -109,7 +110,14 @@ impl SourceControlServiceImpl {
         // Map all the changeset IDs into the requested identity schemes.  Keep a mapping of
         // which bonsai changeset ID corresponds to which mapped commit ID index, so we can look
         // them up later.
-        let (content, blame) = path.blame().await?;
+        let ignore_revs = match &params.ignore_revs {
+            Some(ignore_revs) => {
+                future::try_join_all(ignore_revs.iter().map(|id| self.changeset_id(&repo, id)))
+                    .await?
+            }
+            None => Vec::new(),
+        };
+        let (content, blame) = path.blame_ignoring(ignore_revs).await?;
         let csids: Vec<_> = blame
             .ranges()
             .iter()
@@ -259,6 +267,8 @@ impl SourceControlServiceImpl {
                 descendants_of,
                 exclude_changeset_and_ancestors,
                 follow_history_across_deletions: params.follow_history_across_deletions,
+                follow_renames: params.follow_renames,
+                ..Default::default()
             })
             .await?;
         let history = collect_history(
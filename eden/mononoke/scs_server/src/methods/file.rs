@@ -6,6 +6,7 @@
  */
 
 use context::CoreContext;
+use futures::try_join;
 use source_control as thrift;
 
 use crate::errors;
@@ -34,7 +35,12 @@ impl SourceControlServiceImpl {
         _params: thrift::FileInfoParams,
     ) -> Result<thrift::FileInfo, errors::ServiceError> {
         match self.repo_file(ctx, &file).await? {
-            (_repo, Some(file)) => Ok(file.metadata().await?.into_response()),
+            (_repo, Some(file)) => {
+                let (metadata, classification) = try_join!(file.metadata(), file.classification())?;
+                let mut info: thrift::FileInfo = metadata.into_response();
+                info.classification = Some(classification.into_response());
+                Ok(info)
+            }
             (_repo, None) => Err(errors::file_not_found(file.description()).into()),
         }
     }
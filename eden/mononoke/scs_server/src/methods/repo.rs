@@ -552,6 +552,69 @@ impl SourceControlServiceImpl {
         Ok(thrift::RepoLandStackResponse { pushrebase_outcome })
     }
 
+    pub(crate) async fn repo_rebase_stack(
+        &self,
+        ctx: CoreContext,
+        repo: thrift::RepoSpecifier,
+        params: thrift::RepoRebaseStackParams,
+    ) -> Result<thrift::RepoRebaseStackResponse, errors::ServiceError> {
+        let repo = self.repo(ctx, &repo).await?;
+        let repo = match params.service_identity {
+            Some(service_identity) => repo.service_write(service_identity).await?,
+            None => repo.write().await?,
+        };
+        let stack =
+            try_join_all(params.stack.iter().map(|id| self.changeset_id(&repo, id))).await?;
+        let onto = self.changeset_id(&repo, &params.onto).await?;
+
+        let response = repo
+            .rebase_stack(stack, onto)
+            .await?
+            .into_response_with(&(repo.clone(), params.identity_schemes))
+            .await?;
+        Ok(response)
+    }
+
+    /// Compare two commits, returning the commits that are only ancestors
+    /// of one of them, and their merge base.
+    pub(crate) async fn repo_compare_commits(
+        &self,
+        ctx: CoreContext,
+        repo: thrift::RepoSpecifier,
+        params: thrift::RepoCompareCommitsParams,
+    ) -> Result<thrift::RepoCompareCommitsResponse, errors::ServiceError> {
+        let repo = self.repo(ctx, &repo).await?;
+        // Only the counted commits can be listed, so skipping past them would
+        // return nothing.
+        let skip: usize = check_range_and_convert(
+            "skip",
+            params.skip,
+            0..=source_control::REPO_COMPARE_COMMITS_COUNT_LIMIT,
+        )?;
+        let limit: usize = check_range_and_convert(
+            "limit",
+            params.limit,
+            0..=source_control::REPO_COMPARE_COMMITS_MAX_LIMIT,
+        )?;
+        let (a, b) = try_join!(
+            self.changeset_id(&repo, &params.commit_a),
+            self.changeset_id(&repo, &params.commit_b),
+        )?;
+
+        let response = repo
+            .compare_commits(
+                a,
+                b,
+                skip,
+                limit,
+                source_control::REPO_COMPARE_COMMITS_COUNT_LIMIT as usize,
+            )
+            .await?
+            .into_response_with(&(repo.clone(), params.identity_schemes))
+            .await?;
+        Ok(response)
+    }
+
     pub(crate) async fn repo_list_hg_manifest(
         &self,
         ctx: CoreContext,
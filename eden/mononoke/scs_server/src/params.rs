@@ -84,6 +84,26 @@ impl AddScubaParams for thrift::RepoLandStackParams {
     }
 }
 
+impl AddScubaParams for thrift::RepoRebaseStackParams {
+    fn add_scuba_params(&self, scuba: &mut ScubaSampleBuilder) {
+        if let Some(head) = self.stack.last() {
+            scuba.add("commit", head.to_string());
+        }
+        scuba.add("param_onto", self.onto.to_string());
+        self.identity_schemes.add_scuba_params(scuba);
+    }
+}
+
+impl AddScubaParams for thrift::RepoCompareCommitsParams {
+    fn add_scuba_params(&self, scuba: &mut ScubaSampleBuilder) {
+        scuba.add("commit", self.commit_a.to_string());
+        scuba.add("other_commit", self.commit_b.to_string());
+        scuba.add("param_skip", self.skip);
+        scuba.add("param_limit", self.limit);
+        self.identity_schemes.add_scuba_params(scuba);
+    }
+}
+
 impl AddScubaParams for thrift::RepoListBookmarksParams {
     fn add_scuba_params(&self, scuba: &mut ScubaSampleBuilder) {
         scuba.add("param_include_scratch", self.include_scratch as i32);
@@ -143,12 +163,50 @@ impl AddScubaParams for thrift::CommitFindFilesParams {
         if let Some(prefixes) = &self.prefixes {
             scuba.add("param_prefixes", prefixes.iter().collect::<ScubaValue>());
         }
+        if let Some(languages) = &self.languages {
+            scuba.add("param_languages", languages.iter().collect::<ScubaValue>());
+        }
+        scuba.add("param_exclude_generated", self.exclude_generated);
+        scuba.add("param_exclude_vendored", self.exclude_vendored);
+        scuba.add("param_exclude_binary", self.exclude_binary);
+    }
+}
+
+impl AddScubaParams for thrift::CommitArchiveChunkParams {
+    fn add_scuba_params(&self, scuba: &mut ScubaSampleBuilder) {
+        scuba.add("param_format", self.format.to_string());
+        if let Some(path_prefix) = &self.path_prefix {
+            scuba.add("param_path_prefix", path_prefix.as_str());
+        }
+        scuba.add("param_offset", self.offset);
+        scuba.add("param_size", self.size);
+        if let Some(after) = &self.after {
+            scuba.add("param_after", after.as_str());
+        }
+    }
+}
+
+impl AddScubaParams for thrift::CommitGrepParams {
+    fn add_scuba_params(&self, scuba: &mut ScubaSampleBuilder) {
+        scuba.add("param_pattern", self.pattern.as_str());
+        scuba.add("param_limit", self.limit);
+        if let Some(prefixes) = &self.prefixes {
+            scuba.add("param_prefixes", prefixes.iter().collect::<ScubaValue>());
+        }
+        scuba.add("param_case_insensitive", self.case_insensitive);
+        if let Some(max_matches_per_file) = self.max_matches_per_file {
+            scuba.add("param_max_matches_per_file", max_matches_per_file);
+        }
+        if let Some(max_file_size) = self.max_file_size {
+            scuba.add("param_max_file_size", max_file_size);
+        }
     }
 }
 
 impl AddScubaParams for thrift::CommitInfoParams {
     fn add_scuba_params(&self, scuba: &mut ScubaSampleBuilder) {
         self.identity_schemes.add_scuba_params(scuba);
+        scuba.add("param_include_diffstat", self.include_diffstat);
     }
 }
 
@@ -164,6 +222,13 @@ impl AddScubaParams for thrift::CommitCommonBaseWithParams {
     }
 }
 
+impl AddScubaParams for thrift::CommitCherryPickParams {
+    fn add_scuba_params(&self, scuba: &mut ScubaSampleBuilder) {
+        scuba.add("param_onto", self.onto.to_string());
+        self.identity_schemes.add_scuba_params(scuba);
+    }
+}
+
 impl AddScubaParams for thrift::CommitLookupParams {
     fn add_scuba_params(&self, scuba: &mut ScubaSampleBuilder) {
         self.identity_schemes.add_scuba_params(scuba);
@@ -207,6 +272,15 @@ impl AddScubaParams for thrift::CommitLookupXRepoParams {
 impl AddScubaParams for thrift::CommitPathBlameParams {
     fn add_scuba_params(&self, scuba: &mut ScubaSampleBuilder) {
         scuba.add("param_format", self.format.to_string());
+        if let Some(ignore_revs) = &self.ignore_revs {
+            scuba.add(
+                "param_ignore_revs",
+                ignore_revs
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<ScubaValue>(),
+            );
+        }
         self.identity_schemes.add_scuba_params(scuba);
     }
 }
@@ -226,6 +300,7 @@ impl AddScubaParams for thrift::CommitPathHistoryParams {
             "follow_history_across_deletions",
             self.follow_history_across_deletions,
         );
+        scuba.add("follow_renames", self.follow_renames);
         self.identity_schemes.add_scuba_params(scuba);
     }
 }
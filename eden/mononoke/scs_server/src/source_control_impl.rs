@@ -449,6 +449,16 @@ impl SourceControlService for SourceControlServiceThriftImpl {
             params: thrift::CommitFindFilesParams,
         ) -> Result<thrift::CommitFindFilesResponse, service::CommitFindFilesExn>;
 
+        async fn commit_grep(
+            commit: thrift::CommitSpecifier,
+            params: thrift::CommitGrepParams,
+        ) -> Result<thrift::CommitGrepResponse, service::CommitGrepExn>;
+
+        async fn commit_archive_chunk(
+            commit: thrift::CommitSpecifier,
+            params: thrift::CommitArchiveChunkParams,
+        ) -> Result<thrift::CommitArchiveChunk, service::CommitArchiveChunkExn>;
+
         async fn commit_history(
             commit: thrift::CommitSpecifier,
             params: thrift::CommitHistoryParams,
@@ -464,6 +474,11 @@ impl SourceControlService for SourceControlServiceThriftImpl {
             params: thrift::CommitLookupXRepoParams,
         ) -> Result<thrift::CommitLookupResponse, service::CommitLookupXrepoExn>;
 
+        async fn commit_cherry_pick(
+            commit: thrift::CommitSpecifier,
+            params: thrift::CommitCherryPickParams,
+        ) -> Result<thrift::CommitCherryPickResponse, service::CommitCherryPickExn>;
+
         async fn commit_path_info(
             commit_path: thrift::CommitPathSpecifier,
             params: thrift::CommitPathInfoParams,
@@ -529,6 +544,16 @@ impl SourceControlService for SourceControlServiceThriftImpl {
             params: thrift::RepoLandStackParams,
         ) -> Result<thrift::RepoLandStackResponse, service::RepoLandStackExn>;
 
+        async fn repo_compare_commits(
+            repo: thrift::RepoSpecifier,
+            params: thrift::RepoCompareCommitsParams,
+        ) -> Result<thrift::RepoCompareCommitsResponse, service::RepoCompareCommitsExn>;
+
+        async fn repo_rebase_stack(
+            repo: thrift::RepoSpecifier,
+            params: thrift::RepoRebaseStackParams,
+        ) -> Result<thrift::RepoRebaseStackResponse, service::RepoRebaseStackExn>;
+
         async fn repo_list_hg_manifest(
             repo: thrift::RepoSpecifier,
             params: thrift::RepoListHgManifestParams,
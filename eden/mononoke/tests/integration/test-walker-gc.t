@@ -0,0 +1,50 @@
+# Copyright (c) Facebook, Inc. and its affiliates.
+#
+# This software may be used and distributed according to the terms of the
+# GNU General Public License found in the LICENSE file in the root
+# directory of this source tree.
+
+  $ . "${TEST_FIXTURES}/library.sh"
+
+setup configuration
+  $ default_setup_pre_blobimport "blob_files"
+  hg repo
+  o  C [draft;rev=2;26805aba1e60]
+  |
+  o  B [draft;rev=1;112478962961]
+  |
+  o  A [draft;rev=0;426bada5c675]
+  $
+  $ blobimport repo-hg/.hg repo --derived-data-type=fsnodes
+
+add unreachable blobs, one old enough to be swept and one still in the grace period
+  $ BLOBPREFIX="$TESTTMP/blobstore/blobs/blob-repo0000"
+  $ OLD_ORPHAN="$BLOBPREFIX.content.blake2.0000000000000000000000000000000000000000000000000000000000000000"
+  $ NEW_ORPHAN="$BLOBPREFIX.content.blake2.1111111111111111111111111111111111111111111111111111111111111111"
+  $ echo old > "$OLD_ORPHAN"
+  $ touch -d "30 days ago" "$OLD_ORPHAN"
+  $ echo new > "$NEW_ORPHAN"
+
+gc refuses to run from a single bookmark, as that could sweep data reachable from other bookmarks
+  $ mononoke_walker --storage-id=blobstore gc -q --bookmark master_bookmark 2>&1 | strip_glog | grep -o "gc needs to walk from all bookmarks.*"
+  gc needs to walk from all bookmarks, pass --walk-root PublishedBookmarks
+
+report only by default
+  $ mononoke_walker --storage-id=blobstore gc -q --walk-root PublishedBookmarks 2>&1 | strip_glog | grep -E "^(Sweepable|Report complete)"
+  Sweepable repo0000.content.blake2.0000000000000000000000000000000000000000000000000000000000000000
+  Report complete, nothing was swept as --enable-sweep is not set: SweepStats { enumerated: *, unreachable: 2, sweepable: 1, swept: 0 } (glob)
+  $ ls "$OLD_ORPHAN" "$NEW_ORPHAN" | wc -l
+  2
+
+sweep
+  $ mononoke_walker --storage-id=blobstore gc -q --walk-root PublishedBookmarks --enable-sweep 2>&1 | strip_glog | grep -E "^(Swept repo|Sweep complete)"
+  Swept repo0000.content.blake2.0000000000000000000000000000000000000000000000000000000000000000
+  Sweep complete: SweepStats { enumerated: *, unreachable: 2, sweepable: 1, swept: 1 } (glob)
+  $ ls "$OLD_ORPHAN" 2>/dev/null | wc -l
+  0
+  $ ls "$NEW_ORPHAN" | wc -l
+  1
+
+reachable data is still intact
+  $ mononoke_walker --storage-id=blobstore --readonly-storage validate -q --walk-root PublishedBookmarks 2>&1 | strip_glog | grep -o "Final count:.*"
+  Final count: * (glob)
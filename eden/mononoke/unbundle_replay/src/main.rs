@@ -260,6 +260,7 @@ async fn maybe_unbundle(
                 &ctx,
                 &repo,
                 false, // infinitepush_writes_allowed
+                None,  // infinitepush_bundle_ttl
                 Box::new(bundle_stream),
                 RepoReadOnly::ReadWrite,
                 None,  // maybe_full_content
@@ -8,12 +8,14 @@
 use crate::validate::{CHECK_FAIL, CHECK_TYPE, NODE_KEY, REPO};
 
 use anyhow::{format_err, Error};
-use blobstore::{Blobstore, BlobstoreMetadata};
+use blobstore::{Blobstore, BlobstoreGetData, BlobstoreMetadata, SweepableBlobstore};
 use blobstore_factory::{
-    make_blobstore, make_blobstore_multiplexed, BlobstoreOptions, ReadOnlyStorage,
+    make_blobstore, make_blobstore_multiplexed, make_sweep_blobstore, BlobstoreOptions,
+    ReadOnlyStorage,
 };
 use context::CoreContext;
 use fbinit::FacebookInit;
+use futures::future;
 use inlinable_string::InlinableString;
 use metaconfig_types::{BlobConfig, BlobstoreId, ScrubAction};
 use multiplexedblob::{LoggingScrubHandler, ScrubHandler};
@@ -25,11 +27,13 @@ use slog::Logger;
 use sql_ext::facebook::MysqlOptions;
 use stats::prelude::*;
 use std::{convert::From, sync::Arc};
+use tieredblob::{LoggingTierCheckHandler, TierCheckBlobstore, TierCheckHandler};
 
 define_stats! {
     prefix = "mononoke.walker";
     scrub_repaired: dynamic_timeseries("{}.blobstore.{}.{}.repaired", (subcommand: &'static str, blobstore_id: String, repo: String); Rate, Sum),
     scrub_repair_required: dynamic_timeseries("{}.blobstore.{}.{}.repair_required", (subcommand: &'static str, blobstore_id: String, repo: String); Rate, Sum),
+    tier_check_fail: dynamic_timeseries("{}.blobstore.tier_check_fail.{}", (subcommand: &'static str, repo: String); Rate, Sum),
 }
 
 pub const BLOBSTORE_ID: &'static str = "blobstore_id";
@@ -108,6 +112,52 @@ impl ScrubHandler for StatsScrubHandler {
     }
 }
 
+pub struct StatsTierCheckHandler {
+    scuba: ScubaSampleBuilder,
+    subcommand_stats_key: &'static str,
+    repo_stats_key: String,
+    inner: LoggingTierCheckHandler,
+}
+
+impl StatsTierCheckHandler {
+    pub fn new(
+        quiet: bool,
+        scuba: ScubaSampleBuilder,
+        subcommand_stats_key: &'static str,
+        repo_stats_key: String,
+    ) -> Self {
+        Self {
+            scuba,
+            subcommand_stats_key,
+            repo_stats_key,
+            inner: LoggingTierCheckHandler::new(quiet),
+        }
+    }
+}
+
+impl TierCheckHandler for StatsTierCheckHandler {
+    fn on_both_tiers(&self, ctx: &CoreContext, key: &str, hot: &BlobstoreGetData) {
+        self.inner.on_both_tiers(ctx, key, hot);
+
+        let ctime = match hot.as_meta().ctime() {
+            Some(ctime) => ScubaValue::from(ctime),
+            None => ScubaValue::Null(NullScubaValue::Int),
+        };
+
+        self.scuba
+            .clone()
+            .add(REPO, self.repo_stats_key.clone())
+            .add(NODE_KEY, key)
+            .add(CHECK_TYPE, "tier_check")
+            .add(CHECK_FAIL, 1)
+            .add("session", ctx.session().metadata().session_id().to_string())
+            .add("ctime", ctime)
+            .log();
+        STATS::tier_check_fail
+            .add_value(1, (self.subcommand_stats_key, self.repo_stats_key.clone()));
+    }
+}
+
 fn get_blobconfig(
     blob_config: BlobConfig,
     inner_blobstore_id: Option<u64>,
@@ -147,6 +197,7 @@ pub async fn open_blobstore(
     prefix: Option<String>,
     readonly_storage: ReadOnlyStorage,
     scrub_action: Option<ScrubAction>,
+    check_tiers: bool,
     blobstore_sampler: Option<Arc<dyn SamplingHandler>>,
     scuba_builder: ScubaSampleBuilder,
     walk_stats_key: &'static str,
@@ -166,6 +217,42 @@ pub async fn open_blobstore(
     });
 
     let blobstore = match (scrub_handler, blobconfig) {
+        (None, BlobConfig::Tiered { hot, cold }) if check_tiers => {
+            // Zero the failure stat so that a clean check still has a datapoint
+            STATS::tier_check_fail.add_value(0, (walk_stats_key, repo_stats_key.clone()));
+
+            let (hot, cold) = future::try_join(
+                make_blobstore(
+                    fb,
+                    *hot,
+                    mysql_options,
+                    readonly_storage,
+                    &blobstore_options,
+                    &logger,
+                ),
+                make_blobstore(
+                    fb,
+                    *cold,
+                    mysql_options,
+                    readonly_storage,
+                    &blobstore_options,
+                    &logger,
+                ),
+            )
+            .await?;
+            let tier_check_handler = Arc::new(StatsTierCheckHandler::new(
+                false,
+                scuba_builder.clone(),
+                walk_stats_key,
+                repo_stats_key.clone(),
+            )) as Arc<dyn TierCheckHandler>;
+            Arc::new(TierCheckBlobstore::new(hot, cold, tier_check_handler)) as Arc<dyn Blobstore>
+        }
+        (_, _) if check_tiers => {
+            return Err(format_err!(
+                "Tier check passed for non-tiered store, or together with a scrub action"
+            ));
+        }
         (
             Some(scrub_handler),
             BlobConfig::Scrub {
@@ -196,6 +283,7 @@ pub async fn open_blobstore(
                 blobstores,
                 minimum_successful_writes,
                 Some((scrub_handler, scrub_action)),
+                None,
                 mysql_options,
                 readonly_storage,
                 &blobstore_options,
@@ -212,6 +300,7 @@ pub async fn open_blobstore(
                 blobstores,
                 minimum_successful_writes,
                 queue_db,
+                read_verification,
             },
         ) => {
             make_blobstore_multiplexed(
@@ -223,6 +312,7 @@ pub async fn open_blobstore(
                 blobstores,
                 minimum_successful_writes,
                 None,
+                read_verification,
                 mysql_options,
                 readonly_storage,
                 &blobstore_options,
@@ -261,3 +351,21 @@ pub async fn open_blobstore(
 
     Ok(blobstore)
 }
+
+// Opens the same store that open_blobstore reads from, without any of the wrappers, so that the
+// keys enumerated match the keys seen by the walk.
+pub async fn open_sweep_blobstore(
+    fb: FacebookInit,
+    blob_config: BlobConfig,
+    inner_blobstore_id: Option<u64>,
+    mysql_options: MysqlOptions,
+    readonly_storage: ReadOnlyStorage,
+) -> Result<Arc<dyn SweepableBlobstore>, Error> {
+    make_sweep_blobstore(
+        fb,
+        get_blobconfig(blob_config, inner_blobstore_id)?,
+        mysql_options,
+        readonly_storage,
+    )
+    .await
+}
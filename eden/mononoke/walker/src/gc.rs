@@ -0,0 +1,362 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::blobstore::open_sweep_blobstore;
+use crate::graph::{FileContentData, Node, NodeData, NodeType};
+use crate::progress::{progress_stream, report_state};
+use crate::setup::{
+    setup_common, RepoWalkParams, DEEP_INCLUDE_EDGE_TYPES, ENABLE_SWEEP_ARG, GC, GRACE_PERIOD_ARG,
+    INNER_BLOBSTORE_ID_ARG,
+};
+use crate::state::WalkState;
+use crate::tail::{walk_exact_tail, RepoWalkRun};
+use crate::walk::EmptyRoute;
+
+use anyhow::{format_err, Error};
+use blobrepo_factory::Caching;
+use blobstore::{BlobstoreKeyParam, SweepableBlobstore};
+use clap::ArgMatches;
+use cloned::cloned;
+use cmdlib::args;
+use context::CoreContext;
+use dashmap::DashMap;
+use derive_more::Add;
+use fbinit::FacebookInit;
+use futures::{
+    future::{self, FutureExt},
+    stream::{self, Stream, StreamExt, TryStreamExt},
+    TryFutureExt,
+};
+use mononoke_types::BlobstoreBytes;
+use samplingblob::SamplingHandler;
+use slog::{info, Logger};
+use stats::prelude::*;
+use std::{
+    collections::{BTreeSet, HashSet},
+    convert::TryFrom,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+define_stats! {
+    prefix = "mononoke.walker";
+    gc_unreachable_keys: dynamic_timeseries("gc.{}.unreachable_keys", (repo: String); Rate, Sum),
+    gc_swept_keys: dynamic_timeseries("gc.{}.swept_keys", (repo: String); Rate, Sum),
+}
+
+const DEFAULT_GRACE_PERIOD_SECS: u64 = 7 * 24 * 60 * 60;
+
+// Records the key of every blob loaded during the walk
+#[derive(Debug, Default)]
+struct GcMarker {
+    marked: DashMap<String, ()>,
+}
+
+impl SamplingHandler for GcMarker {
+    fn sample_get(
+        &self,
+        _ctx: CoreContext,
+        key: String,
+        value: Option<&BlobstoreBytes>,
+    ) -> Result<(), Error> {
+        if value.is_some() {
+            self.marked.insert(key, ());
+        }
+        Ok(())
+    }
+}
+
+// Blobstore key prefixes (after the repo prefix) of the blobs loaded when stepping to each node
+// type. Only keys under the prefixes of walked node types are swept, so that data the walker
+// can't reach (e.g. unodes or blame) is never considered unreachable.
+fn sweep_key_prefixes(node_type: NodeType) -> &'static [&'static str] {
+    match node_type {
+        NodeType::Root => &[],
+        // Bonsai
+        NodeType::Bookmark => &[],
+        NodeType::BonsaiChangeset => &["changeset.blake2."],
+        NodeType::BonsaiHgMapping => &[],
+        NodeType::BonsaiPhaseMapping => &[],
+        NodeType::PublishedBookmarks => &[],
+        // Hg
+        NodeType::HgBonsaiMapping => &[],
+        NodeType::HgChangeset => &["hgchangeset.sha1."],
+        NodeType::HgManifest => &["hgmanifest.sha1."],
+        NodeType::HgFileEnvelope => &["hgfilenode.sha1."],
+        NodeType::HgFileNode => &[],
+        // Content
+        NodeType::FileContent => &["content.blake2.", "chunk.blake2."],
+        NodeType::FileContentMetadata => &["content_metadata.blake2."],
+        NodeType::AliasContentMapping => &["alias."],
+        // Derived data
+        NodeType::BonsaiFsnodeMapping => &["derived_root_fsnode."],
+        NodeType::Fsnode => &["fsnode.blake2."],
+    }
+}
+
+// Anything not loaded by the walk gets swept, so refuse to run unless the walk sees everything
+fn check_complete_walk(
+    matches: &ArgMatches<'_>,
+    walk_params: &RepoWalkParams,
+) -> Result<(), Error> {
+    if walk_params.tail_secs.is_some() {
+        return Err(format_err!("gc does not support tailing"));
+    }
+    if !walk_params.error_as_data_node_types.is_empty()
+        || !walk_params.error_as_data_edge_types.is_empty()
+    {
+        return Err(format_err!(
+            "gc needs a complete walk, errors as data are not allowed"
+        ));
+    }
+    if args::parse_caching(matches) != Caching::Disabled {
+        return Err(format_err!(
+            "gc needs to see every blob loaded, pass --skip-caching"
+        ));
+    }
+    if !walk_params
+        .walk_roots
+        .iter()
+        .any(|e| e.target == Node::PublishedBookmarks)
+    {
+        return Err(format_err!(
+            "gc needs to walk from all bookmarks, pass --walk-root PublishedBookmarks"
+        ));
+    }
+    let missing_edge_types: Vec<_> = DEEP_INCLUDE_EDGE_TYPES
+        .iter()
+        .filter(|e| {
+            walk_params.include_node_types.contains(&e.outgoing_type())
+                && !walk_params.include_edge_types.contains(e)
+        })
+        .collect();
+    if !missing_edge_types.is_empty() {
+        return Err(format_err!(
+            "gc needs a deep walk of each node type, missing edge types {:?}",
+            missing_edge_types
+        ));
+    }
+    Ok(())
+}
+
+// Force load of file contents so that their chunks are marked
+fn loading_stream<InStream, SS>(
+    scheduled_max: usize,
+    s: InStream,
+) -> impl Stream<Item = Result<(Node, Option<NodeData>, Option<SS>), Error>>
+where
+    InStream: Stream<Item = Result<(Node, Option<NodeData>, Option<SS>), Error>> + 'static + Send,
+    SS: 'static + Send,
+{
+    s.map_ok(move |(n, nd, stats)| match nd {
+        Some(NodeData::FileContent(FileContentData::ContentStream(file_bytes_stream))) => {
+            file_bytes_stream
+                .try_fold(0, |acc, file_bytes| future::ok(acc + file_bytes.size()))
+                .map_ok(move |num_bytes| {
+                    (
+                        n,
+                        Some(NodeData::FileContent(FileContentData::Consumed(num_bytes))),
+                        stats,
+                    )
+                })
+                .map_err(|e| e.context(format_err!("While marking file content stream")))
+                .left_future()
+        }
+        data_opt => future::ok((n, data_opt, stats)).right_future(),
+    })
+    .try_buffer_unordered(scheduled_max)
+}
+
+#[derive(Add, Clone, Copy, Debug, Default)]
+struct SweepStats {
+    enumerated: u64,
+    unreachable: u64,
+    // Unreachable keys older than the grace period
+    sweepable: u64,
+    swept: u64,
+}
+
+// Returns true if the key was old enough to be swept, or to be reported as sweepable
+async fn sweep_key(
+    ctx: CoreContext,
+    blobstore: Arc<dyn SweepableBlobstore>,
+    key: String,
+    cutoff: i64,
+    enable_sweep: bool,
+) -> Result<bool, Error> {
+    let ctime = blobstore
+        .get_metadata(ctx.clone(), key.clone())
+        .await?
+        .and_then(|meta| meta.ctime());
+    match ctime {
+        Some(ctime) if ctime < cutoff => {}
+        // Keys without a ctime are kept, as we can't tell if they are still being uploaded
+        _ => return Ok(false),
+    }
+    if enable_sweep {
+        blobstore.unlink(ctx.clone(), key.clone()).await?;
+        info!(ctx.logger(), "Swept {}", key);
+    } else {
+        info!(ctx.logger(), "Sweepable {}", key);
+    }
+    Ok(true)
+}
+
+async fn sweep(
+    ctx: &CoreContext,
+    blobstore: &Arc<dyn SweepableBlobstore>,
+    marker: &GcMarker,
+    key_prefix: String,
+    cutoff: i64,
+    enable_sweep: bool,
+    scheduled_max: usize,
+) -> Result<SweepStats, Error> {
+    let mut stats = SweepStats::default();
+    let mut range = BlobstoreKeyParam::from(key_prefix.clone()..format!("{}\x7f", key_prefix));
+    loop {
+        let entries = blobstore.enumerate(range).await?;
+        stats.enumerated += entries.keys.len() as u64;
+
+        let unreachable: Vec<String> = entries
+            .keys
+            .into_iter()
+            .filter(|key| !marker.marked.contains_key(key))
+            .collect();
+        stats.unreachable += unreachable.len() as u64;
+
+        let sweepable = stream::iter(unreachable)
+            .map(|key| sweep_key(ctx.clone(), blobstore.clone(), key, cutoff, enable_sweep))
+            .buffer_unordered(scheduled_max)
+            .try_fold(0, |acc, sweepable| future::ok(acc + sweepable as u64))
+            .await?;
+        stats.sweepable += sweepable;
+        if enable_sweep {
+            stats.swept += sweepable;
+        }
+
+        match entries.next_token {
+            Some(next_token) => range = next_token,
+            None => return Ok(stats),
+        }
+    }
+}
+
+// Marks all keys loaded by a walk from the bookmarks, then sweeps the unmarked keys
+pub async fn garbage_collect<'a>(
+    fb: FacebookInit,
+    logger: Logger,
+    matches: &'a ArgMatches<'a>,
+    sub_m: &'a ArgMatches<'a>,
+) -> Result<(), Error> {
+    let marker = Arc::new(GcMarker::default());
+
+    let (datasources, walk_params) =
+        setup_common(GC, fb, &logger, Some(marker.clone()), matches, sub_m).await?;
+    check_complete_walk(matches, &walk_params)?;
+
+    let repo_stats_key = args::get_repo_name(fb, &matches)?;
+    let enable_sweep = sub_m.is_present(ENABLE_SWEEP_ARG);
+    let grace_period = Duration::from_secs(
+        args::get_u64_opt(&sub_m, GRACE_PERIOD_ARG).unwrap_or(DEFAULT_GRACE_PERIOD_SECS),
+    );
+    if enable_sweep && args::parse_readonly_storage(&matches).0 {
+        return Err(format_err!("Can't sweep with --readonly-storage"));
+    }
+
+    let sweep_blobstore = open_sweep_blobstore(
+        fb,
+        datasources.blobstore_config.clone(),
+        args::get_u64_opt(&sub_m, INNER_BLOBSTORE_ID_ARG),
+        args::parse_mysql_options(&matches),
+        args::parse_readonly_storage(&matches),
+    )
+    .await?;
+    let repo_prefix = datasources.blobrepo.get_repoid().prefix();
+    let key_prefixes: BTreeSet<String> = walk_params
+        .include_node_types
+        .iter()
+        .flat_map(|t| sweep_key_prefixes(*t).iter())
+        .map(|prefix| format!("{}{}", repo_prefix, prefix))
+        .collect();
+    info!(logger, "Sweeping key prefixes {:?}", key_prefixes);
+
+    let make_sink = {
+        cloned!(
+            walk_params.progress_state,
+            walk_params.quiet,
+            walk_params.scheduled_max,
+        );
+        move |run: RepoWalkRun| {
+            cloned!(run.ctx);
+            async move |walk_output| {
+                let walk_progress = progress_stream(quiet, &progress_state, walk_output);
+                let loading = loading_stream(scheduled_max, walk_progress);
+                report_state(ctx.clone(), progress_state, loading).await?;
+                info!(ctx.logger(), "Marked {} keys", marker.marked.len());
+
+                // Take the cutoff after the mark, anything newer could have been written since
+                let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+                let cutoff =
+                    i64::try_from(now.checked_sub(grace_period).unwrap_or_default().as_secs())?;
+
+                let mut total = SweepStats::default();
+                for key_prefix in key_prefixes {
+                    let stats = sweep(
+                        &ctx,
+                        &sweep_blobstore,
+                        &marker,
+                        key_prefix.clone(),
+                        cutoff,
+                        enable_sweep,
+                        scheduled_max,
+                    )
+                    .await?;
+                    if enable_sweep {
+                        info!(ctx.logger(), "Swept prefix {}: {:?}", key_prefix, stats);
+                    } else {
+                        info!(ctx.logger(), "Checked prefix {}: {:?}", key_prefix, stats);
+                    }
+                    total = total + stats;
+                }
+
+                STATS::gc_unreachable_keys
+                    .add_value(total.unreachable as i64, (repo_stats_key.clone(),));
+                if enable_sweep {
+                    STATS::gc_swept_keys.add_value(total.swept as i64, (repo_stats_key.clone(),));
+                }
+                if enable_sweep {
+                    info!(ctx.logger(), "Sweep complete: {:?}", total);
+                } else {
+                    info!(
+                        ctx.logger(),
+                        "Report complete, nothing was swept as --enable-sweep is not set: {:?}",
+                        total
+                    );
+                }
+                Ok(())
+            }
+        }
+    };
+
+    let walk_state = Arc::new(WalkState::new(
+        walk_params.include_node_types.clone(),
+        walk_params.include_edge_types.clone(),
+        HashSet::new(),
+    ));
+    walk_exact_tail::<_, _, _, _, _, EmptyRoute>(
+        fb,
+        logger,
+        datasources,
+        walk_params,
+        &[NodeType::FileContent],
+        None,
+        walk_state,
+        make_sink,
+        false,
+    )
+    .await
+}
@@ -16,6 +16,7 @@ use cmdlib::{args, helpers::block_execute};
 
 mod blobstore;
 mod corpus;
+mod gc;
 #[macro_use]
 mod graph;
 mod parse_node;
@@ -43,6 +44,9 @@ fn main(fb: FacebookInit) -> Result<(), Error> {
             sizing::compression_benefit(fb, logger.clone(), &matches, sub_m).boxed()
         }
         (setup::CORPUS, Some(sub_m)) => corpus::corpus(fb, logger.clone(), &matches, sub_m).boxed(),
+        (setup::GC, Some(sub_m)) => {
+            gc::garbage_collect(fb, logger.clone(), &matches, sub_m).boxed()
+        }
         (setup::SCRUB, Some(sub_m)) => {
             scrub::scrub_objects(fb, logger.clone(), &matches, sub_m).boxed()
         }
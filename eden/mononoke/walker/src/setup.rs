@@ -28,7 +28,7 @@ use futures::{
     future::{self, Future},
 };
 use lazy_static::lazy_static;
-use metaconfig_types::{Redaction, ScrubAction};
+use metaconfig_types::{BlobConfig, Redaction, ScrubAction};
 use samplingblob::SamplingHandler;
 use scuba_ext::{ScubaSampleBuilder, ScubaSampleBuilderExt};
 use slog::{info, warn, Logger};
@@ -36,6 +36,7 @@ use std::{collections::HashSet, iter::FromIterator, str::FromStr, sync::Arc, tim
 
 pub struct RepoWalkDatasources {
     pub blobrepo: BlobRepo,
+    pub blobstore_config: BlobConfig,
     pub scuba_builder: ScubaSampleBuilder,
 }
 
@@ -61,6 +62,7 @@ pub const SCRUB: &str = "scrub";
 pub const COMPRESSION_BENEFIT: &str = "compression-benefit";
 pub const VALIDATE: &str = "validate";
 pub const CORPUS: &str = "corpus";
+pub const GC: &str = "gc";
 
 // Subcommand args
 const QUIET_ARG: &str = "quiet";
@@ -75,8 +77,9 @@ const EXCLUDE_EDGE_TYPE_ARG: &str = "exclude-edge-type";
 const INCLUDE_EDGE_TYPE_ARG: &str = "include-edge-type";
 const BOOKMARK_ARG: &str = "bookmark";
 const WALK_ROOT_ARG: &str = "walk-root";
-const INNER_BLOBSTORE_ID_ARG: &str = "inner-blobstore-id";
+pub const INNER_BLOBSTORE_ID_ARG: &str = "inner-blobstore-id";
 const SCRUB_BLOBSTORE_ACTION_ARG: &str = "scrub-blobstore-action";
+const CHECK_TIERS_ARG: &str = "check-tiers";
 const ENABLE_DERIVE_ARG: &str = "enable-derive";
 pub const PROGRESS_SAMPLE_RATE_ARG: &str = "progress-sample-rate";
 pub const PROGRESS_INTERVAL_ARG: &str = "progress-interval";
@@ -90,6 +93,8 @@ pub const SAMPLE_PATH_REGEX_ARG: &str = "sample-path-regex";
 pub const EXCLUDE_SAMPLE_NODE_TYPE_ARG: &str = "exclude-sample-node-type";
 pub const INCLUDE_SAMPLE_NODE_TYPE_ARG: &str = "include-sample-node-type";
 pub const OUTPUT_DIR_ARG: &str = "output-dir";
+pub const GRACE_PERIOD_ARG: &str = "grace-period";
+pub const ENABLE_SWEEP_ARG: &str = "enable-sweep";
 const SCUBA_TABLE_ARG: &str = "scuba-table";
 const SCUBA_LOG_FILE_ARG: &str = "scuba-log-file";
 
@@ -123,7 +128,7 @@ pub const DEFAULT_INCLUDE_NODE_TYPES: &[NodeType] = &[
 ];
 
 // Goes as far into history as it can
-const DEEP_INCLUDE_EDGE_TYPES: &[EdgeType] = &[
+pub const DEEP_INCLUDE_EDGE_TYPES: &[EdgeType] = &[
     // Bonsai
     EdgeType::BookmarkToBonsaiChangeset,
     EdgeType::BonsaiChangesetToFileContent,
@@ -357,6 +362,24 @@ pub fn setup_toplevel_app<'a, 'b>(app_name: &str) -> App<'a, 'b> {
             .help(&INCLUDE_CHECK_TYPE_HELP),
     );
 
+    let gc = setup_subcommand_args(
+        SubCommand::with_name(GC).about("mark all blobs reachable from the walk roots, then sweep unreachable keys from the blobstore. Report only unless --enable-sweep is passed"),
+    )
+    .arg(
+        Arg::with_name(GRACE_PERIOD_ARG)
+            .long(GRACE_PERIOD_ARG)
+            .takes_value(true)
+            .required(false)
+            .help("Only sweep unreachable keys whose ctime is older than this many seconds, so that blobs being uploaded are not removed before they are referenced. Default is 7 days."),
+    )
+    .arg(
+        Arg::with_name(ENABLE_SWEEP_ARG)
+            .long(ENABLE_SWEEP_ARG)
+            .takes_value(false)
+            .required(false)
+            .help("Unlink the unreachable keys found. Default is to log them only."),
+    );
+
     app_template.build()
         .version("0.0.0")
         .about("Walks the mononoke commit and/or derived data graphs, with option of performing validations and modifications")
@@ -369,6 +392,7 @@ pub fn setup_toplevel_app<'a, 'b>(app_name: &str) -> App<'a, 'b> {
         )
         .subcommand(compression_benefit)
         .subcommand(corpus)
+        .subcommand(gc)
         .subcommand(scrub_objects)
         .subcommand(validate)
 }
@@ -434,6 +458,13 @@ fn setup_subcommand_args<'a, 'b>(subcmd: App<'a, 'b>) -> App<'a, 'b> {
                 .required(false)
                 .help("Enable ScrubBlobstore with the given action. Checks for keys missing from stores. In ReportOnly mode this logs only, otherwise it performs a copy to the missing stores."),
         )
+        .arg(
+            Arg::with_name(CHECK_TIERS_ARG)
+                .long(CHECK_TIERS_ARG)
+                .takes_value(false)
+                .required(false)
+                .help("Read both tiers of a tiered blobstore and report keys that are present in both, rather than in exactly one tier."),
+        )
         .arg(
             Arg::with_name(EXCLUDE_NODE_TYPE_ARG)
                 .long(EXCLUDE_NODE_TYPE_ARG)
@@ -798,14 +829,16 @@ pub fn setup_common<'a>(
             .transpose()?;
 
         // Open the blobstore explicitly so we can do things like run on one side of a multiplex
+        let blobstore_config = storage_config.blobstore;
         let blobstore = blobstore::open_blobstore(
             fb,
             mysql_options,
-            storage_config.blobstore,
+            blobstore_config.clone(),
             inner_blobstore_id,
             None,
             readonly_storage,
             scrub_action,
+            sub_m.is_present(CHECK_TIERS_ARG),
             blobstore_sampler,
             scuba_builder.clone(),
             walk_stats_key,
@@ -828,6 +861,7 @@ pub fn setup_common<'a>(
         let blobrepo = open_blobrepo_given_datasources(
             fb,
             blobstore,
+            None,
             sql_factory,
             config.repoid,
             caching,
@@ -861,6 +895,7 @@ pub fn setup_common<'a>(
         Ok((
             RepoWalkDatasources {
                 blobrepo,
+                blobstore_config,
                 scuba_builder,
             },
             RepoWalkParams {